@@ -8,6 +8,15 @@ use fixedbitset::FixedBitSet;
 pub struct Access {
     config_writes: FixedBitSet,
     config_read_and_writes: FixedBitSet,
+    pcd_writes: FixedBitSet,
+    pcd_read_and_writes: FixedBitSet,
+    variable_writes: FixedBitSet,
+    variable_read_and_writes: FixedBitSet,
+    // Borrows of the storage that are not tracked per resource, e.g. of the protocol database or HOB list.
+    storage_read: bool,
+    storage_write: bool,
+    // A reference to the whole storage is held, e.g. by a `&Storage` param.
+    whole_storage: bool,
     exclusive: bool,
 }
 
@@ -33,6 +42,90 @@ impl Access {
         self.exclusive | self.config_read_and_writes.contains(id)
     }
 
+    /// Returns true if the component needs mutable access to any config resource.
+    pub fn has_any_config_write(&self) -> bool {
+        !self.config_writes.is_clear()
+    }
+
+    /// Registers a write access to a PCD resource.
+    pub fn add_pcd_write(&mut self, id: usize) {
+        self.pcd_writes.grow_and_insert(id);
+        self.pcd_read_and_writes.grow_and_insert(id);
+    }
+
+    /// Registers a read access to a PCD resource.
+    pub fn add_pcd_read(&mut self, id: usize) {
+        self.pcd_read_and_writes.grow_and_insert(id);
+    }
+
+    /// Returns true if the component needs mutable access to the PCD resource denoted by `id`.
+    pub fn has_pcd_write(&self, id: usize) -> bool {
+        self.exclusive | self.pcd_writes.contains(id)
+    }
+
+    /// Returns true if the component needs read access to the PCD resource denoted by `id`.
+    pub fn has_pcd_read(&self, id: usize) -> bool {
+        self.exclusive | self.pcd_read_and_writes.contains(id)
+    }
+
+    /// Registers a write access to a variable resource.
+    pub fn add_variable_write(&mut self, id: usize) {
+        self.variable_writes.grow_and_insert(id);
+        self.variable_read_and_writes.grow_and_insert(id);
+    }
+
+    /// Registers a read access to a variable resource.
+    pub fn add_variable_read(&mut self, id: usize) {
+        self.variable_read_and_writes.grow_and_insert(id);
+    }
+
+    /// Returns true if the component needs mutable access to the variable resource denoted by `id`.
+    pub fn has_variable_write(&self, id: usize) -> bool {
+        self.exclusive | self.variable_writes.contains(id)
+    }
+
+    /// Returns true if the component needs read access to the variable resource denoted by `id`.
+    pub fn has_variable_read(&self, id: usize) -> bool {
+        self.exclusive | self.variable_read_and_writes.contains(id)
+    }
+
+    /// Registers a shared borrow of the storage that is not tracked per resource.
+    pub fn add_storage_read(&mut self) {
+        self.storage_read = true;
+    }
+
+    /// Registers a shared borrow of the whole storage, which implies exclusive access.
+    pub fn add_whole_storage_read(&mut self) {
+        self.whole_storage = true;
+        self.exclusive = true;
+    }
+
+    /// Registers a mutable borrow of the whole storage, which implies exclusive access.
+    pub fn add_storage_write(&mut self) {
+        self.storage_write = true;
+        self.whole_storage = true;
+        self.exclusive = true;
+    }
+
+    /// Returns true if the component borrows the whole storage, shared or mutably.
+    pub fn borrows_whole_storage(&self) -> bool {
+        self.whole_storage
+    }
+
+    /// Returns true if the component borrows the whole storage mutably.
+    pub fn has_storage_write(&self) -> bool {
+        self.storage_write
+    }
+
+    /// Returns true if no access has been registered.
+    pub fn is_empty(&self) -> bool {
+        self.config_read_and_writes.is_clear()
+            && self.pcd_read_and_writes.is_clear()
+            && self.variable_read_and_writes.is_clear()
+            && !self.storage_read
+            && !self.exclusive
+    }
+
     pub fn set_exclusive(&mut self) {
         self.exclusive = true;
     }
@@ -46,6 +139,10 @@ impl fmt::Debug for Access {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Access")
             .field("config_writes", &PrettyFixedBitSet(&self.config_writes))
+            .field("pcd_writes", &PrettyFixedBitSet(&self.pcd_writes))
+            .field("variable_writes", &PrettyFixedBitSet(&self.variable_writes))
+            .field("storage_write", &self.storage_write)
+            .field("whole_storage", &self.whole_storage)
             .field("exclusive", &self.exclusive)
             .finish()
     }
@@ -0,0 +1,882 @@
+//! A bridge that exposes the protocol database of a [Storage] through an EFI Boot Services table.
+//!
+//! C based drivers only receive a pointer to an [efi::BootServices] table, so the functions in this module cannot be
+//! passed a [Storage] directly. Instead, they operate on the [Storage] most recently bound with [set_storage]. The
+//! [ComponentManager](crate::ComponentManager) binds its storage while components run, so C based drivers see the
+//! same protocol database as Rust components that use the `Protocol<P>` param.
+//!
+//! With the `std` feature, a storage is bound to the calling thread only, so managers running on different threads,
+//! e.g. in parallel tests, each see their own storage through the tables. Without it, the binding is global, as the
+//! firmware runs on a single thread.
+//!
+//! The protocol handler, memory allocation, event, timer, task priority and configuration table services are
+//! implemented. All other services return `UNSUPPORTED`.
+//!
+//! Events are backed by the [EventDatabase](sdk::event::EventDatabase) of the storage, so time is virtual: `Stall`
+//! advances the clock, and `WaitForEvent` fast-forwards it to the next timer of the events being waited on. The
+//! bridge only dispatches notify functions registered through `CreateEvent(Ex)`; component notify functions are
+//! dispatched by the [ComponentManager](crate::ComponentManager) once control returns to it.
+//!
+//! Memory is backed by the [MemoryMap](sdk::memory::MemoryMap) of the storage. Pool allocations fall back to the host
+//! heap until the memory map has an arena, so buffers can be returned to the caller without one.
+//!
+//! Each service only borrows the part of the bound [Storage] it operates on, e.g. the protocol database or the event
+//! database, and only for the duration of the call. Params never hold a borrow of those parts, so components can call
+//! the services through the table while holding their params, as can drivers and images. Components with a `&Storage`
+//! or `&mut Storage` param would alias any such borrow, so the [ComponentManager](crate::ComponentManager) unbinds its
+//! storage while they run, and the services return `NOT_READY` to them.
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, Layout};
+#[cfg(feature = "std")]
+use core::cell::Cell;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::{ffi::c_void, ptr};
+
+use r_efi::efi::{self, Guid, Handle, Status};
+use sdk::{
+    component::Storage,
+    config_table::ConfigTableDatabase,
+    event::{EventDatabase, EventNotify},
+    memory::MemoryMap,
+    protocol::{ProtocolDatabase, ProtocolInterface},
+    variable::VariableStore,
+};
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static STORAGE: Cell<*mut Storage> = const { Cell::new(ptr::null_mut()) };
+}
+
+#[cfg(not(feature = "std"))]
+static STORAGE: AtomicPtr<Storage> = AtomicPtr::new(ptr::null_mut());
+
+/// Binds `storage`, returning the previously bound storage, or null.
+#[cfg(feature = "std")]
+fn swap_storage(storage: *mut Storage) -> *mut Storage {
+    STORAGE.with(|bound| bound.replace(storage))
+}
+
+/// Binds `storage`, returning the previously bound storage, or null.
+#[cfg(not(feature = "std"))]
+fn swap_storage(storage: *mut Storage) -> *mut Storage {
+    STORAGE.swap(storage, Ordering::AcqRel)
+}
+
+/// Returns the bound storage, or null.
+#[cfg(feature = "std")]
+fn load_storage() -> *mut Storage {
+    STORAGE.with(|bound| bound.get())
+}
+
+/// Returns the bound storage, or null.
+#[cfg(not(feature = "std"))]
+fn load_storage() -> *mut Storage {
+    STORAGE.load(Ordering::Acquire)
+}
+
+const OPEN_PROTOCOL_BY_DRIVER_EXCLUSIVE: u32 =
+    efi::OPEN_PROTOCOL_BY_DRIVER | efi::OPEN_PROTOCOL_EXCLUSIVE;
+
+/// Binds `storage` as the storage the boot services operate on, returning the previously bound storage, or null.
+/// With the `std` feature, the storage is only bound for services called on the current thread.
+///
+/// A caller that binds its storage temporarily should bind the returned one again afterwards, so that a storage bound
+/// for longer, e.g. by a mock system table, is not left unbound.
+///
+/// ## Safety
+///
+/// - `storage` must remain valid until [clear_storage] is called or another storage is bound.
+/// - No reference to the whole storage, or to a part of it the services operate on, may be in use while a service is
+///   executing.
+pub unsafe fn set_storage(storage: *mut Storage) -> *mut Storage {
+    swap_storage(storage)
+}
+
+/// Unbinds the storage. Boot services return `NOT_READY` until a storage is bound again.
+pub fn clear_storage() {
+    swap_storage(ptr::null_mut());
+}
+
+/// Runs `f` with `storage` unbound if it is the bound storage, binding it again afterwards, even if `f` panics.
+///
+/// Used while a component that borrows the whole storage runs, so that the services cannot alias its borrow.
+pub(crate) fn with_storage_unbound<R>(storage: *mut Storage, f: impl FnOnce() -> R) -> R {
+    struct Rebind(*mut Storage);
+
+    impl Drop for Rebind {
+        fn drop(&mut self) {
+            swap_storage(self.0);
+            // The component may have installed tables directly in the storage.
+            tables_changed();
+        }
+    }
+
+    let _rebind = unbind_storage(storage).then_some(Rebind(storage));
+    f()
+}
+
+/// Unbinds `storage` if it is the bound storage, returning true if it was.
+#[cfg(feature = "std")]
+pub(crate) fn unbind_storage(storage: *mut Storage) -> bool {
+    STORAGE.with(|bound| {
+        let unbind = bound.get() == storage;
+        if unbind {
+            bound.set(ptr::null_mut());
+        }
+        unbind
+    })
+}
+
+/// Unbinds `storage` if it is the bound storage, returning true if it was.
+#[cfg(not(feature = "std"))]
+pub(crate) fn unbind_storage(storage: *mut Storage) -> bool {
+    STORAGE
+        .compare_exchange(
+            storage,
+            ptr::null_mut(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_ok()
+}
+
+/// Returns the bound storage, or `NOT_READY` if no storage is bound.
+fn bound_storage() -> Result<*mut Storage, Status> {
+    let storage = load_storage();
+    match storage.is_null() {
+        true => Err(Status::NOT_READY),
+        false => Ok(storage),
+    }
+}
+
+/// Runs `f` on the protocol database of the bound storage, returning `NOT_READY` if no storage is bound.
+pub(crate) fn with_protocols<R>(
+    f: impl FnOnce(&mut ProtocolDatabase) -> Result<R, Status>,
+) -> Result<R, Status> {
+    let storage = bound_storage()?;
+    // SAFETY: set_storage requires the storage to be valid, and params only borrow the database while retrieved.
+    f(unsafe { &mut *Storage::protocol_db_ptr(storage) })
+}
+
+/// Runs `f` on the event database of the bound storage, returning `NOT_READY` if no storage is bound.
+pub(crate) fn with_events<R>(
+    f: impl FnOnce(&mut EventDatabase) -> Result<R, Status>,
+) -> Result<R, Status> {
+    let storage = bound_storage()?;
+    // SAFETY: set_storage requires the storage to be valid, and params never borrow the event database.
+    f(unsafe { &mut *Storage::events_ptr(storage) })
+}
+
+/// Runs `f` on the configuration tables of the bound storage, returning `NOT_READY` if no storage is bound.
+pub(crate) fn with_config_tables<R>(
+    f: impl FnOnce(&mut ConfigTableDatabase) -> Result<R, Status>,
+) -> Result<R, Status> {
+    let storage = bound_storage()?;
+    // SAFETY: set_storage requires the storage to be valid, and params only borrow the tables while retrieved.
+    f(unsafe { &mut *Storage::config_tables_ptr(storage) })
+}
+
+/// Runs `f` on the variable store of the bound storage, returning `NOT_READY` if no storage is bound.
+///
+/// Params borrow the entries of individual variables, which the store keeps in place and only borrows through their
+/// cells, so they are not aliased.
+pub(crate) fn with_variables<R>(
+    f: impl FnOnce(&mut VariableStore) -> Result<R, Status>,
+) -> Result<R, Status> {
+    let storage = bound_storage()?;
+    // SAFETY: set_storage requires the storage to be valid.
+    f(unsafe { &mut *Storage::variables_ptr(storage) })
+}
+
+/// Runs `f` on the memory map of the bound storage, returning `NOT_READY` if no storage is bound, or `ACCESS_DENIED` if
+/// the memory map is borrowed.
+pub(crate) fn with_memory<R>(
+    f: impl FnOnce(&mut MemoryMap) -> Result<R, Status>,
+) -> Result<R, Status> {
+    let storage = bound_storage()?;
+    // SAFETY: set_storage requires the storage to be valid. Params only borrow the memory map through its cell.
+    let mut memory = unsafe { Storage::memory_raw(storage) }
+        .try_borrow_mut()
+        .map_err(|_| Status::ACCESS_DENIED)?;
+    f(&mut memory)
+}
+
+/// Installs, replaces or removes a configuration table, signaling the event group of its GUID on success.
+pub(crate) fn install_table(guid: &Guid, table: *mut c_void) -> Result<(), Status> {
+    with_config_tables(|tables| tables.install(guid, table))?;
+    // Notify functions of the group may read the system table.
+    tables_changed();
+    with_events(|events| {
+        events.signal_group(guid);
+        Ok(())
+    })
+}
+
+/// Points the system table of a [MockSystemTable](crate::mock::MockSystemTable), if one exists, at the configuration
+/// tables of the bound storage. Called whenever they may have changed.
+pub(crate) fn tables_changed() {
+    #[cfg(feature = "std")]
+    crate::mock::sync_configuration_tables();
+}
+
+pub(crate) fn into_status(result: Result<(), Status>) -> Status {
+    match result {
+        Ok(()) => Status::SUCCESS,
+        Err(status) => status,
+    }
+}
+
+/// Returns a Boot Services table whose protocol handler services operate on the bound storage.
+pub fn boot_services() -> efi::BootServices {
+    efi::BootServices {
+        hdr: efi::TableHeader {
+            signature: efi::BOOT_SERVICES_SIGNATURE,
+            revision: efi::BOOT_SERVICES_REVISION,
+            header_size: core::mem::size_of::<efi::BootServices>() as u32,
+            crc32: 0,
+            reserved: 0,
+        },
+        raise_tpl,
+        restore_tpl,
+        allocate_pages,
+        free_pages,
+        get_memory_map,
+        allocate_pool,
+        free_pool,
+        create_event,
+        set_timer,
+        wait_for_event,
+        signal_event,
+        close_event,
+        check_event,
+        install_protocol_interface,
+        reinstall_protocol_interface,
+        uninstall_protocol_interface,
+        handle_protocol,
+        reserved: ptr::null_mut(),
+        register_protocol_notify,
+        locate_handle,
+        locate_device_path,
+        install_configuration_table,
+        load_image,
+        start_image,
+        exit,
+        unload_image,
+        exit_boot_services,
+        get_next_monotonic_count,
+        stall,
+        set_watchdog_timer,
+        connect_controller,
+        disconnect_controller,
+        open_protocol,
+        close_protocol,
+        open_protocol_information,
+        protocols_per_handle,
+        locate_handle_buffer,
+        locate_protocol,
+        install_multiple_protocol_interfaces,
+        uninstall_multiple_protocol_interfaces,
+        calculate_crc32,
+        copy_mem,
+        set_mem,
+        create_event_ex,
+    }
+}
+
+pub(crate) extern "efiapi" fn install_protocol_interface(
+    handle: *mut Handle,
+    protocol: *mut Guid,
+    interface_type: efi::InterfaceType,
+    interface: *mut c_void,
+) -> Status {
+    if handle.is_null() || protocol.is_null() || interface_type != efi::NATIVE_INTERFACE {
+        return Status::INVALID_PARAMETER;
+    }
+    // SAFETY: Both pointers were checked for null and must be valid per the UEFI specification.
+    let (requested, guid) = unsafe { (*handle, &*protocol) };
+    let requested = (!requested.is_null()).then_some(requested);
+    match with_protocols(|protocols| {
+        protocols.install(requested, guid, ProtocolInterface::from_raw(interface))
+    }) {
+        Ok(installed) => {
+            unsafe { *handle = installed };
+            Status::SUCCESS
+        }
+        Err(status) => status,
+    }
+}
+
+pub(crate) extern "efiapi" fn uninstall_protocol_interface(
+    handle: Handle,
+    protocol: *mut Guid,
+    interface: *mut c_void,
+) -> Status {
+    if handle.is_null() || protocol.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let guid = unsafe { &*protocol };
+    into_status(with_protocols(|protocols| {
+        // An interface installed from Rust is owned by the storage, and components may hold a reference to it.
+        if protocols
+            .get(handle, guid)
+            .is_some_and(|installed| installed.as_ptr() == interface && installed.is_owned())
+        {
+            return Err(Status::ACCESS_DENIED);
+        }
+        protocols.uninstall(handle, guid, interface).map(|_| ())
+    }))
+}
+
+pub(crate) extern "efiapi" fn handle_protocol(
+    handle: Handle,
+    protocol: *mut Guid,
+    interface: *mut *mut c_void,
+) -> Status {
+    if handle.is_null() || protocol.is_null() || interface.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let guid = unsafe { &*protocol };
+    match with_protocols(|protocols| handle_protocol_in(protocols, handle, guid)) {
+        Ok(found) => {
+            unsafe { *interface = found };
+            Status::SUCCESS
+        }
+        Err(status) => {
+            unsafe { *interface = ptr::null_mut() };
+            status
+        }
+    }
+}
+
+pub(crate) extern "efiapi" fn locate_protocol(
+    protocol: *mut Guid,
+    _registration: *mut c_void,
+    interface: *mut *mut c_void,
+) -> Status {
+    if protocol.is_null() || interface.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let guid = unsafe { &*protocol };
+    match with_protocols(|protocols| {
+        protocols
+            .locate(guid)
+            .map(|(_, interface)| interface.as_ptr())
+            .ok_or(Status::NOT_FOUND)
+    }) {
+        Ok(found) => {
+            unsafe { *interface = found };
+            Status::SUCCESS
+        }
+        Err(status) => {
+            unsafe { *interface = ptr::null_mut() };
+            status
+        }
+    }
+}
+
+pub(crate) extern "efiapi" fn locate_handle_buffer(
+    search_type: efi::LocateSearchType,
+    protocol: *mut Guid,
+    _search_key: *mut c_void,
+    no_handles: *mut usize,
+    buffer: *mut *mut Handle,
+) -> Status {
+    if no_handles.is_null() || buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let guid = match search_type {
+        efi::ALL_HANDLES => None,
+        efi::BY_PROTOCOL if !protocol.is_null() => Some(unsafe { &*protocol }),
+        efi::BY_PROTOCOL => return Status::INVALID_PARAMETER,
+        // Protocol notifications are not supported, so there is never a registration to search by.
+        _ => return Status::INVALID_PARAMETER,
+    };
+
+    let handles = match with_protocols(|protocols| Ok(protocols.locate_handles(guid))) {
+        Ok(handles) => handles,
+        Err(status) => return status,
+    };
+    unsafe {
+        *no_handles = 0;
+        *buffer = ptr::null_mut();
+    }
+    if handles.is_empty() {
+        return Status::NOT_FOUND;
+    }
+
+    let size = handles.len() * core::mem::size_of::<Handle>();
+    let mut allocation = ptr::null_mut();
+    let status = allocate_pool(efi::BOOT_SERVICES_DATA, size, &mut allocation);
+    if status.is_error() {
+        return status;
+    }
+    unsafe {
+        ptr::copy_nonoverlapping(handles.as_ptr(), allocation.cast(), handles.len());
+        *no_handles = handles.len();
+        *buffer = allocation.cast();
+    }
+    Status::SUCCESS
+}
+
+/// Opens a protocol interface. Opens are not tracked, so `ACCESS_DENIED` and `ALREADY_STARTED` are never returned.
+pub(crate) extern "efiapi" fn open_protocol(
+    handle: Handle,
+    protocol: *mut Guid,
+    interface: *mut *mut c_void,
+    agent_handle: Handle,
+    controller_handle: Handle,
+    attributes: u32,
+) -> Status {
+    if protocol.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    if attributes != efi::OPEN_PROTOCOL_TEST_PROTOCOL && interface.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let guid = unsafe { &*protocol };
+
+    let result = with_protocols(|protocols| {
+        if !protocols.contains_handle(handle) {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let valid_agent = protocols.contains_handle(agent_handle);
+        let valid_controller = protocols.contains_handle(controller_handle);
+        let valid = match attributes {
+            efi::OPEN_PROTOCOL_BY_HANDLE_PROTOCOL
+            | efi::OPEN_PROTOCOL_GET_PROTOCOL
+            | efi::OPEN_PROTOCOL_TEST_PROTOCOL => true,
+            efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER | efi::OPEN_PROTOCOL_BY_DRIVER => {
+                valid_agent && valid_controller && handle != controller_handle
+            }
+            OPEN_PROTOCOL_BY_DRIVER_EXCLUSIVE => valid_agent && valid_controller,
+            efi::OPEN_PROTOCOL_EXCLUSIVE => valid_agent,
+            _ => false,
+        };
+        if !valid {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        handle_protocol_in(protocols, handle, guid)
+    });
+
+    if attributes == efi::OPEN_PROTOCOL_TEST_PROTOCOL {
+        return into_status(result.map(|_| ()));
+    }
+    let (found, status) = match result {
+        Ok(found) => (found, Status::SUCCESS),
+        Err(status) => (ptr::null_mut(), status),
+    };
+    unsafe { *interface = found };
+    status
+}
+
+/// Closes a protocol interface. Opens are not tracked, so this only checks that the protocol is installed.
+pub(crate) extern "efiapi" fn close_protocol(
+    handle: Handle,
+    protocol: *mut Guid,
+    agent_handle: Handle,
+    _controller_handle: Handle,
+) -> Status {
+    if protocol.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let guid = unsafe { &*protocol };
+    into_status(with_protocols(|protocols| {
+        if !protocols.contains_handle(handle) || !protocols.contains_handle(agent_handle) {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        handle_protocol_in(protocols, handle, guid)
+            .map(|_| ())
+            .map_err(|_| Status::NOT_FOUND)
+    }))
+}
+
+pub(crate) extern "efiapi" fn allocate_pages(
+    allocate_type: efi::AllocateType,
+    memory_type: efi::MemoryType,
+    pages: usize,
+    memory: *mut efi::PhysicalAddress,
+) -> Status {
+    if memory.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The pointer was checked for null and must be valid per the UEFI specification.
+    let address = unsafe { *memory };
+    match with_memory(|memory| memory.allocate_pages(allocate_type, memory_type, pages, address)) {
+        Ok(allocated) => {
+            unsafe { *memory = allocated };
+            Status::SUCCESS
+        }
+        Err(status) => status,
+    }
+}
+
+pub(crate) extern "efiapi" fn free_pages(memory: efi::PhysicalAddress, pages: usize) -> Status {
+    into_status(with_memory(|map| map.free_pages(memory, pages)))
+}
+
+pub(crate) extern "efiapi" fn get_memory_map(
+    memory_map_size: *mut usize,
+    memory_map: *mut efi::MemoryDescriptor,
+    map_key: *mut usize,
+    descriptor_size: *mut usize,
+    descriptor_version: *mut u32,
+) -> Status {
+    if memory_map_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let (descriptors, key) =
+        match with_memory(|memory| Ok((memory.descriptors(), memory.map_key()))) {
+            Ok(map) => map,
+            Err(status) => return status,
+        };
+    let size = descriptors.len() * core::mem::size_of::<efi::MemoryDescriptor>();
+    // SAFETY: The pointers are checked for null, and must be valid per the UEFI specification.
+    unsafe {
+        if !descriptor_size.is_null() {
+            *descriptor_size = core::mem::size_of::<efi::MemoryDescriptor>();
+        }
+        if !descriptor_version.is_null() {
+            *descriptor_version = efi::MEMORY_DESCRIPTOR_VERSION;
+        }
+        if *memory_map_size < size {
+            *memory_map_size = size;
+            return Status::BUFFER_TOO_SMALL;
+        }
+        if memory_map.is_null() || map_key.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        ptr::copy_nonoverlapping(descriptors.as_ptr(), memory_map, descriptors.len());
+        *memory_map_size = size;
+        *map_key = key;
+    }
+    Status::SUCCESS
+}
+
+// Pool allocations are made from the memory map once it has an arena, so they are tracked by type. Until then, they
+// are made from the host heap and store their size before the returned buffer, so they can be freed without the
+// caller providing it. The header also keeps the buffer 8 byte aligned, as required by the UEFI specification.
+const POOL_HEADER: usize = 8;
+
+fn pool_layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(POOL_HEADER)?, POOL_HEADER).ok()
+}
+
+pub(crate) extern "efiapi" fn allocate_pool(
+    pool_type: efi::MemoryType,
+    size: usize,
+    buffer: *mut *mut c_void,
+) -> Status {
+    if buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let tracked = with_memory(|memory| match memory.has_arena() {
+        true => memory.allocate_pool(pool_type, size).map(Some),
+        false => Ok(None),
+    });
+    match tracked {
+        Ok(Some(allocation)) => {
+            unsafe { *buffer = allocation };
+            return Status::SUCCESS;
+        }
+        Err(status) if status != Status::NOT_READY => return status,
+        _ => {}
+    }
+
+    let Some(layout) = pool_layout(size) else {
+        return Status::OUT_OF_RESOURCES;
+    };
+    // SAFETY: The layout is never zero sized, as it always contains the header.
+    let allocation = unsafe { alloc(layout) };
+    if allocation.is_null() {
+        return Status::OUT_OF_RESOURCES;
+    }
+    unsafe {
+        allocation.cast::<usize>().write(size);
+        *buffer = allocation.add(POOL_HEADER).cast();
+    }
+    Status::SUCCESS
+}
+
+pub(crate) extern "efiapi" fn free_pool(buffer: *mut c_void) -> Status {
+    if buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let tracked = with_memory(
+        |memory| match memory.contains(buffer as efi::PhysicalAddress) {
+            true => memory.free_pool(buffer).map(|_| true),
+            false => Ok(false),
+        },
+    );
+    match tracked {
+        Ok(true) => return Status::SUCCESS,
+        Err(status) if status != Status::NOT_READY => return status,
+        _ => {}
+    }
+
+    // SAFETY: The buffer must have been returned by allocate_pool, so the header precedes it.
+    unsafe {
+        let allocation = buffer.cast::<u8>().sub(POOL_HEADER);
+        let size = allocation.cast::<usize>().read();
+        dealloc(
+            allocation,
+            pool_layout(size).expect("Layout was valid when allocated"),
+        );
+    }
+    Status::SUCCESS
+}
+
+extern "efiapi" fn copy_mem(destination: *mut c_void, source: *mut c_void, length: usize) {
+    // SAFETY: The caller must provide valid buffers of at least `length` bytes. They may overlap.
+    unsafe { ptr::copy(source.cast::<u8>(), destination.cast::<u8>(), length) }
+}
+
+extern "efiapi" fn set_mem(buffer: *mut c_void, size: usize, value: u8) {
+    // SAFETY: The caller must provide a valid buffer of at least `size` bytes.
+    unsafe { ptr::write_bytes(buffer.cast::<u8>(), value, size) }
+}
+
+/// Installs, replaces or removes a configuration table, signaling the event group of its GUID.
+pub(crate) extern "efiapi" fn install_configuration_table(
+    guid: *mut Guid,
+    table: *mut c_void,
+) -> Status {
+    if guid.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The pointer was checked for null and must be valid per the UEFI specification.
+    let guid = unsafe { &*guid };
+    let status = into_status(install_table(guid, table));
+    dispatch_notifies();
+    status
+}
+
+/// Dispatches ready EFI notify functions, until none are ready or the next ready notification belongs to a component.
+///
+/// Each notify function is called at its notify TPL, without any reference to the storage held, so it may call boot
+/// services itself.
+pub(crate) fn dispatch_notifies() {
+    loop {
+        let next = with_events(|events| {
+            events
+                .begin_notify(|notify| matches!(notify, EventNotify::Efi { .. }))
+                .ok_or(Status::NOT_FOUND)
+        });
+        let Ok(notification) = next else {
+            return;
+        };
+        if let EventNotify::Efi { function, context } = notification.notify {
+            function(notification.event, context);
+        }
+        let _ = with_events(|events| {
+            events.end_notify(&notification);
+            Ok(())
+        });
+    }
+}
+
+extern "efiapi" fn raise_tpl(new_tpl: efi::Tpl) -> efi::Tpl {
+    with_events(|events| {
+        if new_tpl < events.current_tpl() {
+            log::error!(
+                "RaiseTPL({}) is below the current TPL {}.",
+                new_tpl,
+                events.current_tpl()
+            );
+        }
+        Ok(events.raise_tpl(new_tpl))
+    })
+    .unwrap_or(efi::TPL_APPLICATION)
+}
+
+extern "efiapi" fn restore_tpl(old_tpl: efi::Tpl) {
+    let _ = with_events(|events| {
+        if old_tpl > events.current_tpl() {
+            log::error!(
+                "RestoreTPL({}) is above the current TPL {}.",
+                old_tpl,
+                events.current_tpl()
+            );
+        }
+        events.restore_tpl(old_tpl);
+        Ok(())
+    });
+    dispatch_notifies();
+}
+
+pub(crate) extern "efiapi" fn create_event(
+    event_type: u32,
+    notify_tpl: efi::Tpl,
+    notify_function: Option<efi::EventNotify>,
+    notify_context: *mut c_void,
+    event: *mut efi::Event,
+) -> Status {
+    create_event_ex(
+        event_type,
+        notify_tpl,
+        notify_function,
+        notify_context,
+        ptr::null(),
+        event,
+    )
+}
+
+pub(crate) extern "efiapi" fn create_event_ex(
+    event_type: u32,
+    notify_tpl: efi::Tpl,
+    notify_function: Option<efi::EventNotify>,
+    notify_context: *const c_void,
+    event_group: *const Guid,
+    event: *mut efi::Event,
+) -> Status {
+    if event.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let notify = match notify_function {
+        Some(function) => EventNotify::Efi {
+            function,
+            context: notify_context.cast_mut(),
+        },
+        None => EventNotify::None,
+    };
+    // SAFETY: The group is optional, and must be valid per the UEFI specification if provided.
+    let group = unsafe { event_group.as_ref() };
+    match with_events(|events| events.create(event_type, notify_tpl, notify, group)) {
+        Ok(created) => {
+            unsafe { *event = created };
+            Status::SUCCESS
+        }
+        Err(status) => status,
+    }
+}
+
+pub(crate) extern "efiapi" fn set_timer(
+    event: efi::Event,
+    timer_type: efi::TimerDelay,
+    trigger_time: u64,
+) -> Status {
+    into_status(with_events(|events| {
+        events.set_timer(event, timer_type, trigger_time)
+    }))
+}
+
+pub(crate) extern "efiapi" fn signal_event(event: efi::Event) -> Status {
+    let status = into_status(with_events(|events| events.signal(event)));
+    dispatch_notifies();
+    status
+}
+
+pub(crate) extern "efiapi" fn close_event(event: efi::Event) -> Status {
+    into_status(with_events(|events| events.close(event)))
+}
+
+pub(crate) extern "efiapi" fn check_event(event: efi::Event) -> Status {
+    let status = into_status(with_events(|events| events.check(event)));
+    if status != Status::NOT_READY {
+        return status;
+    }
+    // The wait notify function was queued, and may signal the event.
+    dispatch_notifies();
+    into_status(with_events(|events| match events.is_signaled(event)? {
+        true => events.check(event),
+        false => Err(Status::NOT_READY),
+    }))
+}
+
+/// Waits for one of the events to be signaled.
+///
+/// As time is virtual, the clock is fast-forwarded to the next timer of the events instead of waiting. If no event is
+/// signaled and none has a pending timer, the wait could never complete, so `NOT_READY` is returned instead.
+pub(crate) extern "efiapi" fn wait_for_event(
+    number_of_events: usize,
+    event: *mut efi::Event,
+    index: *mut usize,
+) -> Status {
+    if number_of_events == 0 || event.is_null() || index.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The caller must provide an array of `number_of_events` events.
+    let events = unsafe { core::slice::from_raw_parts(event, number_of_events) };
+    match with_events(|events| Ok(events.current_tpl())) {
+        Ok(efi::TPL_APPLICATION) => {}
+        Ok(_) => return Status::UNSUPPORTED,
+        Err(status) => return status,
+    }
+
+    loop {
+        for (i, event) in events.iter().enumerate() {
+            // CheckEvent returns INVALID_PARAMETER for notify signal events, as WaitForEvent requires.
+            let status = check_event(*event);
+            if status != Status::NOT_READY {
+                unsafe { *index = i };
+                return status;
+            }
+        }
+        let advanced = with_events(|events_db| {
+            let next = events_db
+                .next_trigger_time(Some(events))
+                .ok_or(Status::NOT_READY)?;
+            events_db.advance_time(next.saturating_sub(events_db.now()));
+            Ok(())
+        });
+        if let Err(status) = advanced {
+            log::error!("WaitForEvent would never complete, as no event has a pending timer.");
+            return status;
+        }
+        dispatch_notifies();
+    }
+}
+
+pub(crate) extern "efiapi" fn stall(microseconds: usize) -> Status {
+    let result = with_events(|events| {
+        events.advance_time(microseconds as u64 * 10);
+        Ok(())
+    });
+    dispatch_notifies();
+    into_status(result)
+}
+
+/// Returns the interface of the protocol installed on `handle`, or `UNSUPPORTED` if it is not installed.
+fn handle_protocol_in(
+    protocols: &ProtocolDatabase,
+    handle: Handle,
+    guid: &Guid,
+) -> Result<*mut c_void, Status> {
+    protocols
+        .get(handle, guid)
+        .map(ProtocolInterface::as_ptr)
+        .ok_or(Status::UNSUPPORTED)
+}
+
+/// Generates boot services that are not supported by the bridge.
+macro_rules! unsupported {
+    ($($name:ident($($arg:ty),*);)*) => {
+        $(
+            extern "efiapi" fn $name($(_: $arg),*) -> Status {
+                Status::UNSUPPORTED
+            }
+        )*
+    };
+}
+
+unsupported! {
+    reinstall_protocol_interface(Handle, *mut Guid, *mut c_void, *mut c_void);
+    register_protocol_notify(*mut Guid, efi::Event, *mut *mut c_void);
+    locate_handle(efi::LocateSearchType, *mut Guid, *mut c_void, *mut usize, *mut Handle);
+    locate_device_path(*mut Guid, *mut *mut efi::protocols::device_path::Protocol, *mut Handle);
+    load_image(efi::Boolean, Handle, *mut efi::protocols::device_path::Protocol, *mut c_void, usize, *mut Handle);
+    start_image(Handle, *mut usize, *mut *mut efi::Char16);
+    exit(Handle, Status, usize, *mut efi::Char16);
+    unload_image(Handle);
+    exit_boot_services(Handle, usize);
+    get_next_monotonic_count(*mut u64);
+    set_watchdog_timer(usize, u64, usize, *mut efi::Char16);
+    connect_controller(Handle, *mut Handle, *mut efi::protocols::device_path::Protocol, efi::Boolean);
+    disconnect_controller(Handle, Handle, Handle);
+    open_protocol_information(Handle, *mut Guid, *mut *mut efi::OpenProtocolInformationEntry, *mut usize);
+    protocols_per_handle(Handle, *mut *mut *mut Guid, *mut usize);
+    install_multiple_protocol_interfaces(*mut Handle, *mut c_void, *mut c_void);
+    uninstall_multiple_protocol_interfaces(Handle, *mut c_void, *mut c_void);
+    calculate_crc32(*mut c_void, usize, *mut u32);
+}
@@ -0,0 +1,90 @@
+//! A Module representing a [Component] implementation for a DXE driver discovered in a firmware volume.
+//!
+//! The driver is gated on its dependency expression, and is dispatched by a caller provided function once the
+//! expression is satisfied. Drivers whose expression starts with SOR are held until they are scheduled, as with the
+//! DXE Services `Schedule()`. Drivers run with exclusive access to the storage, as a driver can install and use any
+//! protocol, and the dispatch function accesses the storage through the service tables, like the driver itself.
+use alloc::boxed::Box;
+
+use crate::{unsafe_storage::UnsafeStorageCell, Component, MetaData, RegistrationError};
+use r_efi::efi::Guid;
+use sdk::{
+    component::{Storage, StorageError},
+    fv::Driver,
+};
+
+type DispatchFn = dyn FnMut(&Driver);
+
+/// A [Component] implementation for a DXE driver.
+pub struct DriverComponent {
+    driver: Driver,
+    dispatch: Box<DispatchFn>,
+    metadata: MetaData,
+    // Set for SOR drivers until they are scheduled.
+    held: bool,
+}
+
+impl DriverComponent {
+    pub fn new(driver: Driver, dispatch: impl FnMut(&Driver) + 'static) -> Self {
+        let mut metadata = MetaData::new::<Self>();
+        metadata.name = alloc::format!("{}", driver).into();
+        metadata.depex = driver.depex.clone();
+        let held = driver
+            .depex
+            .as_ref()
+            .is_some_and(|depex| depex.is_schedule_on_request());
+        Self {
+            driver,
+            dispatch: Box::new(dispatch),
+            metadata,
+            held,
+        }
+    }
+}
+
+impl Component for DriverComponent {
+    /// Dispatches the driver if it is not held and its depex is satisfied.
+    ///
+    /// ## Safety
+    ///
+    /// - The component must have exclusive access to the storage.
+    unsafe fn run_unsafe(&mut self, storage: UnsafeStorageCell) -> Result<bool, StorageError> {
+        if self.held {
+            return Ok(false);
+        }
+        if let Some(depex) = &self.metadata.depex {
+            if !depex.evaluate(storage.storage()) {
+                return Ok(false);
+            }
+        }
+
+        (self.dispatch)(&self.driver);
+
+        Ok(true)
+    }
+
+    /// One time initialization of the component. Drivers require exclusive access to the storage.
+    fn initialize(&mut self, _storage: &mut Storage) -> Result<(), RegistrationError> {
+        self.metadata.access.set_exclusive();
+        Ok(())
+    }
+
+    /// Releases the driver if it is a held SOR driver named `name`.
+    fn schedule(&mut self, name: &Guid) -> bool {
+        let scheduled = self.held && self.driver.name == *name;
+        if scheduled {
+            self.held = false;
+        }
+        scheduled
+    }
+
+    /// Returns the metadata of the component.
+    fn metadata(&self) -> &MetaData {
+        &self.metadata
+    }
+
+    /// Returns the mutable metadata of the component.
+    fn metadata_mut(&mut self) -> &mut MetaData {
+        &mut self.metadata
+    }
+}
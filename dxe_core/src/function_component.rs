@@ -14,8 +14,8 @@
 //!
 use core::marker::PhantomData;
 
-use crate::{unsafe_storage::UnsafeStorageCell, MetaData};
-use sdk::component::Storage;
+use crate::{unsafe_storage::UnsafeStorageCell, MetaData, RegistrationError};
+use sdk::component::{Storage, StorageError};
 
 use super::{params::ComponentParam, Component, IntoComponent};
 
@@ -38,22 +38,28 @@ where
     Marker: 'static,
     Func: ComponentParamFunction<Marker>,
 {
-    /// Runs the component if all parameters are retrievable from storage.
+    /// Runs the component if its depex is satisfied and all parameters are retrievable from storage.
     ///
     /// ## Safety
     ///
     /// - Each parameter must properly register its access type.
-    unsafe fn run_unsafe(&mut self, storage: UnsafeStorageCell) -> bool {
+    unsafe fn run_unsafe(&mut self, storage: UnsafeStorageCell) -> Result<bool, StorageError> {
+        if let Some(depex) = &self.metadata.depex {
+            if !depex.evaluate(storage.storage()) {
+                return Ok(false);
+            }
+        }
+
         let param_state = self.param_state.as_mut().expect("Should Exist");
         if !Func::Param::validate(param_state, storage) {
-            return false;
+            return Ok(false);
         }
 
-        let param_value = Func::Param::retrieve(param_state, storage);
+        let param_value = Func::Param::retrieve(param_state, storage)?;
 
         self.func.run(param_value);
 
-        true
+        Ok(true)
     }
 
     /// Returns the metadata of the component.
@@ -61,9 +67,15 @@ where
         &self.metadata
     }
 
+    /// Returns the mutable metadata of the component.
+    fn metadata_mut(&mut self) -> &mut MetaData {
+        &mut self.metadata
+    }
+
     /// One time initialization of the component. Should set access requirements.
-    fn initialize(&mut self, storage: &mut Storage) {
-        self.param_state = Some(Func::Param::initialize(storage, &mut self.metadata));
+    fn initialize(&mut self, storage: &mut Storage) -> Result<(), RegistrationError> {
+        self.param_state = Some(Func::Param::initialize(storage, &mut self.metadata)?);
+        Ok(())
     }
 }
 
@@ -0,0 +1,134 @@
+//! A Module representing a [Component] implementation for a PE/COFF image.
+//!
+//! The image is gated on its dependency expression, on the protocols it declares and on the memory services being
+//! available. Once all are satisfied, it is loaded into pages allocated from the memory map and relocated, and a caller provided function is
+//! given the loaded image, e.g. to call its entry point. Images run with exclusive access to the storage, as an image
+//! can install and use any protocol, and the dispatch function accesses the storage through the service tables.
+use alloc::{borrow::Cow, boxed::Box, vec::Vec};
+use core::cell::RefCell;
+
+use crate::{unsafe_storage::UnsafeStorageCell, Component, MetaData, RegistrationError};
+use r_efi::efi;
+use sdk::{
+    component::{Storage, StorageError},
+    memory::{MemoryMap, PAGE_SIZE},
+    pecoff::{LoadedImage, PeImage, Subsystem},
+};
+
+type DispatchFn = dyn FnMut(&LoadedImage);
+
+/// A [Component] implementation for a PE/COFF image.
+pub struct ImageComponent {
+    image: PeImage,
+    // Protocols that must be installed before the image is loaded, in addition to the depex.
+    protocols: Vec<efi::Guid>,
+    dispatch: Box<DispatchFn>,
+    metadata: MetaData,
+}
+
+impl ImageComponent {
+    pub fn new(
+        name: Cow<'static, str>,
+        image: PeImage,
+        protocols: Vec<efi::Guid>,
+        dispatch: impl FnMut(&LoadedImage) + 'static,
+    ) -> Self {
+        let mut metadata = MetaData::new::<Self>();
+        metadata.name = name;
+        Self {
+            image,
+            protocols,
+            dispatch: Box::new(dispatch),
+            metadata,
+        }
+    }
+
+    /// Allocates memory for the image, of a type matching its subsystem, and loads it there.
+    fn load(&self, memory: &RefCell<MemoryMap>) -> Result<LoadedImage, Cow<'static, str>> {
+        let memory_type = match self.image.subsystem {
+            Subsystem::EFI_APPLICATION => efi::LOADER_CODE,
+            Subsystem::EFI_RUNTIME_DRIVER => efi::RUNTIME_SERVICES_CODE,
+            _ => efi::BOOT_SERVICES_CODE,
+        };
+        // Pages are only page aligned, so over-allocate to align the image to a larger section alignment.
+        let alignment = (self.image.section_alignment as usize).max(PAGE_SIZE);
+        let pages = (self.image.image_size() + alignment - PAGE_SIZE).div_ceil(PAGE_SIZE);
+        let allocation = memory
+            .borrow_mut()
+            .allocate_pages(efi::ALLOCATE_ANY_PAGES, memory_type, pages, 0)
+            .map_err(|status| alloc::format!("{:?}", status))?;
+        let base = allocation.next_multiple_of(alignment as u64);
+
+        // SAFETY: The image lies within the pages just allocated from the arena, which nothing else references.
+        let destination =
+            unsafe { core::slice::from_raw_parts_mut(base as *mut u8, self.image.image_size()) };
+        self.image.load(destination, base).map_err(|err| {
+            let _ = memory.borrow_mut().free_pages(allocation, pages);
+            alloc::format!("{}", err).into()
+        })
+    }
+}
+
+impl Component for ImageComponent {
+    /// Loads and dispatches the image if its depex is satisfied, its protocols are installed and memory is available.
+    ///
+    /// An image that fails to load is logged and not retried.
+    ///
+    /// ## Safety
+    ///
+    /// - The component must have exclusive access to the storage.
+    unsafe fn run_unsafe(&mut self, storage: UnsafeStorageCell) -> Result<bool, StorageError> {
+        if let Some(depex) = &self.metadata.depex {
+            if !depex.evaluate(storage.storage()) {
+                return Ok(false);
+            }
+        }
+        let installed = storage.storage();
+        if !self
+            .protocols
+            .iter()
+            .all(|guid| installed.contains_protocol(guid))
+        {
+            return Ok(false);
+        }
+        let memory = storage.memory();
+        if !memory
+            .try_borrow()
+            .map_err(|_| StorageError::MemoryBorrowed)?
+            .has_arena()
+        {
+            return Ok(false);
+        }
+
+        match self.load(memory) {
+            Ok(loaded) => {
+                log::debug!(
+                    "Image {} loaded at {:#x}, entry point {:#x}.",
+                    self.metadata.name,
+                    loaded.base,
+                    loaded.entry_point
+                );
+                (self.dispatch)(&loaded);
+            }
+            Err(err) => log::error!("Image {} failed to load: {}", self.metadata.name, err),
+        }
+
+        Ok(true)
+    }
+
+    /// One time initialization of the component. Images require exclusive access to the storage.
+    fn initialize(&mut self, _storage: &mut Storage) -> Result<(), RegistrationError> {
+        self.metadata.access.set_exclusive();
+        Ok(())
+    }
+
+    /// Returns the metadata of the component.
+    fn metadata(&self) -> &MetaData {
+        &self.metadata
+    }
+
+    /// Returns the mutable metadata of the component.
+    fn metadata_mut(&mut self) -> &mut MetaData {
+        &mut self.metadata
+    }
+}
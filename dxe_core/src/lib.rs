@@ -1,18 +1,42 @@
 #![no_std]
 
 mod access;
+pub mod boot_services;
+mod driver_component;
 mod function_component;
+mod image_component;
+#[cfg(feature = "std")]
+pub mod mock;
 mod params;
+pub mod runtime_services;
 mod struct_component;
+#[cfg(feature = "std")]
+pub mod testing;
 mod unsafe_storage;
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
 use access::Access;
-use alloc::{borrow::Cow, boxed::Box, vec::Vec};
-use sdk::component::Storage;
+use alloc::{borrow::Cow, boxed::Box, string::String, vec::Vec};
+use core::fmt;
+use r_efi::efi;
+use sdk::{
+    component::{InvalidConfig, SnapshotError, Storage, StorageError, StorageSnapshot},
+    config_table::ConfigTableChange,
+    depex::Depex,
+    event::EventNotify,
+    fv::{Driver, FirmwareVolume, FvError},
+    hob::HobList,
+    memory::MemoryMap,
+    pcd::PcdToken,
+    pecoff::{LoadedImage, PeImage},
+};
 use unsafe_storage::UnsafeStorageCell;
 
+pub use params::{RegistrationError, Resource};
+
 type StoredComponent = Box<dyn Component>;
 
 #[derive(Default)]
@@ -21,6 +45,8 @@ struct MetaData {
     access: Access,
     /// The name of the component.
     name: Cow<'static, str>,
+    /// An optional dependency expression that must be satisfied before the component can run.
+    depex: Option<Depex>,
 }
 
 impl MetaData {
@@ -29,6 +55,7 @@ impl MetaData {
         Self {
             access: Access::default(),
             name: name.into(),
+            depex: None,
         }
     }
 }
@@ -41,21 +68,37 @@ trait Component {
     ///
     /// - Each Parameter must properly register its access, so the scheduler can
     ///   ensure that there are no data conflicts.
-    unsafe fn run_unsafe(&mut self, storage: UnsafeStorageCell) -> bool;
+    ///
+    /// Returns true if the component ran, false if it is waiting on its parameters, or an error if a parameter could
+    /// not be retrieved.
+    unsafe fn run_unsafe(&mut self, storage: UnsafeStorageCell) -> Result<bool, StorageError>;
 
     /// Runs the component with exclusive access to the storage.
     ///
-    /// Due to this, any deferred storage updates can also be performed.
-    fn run(&mut self, storage: &mut Storage) -> bool {
-        let storage_cell = UnsafeStorageCell::from(storage);
-        let result = unsafe { self.run_unsafe(storage_cell) };
+    /// Due to this, any deferred storage updates can also be performed. The storage is passed as a pointer rather than
+    /// a mutable reference, as the service tables access it through the same pointer while the component runs.
+    ///
+    /// # Safety
+    ///
+    /// - `storage` must be valid, and only be accessed through the component or the service tables until this returns.
+    unsafe fn run(&mut self, storage: *mut Storage) -> Result<bool, StorageError> {
+        let storage_cell = UnsafeStorageCell::from_raw(storage);
+        let result = self.run_unsafe(storage_cell);
         // storage.apply_deferred()
         result
     }
     /// One time initialization of the component. Should set access requirements.
-    fn initialize(&mut self, storage: &mut Storage);
+    fn initialize(&mut self, storage: &mut Storage) -> Result<(), RegistrationError>;
+    /// Releases the component if it is held until scheduled by name, like a DXE driver whose depex starts with SOR.
+    ///
+    /// Returns true if the component was held and is now released.
+    fn schedule(&mut self, _name: &efi::Guid) -> bool {
+        false
+    }
     /// Returns the metadata of the component.
     fn metadata(&self) -> &MetaData;
+    /// Returns the mutable metadata of the component.
+    fn metadata_mut(&mut self) -> &mut MetaData;
 }
 
 /// Helper trait to convert an object into a Component.
@@ -69,6 +112,217 @@ trait IntoComponent<Input> {
 pub struct ComponentManager {
     components: Vec<StoredComponent>,
     storage: Storage,
+    config_errors: Vec<InvalidConfig>,
+    boot_services: Option<Box<r_efi::efi::BootServices>>,
+    runtime_services: Option<Box<r_efi::efi::RuntimeServices>>,
+    // Components registered as event notify functions, indexed by their `EventNotify::Owner` id.
+    notify_components: Vec<StoredComponent>,
+    failures: Vec<ComponentFailure>,
+}
+
+/// A component that failed to run.
+#[derive(Debug, Clone)]
+pub struct ComponentFailure {
+    /// The name of the component.
+    pub component: Cow<'static, str>,
+    /// Why the component failed.
+    pub error: ComponentError,
+}
+
+impl fmt::Display for ComponentFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Component {} failed: {}", self.component, self.error)
+    }
+}
+
+/// Why a component failed to run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentError {
+    /// One of its parameters could not be retrieved from storage.
+    Storage(StorageError),
+    /// It panicked while running, with the given message. Panics are only caught with the `std` feature.
+    Panic(String),
+}
+
+impl From<StorageError> for ComponentError {
+    fn from(error: StorageError) -> Self {
+        Self::Storage(error)
+    }
+}
+
+impl fmt::Display for ComponentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Storage(error) => write!(f, "{}", error),
+            Self::Panic(message) => write!(f, "panicked with '{}'", message),
+        }
+    }
+}
+
+/// Why an event with a component as its notify function could not be created.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CreateEventError {
+    /// The event could not be created, following the rules of CreateEventEx.
+    Status(efi::Status),
+    /// The component could not be registered.
+    Registration(RegistrationError),
+}
+
+impl From<efi::Status> for CreateEventError {
+    fn from(status: efi::Status) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl From<RegistrationError> for CreateEventError {
+    fn from(error: RegistrationError) -> Self {
+        Self::Registration(error)
+    }
+}
+
+impl fmt::Display for CreateEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Status(status) => write!(f, "Event cannot be created: {:?}", status),
+            Self::Registration(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Records the failure of a component, logging it.
+fn record_failure(
+    failures: &mut Vec<ComponentFailure>,
+    component: &dyn Component,
+    error: ComponentError,
+) {
+    let failure = ComponentFailure {
+        component: component.metadata().name.clone(),
+        error,
+    };
+    log::error!("{}", failure);
+    failures.push(failure);
+}
+
+/// Runs a component with exclusive access to the storage.
+///
+/// With the `std` feature, a panic is caught and returned as an error, so that the rest of the dispatch can continue.
+/// The parameters of the component are dropped while unwinding, which releases their borrows of the storage; any
+/// borrow the component leaked instead is released here too, and a task priority level it raised is restored.
+///
+/// ## Safety
+///
+/// `storage` must be valid, and only be accessed through the component or the service tables until this returns.
+#[cfg(feature = "std")]
+unsafe fn run_component(
+    component: &mut StoredComponent,
+    storage: *mut Storage,
+) -> Result<bool, ComponentError> {
+    let tpl = (*Storage::events_ptr(storage)).current_tpl();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        run_with_service_tables(component, storage)
+    }));
+    match result {
+        Ok(result) => result.map_err(ComponentError::from),
+        Err(payload) => {
+            // The component has unwound, so nothing else references the storage anymore.
+            let storage = &mut *storage;
+            let released = storage.release_leaked_borrows();
+            if released > 0 {
+                log::warn!(
+                    "Released {} leaked storage borrows of component {}.",
+                    released,
+                    component.metadata().name
+                );
+            }
+            storage.events_mut().restore_tpl(tpl);
+            let message = if let Some(message) = payload.downcast_ref::<&str>() {
+                String::from(*message)
+            } else if let Some(message) = payload.downcast_ref::<String>() {
+                message.clone()
+            } else {
+                String::from("Box<dyn Any>")
+            };
+            Err(ComponentError::Panic(message))
+        }
+    }
+}
+
+/// Runs a component with exclusive access to the storage.
+///
+/// ## Safety
+///
+/// `storage` must be valid, and only be accessed through the component or the service tables until this returns.
+#[cfg(not(feature = "std"))]
+unsafe fn run_component(
+    component: &mut StoredComponent,
+    storage: *mut Storage,
+) -> Result<bool, ComponentError> {
+    run_with_service_tables(component, storage).map_err(ComponentError::from)
+}
+
+/// Runs a component, unbinding `storage` from the service tables while it runs if the component borrows the whole
+/// storage, as the services would alias that borrow.
+///
+/// ## Safety
+///
+/// `storage` must be valid, and only be accessed through the component or the service tables until this returns.
+unsafe fn run_with_service_tables(
+    component: &mut StoredComponent,
+    storage: *mut Storage,
+) -> Result<bool, StorageError> {
+    match component.metadata().access.borrows_whole_storage() {
+        true => boot_services::with_storage_unbound(storage, || component.run(storage)),
+        false => component.run(storage),
+    }
+}
+
+/// Moves any config validation failures recorded in storage into `errors`, logging each one.
+fn collect_config_errors(storage: &mut Storage, errors: &mut Vec<InvalidConfig>, source: &str) {
+    for error in storage.take_config_errors() {
+        log::error!("{} (from {})", error, source);
+        errors.push(error);
+    }
+}
+
+/// Dispatches ready event notifications, calling EFI notify functions and running notify components.
+///
+/// ## Safety
+///
+/// `storage` must be valid, and the only way the storage is accessed until this returns, other than through the boot
+/// services.
+unsafe fn dispatch_notifications(
+    storage: *mut Storage,
+    notify_components: &mut [StoredComponent],
+    errors: &mut Vec<InvalidConfig>,
+    failures: &mut Vec<ComponentFailure>,
+) {
+    while let Some(notification) = (*storage).events_mut().begin_notify(|_| true) {
+        match notification.notify {
+            EventNotify::Efi { function, context } => function(notification.event, context),
+            EventNotify::Owner(index) => {
+                let component = &mut notify_components[index];
+                match run_component(component, storage) {
+                    Ok(true) => {
+                        log::trace!("Notify component {} ran.", component.metadata().name);
+                        collect_config_errors(&mut *storage, errors, &component.metadata().name);
+                    }
+                    Ok(false) => log::warn!(
+                        "Notify component {} was skipped, as its parameters are not available.",
+                        component.metadata().name
+                    ),
+                    Err(err) => record_failure(failures, component.as_ref(), err),
+                }
+            }
+            EventNotify::None => {}
+        }
+        (*storage).events_mut().end_notify(&notification);
+    }
+}
+
+impl Default for ComponentManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ComponentManager {
@@ -77,6 +331,11 @@ impl ComponentManager {
         Self {
             components: Vec::new(),
             storage: Storage::new(),
+            config_errors: Vec::new(),
+            boot_services: None,
+            runtime_services: None,
+            notify_components: Vec::new(),
+            failures: Vec::new(),
         }
     }
 
@@ -85,31 +344,550 @@ impl ComponentManager {
         self.components.len()
     }
 
+    /// Returns the names of the components that have not run yet, in the order they were added.
+    pub fn pending_components(&self) -> impl Iterator<Item = &str> {
+        self.components
+            .iter()
+            .map(|component| component.metadata().name.as_ref())
+    }
+
+    /// Returns the components that failed to run, in the order they failed.
+    ///
+    /// A component fails if one of its parameters cannot be retrieved from storage, e.g. because a config it accesses
+    /// is already borrowed, or, with the `std` feature, if it panics. Failed components are not retried.
+    pub fn failures(&self) -> &[ComponentFailure] {
+        &self.failures
+    }
+
+    /// Returns the storage shared by all components, e.g. to inspect its contents.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Returns a Boot Services table whose protocol handler services operate on this manager's storage.
+    ///
+    /// The table is created on the first call, and remains valid for the lifetime of the manager. The storage is only
+    /// bound to the boot services while components run, so the table should only be used by components.
+    pub fn boot_services(&mut self) -> *mut r_efi::efi::BootServices {
+        self.boot_services
+            .get_or_insert_with(|| Box::new(boot_services::boot_services()))
+            .as_mut()
+    }
+
+    /// Returns a Runtime Services table whose variable services operate on this manager's storage.
+    ///
+    /// Like [boot_services](Self::boot_services), the storage is only bound while components run.
+    pub fn runtime_services(&mut self) -> *mut r_efi::efi::RuntimeServices {
+        self.runtime_services
+            .get_or_insert_with(|| Box::new(runtime_services::runtime_services()))
+            .as_mut()
+    }
+
+    /// Returns true if a service table that operates on the storage has been handed out.
+    fn has_service_tables(&self) -> bool {
+        self.boot_services.is_some() || self.runtime_services.is_some()
+    }
+
     /// Runs all components in the manager.
+    ///
+    /// Event notifications that become ready while a component runs are dispatched once it returns.
     pub fn run(&mut self) {
+        // All accesses to storage while running go through this pointer, so that the boot services can use it too.
+        let storage: *mut Storage = &mut self.storage;
+        let previous = self.bind_storage(storage);
         loop {
             let len = self.components.len();
-            self.components
-                .retain_mut(|component| !component.run(&mut self.storage));
+            self.components.retain_mut(|component| {
+                // SAFETY: The storage is only accessed through the pointer until the loop ends.
+                let done = match unsafe { run_component(component, storage) } {
+                    Ok(ran) => {
+                        if ran {
+                            log::trace!("Component {} ran.", component.metadata().name);
+                        }
+                        ran
+                    }
+                    // A failed component is removed rather than retried, so the remaining ones still run.
+                    Err(err) => {
+                        record_failure(&mut self.failures, component.as_ref(), err);
+                        true
+                    }
+                };
+                if done {
+                    collect_config_errors(
+                        unsafe { &mut *storage },
+                        &mut self.config_errors,
+                        &component.metadata().name,
+                    );
+                }
+                unsafe {
+                    dispatch_notifications(
+                        storage,
+                        &mut self.notify_components,
+                        &mut self.config_errors,
+                        &mut self.failures,
+                    )
+                };
+                !done
+            });
             if len == self.components.len() {
                 break;
             }
         }
+        Self::unbind_storage(previous);
+    }
+
+    /// Binds `storage` to the service tables if any were handed out, returning the storage bound before, if any.
+    ///
+    /// The storage bound before is bound again by [unbind_storage](Self::unbind_storage), so a storage bound for
+    /// longer, e.g. by a mock system table, is only replaced while the manager runs.
+    fn bind_storage(&self, storage: *mut Storage) -> Option<*mut Storage> {
+        // SAFETY: The storage is bound only until unbind_storage is called, at the end of the same method.
+        self.has_service_tables()
+            .then(|| unsafe { boot_services::set_storage(storage) })
+    }
+
+    /// Binds the storage returned by [bind_storage](Self::bind_storage) again.
+    fn unbind_storage(previous: Option<*mut Storage>) {
+        if let Some(previous) = previous {
+            // SAFETY: Whoever bound the previous storage keeps it valid until they unbind it.
+            unsafe { boot_services::set_storage(previous) };
+            boot_services::tables_changed();
+        }
     }
 
     #[allow(private_bounds)]
     /// Adds a component to the manager.
-    pub fn add_component<'a, I, C: Component + 'static>(
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the component cannot be registered. See [try_add_component](Self::try_add_component).
+    pub fn add_component<I, C: Component + 'static>(
         &mut self,
         component: impl IntoComponent<I, Component = C>,
+    ) {
+        self.push_component(component.into_component());
+    }
+
+    #[allow(private_bounds)]
+    /// Adds a component to the manager, returning an error instead of panicking if it cannot be registered, e.g.
+    /// because two of its parameters access the same config and one of them mutably.
+    ///
+    /// A component that fails to register is not added, so the platform can log the error and continue booting.
+    pub fn try_add_component<I, C: Component + 'static>(
+        &mut self,
+        component: impl IntoComponent<I, Component = C>,
+    ) -> Result<(), RegistrationError> {
+        self.try_push_component(component.into_component())
+    }
+
+    #[allow(private_bounds)]
+    /// Adds a component to the manager that only runs once `depex` is satisfied, in addition to its parameters.
+    ///
+    /// The depex is evaluated against the protocols installed in storage each time the component is considered.
+    pub fn add_component_with_depex<I, C: Component + 'static>(
+        &mut self,
+        component: impl IntoComponent<I, Component = C>,
+        depex: Depex,
     ) {
         let mut component = component.into_component();
-        component.initialize(&mut self.storage);
+        component.metadata_mut().depex = Some(depex);
+        self.push_component(component);
+    }
+
+    /// Initializes a component and adds it to the manager, panicking if it cannot be registered.
+    fn push_component(&mut self, component: impl Component + 'static) {
+        if let Err(err) = self.try_push_component(component) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Initializes a component and adds it to the manager.
+    fn try_push_component(
+        &mut self,
+        mut component: impl Component + 'static,
+    ) -> Result<(), RegistrationError> {
+        let result = component.initialize(&mut self.storage);
+        collect_config_errors(
+            &mut self.storage,
+            &mut self.config_errors,
+            &component.metadata().name,
+        );
+        result?;
         self.components.push(Box::new(component));
+        Ok(())
+    }
+
+    /// Adds a DXE driver to the manager, which is dispatched by `dispatch` once its depex is satisfied.
+    ///
+    /// Drivers are scheduled alongside all other components, with exclusive access to the storage. `dispatch` accesses
+    /// the storage through the service tables, e.g. those returned by [boot_services](Self::boot_services). Drivers whose
+    /// depex starts with SOR are held until they are released with [schedule](Self::schedule). BEFORE and AFTER are not
+    /// enforced; such drivers are dispatched as if they had no depex.
+    pub fn add_driver(&mut self, driver: Driver, dispatch: impl FnMut(&Driver) + 'static) {
+        self.push_component(driver_component::DriverComponent::new(driver, dispatch));
+    }
+
+    /// Releases the held SOR driver named `name`, so that it is dispatched once the rest of its depex is satisfied.
+    ///
+    /// Returns false if no driver with that name is held, e.g. because its depex does not start with SOR or it was
+    /// already released.
+    pub fn schedule(&mut self, name: &efi::Guid) -> bool {
+        self.components
+            .iter_mut()
+            .any(|component| component.schedule(name))
+    }
+
+    /// Adds all DXE drivers found in a firmware volume image to the manager. See [add_driver](Self::add_driver).
+    ///
+    /// Returns the number of drivers added.
+    pub fn add_drivers_from_fv(
+        &mut self,
+        fv: &[u8],
+        dispatch: impl FnMut(&Driver) + Clone + 'static,
+    ) -> Result<usize, FvError> {
+        let drivers = FirmwareVolume::parse(fv)?.drivers()?;
+        let count = drivers.len();
+        for driver in drivers {
+            self.add_driver(driver, dispatch.clone());
+        }
+        Ok(count)
+    }
+
+    /// Adds a PE/COFF image to the manager, which is loaded and then dispatched by `dispatch` once `depex` is satisfied.
+    ///
+    /// Images are gated on their depex, e.g. one created with [Depex::from_protocols] for the protocols they consume,
+    /// and on memory having been added with [add_memory](Self::add_memory). They are loaded into pages of a memory type
+    /// matching their subsystem and relocated, and then dispatched with exclusive access to the storage, which
+    /// `dispatch` accesses through the service tables.
+    pub fn add_image(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        image: PeImage,
+        depex: Option<Depex>,
+        dispatch: impl FnMut(&LoadedImage) + 'static,
+    ) {
+        let mut component =
+            image_component::ImageComponent::new(name.into(), image, Vec::new(), dispatch);
+        component.metadata_mut().depex = depex;
+        self.push_component(component);
+    }
+
+    /// Adds a PE/COFF image to the manager, which is loaded and then dispatched by `dispatch` once all of `protocols`
+    /// are installed.
+    ///
+    /// This declares the protocols the image consumes, like the `Protocol<P>` params of a component, rather than
+    /// expressing them as a depex. Otherwise, the image is loaded and dispatched as with [add_image](Self::add_image).
+    pub fn add_image_with_protocols(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        image: PeImage,
+        protocols: &[efi::Guid],
+        dispatch: impl FnMut(&LoadedImage) + 'static,
+    ) {
+        self.push_component(image_component::ImageComponent::new(
+            name.into(),
+            image,
+            protocols.to_vec(),
+            dispatch,
+        ));
+    }
+
+    /// Loads the HOBs handed off from PEI, making them available to components through `Hob<T>` and `GuidHob<G>`.
+    pub fn add_hobs(&mut self, hobs: HobList) {
+        log::debug!("Loaded {} HOBs.", hobs.len());
+        self.storage.add_hobs(hobs);
+    }
+
+    /// Backs the memory services with an arena of `pages` pages of host memory, all conventional memory to start.
+    ///
+    /// Components with a `MemoryServices` param wait until this is called. Outstanding allocations can be inspected
+    /// through [storage](Self::storage), e.g. to detect leaks.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if memory was already added, as existing allocations would be invalidated.
+    pub fn add_memory(&mut self, pages: usize) {
+        if let Err(err) = self.storage.set_memory(MemoryMap::with_arena(pages)) {
+            panic!("{}", err);
+        }
+        log::debug!("Added {} pages of memory.", pages);
+    }
+
+    /// Loads non volatile variables persisted with [save_variables](Self::save_variables).
+    ///
+    /// Returns the number of variables loaded.
+    #[cfg(feature = "std")]
+    pub fn load_variables(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<usize> {
+        let bytes = std::fs::read(path)?;
+        self.storage.variables_mut().load(&bytes).map_err(|status| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                alloc::format!("{:?}", status),
+            )
+        })
+    }
+
+    /// Persists all non volatile variables to a file.
+    #[cfg(feature = "std")]
+    pub fn save_variables(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.storage.variables().to_bytes())
+    }
+
+    /// Dispatches ready event notifications, with the storage bound to the service tables.
+    fn notify(&mut self) {
+        let storage: *mut Storage = &mut self.storage;
+        let previous = self.bind_storage(storage);
+        // SAFETY: The storage is only accessed through the pointer until dispatching ends.
+        unsafe {
+            dispatch_notifications(
+                storage,
+                &mut self.notify_components,
+                &mut self.config_errors,
+                &mut self.failures,
+            )
+        };
+        Self::unbind_storage(previous);
+    }
+
+    /// Creates an event without a notify function, following the rules of CreateEventEx.
+    pub fn create_event(
+        &mut self,
+        event_type: u32,
+        group: Option<&efi::Guid>,
+    ) -> Result<efi::Event, efi::Status> {
+        self.storage
+            .events_mut()
+            .create(event_type, efi::TPL_APPLICATION, EventNotify::None, group)
+    }
+
+    #[allow(private_bounds)]
+    /// Creates an event whose notify function is a component, following the rules of CreateEventEx.
+    ///
+    /// The component runs at `notify_tpl` each time the event is notified, with its parameters injected as usual. A
+    /// notification is skipped, and logged, if the parameters are not available at that time.
+    ///
+    /// The component is registered before the event is created, so no event is left behind if it cannot be
+    /// registered, e.g. because two of its parameters conflict.
+    pub fn create_event_with_component<I, C: Component + 'static>(
+        &mut self,
+        event_type: u32,
+        notify_tpl: efi::Tpl,
+        group: Option<&efi::Guid>,
+        component: impl IntoComponent<I, Component = C>,
+    ) -> Result<efi::Event, CreateEventError> {
+        let mut component = component.into_component();
+        let result = component.initialize(&mut self.storage);
+        collect_config_errors(
+            &mut self.storage,
+            &mut self.config_errors,
+            &component.metadata().name,
+        );
+        result?;
+        let notify = EventNotify::Owner(self.notify_components.len());
+        let event = self
+            .storage
+            .events_mut()
+            .create(event_type, notify_tpl, notify, group)?;
+        self.notify_components.push(Box::new(component));
+        Ok(event)
+    }
+
+    /// Signals an event, or all events of its group, following the rules of SignalEvent.
+    pub fn signal_event(&mut self, event: efi::Event) -> Result<(), efi::Status> {
+        self.storage.events_mut().signal(event)?;
+        self.notify();
+        Ok(())
+    }
+
+    /// Signals all events of an event group, e.g. [EVENT_GROUP_EXIT_BOOT_SERVICES](sdk::event::EVENT_GROUP_EXIT_BOOT_SERVICES).
+    ///
+    /// Returns the number of events signaled.
+    pub fn signal_event_group(&mut self, group: &efi::Guid) -> usize {
+        let count = self.storage.events_mut().signal_group(group);
+        self.notify();
+        count
+    }
+
+    /// Checks whether an event is signaled, following the rules of CheckEvent.
+    pub fn check_event(&mut self, event: efi::Event) -> Result<(), efi::Status> {
+        match self.storage.events_mut().check(event) {
+            Err(efi::Status::NOT_READY) => {}
+            result => return result,
+        }
+        // The wait notify function was queued, and may signal the event.
+        self.notify();
+        match self.storage.events().is_signaled(event)? {
+            true => self.storage.events_mut().check(event),
+            false => Err(efi::Status::NOT_READY),
+        }
+    }
+
+    /// Sets, or cancels, the timer of an event, following the rules of SetTimer. `trigger_time` is in 100ns units of
+    /// the virtual clock.
+    pub fn set_timer(
+        &mut self,
+        event: efi::Event,
+        timer_type: efi::TimerDelay,
+        trigger_time: u64,
+    ) -> Result<(), efi::Status> {
+        self.storage
+            .events_mut()
+            .set_timer(event, timer_type, trigger_time)
+    }
+
+    /// Closes an event.
+    pub fn close_event(&mut self, event: efi::Event) -> Result<(), efi::Status> {
+        self.storage.events_mut().close(event)
+    }
+
+    /// Installs, replaces or, if `table` is null, removes a configuration table, dispatching the notifications of
+    /// the table's event group.
+    pub fn install_configuration_table(
+        &mut self,
+        guid: &efi::Guid,
+        table: *mut core::ffi::c_void,
+    ) -> Result<ConfigTableChange, efi::Status> {
+        let change = self.storage.install_configuration_table(guid, table)?;
+        self.notify();
+        Ok(change)
+    }
+
+    /// Moves the virtual clock forward by `delta` 100ns units, signaling expired timers and dispatching their
+    /// notifications.
+    ///
+    /// Returns the number of timers that expired.
+    pub fn advance_time(&mut self, delta: u64) -> usize {
+        let expired = self.storage.events_mut().advance_time(delta);
+        self.notify();
+        expired
+    }
+
+    /// Returns the current time of the virtual clock, in 100ns units.
+    pub fn now(&self) -> u64 {
+        self.storage.events().now()
+    }
+
+    /// Raises the TPL, following the rules of RaiseTPL. Returns the previous TPL.
+    ///
+    /// Notifications at or below the raised TPL are held until it is restored.
+    pub fn raise_tpl(&mut self, new_tpl: efi::Tpl) -> efi::Tpl {
+        self.storage.events_mut().raise_tpl(new_tpl)
+    }
+
+    /// Restores the TPL, following the rules of RestoreTPL, and dispatches notifications that became ready.
+    pub fn restore_tpl(&mut self, old_tpl: efi::Tpl) {
+        self.storage.events_mut().restore_tpl(old_tpl);
+        self.notify();
+    }
+
+    /// Returns the current TPL.
+    pub fn current_tpl(&self) -> efi::Tpl {
+        self.storage.events().current_tpl()
     }
 
     /// Adds a Configuration value to the manager.
+    ///
+    /// The value is not added if it fails validation. See [add_config_validator](Self::add_config_validator).
+    ///
+    /// ## Panics
+    ///
+    /// Panics if configs are frozen.
     pub fn add_config<C: Default + 'static>(&mut self, config: C) {
-        self.storage.add_config(config);
+        if let Err(err) = self.storage.add_config(config) {
+            panic!(
+                "Config {} cannot be added: {}",
+                core::any::type_name::<C>(),
+                err
+            );
+        }
+        collect_config_errors(&mut self.storage, &mut self.config_errors, "add_config");
+    }
+
+    /// Registers a validator that is run whenever a value of Configuration `C` enters storage.
+    ///
+    /// Values are validated when added with [add_config](Self::add_config), when a default value is created for a
+    /// component, and when a `ConfigMut<C>` is dropped. A value that already exists is validated immediately. Types
+    /// implementing [ValidateConfig](sdk::component::ValidateConfig) can pass `C::validate` as the validator.
+    /// Failures are logged and available from [config_errors](Self::config_errors).
+    pub fn add_config_validator<C: Default + 'static>(
+        &mut self,
+        validator: impl Fn(&C) -> Result<(), Cow<'static, str>> + 'static,
+    ) {
+        self.storage.add_config_validator(validator);
+        collect_config_errors(
+            &mut self.storage,
+            &mut self.config_errors,
+            "add_config_validator",
+        );
+    }
+
+    /// Registers Configuration `C` as cloneable, so that it can be copied by [snapshot](Self::snapshot).
+    pub fn add_config_cloner<C: Default + Clone + 'static>(&mut self) {
+        self.storage.add_config_cloner::<C>();
+    }
+
+    /// Captures the current state of storage, including events, the TPL and memory.
+    ///
+    /// Configuration values registered with [add_config_cloner](Self::add_config_cloner) are copied. Others are shared
+    /// with the snapshot and are read-only while it exists, so components with a `ConfigMut` parameter for them fail
+    /// with [StorageError::ConfigShared].
+    pub fn snapshot(&self) -> StorageSnapshot {
+        self.storage.snapshot()
+    }
+
+    /// Restores storage to the state captured by `snapshot`.
+    ///
+    /// All pending components are removed, as they may depend on resources registered after the snapshot was taken.
+    /// Events notifying a component are closed and their components removed for the same reason. Components, and
+    /// events with component notify functions, must be added again after restoring.
+    ///
+    /// Fails, leaving the manager unchanged, if the memory captured by the snapshot has since been replaced, e.g. by
+    /// restoring an earlier snapshot and adding memory again.
+    pub fn restore(&mut self, snapshot: &StorageSnapshot) -> Result<(), SnapshotError> {
+        self.storage.restore(snapshot)?;
+        self.components.clear();
+        self.storage
+            .events_mut()
+            .close_all(|notify| matches!(notify, EventNotify::Owner(_)));
+        self.notify_components.clear();
+        Ok(())
+    }
+
+    /// Returns all config validation failures that have occurred.
+    pub fn config_errors(&self) -> &[InvalidConfig] {
+        &self.config_errors
+    }
+
+    /// Freezes all Configuration values, making them read-only.
+    ///
+    /// This is intended to be called once all platform configs are loaded, or at a phase boundary. Afterwards, no
+    /// config can be added, including by components with a `&mut Storage` parameter. A component with a `ConfigMut`
+    /// parameter, or a `Config` parameter for a config without a value, can no longer be registered, and pending
+    /// components with a `ConfigMut` parameter fail with [StorageError::ConfigsFrozen], as they can no longer run.
+    /// Other components with `Config` parameters are unaffected.
+    pub fn freeze_configs(&mut self) {
+        self.storage.freeze_configs();
+        let failures = &mut self.failures;
+        self.components.retain(|component| {
+            let writes = component.metadata().access.has_any_config_write();
+            if writes {
+                record_failure(
+                    failures,
+                    component.as_ref(),
+                    ComponentError::Storage(StorageError::ConfigsFrozen),
+                );
+            }
+            !writes
+        });
+    }
+
+    /// Returns true if Configuration values are frozen.
+    pub fn configs_frozen(&self) -> bool {
+        self.storage.configs_frozen()
+    }
+
+    /// Adds a PCD value to the manager.
+    pub fn add_pcd<T: PcdToken>(&mut self, value: T::Value) {
+        self.storage.add_pcd::<T>(value);
     }
 }
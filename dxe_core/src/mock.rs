@@ -0,0 +1,575 @@
+//! A host-runnable fake EFI System Table for testing code that consumes r_efi tables.
+//!
+//! [MockSystemTable] provides boot services, runtime services, a ConOut protocol and configuration tables. Boot
+//! services are backed by a [Storage] through the [boot_services](crate::boot_services) bridge, and variable services
+//! through the [runtime_services](crate::runtime_services) bridge, so protocols and variables set by the code under
+//! test can be inspected afterwards. Every service call returning a [Status] is recorded, and the
+//! status returned by the next call to a service can be scripted with [MockSystemTable::script].
+//!
+//! The extern "efiapi" functions in the tables cannot be given any context, so the mock uses global state. Only one
+//! [MockSystemTable] can exist at a time; creating another blocks until the first is dropped, which serializes tests
+//! that use the mock. Its storage is bound to the tables on the thread that created it, so the code under test must
+//! call the services on that thread.
+extern crate alloc;
+extern crate std;
+
+pub mod protocols;
+
+use alloc::{boxed::Box, collections::VecDeque, string::String, vec::Vec};
+use core::{
+    cell::Cell,
+    ffi::c_void,
+    ops::{Deref, DerefMut},
+    ptr,
+};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use r_efi::efi::{self, protocols::simple_text_output, Guid, Handle, Status};
+use sdk::component::Storage;
+
+use crate::{
+    boot_services::{self, with_config_tables},
+    runtime_services,
+};
+
+/// A recorded call to a service of the [MockSystemTable], or to a function of a [mock protocol](protocols).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// The name of the service, e.g. `locate_protocol`.
+    pub service: &'static str,
+    /// The status the service returned.
+    pub status: Status,
+}
+
+struct MockState {
+    calls: Vec<Call>,
+    scripted: HashMap<&'static str, VecDeque<Status>>,
+    con_out: String,
+    system_table: *mut efi::SystemTable,
+    // The copies of the configuration tables the system table has pointed at, the current one last. Earlier copies
+    // are kept until the mock is dropped, as the code under test may still hold a pointer to them.
+    tables: Vec<Box<[efi::ConfigurationTable]>>,
+}
+
+impl MockState {
+    /// Points the system table at a copy of `tables`, unless the current copy already holds the same tables.
+    fn publish(&mut self, tables: &[efi::ConfigurationTable]) {
+        let current = self.tables.last().map_or(&[][..], |copy| &copy[..]);
+        let unchanged = current.len() == tables.len()
+            && current
+                .iter()
+                .zip(tables)
+                .all(|(a, b)| a.vendor_guid == b.vendor_guid && a.vendor_table == b.vendor_table);
+        if unchanged {
+            return;
+        }
+        let mut copy: Box<[efi::ConfigurationTable]> = tables.into();
+        // SAFETY: The system table outlives the state, which is cleared when the mock is dropped.
+        unsafe {
+            (*self.system_table).number_of_table_entries = copy.len();
+            (*self.system_table).configuration_table = copy.as_mut_ptr();
+        }
+        self.tables.push(copy);
+    }
+}
+
+// SAFETY: The system table pointer is only dereferenced while the owning MockSystemTable is alive, and access to the
+// state is serialized through the STATE mutex.
+unsafe impl Send for MockState {}
+
+static STATE: Mutex<Option<MockState>> = Mutex::new(None);
+static MOCK_LOCK: Mutex<()> = Mutex::new(());
+
+fn state() -> MutexGuard<'static, Option<MockState>> {
+    // A test panicking while holding the lock must not break other tests.
+    STATE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Records a call to `service`, returning the scripted status if one exists, or the result of `f` otherwise.
+fn intercept(service: &'static str, f: impl FnOnce() -> Status) -> Status {
+    let scripted = state()
+        .as_mut()
+        .and_then(|state| state.scripted.get_mut(service)?.pop_front());
+    // The lock is not held while calling `f`, as it may call other services.
+    let status = scripted.unwrap_or_else(f);
+    if let Some(state) = state().as_mut() {
+        state.calls.push(Call { service, status });
+    }
+    status
+}
+
+/// A fake EFI System Table backed by a [Storage]. See the [module](self) documentation.
+pub struct MockSystemTable {
+    // Owned through a raw pointer, as the state writes the configuration tables through the same pointer.
+    system_table: *mut efi::SystemTable,
+    _boot_services: Box<efi::BootServices>,
+    _runtime_services: Box<efi::RuntimeServices>,
+    _con_out: Box<simple_text_output::Protocol>,
+    _con_out_mode: Box<simple_text_output::Mode>,
+    _firmware_vendor: Box<[u16]>,
+    // Owned through a raw pointer, as the boot services bridge accesses it through a pointer as well.
+    storage: *mut Storage,
+    // The storage bound to the boot services before the mock, bound again once the mock is dropped.
+    previous_storage: *mut Storage,
+    // The number of live StorageRefs, and whether the first one unbound the storage from the tables.
+    storage_refs: Cell<usize>,
+    unbound: Cell<bool>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl MockSystemTable {
+    /// Creates a mock system table whose boot services operate on `storage`.
+    pub fn new(storage: Storage) -> Self {
+        let lock = MOCK_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        let mut con_out_mode = Box::new(simple_text_output::Mode {
+            max_mode: 1,
+            mode: 0,
+            attribute: 0,
+            cursor_column: 0,
+            cursor_row: 0,
+            cursor_visible: efi::Boolean::FALSE,
+        });
+        let mut con_out = Box::new(simple_text_output::Protocol {
+            reset: con_out_reset,
+            output_string: con_out_output_string,
+            test_string: con_out_test_string,
+            query_mode: con_out_query_mode,
+            set_mode: con_out_set_mode,
+            set_attribute: con_out_set_attribute,
+            clear_screen: con_out_clear_screen,
+            set_cursor_position: con_out_set_cursor_position,
+            enable_cursor: con_out_enable_cursor,
+            mode: con_out_mode.as_mut(),
+        });
+        let mut boot_services = Box::new(mock_boot_services());
+        let mut runtime_services = Box::new(mock_runtime_services());
+        let mut firmware_vendor: Box<[u16]> = "Mock".encode_utf16().chain([0]).collect();
+
+        let system_table = Box::into_raw(Box::new(efi::SystemTable {
+            hdr: efi::TableHeader {
+                signature: efi::SYSTEM_TABLE_SIGNATURE,
+                revision: efi::SYSTEM_TABLE_REVISION,
+                header_size: core::mem::size_of::<efi::SystemTable>() as u32,
+                crc32: 0,
+                reserved: 0,
+            },
+            firmware_vendor: firmware_vendor.as_mut_ptr(),
+            firmware_revision: 0,
+            console_in_handle: ptr::null_mut(),
+            con_in: ptr::null_mut(),
+            console_out_handle: ptr::null_mut(),
+            con_out: con_out.as_mut(),
+            standard_error_handle: ptr::null_mut(),
+            std_err: con_out.as_mut(),
+            runtime_services: runtime_services.as_mut(),
+            boot_services: boot_services.as_mut(),
+            number_of_table_entries: 0,
+            configuration_table: ptr::null_mut(),
+        }));
+
+        let storage = Box::into_raw(Box::new(storage));
+        // SAFETY: The storage is valid until the mock is dropped, which unbinds it first.
+        let previous_storage = unsafe { boot_services::set_storage(storage) };
+
+        *state() = Some(MockState {
+            calls: Vec::new(),
+            scripted: HashMap::new(),
+            con_out: String::new(),
+            system_table,
+            tables: Vec::new(),
+        });
+        sync_configuration_tables();
+
+        Self {
+            system_table,
+            _boot_services: boot_services,
+            _runtime_services: runtime_services,
+            _con_out: con_out,
+            _con_out_mode: con_out_mode,
+            _firmware_vendor: firmware_vendor,
+            storage,
+            previous_storage,
+            storage_refs: Cell::new(0),
+            unbound: Cell::new(false),
+            _lock: lock,
+        }
+    }
+
+    /// Returns a pointer to the system table, to be passed to the code under test.
+    pub fn system_table(&mut self) -> *mut efi::SystemTable {
+        // Storage may have been bound through boot_services::set_storage since the tables were last published.
+        sync_configuration_tables();
+        self.system_table
+    }
+
+    /// Returns the storage backing the boot services.
+    ///
+    /// The storage is unbound from the tables while the returned reference is held, so services called meanwhile
+    /// return `NOT_READY` rather than aliasing it.
+    pub fn storage(&self) -> StorageRef<'_> {
+        let refs = self.storage_refs.get();
+        if refs == 0 {
+            self.unbound
+                .set(boot_services::unbind_storage(self.storage));
+        }
+        self.storage_refs.set(refs + 1);
+        StorageRef { mock: self }
+    }
+
+    /// Returns the storage backing the boot services mutably. Like [storage](Self::storage), the storage is unbound
+    /// from the tables while the returned reference is held.
+    pub fn storage_mut(&mut self) -> StorageMut<'_> {
+        let rebind = boot_services::unbind_storage(self.storage).then_some(self.storage);
+        StorageMut {
+            // SAFETY: The storage is valid while the mock is, and unbound while the reference is held.
+            storage: unsafe { &mut *self.storage },
+            rebind,
+        }
+    }
+
+    /// Returns all recorded service calls, in the order they were made.
+    pub fn calls(&self) -> Vec<Call> {
+        state()
+            .as_ref()
+            .map(|s| s.calls.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of recorded calls to `service`.
+    pub fn call_count(&self, service: &str) -> usize {
+        self.calls().iter().filter(|c| c.service == service).count()
+    }
+
+    /// Clears all recorded service calls.
+    pub fn clear_calls(&mut self) {
+        if let Some(state) = state().as_mut() {
+            state.calls.clear();
+        }
+    }
+
+    /// Scripts the status returned by the next call to `service`. The service is not executed for that call.
+    ///
+    /// Scripting the same service multiple times queues the statuses in order. Runtime services other than the
+    /// variable services are not implemented, and return `UNSUPPORTED` unless scripted.
+    pub fn script(&mut self, service: &'static str, status: Status) {
+        if let Some(state) = state().as_mut() {
+            state.scripted.entry(service).or_default().push_back(status);
+        }
+    }
+
+    /// Returns all text written to ConOut or StdErr.
+    pub fn con_out(&self) -> String {
+        state()
+            .as_ref()
+            .map(|s| s.con_out.clone())
+            .unwrap_or_default()
+    }
+
+    /// Installs, replaces or, if `table` is null, removes a configuration table.
+    pub fn install_configuration_table(&mut self, guid: Guid, table: *mut c_void) -> Status {
+        let mut guid = guid;
+        install_configuration_table(&mut guid, table)
+    }
+
+    /// Returns the installed configuration tables.
+    pub fn configuration_tables(&self) -> Vec<(Guid, *mut c_void)> {
+        self.storage()
+            .configuration_tables()
+            .iter()
+            .map(|t| (t.vendor_guid, t.vendor_table))
+            .collect()
+    }
+
+    /// Consumes the mock, returning the storage backing the boot services.
+    pub fn into_storage(mut self) -> Storage {
+        self.unbind();
+        let storage = core::mem::replace(&mut self.storage, ptr::null_mut());
+        unsafe { *Box::from_raw(storage) }
+    }
+
+    /// Binds the storage that was bound before the mock again, if the mock's storage is still bound.
+    fn unbind(&mut self) {
+        if self.storage.is_null() {
+            return;
+        }
+        // SAFETY: Whoever bound the previous storage keeps it valid until they unbind it.
+        unsafe { boot_services::set_storage(self.previous_storage) };
+    }
+}
+
+impl Drop for MockSystemTable {
+    fn drop(&mut self) {
+        self.unbind();
+        *state() = None;
+        // SAFETY: The state no longer points at the system table.
+        drop(unsafe { Box::from_raw(self.system_table) });
+        if !self.storage.is_null() {
+            drop(unsafe { Box::from_raw(self.storage) });
+        }
+    }
+}
+
+/// A reference to the storage of a [MockSystemTable], returned by [MockSystemTable::storage].
+pub struct StorageRef<'m> {
+    mock: &'m MockSystemTable,
+}
+
+impl Deref for StorageRef<'_> {
+    type Target = Storage;
+
+    fn deref(&self) -> &Storage {
+        // SAFETY: The storage is valid while the mock is, and unbound while any StorageRef is held.
+        unsafe { &*self.mock.storage }
+    }
+}
+
+impl Drop for StorageRef<'_> {
+    fn drop(&mut self) {
+        let refs = self.mock.storage_refs.get() - 1;
+        self.mock.storage_refs.set(refs);
+        if refs == 0 && self.mock.unbound.get() {
+            // SAFETY: No reference to the storage remains.
+            unsafe { boot_services::set_storage(self.mock.storage) };
+        }
+    }
+}
+
+/// A mutable reference to the storage of a [MockSystemTable], returned by [MockSystemTable::storage_mut].
+pub struct StorageMut<'m> {
+    storage: &'m mut Storage,
+    // The pointer the storage was bound through, if it was bound before the reference was created.
+    rebind: Option<*mut Storage>,
+}
+
+impl Deref for StorageMut<'_> {
+    type Target = Storage;
+
+    fn deref(&self) -> &Storage {
+        self.storage
+    }
+}
+
+impl DerefMut for StorageMut<'_> {
+    fn deref_mut(&mut self) -> &mut Storage {
+        self.storage
+    }
+}
+
+impl Drop for StorageMut<'_> {
+    fn drop(&mut self) {
+        if let Some(storage) = self.rebind {
+            // SAFETY: The reference is no longer used.
+            unsafe { boot_services::set_storage(storage) };
+            // Tables may have been installed directly in the storage.
+            sync_configuration_tables();
+        }
+    }
+}
+
+/// Points the system table of the mock, if one exists, at a copy of the configuration tables of the bound storage.
+///
+/// The system table never points into the storage itself, whose tables move when more are installed, so a pointer
+/// the code under test read earlier stays valid, if stale, until the mock is dropped.
+pub(crate) fn sync_configuration_tables() {
+    let _ = with_config_tables(|tables| {
+        if let Some(state) = state().as_mut() {
+            state.publish(tables.tables());
+        }
+        Ok(())
+    });
+}
+
+/// Generates boot services that record the call and otherwise delegate to the boot services bridge.
+macro_rules! mock_boot_services {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            extern "efiapi" fn $name($($arg: $ty),*) -> Status {
+                intercept(stringify!($name), || (boot_services::boot_services().$name)($($arg),*))
+            }
+        )*
+
+        fn mock_boot_services() -> efi::BootServices {
+            efi::BootServices {
+                $($name,)*
+                ..boot_services::boot_services()
+            }
+        }
+    };
+}
+
+mock_boot_services! {
+    allocate_pages(a: efi::AllocateType, b: efi::MemoryType, c: usize, d: *mut efi::PhysicalAddress);
+    free_pages(a: efi::PhysicalAddress, b: usize);
+    get_memory_map(a: *mut usize, b: *mut efi::MemoryDescriptor, c: *mut usize, d: *mut usize, e: *mut u32);
+    allocate_pool(a: efi::MemoryType, b: usize, c: *mut *mut c_void);
+    free_pool(a: *mut c_void);
+    create_event(a: u32, b: efi::Tpl, c: Option<efi::EventNotify>, d: *mut c_void, e: *mut efi::Event);
+    set_timer(a: efi::Event, b: efi::TimerDelay, c: u64);
+    wait_for_event(a: usize, b: *mut efi::Event, c: *mut usize);
+    signal_event(a: efi::Event);
+    close_event(a: efi::Event);
+    check_event(a: efi::Event);
+    install_protocol_interface(a: *mut Handle, b: *mut Guid, c: efi::InterfaceType, d: *mut c_void);
+    reinstall_protocol_interface(a: Handle, b: *mut Guid, c: *mut c_void, d: *mut c_void);
+    uninstall_protocol_interface(a: Handle, b: *mut Guid, c: *mut c_void);
+    install_configuration_table(a: *mut Guid, b: *mut c_void);
+    handle_protocol(a: Handle, b: *mut Guid, c: *mut *mut c_void);
+    register_protocol_notify(a: *mut Guid, b: efi::Event, c: *mut *mut c_void);
+    locate_handle(a: efi::LocateSearchType, b: *mut Guid, c: *mut c_void, d: *mut usize, e: *mut Handle);
+    locate_device_path(a: *mut Guid, b: *mut *mut efi::protocols::device_path::Protocol, c: *mut Handle);
+    load_image(
+        a: efi::Boolean,
+        b: Handle,
+        c: *mut efi::protocols::device_path::Protocol,
+        d: *mut c_void,
+        e: usize,
+        f: *mut Handle
+    );
+    start_image(a: Handle, b: *mut usize, c: *mut *mut efi::Char16);
+    exit(a: Handle, b: Status, c: usize, d: *mut efi::Char16);
+    unload_image(a: Handle);
+    exit_boot_services(a: Handle, b: usize);
+    get_next_monotonic_count(a: *mut u64);
+    stall(a: usize);
+    set_watchdog_timer(a: usize, b: u64, c: usize, d: *mut efi::Char16);
+    connect_controller(a: Handle, b: *mut Handle, c: *mut efi::protocols::device_path::Protocol, d: efi::Boolean);
+    disconnect_controller(a: Handle, b: Handle, c: Handle);
+    open_protocol(a: Handle, b: *mut Guid, c: *mut *mut c_void, d: Handle, e: Handle, f: u32);
+    close_protocol(a: Handle, b: *mut Guid, c: Handle, d: Handle);
+    open_protocol_information(a: Handle, b: *mut Guid, c: *mut *mut efi::OpenProtocolInformationEntry, d: *mut usize);
+    protocols_per_handle(a: Handle, b: *mut *mut *mut Guid, c: *mut usize);
+    locate_handle_buffer(a: efi::LocateSearchType, b: *mut Guid, c: *mut c_void, d: *mut usize, e: *mut *mut Handle);
+    locate_protocol(a: *mut Guid, b: *mut c_void, c: *mut *mut c_void);
+    install_multiple_protocol_interfaces(a: *mut Handle, b: *mut c_void, c: *mut c_void);
+    uninstall_multiple_protocol_interfaces(a: Handle, b: *mut c_void, c: *mut c_void);
+    calculate_crc32(a: *mut c_void, b: usize, c: *mut u32);
+    create_event_ex(a: u32, b: efi::Tpl, c: Option<efi::EventNotify>, d: *const c_void, e: *const Guid, f: *mut efi::Event);
+}
+
+/// Generates runtime services that record the call and return `UNSUPPORTED` unless scripted. The variable services
+/// delegate to the runtime services bridge instead.
+macro_rules! mock_runtime_services {
+    (
+        delegated { $($delegated:ident($($arg:ident: $arg_ty:ty),*);)* }
+        $($name:ident($($ty:ty),*);)*
+    ) => {
+        $(
+            extern "efiapi" fn $delegated($($arg: $arg_ty),*) -> Status {
+                intercept(stringify!($delegated), || {
+                    (runtime_services::runtime_services().$delegated)($($arg),*)
+                })
+            }
+        )*
+
+        $(
+            extern "efiapi" fn $name($(_: $ty),*) -> Status {
+                intercept(stringify!($name), || Status::UNSUPPORTED)
+            }
+        )*
+
+        fn mock_runtime_services() -> efi::RuntimeServices {
+            efi::RuntimeServices {
+                hdr: efi::TableHeader {
+                    signature: efi::RUNTIME_SERVICES_SIGNATURE,
+                    revision: efi::RUNTIME_SERVICES_REVISION,
+                    header_size: core::mem::size_of::<efi::RuntimeServices>() as u32,
+                    crc32: 0,
+                    reserved: 0,
+                },
+                $($delegated,)*
+                $($name,)*
+                reset_system,
+            }
+        }
+    };
+}
+
+mock_runtime_services! {
+    delegated {
+        get_variable(a: *mut efi::Char16, b: *mut Guid, c: *mut u32, d: *mut usize, e: *mut c_void);
+        get_next_variable_name(a: *mut usize, b: *mut efi::Char16, c: *mut Guid);
+        set_variable(a: *mut efi::Char16, b: *mut Guid, c: u32, d: usize, e: *mut c_void);
+    }
+    get_time(*mut efi::Time, *mut efi::TimeCapabilities);
+    set_time(*mut efi::Time);
+    get_wakeup_time(*mut efi::Boolean, *mut efi::Boolean, *mut efi::Time);
+    set_wakeup_time(efi::Boolean, *mut efi::Time);
+    set_virtual_address_map(usize, usize, u32, *mut efi::MemoryDescriptor);
+    convert_pointer(usize, *mut *mut c_void);
+    get_next_high_mono_count(*mut u32);
+    update_capsule(*mut *mut efi::CapsuleHeader, usize, efi::PhysicalAddress);
+    query_capsule_capabilities(*mut *mut efi::CapsuleHeader, usize, *mut u64, *mut efi::ResetType);
+    query_variable_info(u32, *mut u64, *mut u64, *mut u64);
+}
+
+extern "efiapi" fn reset_system(_: efi::ResetType, status: Status, _: usize, _: *mut c_void) {
+    intercept("reset_system", || status);
+}
+
+extern "efiapi" fn con_out_output_string(
+    _: *mut simple_text_output::Protocol,
+    string: *mut efi::Char16,
+) -> Status {
+    intercept("output_string", || {
+        if string.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        // SAFETY: The caller must provide a null terminated UCS-2 string.
+        let text = unsafe {
+            let len = (0..).take_while(|&i| *string.add(i) != 0).count();
+            core::slice::from_raw_parts(string, len)
+        };
+        if let Some(state) = state().as_mut() {
+            state
+                .con_out
+                .extend(char::decode_utf16(text.iter().copied()).map(|c| c.unwrap_or('\u{FFFD}')));
+        }
+        Status::SUCCESS
+    })
+}
+
+extern "efiapi" fn con_out_query_mode(
+    _: *mut simple_text_output::Protocol,
+    mode: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> Status {
+    intercept("query_mode", || {
+        if columns.is_null() || rows.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        if mode != 0 {
+            return Status::UNSUPPORTED;
+        }
+        unsafe {
+            *columns = 80;
+            *rows = 25;
+        }
+        Status::SUCCESS
+    })
+}
+
+/// Generates ConOut services that record the call and otherwise succeed.
+macro_rules! mock_con_out {
+    ($($name:ident: $service:literal($($ty:ty),*);)*) => {
+        $(
+            extern "efiapi" fn $name(_: *mut simple_text_output::Protocol, $(_: $ty),*) -> Status {
+                intercept($service, || Status::SUCCESS)
+            }
+        )*
+    };
+}
+
+mock_con_out! {
+    con_out_reset: "reset"(efi::Boolean);
+    con_out_test_string: "test_string"(*mut efi::Char16);
+    con_out_set_mode: "set_mode"(usize);
+    con_out_set_attribute: "set_attribute"(usize);
+    con_out_clear_screen: "clear_screen"();
+    con_out_set_cursor_position: "set_cursor_position"(usize, usize);
+    con_out_enable_cursor: "enable_cursor"(efi::Boolean);
+}
@@ -0,0 +1,341 @@
+//! Mock protocols whose functions are backed by Rust closures, for testing code that consumes a protocol.
+//!
+//! [mock_protocol!](crate::mock_protocol) generates a mock for a protocol struct. The mock embeds the protocol, with
+//! each function pointing at a generated extern "efiapi" trampoline, so once installed with its `install` method, it is
+//! consumed like the real protocol, e.g. through a `Protocol<rng::Protocol>` param. Every call is
+//! recorded, and returns the next status scripted for the function if there is one, or else the result of the closure
+//! handling the function, or `UNSUPPORTED` if there is none.
+//!
+//! A consumer may only hold a reference to the embedded protocol, so the trampolines cannot use the `this` pointer to
+//! find the rest of the mock. Instead, each mock occupies one of [MOCK_SLOTS] slots of its type on the thread that
+//! created it, and the trampolines generated for a slot look the mock up by its index. Mocks must therefore be called
+//! on the thread that created them. A closure that panics aborts the process, as it is called from an extern "efiapi"
+//! function.
+//!
+//! [MockRng] and [MockUdp4] are provided.
+extern crate alloc;
+extern crate std;
+
+use alloc::{boxed::Box, collections::VecDeque, rc::Rc, vec::Vec};
+use core::{any::Any, cell::RefCell};
+use std::{collections::HashMap, thread::LocalKey};
+
+use r_efi::{
+    efi::{self, Status},
+    protocols::{rng, udp4},
+};
+
+use super::Call;
+
+/// The number of mocks of a single protocol that can exist at the same time on one thread.
+pub const MOCK_SLOTS: usize = 8;
+
+/// The slots of a mock protocol on one thread.
+#[doc(hidden)]
+pub type Slots = RefCell<[Option<Rc<MockState>>; MOCK_SLOTS]>;
+
+/// Items used by [mock_protocol!](crate::mock_protocol), which expands in other crates.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::{boxed::Box, rc::Rc};
+    pub use r_efi::efi::{Guid, Handle, Status};
+    pub use sdk::{component::Storage, protocol::Protocol};
+}
+
+/// The calls, scripted statuses and handlers of a mock, shared by the mock and its [MockHandle]s.
+#[doc(hidden)]
+#[derive(Default)]
+pub struct MockState {
+    calls: RefCell<Vec<Call>>,
+    scripted: RefCell<HashMap<&'static str, VecDeque<Status>>>,
+    // Each handler is a `Box<dyn FnMut(..) -> Status>` for the arguments of its function.
+    handlers: RefCell<HashMap<&'static str, Box<dyn Any>>>,
+}
+
+impl MockState {
+    /// Handles calls to `function` with `handler`.
+    pub fn set_handler<F: ?Sized + 'static>(&self, function: &'static str, handler: Box<F>) {
+        self.handlers
+            .borrow_mut()
+            .insert(function, Box::new(handler));
+    }
+
+    /// Records a call to `function`, returning the scripted status if one exists, or the result of calling its handler
+    /// with `call` otherwise.
+    pub fn call<F: ?Sized + 'static>(
+        &self,
+        function: &'static str,
+        call: impl FnOnce(&mut F) -> Status,
+    ) -> Status {
+        let scripted = self
+            .scripted
+            .borrow_mut()
+            .get_mut(function)
+            .and_then(VecDeque::pop_front);
+        let status = scripted.unwrap_or_else(|| {
+            // The handler is taken out while it runs, as it may call the mock again.
+            let Some(mut handler) = self.handlers.borrow_mut().remove(function) else {
+                return Status::UNSUPPORTED;
+            };
+            let status = handler
+                .downcast_mut::<Box<F>>()
+                .map_or(Status::UNSUPPORTED, |handler| call(handler));
+            // A handler set while this one ran replaces it.
+            self.handlers
+                .borrow_mut()
+                .entry(function)
+                .or_insert(handler);
+            status
+        });
+        self.calls.borrow_mut().push(Call {
+            service: function,
+            status,
+        });
+        status
+    }
+}
+
+/// Claims a free slot for a new mock named `mock`, returning the slot and the state of the mock.
+#[doc(hidden)]
+pub fn claim_slot(slots: &'static LocalKey<Slots>, mock: &str) -> (usize, Rc<MockState>) {
+    slots.with_borrow_mut(|slots| {
+        let slot = slots.iter().position(Option::is_none).unwrap_or_else(|| {
+            panic!(
+                "At most {} {} mocks can exist at the same time on a thread.",
+                MOCK_SLOTS, mock
+            )
+        });
+        let state = Rc::new(MockState::default());
+        slots[slot] = Some(state.clone());
+        (slot, state)
+    })
+}
+
+/// Frees the slot of a mock that is dropped.
+#[doc(hidden)]
+pub fn release_slot(slots: &'static LocalKey<Slots>, slot: usize) {
+    // The slots may already be destroyed if the mock is dropped while its thread exits.
+    let _ = slots.try_with(|slots| slots.borrow_mut()[slot] = None);
+}
+
+/// Returns the state of the mock in `slot`.
+#[doc(hidden)]
+pub fn slot(slots: &'static LocalKey<Slots>, slot: usize) -> Rc<MockState> {
+    slots
+        .with_borrow(|slots| slots[slot].clone())
+        .expect("Mock protocols are called on the thread that created them")
+}
+
+/// A handle to the calls and scripted statuses of a mock, which remains usable once the mock is installed.
+#[derive(Clone)]
+pub struct MockHandle(Rc<MockState>);
+
+impl MockHandle {
+    #[doc(hidden)]
+    pub fn new(state: Rc<MockState>) -> Self {
+        Self(state)
+    }
+
+    /// Returns all recorded calls, in the order they were made.
+    pub fn calls(&self) -> Vec<Call> {
+        self.0.calls.borrow().clone()
+    }
+
+    /// Returns the number of recorded calls to `function`.
+    pub fn call_count(&self, function: &str) -> usize {
+        self.0
+            .calls
+            .borrow()
+            .iter()
+            .filter(|c| c.service == function)
+            .count()
+    }
+
+    /// Clears all recorded calls.
+    pub fn clear_calls(&self) {
+        self.0.calls.borrow_mut().clear();
+    }
+
+    /// Scripts the status returned by the next call to `function`. Its handler is not called for that call.
+    ///
+    /// Scripting the same function multiple times queues the statuses in order.
+    pub fn script(&self, function: &'static str, status: Status) {
+        self.0
+            .scripted
+            .borrow_mut()
+            .entry(function)
+            .or_default()
+            .push_back(status);
+    }
+}
+
+/// Generates a mock of a protocol struct, backed by closures. See the [module](crate::mock::protocols) documentation.
+///
+/// Each function of the protocol is listed with its arguments after `this`, and the name of the method that sets its
+/// handler:
+///
+/// ```ignore
+/// mock_protocol! {
+///     /// A mock of the RNG protocol.
+///     pub MockRng(rng::Protocol, &rng::PROTOCOL_GUID) {
+///         get_info(information_size: *mut usize, information: *mut rng::Algorithm) => on_get_info;
+///         get_rng(algorithm: *mut rng::Algorithm, value_length: usize, value: *mut u8) => on_get_rng;
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! mock_protocol {
+    (
+        $(#[$meta:meta])*
+        $vis:vis $mock:ident($protocol:path, $guid:expr) {
+            $($function:ident($($arg:ident: $ty:ty),*) => $setter:ident;)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        $vis struct $mock {
+            // The protocol must come first, as the installed interface points at the mock.
+            protocol: $protocol,
+            slot: usize,
+            state: $crate::mock::protocols::__private::Rc<$crate::mock::protocols::MockState>,
+        }
+
+        impl $mock {
+            fn slots() -> &'static ::std::thread::LocalKey<$crate::mock::protocols::Slots> {
+                ::std::thread_local! {
+                    static SLOTS: $crate::mock::protocols::Slots = ::core::default::Default::default();
+                }
+                &SLOTS
+            }
+
+            /// Creates a mock whose functions return `UNSUPPORTED` until they are handled or scripted.
+            ///
+            /// ## Panics
+            ///
+            /// Panics if [MOCK_SLOTS]($crate::mock::protocols::MOCK_SLOTS) mocks of the protocol already exist on this
+            /// thread.
+            pub fn new() -> Self {
+                // A path cannot start a struct expression, but an alias can.
+                type Protocol = $protocol;
+                let (slot, state) = $crate::mock::protocols::claim_slot(Self::slots(), stringify!($mock));
+                Self {
+                    protocol: Protocol {
+                        $($function: $crate::__mock_protocol_slot!(slot, $mock, $function),)*
+                    },
+                    slot,
+                    state,
+                }
+            }
+
+            /// Installs the mock on a new handle, to be retrieved as the protocol it mocks. Returns the handle.
+            pub fn install(
+                self,
+                storage: &mut $crate::mock::protocols::__private::Storage,
+            ) -> $crate::mock::protocols::__private::Handle {
+                // SAFETY: The mock is repr(C), and starts with the protocol.
+                unsafe { storage.add_protocol_as::<$protocol, Self>(self) }
+            }
+
+            /// Returns a handle to the calls and scripted statuses of the mock, which remains usable once the mock is
+            /// installed.
+            pub fn handle(&self) -> $crate::mock::protocols::MockHandle {
+                $crate::mock::protocols::MockHandle::new(self.state.clone())
+            }
+
+            $(
+                #[doc = concat!("Handles calls to `", stringify!($function), "` with `handler`, unless a status is scripted.")]
+                pub fn $setter(
+                    &mut self,
+                    handler: impl FnMut($($ty),*) -> $crate::mock::protocols::__private::Status + 'static,
+                ) {
+                    let handler: $crate::mock::protocols::__private::Box<
+                        dyn FnMut($($ty),*) -> $crate::mock::protocols::__private::Status,
+                    > = $crate::mock::protocols::__private::Box::new(handler);
+                    self.state.set_handler(stringify!($function), handler);
+                }
+            )*
+
+            $(
+                extern "efiapi" fn $function<const SLOT: usize>(
+                    _this: *mut $protocol,
+                    $($arg: $ty),*
+                ) -> $crate::mock::protocols::__private::Status {
+                    $crate::mock::protocols::slot(Self::slots(), SLOT)
+                        .call::<dyn FnMut($($ty),*) -> $crate::mock::protocols::__private::Status>(
+                            stringify!($function),
+                            |handler| handler($($arg),*),
+                        )
+                }
+            )*
+        }
+
+        impl ::core::default::Default for $mock {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl ::core::ops::Drop for $mock {
+            fn drop(&mut self) {
+                $crate::mock::protocols::release_slot(Self::slots(), self.slot);
+            }
+        }
+
+        impl $crate::mock::protocols::__private::Protocol for $mock {
+            fn guid() -> &'static $crate::mock::protocols::__private::Guid {
+                $guid
+            }
+        }
+    };
+}
+
+/// Selects the trampoline of `function` generated for `slot`. There must be one arm per [MOCK_SLOTS].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __mock_protocol_slot {
+    ($slot:expr, $mock:ident, $function:ident) => {
+        match $slot {
+            0 => $mock::$function::<0>,
+            1 => $mock::$function::<1>,
+            2 => $mock::$function::<2>,
+            3 => $mock::$function::<3>,
+            4 => $mock::$function::<4>,
+            5 => $mock::$function::<5>,
+            6 => $mock::$function::<6>,
+            7 => $mock::$function::<7>,
+            _ => unreachable!("Mock slots are less than MOCK_SLOTS"),
+        }
+    };
+}
+
+crate::mock_protocol! {
+    /// A mock of the RNG protocol.
+    pub MockRng(rng::Protocol, &rng::PROTOCOL_GUID) {
+        get_info(information_size: *mut usize, information: *mut rng::Algorithm) => on_get_info;
+        get_rng(algorithm: *mut rng::Algorithm, value_length: usize, value: *mut u8) => on_get_rng;
+    }
+}
+
+crate::mock_protocol! {
+    /// A mock of the UDP4 protocol.
+    pub MockUdp4(udp4::Protocol, &udp4::PROTOCOL_GUID) {
+        get_mode_data(
+            udp4_config_data: *mut udp4::ConfigData,
+            ip4_mode_data: *mut efi::protocols::ip4::ModeData,
+            mnp_config_data: *mut efi::protocols::managed_network::ConfigData,
+            snp_mode_data: *mut efi::protocols::simple_network::Mode
+        ) => on_get_mode_data;
+        configure(udp_config_data: *mut udp4::ConfigData) => on_configure;
+        groups(join_flag: efi::Boolean, multicast_address: *mut efi::Ipv4Address) => on_groups;
+        routes(
+            delete_route: efi::Boolean,
+            subnet_address: *mut efi::Ipv4Address,
+            subnet_mask: *mut efi::Ipv4Address,
+            gateway_address: *mut efi::Ipv4Address
+        ) => on_routes;
+        transmit(token: *mut udp4::CompletionToken) => on_transmit;
+        receive(token: *mut udp4::CompletionToken) => on_receive;
+        cancel(token: *mut udp4::CompletionToken) => on_cancel;
+        poll() => on_poll;
+    }
+}
@@ -1,14 +1,164 @@
+use alloc::{borrow::Cow, format, string::String, vec::Vec};
+use core::fmt;
+
 use r_efi::efi::Guid;
 use sdk::{
     component::{
-        params::{Config, ConfigMut, Protocol},
-        Storage,
+        params::{
+            Config, ConfigMut, ConfigTable, GuidHob, Hob, MemoryServices, Pcd, Protocol,
+            StorageView, Variable, VariableMut, ViewAccess, ViewDeclaration,
+        },
+        Storage, StorageError,
     },
+    config_table::ConfigTableType,
+    hob::{GuidHobData, HobType},
+    pcd::{PcdDatum, PcdKind, PcdToken},
     protocol,
+    variable::VariableToken,
 };
 
 use crate::{unsafe_storage::UnsafeStorageCell, MetaData};
 
+/// A kind of storage resource whose access is registered by component parameters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Resource {
+    Config,
+    Pcd,
+    Variable,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Config => write!(f, "config"),
+            Resource::Pcd => write!(f, "PCD"),
+            Resource::Variable => write!(f, "variable"),
+        }
+    }
+}
+
+/// A component that cannot be added to the manager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistrationError {
+    /// A parameter accesses a resource already accessed by a previous parameter of the same component, and at least
+    /// one of the two accesses is mutable.
+    Conflict {
+        /// The name of the component.
+        component: Cow<'static, str>,
+        /// The type of the conflicting parameter.
+        param: String,
+        /// The type of the previous parameter it conflicts with.
+        previous: String,
+        /// The kind of resource both parameters access.
+        resource: Resource,
+        /// The id of the resource in storage.
+        id: usize,
+    },
+    /// A component has a `&mut Storage` parameter alongside other parameters, which would alias the storage.
+    StorageConflict {
+        /// The name of the component.
+        component: Cow<'static, str>,
+        /// The type of the parameter registered last.
+        param: String,
+    },
+    /// A config parameter cannot be registered, as configs are frozen. `ConfigMut` parameters are always rejected, and
+    /// `Config` parameters are rejected if the config has no value yet, as its default can no longer be added.
+    ConfigsFrozen {
+        /// The name of the component.
+        component: Cow<'static, str>,
+        /// The type of the parameter.
+        param: String,
+    },
+    /// A `Pcd` parameter declares a datum type that does not match the value of the PCD in storage.
+    PcdTypeMismatch {
+        /// The name of the component.
+        component: Cow<'static, str>,
+        /// The type of the parameter.
+        param: String,
+    },
+}
+
+impl RegistrationError {
+    /// Creates a [Conflict](Self::Conflict) between two parameters accessing the resource `id` of type `ty`.
+    fn conflict(
+        meta: &MetaData,
+        resource: Resource,
+        id: usize,
+        param: &str,
+        previous: &str,
+        ty: &str,
+    ) -> Self {
+        // A parameter requesting the whole storage conflicts with every other access.
+        let previous = match meta.access.is_exclusive() {
+            true => String::from("Storage"),
+            false => format!("{}<{}>", previous, ty),
+        };
+        Self::Conflict {
+            component: meta.name.clone(),
+            param: format!("{}<{}>", param, ty),
+            previous,
+            resource,
+            id,
+        }
+    }
+
+    /// Returns the name of the component that cannot be added.
+    pub fn component(&self) -> &str {
+        match self {
+            Self::Conflict { component, .. }
+            | Self::StorageConflict { component, .. }
+            | Self::ConfigsFrozen { component, .. }
+            | Self::PcdTypeMismatch { component, .. } => component,
+        }
+    }
+}
+
+impl fmt::Display for RegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Conflict {
+                component,
+                param,
+                previous,
+                resource,
+                id,
+            } => write!(
+                f,
+                "{} in component {} conflicts with a previous {} access to {} {}.",
+                param, component, previous, resource, id
+            ),
+            Self::StorageConflict { component, param } => write!(
+                f,
+                "{} in component {} conflicts with another parameter, as a &mut Storage parameter must be the only \
+                 parameter of a component.",
+                param, component
+            ),
+            Self::ConfigsFrozen { component, param } => write!(
+                f,
+                "{} in component {} cannot be registered because configs are frozen.",
+                param, component
+            ),
+            Self::PcdTypeMismatch { component, param } => write!(
+                f,
+                "{} in component {} does not match the datum type of the PCD in storage.",
+                param, component
+            ),
+        }
+    }
+}
+
+/// Registers a shared borrow of the storage that is not tracked per resource, e.g. of the protocol database.
+fn register_storage_read(meta: &mut MetaData, param: String) -> Result<(), RegistrationError> {
+    if meta.access.has_storage_write() {
+        return Err(RegistrationError::StorageConflict {
+            component: meta.name.clone(),
+            param,
+        });
+    }
+    meta.access.add_storage_read();
+    Ok(())
+}
+
 /// Allows automatic retrieval of an implementing type from storage for dependency injection.
 pub trait ComponentParam {
     /// Persistent state for the parameter.
@@ -21,16 +171,31 @@ pub trait ComponentParam {
     /// ## Safety
     ///
     /// - The parameter storage access must be properly registered with the caller.
+    /// - Parameters other than `&mut Storage` must only access the storage through the per-resource accessors of
+    ///   [UnsafeStorageCell], or a shared reference to it.
+    ///
+    /// Returns an error if the parameter cannot be retrieved despite being valid, e.g. because a resource it borrows is
+    /// already borrowed mutably.
     unsafe fn retrieve<'w, 'state>(
         _state: &'state mut Self::State,
         _storage: UnsafeStorageCell<'w>,
-    ) -> Self::Item<'w, 'state>;
+    ) -> Result<Self::Item<'w, 'state>, StorageError>;
 
     /// Validates that the parameter exists, and is in a state that can be retrieved from storage.
-    fn validate<'r>(_state: &Self::State, _storage: UnsafeStorageCell) -> bool;
+    ///
+    /// ## Safety
+    ///
+    /// - The storage must not be borrowed mutably.
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool;
 
     /// Initializes the parameter, if necessary.
-    fn initialize(storage: &mut Storage, meta: &mut MetaData) -> Self::State;
+    ///
+    /// Returns an error if the parameter cannot be registered, e.g. because its access conflicts with that of a
+    /// previous parameter of the same component.
+    fn initialize(
+        storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError>;
 }
 
 impl ComponentParam for &mut Storage {
@@ -40,16 +205,27 @@ impl ComponentParam for &mut Storage {
     unsafe fn retrieve<'w, 'state>(
         _state: &'state mut Self::State,
         storage: UnsafeStorageCell<'w>,
-    ) -> Self::Item<'w, 'state> {
-        storage.storage_mut()
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(storage.storage_mut())
     }
 
-    fn validate<'r>(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
         true
     }
 
-    fn initialize(_storage: &mut Storage, meta: &mut MetaData) {
-        meta.access.set_exclusive();
+    // The mutable reference covers the whole storage, so it cannot coexist with a borrow held by any other parameter.
+    fn initialize(
+        _storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        if !meta.access.is_empty() {
+            return Err(RegistrationError::StorageConflict {
+                component: meta.name.clone(),
+                param: String::from("&mut Storage"),
+            });
+        }
+        meta.access.add_storage_write();
+        Ok(())
     }
 }
 
@@ -60,16 +236,26 @@ impl ComponentParam for &Storage {
     unsafe fn retrieve<'w, 'state>(
         _state: &'state mut Self::State,
         storage: UnsafeStorageCell<'w>,
-    ) -> Self::Item<'w, 'state> {
-        storage.storage()
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(storage.storage())
     }
 
-    fn validate<'r>(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
         true
     }
 
-    fn initialize(_storage: &mut Storage, meta: &mut MetaData) {
-        meta.access.set_exclusive();
+    fn initialize(
+        _storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        if meta.access.has_storage_write() {
+            return Err(RegistrationError::StorageConflict {
+                component: meta.name.clone(),
+                param: String::from("&Storage"),
+            });
+        }
+        meta.access.add_whole_storage_read();
+        Ok(())
     }
 }
 
@@ -84,14 +270,13 @@ impl<'c, T: Default + 'static> ComponentParam for Config<'c, T> {
     unsafe fn retrieve<'w, 'state>(
         state: &'state mut Self::State,
         storage: UnsafeStorageCell<'w>,
-    ) -> Self::Item<'w, 'state> {
-        let id = *state;
-        Config::from(storage.storage().get_config_untyped(id))
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Config::try_from_untyped(storage.config(*state)?)
     }
 
     // Config will always exist, because a default value is registered during `initialize` if it does not already
     // exist.
-    fn validate<'r>(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
         true
     }
 
@@ -100,19 +285,31 @@ impl<'c, T: Default + 'static> ComponentParam for Config<'c, T> {
     // fails to run because it is waiting for some other ComponentParam to be available)), it can be done quickly.
     //
     // Since The config object can be mutable, we register the access type here and check for conflicts with other
-    fn initialize(storage: &mut Storage, meta: &mut MetaData) -> Self::State {
+    fn initialize(
+        storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
         let id = storage.register_config::<T>();
-        storage.try_add_config(id, T::default());
+        if storage.try_add_config(id, T::default()).is_err() {
+            return Err(RegistrationError::ConfigsFrozen {
+                component: meta.name.clone(),
+                param: format!("Config<{}>", core::any::type_name::<T>()),
+            });
+        }
 
-        assert!(
-            !meta.access.has_config_write(id),
-            "Config<{}> in system {} conflicts with a previous ConfigMut<{0}> access.",
-            core::any::type_name::<T>(),
-            meta.name,
-        );
+        if meta.access.has_config_write(id) {
+            return Err(RegistrationError::conflict(
+                meta,
+                Resource::Config,
+                id,
+                "Config",
+                "ConfigMut",
+                core::any::type_name::<T>(),
+            ));
+        }
 
         meta.access.add_config_read(id);
-        id
+        Ok(id)
     }
 }
 
@@ -125,78 +322,456 @@ impl<'c, T: Default + 'static> ComponentParam for ConfigMut<'c, T> {
     unsafe fn retrieve<'w, 'state>(
         state: &'state mut Self::State,
         storage: UnsafeStorageCell<'w>,
-    ) -> Self::Item<'w, 'state> {
-        let id = *state;
-        ConfigMut::from(storage.storage_mut().get_config_mut_untyped(id))
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        storage.config_mut(*state)
     }
 
-    // Config will always exist, as it is created with a default value when registering.
-    fn validate<'r>(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
-        true
+    // Config will always exist, as it is created with a default value when registering. It can only be retrieved
+    // mutably while configs are not frozen, however.
+    unsafe fn validate(_state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        !storage.configs_frozen()
     }
 
-    fn initialize(storage: &mut Storage, meta: &mut MetaData) -> Self::State {
+    fn initialize(
+        storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        if storage.configs_frozen() {
+            return Err(RegistrationError::ConfigsFrozen {
+                component: meta.name.clone(),
+                param: format!("ConfigMut<{}>", core::any::type_name::<T>()),
+            });
+        }
+
         let id = storage.register_config::<T>();
 
-        assert!(
-            !meta.access.has_config_write(id),
-            "ConfigMut<{}> in system {} conflicts with a previous ConfigMut<{0}> access.",
-            core::any::type_name::<T>(),
-            meta.name,
-        );
+        if meta.access.has_config_write(id) {
+            return Err(RegistrationError::conflict(
+                meta,
+                Resource::Config,
+                id,
+                "ConfigMut",
+                "ConfigMut",
+                core::any::type_name::<T>(),
+            ));
+        }
 
-        assert!(
-            !meta.access.has_config_read(id),
-            "ConfigMut<{}> in system {} conflicts with a previous Config<{0}> access.",
-            core::any::type_name::<T>(),
-            meta.name,
-        );
+        if meta.access.has_config_read(id) {
+            return Err(RegistrationError::conflict(
+                meta,
+                Resource::Config,
+                id,
+                "ConfigMut",
+                "Config",
+                core::any::type_name::<T>(),
+            ));
+        }
 
         meta.access.add_config_write(id);
-        id
+        Ok(id)
     }
 }
 
 impl<'p, P: protocol::Protocol + 'static> ComponentParam for Protocol<'p, P> {
-    type State = Guid;
+    type State = ();
     type Item<'w, 'state> = Protocol<'w, P>;
 
+    unsafe fn retrieve<'w, 'state>(
+        _state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(Protocol::from_ptr(storage.protocol::<P>()?))
+    }
+
+    // A protocol installed as a different type does not make the component wait; retrieving it fails instead.
+    unsafe fn validate(_state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        !matches!(
+            storage.protocol::<P>(),
+            Err(StorageError::ProtocolNotFound(_))
+        )
+    }
+
+    fn initialize(
+        _storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        register_storage_read(meta, format!("Protocol<{}>", core::any::type_name::<P>()))
+    }
+}
+
+// Like protocols, configuration tables are owned by their installer, so only a shared borrow of the storage is
+// registered.
+impl<'t, G: ConfigTableType + 'static> ComponentParam for ConfigTable<'t, G> {
+    type State = Guid;
+    type Item<'w, 'state> = ConfigTable<'w, G>;
+
     unsafe fn retrieve<'w, 'state>(
         state: &'state mut Self::State,
         storage: UnsafeStorageCell<'w>,
-    ) -> Self::Item<'w, 'state> {
-        Protocol::from(storage.storage().get_protocol_untyped(state))
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        let table = storage.configuration_table(state)?;
+        Ok(ConfigTable::from(&*table.cast::<G>()))
+    }
+
+    unsafe fn validate(state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        storage.configuration_table(state).is_ok()
     }
 
-    fn validate<'r>(state: &Self::State, storage: UnsafeStorageCell) -> bool {
-        storage.storage().contains_protocol(state)
+    fn initialize(
+        _storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        register_storage_read(
+            meta,
+            format!("ConfigTable<{}>", core::any::type_name::<G>()),
+        )?;
+        Ok(*G::guid())
     }
+}
 
-    fn initialize(_storage: &mut Storage, _meta: &mut MetaData) -> Self::State {
-        *P::guid()
+// HOBs are only added with exclusive access to the storage, so only a shared borrow of the storage is registered.
+impl<'h, T: HobType> ComponentParam for Hob<'h, T> {
+    type State = ();
+    type Item<'w, 'state> = Hob<'w, T>;
+
+    unsafe fn retrieve<'w, 'state>(
+        _state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        // The HOB list cannot change after it is loaded, so a validated HOB always exists.
+        Ok(Hob::new(storage.hobs()).expect("Validated HOB exists"))
+    }
+
+    unsafe fn validate(_state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        Hob::<T>::new(storage.hobs()).is_some()
+    }
+
+    fn initialize(
+        _storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        register_storage_read(meta, format!("Hob<{}>", core::any::type_name::<T>()))?;
+        Ok(())
+    }
+}
+
+impl<'h, G: GuidHobData> ComponentParam for GuidHob<'h, G> {
+    type State = ();
+    type Item<'w, 'state> = GuidHob<'w, G>;
+
+    unsafe fn retrieve<'w, 'state>(
+        _state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(GuidHob::new(storage.hobs()).expect("Validated HOB exists"))
+    }
+
+    unsafe fn validate(_state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        GuidHob::<G>::new(storage.hobs()).is_some()
+    }
+
+    fn initialize(
+        _storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        register_storage_read(meta, format!("GuidHob<{}>", core::any::type_name::<G>()))?;
+        Ok(())
+    }
+}
+
+// Variables always exist in storage once registered, even if they have not been set, so they are always valid.
+impl<'v, T: VariableToken> ComponentParam for Variable<'v, T> {
+    type State = usize;
+    type Item<'w, 'state> = Variable<'w, T>;
+
+    unsafe fn retrieve<'w, 'state>(
+        state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(Variable::from(storage.variable(*state)?))
+    }
+
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+        true
+    }
+
+    fn initialize(
+        storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        let id = storage.register_variable::<T>();
+
+        if meta.access.has_variable_write(id) {
+            return Err(RegistrationError::conflict(
+                meta,
+                Resource::Variable,
+                id,
+                "Variable",
+                "VariableMut",
+                core::any::type_name::<T>(),
+            ));
+        }
+
+        meta.access.add_variable_read(id);
+        Ok(id)
+    }
+}
+
+impl<'v, T: VariableToken> ComponentParam for VariableMut<'v, T> {
+    type State = usize;
+    type Item<'w, 'state> = VariableMut<'w, T>;
+
+    unsafe fn retrieve<'w, 'state>(
+        state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(VariableMut::from(storage.variable_mut(*state)?))
+    }
+
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+        true
+    }
+
+    fn initialize(
+        storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        let id = storage.register_variable::<T>();
+
+        if meta.access.has_variable_write(id) {
+            return Err(RegistrationError::conflict(
+                meta,
+                Resource::Variable,
+                id,
+                "VariableMut",
+                "VariableMut",
+                core::any::type_name::<T>(),
+            ));
+        }
+
+        if meta.access.has_variable_read(id) {
+            return Err(RegistrationError::conflict(
+                meta,
+                Resource::Variable,
+                id,
+                "VariableMut",
+                "Variable",
+                core::any::type_name::<T>(),
+            ));
+        }
+
+        meta.access.add_variable_write(id);
+        Ok(id)
+    }
+}
+
+// The memory map is borrowed for the duration of each allocation rather than for the lifetime of the param, so only a
+// shared borrow of the storage is registered. Components wait until the memory map has an arena to allocate from.
+impl<'m> ComponentParam for MemoryServices<'m> {
+    type State = ();
+    type Item<'w, 'state> = MemoryServices<'w>;
+
+    unsafe fn retrieve<'w, 'state>(
+        _state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(MemoryServices::new(storage.memory()))
+    }
+
+    unsafe fn validate(_state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        storage.memory().borrow().has_arena()
+    }
+
+    fn initialize(
+        _storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        register_storage_read(meta, String::from("MemoryServices"))?;
+        Ok(())
+    }
+}
+
+// A view borrows the storage shared, and only borrows the configs it declares while the component runs. Their access
+// is registered here like that of Config and ConfigMut, so a view never makes the component exclusive.
+impl<'s, D: ViewAccess> ComponentParam for StorageView<'s, D> {
+    type State = ();
+    type Item<'w, 'state> = StorageView<'w, D>;
+
+    unsafe fn retrieve<'w, 'state>(
+        _state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        Ok(storage.view())
+    }
+
+    // Declared resources are looked up while the component runs, so the view is always valid.
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+        true
+    }
+
+    fn initialize(
+        storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        register_storage_read(
+            meta,
+            format!("StorageView<{}>", core::any::type_name::<D>()),
+        )?;
+
+        let mut declarations = Vec::new();
+        D::declare(&mut declarations);
+        for declaration in declarations {
+            let ViewDeclaration::Config {
+                name,
+                writable,
+                register,
+            } = declaration
+            else {
+                continue;
+            };
+
+            if writable && storage.configs_frozen() {
+                return Err(RegistrationError::ConfigsFrozen {
+                    component: meta.name.clone(),
+                    param: format!("WriteConfig<{}>", name),
+                });
+            }
+
+            let param = match writable {
+                true => "WriteConfig",
+                false => "ReadConfig",
+            };
+            let Ok(id) = register(storage) else {
+                return Err(RegistrationError::ConfigsFrozen {
+                    component: meta.name.clone(),
+                    param: format!("{}<{}>", param, name),
+                });
+            };
+
+            if meta.access.has_config_write(id) {
+                return Err(RegistrationError::conflict(
+                    meta,
+                    Resource::Config,
+                    id,
+                    param,
+                    "ConfigMut",
+                    name,
+                ));
+            }
+
+            if writable {
+                if meta.access.has_config_read(id) {
+                    return Err(RegistrationError::conflict(
+                        meta,
+                        Resource::Config,
+                        id,
+                        param,
+                        "Config",
+                        name,
+                    ));
+                }
+                meta.access.add_config_write(id);
+            } else {
+                meta.access.add_config_read(id);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'p, T: PcdToken> ComponentParam for Pcd<'p, T> {
+    // `State` is used to store the global id of the PCD, just like Config.
+    type State = usize;
+    type Item<'w, 'state> = Pcd<'w, T>;
+
+    unsafe fn retrieve<'w, 'state>(
+        state: &'state mut Self::State,
+        storage: UnsafeStorageCell<'w>,
+    ) -> Result<Self::Item<'w, 'state>, StorageError> {
+        let id = *state;
+        let pcd = match T::kind() {
+            PcdKind::Dynamic => Pcd::from(storage.pcd_mut(id)?),
+            _ => Pcd::from(storage.pcd(id)?),
+        };
+        // The datum type is checked on registration, but the platform may have replaced the PCD since.
+        match pcd.try_get() {
+            Ok(_) => Ok(pcd),
+            Err(_) => Err(StorageError::PcdTypeMismatch(id)),
+        }
+    }
+
+    // The PCD will always exist, because the default value is registered during `initialize` if it does not already
+    // exist.
+    unsafe fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+        true
+    }
+
+    // Only dynamic PCDs can be changed by components, so they are the only PCDs registered with write access.
+    fn initialize(
+        storage: &mut Storage,
+        meta: &mut MetaData,
+    ) -> Result<Self::State, RegistrationError> {
+        let id = storage.register_pcd::<T>();
+
+        let entry = storage.get_pcd_untyped(id);
+        if T::Value::from_value(entry.value()).is_none() {
+            return Err(RegistrationError::PcdTypeMismatch {
+                component: meta.name.clone(),
+                param: format!("Pcd<{}>", core::any::type_name::<T>()),
+            });
+        }
+        drop(entry);
+
+        if meta.access.has_pcd_write(id) {
+            return Err(RegistrationError::conflict(
+                meta,
+                Resource::Pcd,
+                id,
+                "Pcd",
+                "dynamic Pcd",
+                core::any::type_name::<T>(),
+            ));
+        }
+
+        if T::kind() == PcdKind::Dynamic {
+            if meta.access.has_pcd_read(id) {
+                return Err(RegistrationError::conflict(
+                    meta,
+                    Resource::Pcd,
+                    id,
+                    "Pcd",
+                    "Pcd",
+                    core::any::type_name::<T>(),
+                ));
+            }
+            meta.access.add_pcd_write(id);
+        } else {
+            meta.access.add_pcd_read(id);
+        }
+        Ok(id)
     }
 }
 
 macro_rules! impl_component_param_tuple {
     ($($param: ident), *) => {
-        #[allow(non_snake_case)]
+        #[allow(non_snake_case, clippy::unused_unit)]
         impl<$($param: ComponentParam),*> ComponentParam for ($($param,)*) {
             type State = ($($param::State,)*);
             type Item<'w, 'state> = ($($param::Item::<'w, 'state>,)*);
 
-            unsafe fn retrieve<'w, 'state>(state: &'state mut Self::State, _storage: UnsafeStorageCell<'w>) -> Self::Item<'w, 'state> {
+            unsafe fn retrieve<'w, 'state>(state: &'state mut Self::State, _storage: UnsafeStorageCell<'w>) -> Result<Self::Item<'w, 'state>, StorageError> {
                 let ($($param,)*) = state;
-                ($($param::retrieve($param, _storage),)*)
+                Ok(($($param::retrieve($param, _storage)?,)*))
             }
 
             #[allow(unused_mut)]
-            fn validate<'r>(state: &Self::State, _storage: UnsafeStorageCell) -> bool {
+            unsafe fn validate(state: &Self::State, _storage: UnsafeStorageCell) -> bool {
                 let ($($param,)*) = state;
                 $($param::validate($param, _storage)&&)* true
             }
 
-            fn initialize(_storage: &mut Storage, _meta: &mut MetaData) -> Self::State {
-                (($($param::initialize(_storage, _meta),)*))
+            fn initialize(_storage: &mut Storage, _meta: &mut MetaData) -> Result<Self::State, RegistrationError> {
+                Ok(($($param::initialize(_storage, _meta)?,)*))
             }
         }
     }
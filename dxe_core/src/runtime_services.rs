@@ -0,0 +1,195 @@
+//! A bridge that exposes the variable store of a [Storage] through an EFI Runtime Services table.
+//!
+//! Like the [boot_services](crate::boot_services) bridge, the functions in this module operate on the [Storage] bound
+//! with [set_storage](crate::boot_services::set_storage) on the calling thread, so C based drivers see the same
+//! variables as Rust components that use the `Variable<T>` param.
+//!
+//! Only the variable services are implemented. All other services return `UNSUPPORTED`.
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{ffi::c_void, ptr};
+
+use r_efi::efi::{self, Guid, Status};
+
+use crate::boot_services::{into_status, with_variables};
+
+/// Returns a Runtime Services table whose variable services operate on the bound storage.
+pub fn runtime_services() -> efi::RuntimeServices {
+    efi::RuntimeServices {
+        hdr: efi::TableHeader {
+            signature: efi::RUNTIME_SERVICES_SIGNATURE,
+            revision: efi::RUNTIME_SERVICES_REVISION,
+            header_size: core::mem::size_of::<efi::RuntimeServices>() as u32,
+            crc32: 0,
+            reserved: 0,
+        },
+        get_time,
+        set_time,
+        get_wakeup_time,
+        set_wakeup_time,
+        set_virtual_address_map,
+        convert_pointer,
+        get_variable,
+        get_next_variable_name,
+        set_variable,
+        get_next_high_mono_count,
+        reset_system,
+        update_capsule,
+        query_capsule_capabilities,
+        query_variable_info,
+    }
+}
+
+/// Reads a null terminated UCS-2 string of at most `max_len` characters, including the terminator.
+///
+/// ## Safety
+///
+/// `name` must be valid for reads up to its terminator, or `max_len` characters.
+unsafe fn read_name(name: *const efi::Char16, max_len: usize) -> Result<String, Status> {
+    let mut units = Vec::new();
+    loop {
+        if units.len() == max_len {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        match *name.add(units.len()) {
+            0 => break,
+            unit => units.push(unit),
+        }
+    }
+    char::decode_utf16(units)
+        .collect::<Result<_, _>>()
+        .map_err(|_| Status::INVALID_PARAMETER)
+}
+
+pub(crate) extern "efiapi" fn get_variable(
+    name: *mut efi::Char16,
+    guid: *mut Guid,
+    attributes: *mut u32,
+    data_size: *mut usize,
+    data: *mut c_void,
+) -> Status {
+    if name.is_null() || guid.is_null() || data_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The pointers were checked for null and must be valid per the UEFI specification.
+    let result = unsafe { read_name(name, usize::MAX) }
+        .and_then(|name| with_variables(|variables| variables.get(unsafe { &*guid }, &name)));
+    let (variable_attributes, value) = match result {
+        Ok(variable) => variable,
+        Err(status) => return status,
+    };
+
+    unsafe {
+        if !attributes.is_null() {
+            *attributes = variable_attributes;
+        }
+        if *data_size < value.len() {
+            *data_size = value.len();
+            return Status::BUFFER_TOO_SMALL;
+        }
+        if data.is_null() && !value.is_empty() {
+            return Status::INVALID_PARAMETER;
+        }
+        ptr::copy_nonoverlapping(value.as_ptr(), data.cast(), value.len());
+        *data_size = value.len();
+    }
+    Status::SUCCESS
+}
+
+pub(crate) extern "efiapi" fn get_next_variable_name(
+    name_size: *mut usize,
+    name: *mut efi::Char16,
+    guid: *mut Guid,
+) -> Status {
+    if name_size.is_null() || name.is_null() || guid.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The pointers were checked for null and must be valid per the UEFI specification. The previous name
+    // must be terminated within the buffer.
+    let previous = match unsafe { read_name(name, *name_size / 2) } {
+        Ok(previous) => previous,
+        Err(status) => return status,
+    };
+    let previous_guid = unsafe { *guid };
+    let previous = (!previous.is_empty()).then_some((&previous_guid, previous.as_str()));
+
+    let (next_guid, next) = match with_variables(|variables| variables.next_name(previous)) {
+        Ok(next) => next,
+        Err(status) => return status,
+    };
+    let next: Vec<u16> = next.encode_utf16().chain([0]).collect();
+    let size = next.len() * 2;
+    unsafe {
+        if *name_size < size {
+            *name_size = size;
+            return Status::BUFFER_TOO_SMALL;
+        }
+        ptr::copy_nonoverlapping(next.as_ptr(), name, next.len());
+        *name_size = size;
+        *guid = next_guid;
+    }
+    Status::SUCCESS
+}
+
+pub(crate) extern "efiapi" fn set_variable(
+    name: *mut efi::Char16,
+    guid: *mut Guid,
+    attributes: u32,
+    data_size: usize,
+    data: *mut c_void,
+) -> Status {
+    if name.is_null() || guid.is_null() || (data.is_null() && data_size != 0) {
+        return Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The pointers were checked for null and must be valid per the UEFI specification.
+    let (name, guid, value) = unsafe {
+        let value = match data_size {
+            0 => &[][..],
+            _ => core::slice::from_raw_parts(data.cast::<u8>(), data_size),
+        };
+        (read_name(name, usize::MAX), &*guid, value)
+    };
+    into_status(
+        name.and_then(|name| {
+            with_variables(|variables| variables.set(guid, &name, attributes, value))
+        }),
+    )
+}
+
+extern "efiapi" fn reset_system(
+    reset_type: efi::ResetType,
+    status: Status,
+    _: usize,
+    _: *mut c_void,
+) {
+    log::error!(
+        "ResetSystem({:?}, {:?}) is not supported.",
+        reset_type,
+        status
+    );
+}
+
+/// Generates runtime services that are not supported by the bridge.
+macro_rules! unsupported {
+    ($($name:ident($($arg:ty),*);)*) => {
+        $(
+            extern "efiapi" fn $name($(_: $arg),*) -> Status {
+                Status::UNSUPPORTED
+            }
+        )*
+    };
+}
+
+unsupported! {
+    get_time(*mut efi::Time, *mut efi::TimeCapabilities);
+    set_time(*mut efi::Time);
+    get_wakeup_time(*mut efi::Boolean, *mut efi::Boolean, *mut efi::Time);
+    set_wakeup_time(efi::Boolean, *mut efi::Time);
+    set_virtual_address_map(usize, usize, u32, *mut efi::MemoryDescriptor);
+    convert_pointer(usize, *mut *mut c_void);
+    get_next_high_mono_count(*mut u32);
+    update_capsule(*mut *mut efi::CapsuleHeader, usize, efi::PhysicalAddress);
+    query_capsule_capabilities(*mut *mut efi::CapsuleHeader, usize, *mut u64, *mut efi::ResetType);
+    query_variable_info(u32, *mut u64, *mut u64, *mut u64);
+}
@@ -0,0 +1,240 @@
+//! Helpers for testing a single component in isolation, without copying the setup of a platform.
+//!
+//! A [ComponentTest] owns a [ComponentManager] with a fresh [Storage]. Seed it with the configs, PCDs, protocols and
+//! memory the component needs, [run](ComponentTest::run) the component, and check the returned [ComponentRun] for
+//! whether it ran and which protocols it installed. Config values, and anything else in storage, can be inspected
+//! afterwards through the [ComponentTest].
+extern crate alloc;
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+
+use r_efi::efi::{Guid, Handle};
+use sdk::{
+    component::{
+        params::{Config, Protocol},
+        Storage,
+    },
+    pcd::PcdToken,
+    protocol,
+};
+
+use crate::{Component, ComponentError, ComponentManager, IntoComponent, RegistrationError};
+
+/// A protocol installed while a component ran.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InstalledProtocol {
+    /// The handle the protocol is installed on.
+    pub handle: Handle,
+    /// The guid the protocol is installed under.
+    pub guid: Guid,
+    /// The type name of the protocol, or `<foreign>` if it was installed through a raw pointer.
+    pub name: &'static str,
+}
+
+/// What happened when a component was run with [ComponentTest::run].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    /// The component ran.
+    Ran,
+    /// The component did not run, as its parameters or depex are not available.
+    Waiting,
+    /// The component failed while running.
+    Failed(ComponentError),
+    /// The component could not be registered, so it was never run.
+    NotRegistered(RegistrationError),
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ran => write!(f, "ran"),
+            Self::Waiting => write!(f, "is waiting on its parameters"),
+            Self::Failed(error) => write!(f, "failed: {}", error),
+            Self::NotRegistered(error) => write!(f, "was not registered: {}", error),
+        }
+    }
+}
+
+/// The result of running a component with [ComponentTest::run].
+#[derive(Debug, Clone)]
+pub struct ComponentRun {
+    /// The name of the component.
+    pub component: Cow<'static, str>,
+    /// What happened to the component.
+    pub outcome: RunOutcome,
+    /// The protocols the component installed, in the order of the protocol database.
+    pub installed_protocols: Vec<InstalledProtocol>,
+}
+
+impl ComponentRun {
+    /// Returns true if the component ran.
+    pub fn ran(&self) -> bool {
+        self.outcome == RunOutcome::Ran
+    }
+
+    /// Returns true if the component installed an instance of protocol `P`.
+    pub fn installed<P: protocol::Protocol>(&self) -> bool {
+        self.installed_protocols
+            .iter()
+            .any(|installed| installed.guid == *P::guid())
+    }
+
+    /// Asserts that the component ran.
+    #[track_caller]
+    pub fn assert_ran(&self) -> &Self {
+        assert!(
+            self.ran(),
+            "Component {} {}, but was expected to run.",
+            self.component,
+            self.outcome
+        );
+        self
+    }
+
+    /// Asserts that the component did not run because it is waiting on its parameters.
+    #[track_caller]
+    pub fn assert_waiting(&self) -> &Self {
+        assert!(
+            self.outcome == RunOutcome::Waiting,
+            "Component {} {}, but was expected to wait.",
+            self.component,
+            self.outcome
+        );
+        self
+    }
+
+    /// Asserts that the component installed an instance of protocol `P`.
+    #[track_caller]
+    pub fn assert_installed<P: protocol::Protocol>(&self) -> &Self {
+        assert!(
+            self.installed::<P>(),
+            "Component {} did not install protocol {}.",
+            self.component,
+            core::any::type_name::<P>()
+        );
+        self
+    }
+}
+
+/// A fresh storage to run a single component against.
+#[derive(Default)]
+pub struct ComponentTest {
+    manager: ComponentManager,
+}
+
+impl ComponentTest {
+    /// Creates a test with an empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a config value to the storage.
+    pub fn add_config<C: Default + 'static>(&mut self, config: C) {
+        self.manager.add_config(config);
+    }
+
+    /// Adds a PCD value to the storage.
+    pub fn add_pcd<T: PcdToken>(&mut self, value: T::Value) {
+        self.manager.add_pcd::<T>(value);
+    }
+
+    /// Installs a protocol on a new handle, returning the handle.
+    pub fn add_protocol<P: protocol::Protocol + 'static>(&mut self, protocol: P) -> Handle {
+        self.manager.storage.add_protocol(protocol)
+    }
+
+    /// Backs the memory services with an arena of `pages` pages. See [ComponentManager::add_memory].
+    pub fn add_memory(&mut self, pages: usize) {
+        self.manager.add_memory(pages);
+    }
+
+    /// Returns the underlying manager, to seed the storage with anything else, e.g. HOBs or variables.
+    pub fn manager_mut(&mut self) -> &mut ComponentManager {
+        &mut self.manager
+    }
+
+    /// Returns the storage, to inspect what the component left behind.
+    pub fn storage(&self) -> &Storage {
+        self.manager.storage()
+    }
+
+    /// Borrows config `C`, e.g. to check how a component mutated it.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the config is not registered, or is borrowed mutably.
+    pub fn config<C: Default + 'static>(&self) -> Config<'_, C> {
+        let storage = self.manager.storage();
+        let id = storage
+            .config_id::<C>()
+            .unwrap_or_else(|| panic!("Config {} is not registered.", core::any::type_name::<C>()));
+        Config::try_new(storage, id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns the first installed instance of protocol `P`, or None if it is not installed, or cannot be read as a
+    /// `P`, e.g. because it was installed as a different type or through a null pointer.
+    pub fn protocol<P: protocol::Protocol + 'static>(&self) -> Option<Protocol<'_, P>> {
+        let interface = self.manager.storage().try_get_protocol::<P>().ok()?;
+        // SAFETY: The interface is a `P`, and remains valid while the storage is borrowed.
+        Some(unsafe { Protocol::from_ptr(interface) })
+    }
+
+    /// Registers and runs a component, returning what happened to it.
+    ///
+    /// Event notifications the component triggers are dispatched before this returns. A component that does not run
+    /// is removed again, so each call runs exactly one component against the storage as it is.
+    #[allow(private_bounds)]
+    pub fn run<I, C: Component + 'static>(
+        &mut self,
+        component: impl IntoComponent<I, Component = C>,
+    ) -> ComponentRun {
+        let component = component.into_component();
+        let name = component.metadata().name.clone();
+        if let Err(err) = self.manager.try_push_component(component) {
+            return ComponentRun {
+                component: name,
+                outcome: RunOutcome::NotRegistered(err),
+                installed_protocols: Vec::new(),
+            };
+        }
+
+        let before: Vec<(Handle, Guid)> = self
+            .storage()
+            .protocols()
+            .map(|info| (info.handle, *info.guid))
+            .collect();
+        let failures = self.manager.failures().len();
+        self.manager.run();
+
+        // Notify components the component triggered may have failed too, so failures are matched by name.
+        let failure = self.manager.failures()[failures..]
+            .iter()
+            .find(|failure| failure.component == name);
+        let outcome = if let Some(failure) = failure {
+            RunOutcome::Failed(failure.error.clone())
+        } else if self.manager.component_count() > 0 {
+            RunOutcome::Waiting
+        } else {
+            RunOutcome::Ran
+        };
+        self.manager.components.clear();
+
+        let installed_protocols = self
+            .storage()
+            .protocols()
+            .filter(|info| !before.contains(&(info.handle, *info.guid)))
+            .map(|info| InstalledProtocol {
+                handle: info.handle,
+                guid: *info.guid,
+                name: info.name,
+            })
+            .collect();
+
+        ComponentRun {
+            component: name,
+            outcome,
+            installed_protocols,
+        }
+    }
+}
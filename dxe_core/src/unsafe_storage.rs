@@ -1,9 +1,35 @@
-use core::{cell::UnsafeCell, marker::PhantomData, ptr};
+use alloc::boxed::Box;
+use core::{
+    any::Any,
+    cell::{Ref, RefCell, RefMut, UnsafeCell},
+    ffi::c_void,
+    marker::PhantomData,
+    ptr,
+};
 
-use sdk::component::Storage;
+use r_efi::efi::Guid;
+use sdk::{
+    component::{
+        params::{ConfigMut, StorageView, ViewAccess},
+        Storage, StorageError,
+    },
+    hob::HobList,
+    memory::MemoryMap,
+    pcd::PcdEntry,
+    protocol::Protocol,
+    variable::VariableEntry,
+};
 
-/// A wrapper around a reference to a [Storage] object that allows for unsafe mutable
-/// access to the storage.
+/// A pointer to a [Storage] object that hands out access to individual resources in the storage.
+///
+/// Resources that components can mutate, such as configs, PCDs and variables, are held in `RefCell`s, so parameters
+/// only ever need a shared borrow of them to access them, even mutably. The per-resource accessors below are what
+/// parameters use; each only borrows the part of the storage it accesses, never the whole storage, so any number of
+/// them can be held at the same time, and the service tables can access other parts of the storage meanwhile.
+///
+/// A reference to the whole storage is only handed out by [storage](Self::storage) and [storage_mut](Self::storage_mut),
+/// for `&Storage` and `&mut Storage` params. Components with such a param have exclusive access, and the service
+/// tables are unbound while they run.
 ///
 /// ## Safety
 ///
@@ -32,6 +58,7 @@ impl<'s> From<&'s Storage> for UnsafeStorageCell<'s> {
 }
 
 impl<'s> UnsafeStorageCell<'s> {
+    /// Creates a cell that only allows shared access. [storage_mut](Self::storage_mut) must not be called on it.
     pub fn new_readonly(storage: &'s Storage) -> Self {
         Self(ptr::from_ref(storage).cast_mut(), PhantomData)
     }
@@ -40,11 +67,147 @@ impl<'s> UnsafeStorageCell<'s> {
         Self(ptr::from_mut(storage), PhantomData)
     }
 
-    pub fn storage_mut(self) -> &'s mut Storage {
-        unsafe { &mut *self.0 }
+    /// Creates a cell from a pointer to the storage, which allows the same access as [new_mutable](Self::new_mutable).
+    ///
+    /// Unlike a mutable reference, the pointer does not claim exclusive access to the storage, so the storage can also
+    /// be accessed through other pointers to it while the cell is in use, e.g. by the service tables.
+    ///
+    /// ## Safety
+    ///
+    /// - `storage` must be valid for `'s`.
+    pub unsafe fn from_raw(storage: *mut Storage) -> Self {
+        Self(storage, PhantomData)
+    }
+
+    /// Returns a mutable reference to the whole storage.
+    ///
+    /// ## Safety
+    ///
+    /// - The cell must have been created with [new_mutable](Self::new_mutable) or [from_raw](Self::from_raw).
+    /// - No other reference to the storage, including one obtained through a copy of this cell, may be used for the
+    ///   lifetime of the returned reference.
+    pub unsafe fn storage_mut(self) -> &'s mut Storage {
+        &mut *self.0
+    }
+
+    /// Returns a shared reference to the whole storage.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used for the lifetime of the returned reference.
+    pub unsafe fn storage(self) -> &'s Storage {
+        &*self.0
+    }
+
+    /// Borrows the config denoted by `id`.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the config is borrowed.
+    pub unsafe fn config(self, id: usize) -> Result<Ref<'s, Box<dyn Any>>, StorageError> {
+        Storage::try_get_config_raw(self.0, id)
+    }
+
+    /// Borrows the config denoted by `id` mutably as a `T`. The value is validated when the [ConfigMut] is dropped.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the config is borrowed.
+    pub unsafe fn config_mut<T: Default + 'static>(
+        self,
+        id: usize,
+    ) -> Result<ConfigMut<'s, T>, StorageError> {
+        ConfigMut::try_new_raw(self.0, id)
+    }
+
+    /// Returns true if configs are frozen.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be in use.
+    pub unsafe fn configs_frozen(self) -> bool {
+        Storage::configs_frozen_raw(self.0)
+    }
+
+    /// Borrows the PCD denoted by `id`.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the PCD is borrowed.
+    pub unsafe fn pcd(self, id: usize) -> Result<Ref<'s, PcdEntry>, StorageError> {
+        Storage::try_get_pcd_raw(self.0, id)
+    }
+
+    /// Borrows the PCD denoted by `id` mutably.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the PCD is borrowed.
+    pub unsafe fn pcd_mut(self, id: usize) -> Result<RefMut<'s, PcdEntry>, StorageError> {
+        Storage::try_get_pcd_mut_raw(self.0, id)
+    }
+
+    /// Borrows the variable denoted by `id`.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the variable is borrowed.
+    pub unsafe fn variable(self, id: usize) -> Result<Ref<'s, VariableEntry>, StorageError> {
+        Storage::try_get_variable_raw(self.0, id)
+    }
+
+    /// Borrows the variable denoted by `id` mutably.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the variable is borrowed.
+    pub unsafe fn variable_mut(self, id: usize) -> Result<RefMut<'s, VariableEntry>, StorageError> {
+        Storage::try_get_variable_mut_raw(self.0, id)
+    }
+
+    /// Returns the first installed instance of protocol `P`.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be in use.
+    pub unsafe fn protocol<P: Protocol + 'static>(self) -> Result<*mut P, StorageError> {
+        Storage::try_get_protocol_raw::<P>(self.0)
+    }
+
+    /// Returns the configuration table with the given GUID.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be in use.
+    pub unsafe fn configuration_table(self, guid: &Guid) -> Result<*mut c_void, StorageError> {
+        Storage::get_configuration_table_raw(self.0, guid)
+            .ok_or(StorageError::ConfigTableNotFound(*guid))
+    }
+
+    /// Borrows the HOB list.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the HOB list is borrowed.
+    pub unsafe fn hobs(self) -> &'s HobList {
+        Storage::hobs_raw(self.0)
+    }
+
+    /// Borrows the cell holding the memory map.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the cell is borrowed.
+    pub unsafe fn memory(self) -> &'s RefCell<MemoryMap> {
+        Storage::memory_raw(self.0)
     }
 
-    pub fn storage(self) -> &'s Storage {
-        unsafe { &*self.0 }
+    /// Creates a view of the resources declared by `D`.
+    ///
+    /// ## Safety
+    ///
+    /// - No mutable reference to the storage may be used while the view is in use.
+    pub unsafe fn view<D: ViewAccess>(self) -> StorageView<'s, D> {
+        StorageView::from_raw(self.0)
     }
 }
@@ -0,0 +1,110 @@
+//! Tests of config validators, and of how the manager records values that fail validation.
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use dxe_core::ComponentManager;
+use sdk::component::{params::ConfigMut, ValidateConfig};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Limits {
+    min: u32,
+    max: u32,
+}
+
+impl ValidateConfig for Limits {
+    fn validate(&self) -> Result<(), Cow<'static, str>> {
+        if self.min > self.max {
+            return Err(format!("min {} exceeds max {}", self.min, self.max).into());
+        }
+        Ok(())
+    }
+}
+
+fn limits(manager: &ComponentManager) -> Limits {
+    let id = manager.storage().config_id::<Limits>().unwrap();
+    *manager
+        .storage()
+        .get_config_untyped(id)
+        .downcast_ref::<Limits>()
+        .unwrap()
+}
+
+fn reasons(manager: &ComponentManager) -> Vec<String> {
+    manager
+        .config_errors()
+        .iter()
+        .map(|error| error.to_string())
+        .collect()
+}
+
+#[test]
+fn invalid_configs_are_recorded_and_not_added() {
+    static VALIDATED: AtomicUsize = AtomicUsize::new(0);
+
+    let mut manager = ComponentManager::new();
+    manager.add_config_validator(|limits: &Limits| {
+        VALIDATED.fetch_add(1, Ordering::SeqCst);
+        limits.validate()
+    });
+    manager.add_config(Limits { min: 1, max: 2 });
+    manager.add_config(Limits { min: 3, max: 2 });
+
+    // Each value is validated once, and the invalid one leaves the previous value in place.
+    assert_eq!(VALIDATED.load(Ordering::SeqCst), 2);
+    assert_eq!(limits(&manager), Limits { min: 1, max: 2 });
+    assert_eq!(
+        reasons(&manager),
+        [format!(
+            "Config {} is invalid: min 3 exceeds max 2",
+            core::any::type_name::<Limits>()
+        )]
+    );
+
+    // A valid value replaces the existing one.
+    manager.add_config(Limits { min: 2, max: 4 });
+    assert_eq!(limits(&manager), Limits { min: 2, max: 4 });
+    assert_eq!(manager.config_errors().len(), 1);
+}
+
+#[test]
+fn invalid_writes_are_recorded_when_released() {
+    fn widen(mut limits: ConfigMut<Limits>) {
+        limits.max = 10;
+    }
+    fn invert(mut limits: ConfigMut<Limits>) {
+        limits.min = limits.max + 1;
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(Limits { min: 1, max: 2 });
+    manager.add_config_validator(Limits::validate);
+    manager.add_component(widen);
+    manager.run();
+    assert!(manager.config_errors().is_empty());
+
+    // A write is made in place, so the invalid value is kept but recorded once the component releases it.
+    manager.add_component(invert);
+    manager.run();
+    assert_eq!(limits(&manager), Limits { min: 11, max: 10 });
+    assert_eq!(
+        reasons(&manager),
+        [format!(
+            "Config {} is invalid: min 11 exceeds max 10",
+            core::any::type_name::<Limits>()
+        )]
+    );
+    assert!(manager.failures().is_empty());
+}
+
+#[test]
+fn existing_values_are_validated_when_a_validator_is_added() {
+    let mut manager = ComponentManager::new();
+    manager.add_config(Limits { min: 5, max: 0 });
+    assert!(manager.config_errors().is_empty());
+
+    manager.add_config_validator(Limits::validate);
+    assert_eq!(manager.config_errors().len(), 1);
+    assert_eq!(manager.config_errors()[0].reason, "min 5 exceeds max 0");
+}
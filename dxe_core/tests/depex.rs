@@ -0,0 +1,220 @@
+//! Tests of the binary and textual [Depex] parsers, and of how expressions evaluate.
+use std::ptr;
+
+use r_efi::efi::Guid;
+use sdk::{
+    component::Storage,
+    depex::{Depex, DepexError, Opcode},
+};
+
+const FIRST: Guid = Guid::from_fields(0x6c1f_0e2a, 0x52b4, 0x4c3d, 0x9a, 0x10, &[1, 2, 3, 4, 5, 6]);
+const SECOND: Guid =
+    Guid::from_fields(0x6c1f_0e2a, 0x52b4, 0x4c3d, 0x9a, 0x10, &[6, 5, 4, 3, 2, 1]);
+const FIRST_TEXT: &str = "6C1F0E2A-52B4-4C3D-9A10-010203040506";
+const SECOND_TEXT: &str = "6C1F0E2A-52B4-4C3D-9A10-060504030201";
+
+/// Returns the binary PUSH opcode for `guid`.
+fn push(guid: &Guid) -> Vec<u8> {
+    let mut bytes = vec![0x02];
+    bytes.extend_from_slice(guid.as_bytes());
+    bytes
+}
+
+#[test]
+fn binary_expressions_parse_into_opcodes() {
+    let bytes = [push(&FIRST), push(&SECOND), vec![0x03, 0x05, 0x08]].concat();
+    let depex = Depex::parse(&bytes).unwrap();
+    assert_eq!(
+        depex.opcodes(),
+        [
+            Opcode::Push(FIRST),
+            Opcode::Push(SECOND),
+            Opcode::And,
+            Opcode::Not,
+            Opcode::End
+        ]
+    );
+    assert_eq!(depex.to_bytes(), bytes);
+}
+
+#[test]
+fn text_expressions_compile_to_postfix() {
+    let text = format!("SOR NOT ({} OR FALSE) AND {} END", FIRST_TEXT, SECOND_TEXT);
+    let depex = Depex::parse_text(&text).unwrap();
+    assert_eq!(
+        depex.opcodes(),
+        [
+            Opcode::Sor,
+            Opcode::Push(FIRST),
+            Opcode::False,
+            Opcode::Or,
+            Opcode::Not,
+            Opcode::Push(SECOND),
+            Opcode::And,
+            Opcode::End
+        ]
+    );
+    assert!(depex.is_schedule_on_request());
+    assert_eq!(Depex::parse(&depex.to_bytes()).unwrap(), depex);
+}
+
+#[test]
+fn and_and_or_evaluate_left_to_right() {
+    // Parsed as (TRUE OR FALSE) AND FALSE, like EDK2, rather than TRUE OR (FALSE AND FALSE).
+    let depex = Depex::parse_text("TRUE OR FALSE AND FALSE").unwrap();
+    assert!(!depex.evaluate_with(|_| true));
+}
+
+#[test]
+fn text_guids_can_be_resolved_by_name() {
+    let resolve = |name: &str| (name == "gFirstProtocolGuid").then_some(FIRST);
+    let depex = Depex::parse_text_with("gFirstProtocolGuid", resolve).unwrap();
+    assert_eq!(depex, Depex::from_protocols(&[FIRST]));
+    assert_eq!(
+        Depex::parse_text_with("gSecondProtocolGuid", resolve),
+        Err(DepexError::UnknownToken("gSecondProtocolGuid".into()))
+    );
+}
+
+#[test]
+fn expressions_evaluate_against_installed_protocols() {
+    let depex = Depex::from_protocols(&[FIRST, SECOND]);
+    assert!(depex.evaluate_with(|_| true));
+    assert!(!depex.evaluate_with(|guid| *guid == FIRST));
+
+    let mut storage = Storage::new();
+    assert!(!depex.evaluate(&storage));
+    storage
+        .install_protocol_interface(None, &FIRST, ptr::null_mut())
+        .unwrap();
+    assert!(!depex.evaluate(&storage));
+    storage
+        .install_protocol_interface(None, &SECOND, ptr::null_mut())
+        .unwrap();
+    assert!(depex.evaluate(&storage));
+
+    let not = Depex::parse_text(&format!("NOT {}", FIRST_TEXT)).unwrap();
+    assert!(!not.evaluate(&storage));
+}
+
+#[test]
+fn empty_protocol_lists_are_always_satisfied() {
+    let depex = Depex::from_protocols(&[]);
+    assert_eq!(depex.opcodes(), [Opcode::True, Opcode::End]);
+    assert!(depex.evaluate_with(|_| false));
+}
+
+#[test]
+fn before_and_after_are_ordering_constraints() {
+    let before = Depex::parse_text(&format!("BEFORE {}", FIRST_TEXT)).unwrap();
+    assert_eq!(before.before(), Some(&FIRST));
+    assert_eq!(before.after(), None);
+    assert!(before.evaluate_with(|_| false));
+
+    let after =
+        Depex::parse(&[vec![0x01], FIRST.as_bytes().to_vec(), vec![0x08]].concat()).unwrap();
+    assert_eq!(after.after(), Some(&FIRST));
+    assert_eq!(after.before(), None);
+    assert!(!after.is_schedule_on_request());
+    assert!(after.evaluate_with(|_| false));
+}
+
+#[test]
+fn malformed_binary_expressions_are_rejected() {
+    assert_eq!(
+        Depex::parse(&[0x06, 0x0a, 0x08]),
+        Err(DepexError::InvalidOpcode {
+            opcode: 0x0a,
+            offset: 1
+        })
+    );
+    assert_eq!(Depex::parse(&[]), Err(DepexError::UnexpectedEnd));
+    assert_eq!(Depex::parse(&[0x06]), Err(DepexError::UnexpectedEnd));
+    assert_eq!(
+        Depex::parse(&push(&FIRST)[..10]),
+        Err(DepexError::UnexpectedEnd)
+    );
+    assert_eq!(
+        Depex::parse(&[0x06, 0x08, 0x06]),
+        Err(DepexError::TrailingData)
+    );
+}
+
+#[test]
+fn unbalanced_stacks_are_rejected() {
+    assert_eq!(Depex::parse(&[0x03, 0x08]), Err(DepexError::InvalidStack));
+    assert_eq!(
+        Depex::parse(&[0x06, 0x04, 0x08]),
+        Err(DepexError::InvalidStack)
+    );
+    assert_eq!(Depex::parse(&[0x05, 0x08]), Err(DepexError::InvalidStack));
+    assert_eq!(
+        Depex::parse(&[0x06, 0x07, 0x08]),
+        Err(DepexError::InvalidStack)
+    );
+    assert_eq!(Depex::parse(&[0x08]), Err(DepexError::InvalidStack));
+    assert_eq!(Depex::new(vec![]), Err(DepexError::InvalidStack));
+}
+
+#[test]
+fn misplaced_sor_before_and_after_are_rejected() {
+    assert_eq!(
+        Depex::parse(&[0x06, 0x09, 0x08]),
+        Err(DepexError::MisplacedSor)
+    );
+    assert_eq!(
+        Depex::new(vec![Opcode::True, Opcode::Before(FIRST)]),
+        Err(DepexError::MisplacedBeforeAfter)
+    );
+    assert_eq!(
+        Depex::new(vec![Opcode::Sor, Opcode::After(FIRST)]),
+        Err(DepexError::MisplacedBeforeAfter)
+    );
+
+    assert_eq!(
+        Depex::parse_text("TRUE AND SOR"),
+        Err(DepexError::MisplacedSor)
+    );
+    assert_eq!(Depex::parse_text("TRUE SOR"), Err(DepexError::MisplacedSor));
+    assert_eq!(
+        Depex::parse_text(&format!("TRUE AND BEFORE {}", FIRST_TEXT)),
+        Err(DepexError::MisplacedBeforeAfter)
+    );
+    assert_eq!(
+        Depex::parse_text(&format!("SOR AFTER {}", FIRST_TEXT)),
+        Err(DepexError::MisplacedBeforeAfter)
+    );
+}
+
+#[test]
+fn malformed_text_expressions_are_rejected() {
+    assert_eq!(
+        Depex::parse_text("TRUE AND MAYBE"),
+        Err(DepexError::UnknownToken("MAYBE".into()))
+    );
+    assert_eq!(
+        Depex::parse_text("TRUE TRUE"),
+        Err(DepexError::UnknownToken("TRUE".into()))
+    );
+    assert_eq!(
+        Depex::parse_text("(TRUE"),
+        Err(DepexError::UnbalancedParentheses)
+    );
+    assert_eq!(
+        Depex::parse_text("(TRUE AND FALSE"),
+        Err(DepexError::UnbalancedParentheses)
+    );
+    assert_eq!(
+        Depex::parse_text("TRUE)"),
+        Err(DepexError::UnbalancedParentheses)
+    );
+    assert_eq!(
+        Depex::parse_text(")"),
+        Err(DepexError::UnbalancedParentheses)
+    );
+    assert_eq!(Depex::parse_text("NOT"), Err(DepexError::UnexpectedEnd));
+    assert_eq!(
+        Depex::parse_text("TRUE END FALSE"),
+        Err(DepexError::UnknownToken("FALSE".into()))
+    );
+}
@@ -0,0 +1,315 @@
+//! Registers the same components in many orders, and reports the components whose behavior depends on the order.
+//!
+//! Each scenario is dispatched once for the registration order it lists, once reversed, and once for each shuffle
+//! drawn from a set of seeds. The outcome of every order is compared to the first one:
+//!
+//! - whether each component ran, is still pending, or failed,
+//! - the values each component observed, as recorded with [observe], and
+//! - the final [Storage], as displayed by its `Debug` implementation, which lists its configs, protocols, PCDs,
+//!   variables, HOBs, configuration tables and events, together with the value of each config.
+//!
+//! The seeds are fixed, so the suite is deterministic. Set `DISPATCH_ORDER_SEED` to explore other orders.
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+};
+
+use dxe_core::ComponentManager;
+use r_efi::efi::Guid;
+use sdk::{
+    component::params::{Config, ConfigMut, Protocol, Storage},
+    component::ConfigInfo,
+    protocol,
+};
+
+const SEEDS: [u64; 4] = [1, 0x5eed, 0xdead_beef, 0x0123_4567_89ab_cdef];
+const SHUFFLES_PER_SEED: usize = 16;
+
+thread_local! {
+    static OBSERVATIONS: RefCell<BTreeMap<&'static str, Vec<String>>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Records a value observed by a component, so that it can be compared across registration orders.
+fn observe(component: &'static str, value: impl Debug) {
+    OBSERVATIONS.with(|observations| {
+        observations
+            .borrow_mut()
+            .entry(component)
+            .or_default()
+            .push(format!("{:?}", value))
+    });
+}
+
+/// Adds a single component to the manager.
+type Register = fn(&mut ComponentManager);
+
+/// A set of components, and the state they are dispatched with.
+struct Scenario {
+    /// Adds configs and other resources, before any component is registered.
+    setup: fn(&mut ComponentManager),
+    /// The components, in their canonical registration order.
+    components: Vec<Register>,
+}
+
+/// What happened to a component in one dispatch.
+#[derive(Debug, Clone, PartialEq)]
+enum Status {
+    Ran,
+    Pending,
+    Failed(String),
+}
+
+/// The outcome of dispatching the components in one order.
+#[derive(Debug)]
+struct Outcome {
+    order: Vec<String>,
+    components: BTreeMap<String, (Status, Vec<String>)>,
+    storage: String,
+}
+
+/// The components whose behavior differs from that of the first order, with an order that shows the difference.
+#[derive(Debug, Default)]
+struct Report {
+    orders: usize,
+    order_dependent: BTreeMap<String, Vec<String>>,
+    storage_dependent: Option<Vec<String>>,
+}
+
+impl Report {
+    fn order_dependent(&self) -> BTreeSet<&str> {
+        self.order_dependent.keys().map(String::as_str).collect()
+    }
+}
+
+/// A xorshift generator, so that shuffles are reproducible without external crates.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn shuffle(&mut self, values: &mut [usize]) {
+        for i in (1..values.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            values.swap(i, j);
+        }
+    }
+}
+
+fn seeds() -> Vec<u64> {
+    match std::env::var("DISPATCH_ORDER_SEED") {
+        Ok(seed) => vec![seed.parse().expect("DISPATCH_ORDER_SEED is a number")],
+        Err(_) => SEEDS.to_vec(),
+    }
+}
+
+/// Dispatches the components of the scenario in the given order.
+fn dispatch(scenario: &Scenario, order: &[usize]) -> Outcome {
+    OBSERVATIONS.with(|observations| observations.borrow_mut().clear());
+
+    let mut manager = ComponentManager::new();
+    (scenario.setup)(&mut manager);
+    for &index in order {
+        (scenario.components[index])(&mut manager);
+    }
+    let registered: Vec<String> = manager.pending_components().map(String::from).collect();
+    manager.run();
+
+    let pending: BTreeSet<&str> = manager.pending_components().collect();
+    let observations = OBSERVATIONS.with(|observations| observations.take());
+    let components = registered
+        .iter()
+        .cloned()
+        .map(|name| {
+            let status = match manager.failures().iter().find(|f| f.component == name) {
+                Some(failure) => Status::Failed(failure.error.to_string()),
+                None if pending.contains(name.as_str()) => Status::Pending,
+                None => Status::Ran,
+            };
+            // Observations are keyed by the short name of the component function.
+            let short = name.rsplit("::").next().unwrap_or(&name);
+            let observed = observations.get(short).cloned().unwrap_or_default();
+            (name, (status, observed))
+        })
+        .collect();
+
+    Outcome {
+        order: registered,
+        components,
+        storage: fingerprint(manager.storage()),
+    }
+}
+
+/// Dispatches the scenario in many orders, and reports the components whose behavior depends on the order.
+fn explore(scenario: &Scenario) -> Report {
+    let canonical: Vec<usize> = (0..scenario.components.len()).collect();
+    let mut orders = vec![canonical.clone(), canonical.iter().rev().copied().collect()];
+    for seed in seeds() {
+        let mut rng = Rng(seed | 1);
+        for _ in 0..SHUFFLES_PER_SEED {
+            let mut order = canonical.clone();
+            rng.shuffle(&mut order);
+            orders.push(order);
+        }
+    }
+
+    let mut report = Report {
+        orders: orders.len(),
+        ..Report::default()
+    };
+    let baseline = dispatch(scenario, &orders[0]);
+    for order in &orders[1..] {
+        let outcome = dispatch(scenario, order);
+        for (name, behavior) in &outcome.components {
+            if baseline.components.get(name) != Some(behavior) {
+                report
+                    .order_dependent
+                    .entry(name.clone())
+                    .or_insert_with(|| outcome.order.clone());
+            }
+        }
+        if outcome.storage != baseline.storage && report.storage_dependent.is_none() {
+            report.storage_dependent = Some(outcome.order.clone());
+        }
+    }
+
+    for (name, order) in &report.order_dependent {
+        println!(
+            "{} depends on the registration order, e.g. {:?}",
+            name, order
+        );
+    }
+    if let Some(order) = &report.storage_dependent {
+        println!(
+            "The final storage depends on the registration order, e.g. {:?}",
+            order
+        );
+    }
+    report
+}
+
+/// Describes the final storage, so that it can be compared across registration orders.
+fn fingerprint(storage: &Storage) -> String {
+    let values: Vec<String> = storage
+        .configs()
+        .filter(|info| info.initialized)
+        .map(|info| config_value(storage, &info))
+        .collect();
+    format!("{:?} {:?}", storage, values)
+}
+
+/// Formats the value of a config, if it has one of the types used by the scenarios.
+fn config_value(storage: &Storage, info: &ConfigInfo) -> String {
+    let config = storage.get_config_untyped(info.id);
+    let value: &dyn Any = config.as_ref();
+    let value: Option<&dyn Debug> = value
+        .downcast_ref::<i32>()
+        .map(|value| value as &dyn Debug)
+        .or_else(|| value.downcast_ref::<u32>().map(|value| value as &dyn Debug));
+    match value {
+        Some(value) => format!("{}: {:?}", info.name, value),
+        None => format!("{}: <unknown>", info.name),
+    }
+}
+
+struct Counter(u32);
+
+impl protocol::Protocol for Counter {
+    fn guid() -> &'static Guid {
+        static GUID: Guid =
+            Guid::from_fields(0x3c1f_7a2e, 0x94d0, 0x4b8a, 0xa1, 0x6e, &[9, 8, 7, 6, 5, 4]);
+        &GUID
+    }
+}
+
+fn two_reads(data: Config<i32>, data2: Config<i32>) {
+    assert_eq!(*data, *data2);
+}
+
+fn increment(mut data: ConfigMut<i32>) {
+    *data += 1;
+}
+
+fn read(data: Config<i32>) {
+    observe("read", *data);
+}
+
+fn install(storage: &mut Storage) {
+    storage.add_protocol(Counter(3));
+}
+
+fn consume(counter: Protocol<Counter>, data: Config<u32>) {
+    observe("consume", counter.0 + *data);
+}
+
+fn double(mut data: ConfigMut<u32>) {
+    *data *= 2;
+}
+
+fn reset(mut data: ConfigMut<u32>) {
+    *data = 1;
+}
+
+fn setup(manager: &mut ComponentManager) {
+    manager.add_config(10i32);
+    manager.add_config(5u32);
+}
+
+// Like `component8` of the platform, a reader of a config only sees the increment if it is registered after it.
+#[test]
+fn readers_depend_on_writer_order() {
+    let scenario = Scenario {
+        setup,
+        components: vec![
+            |m| m.add_component(two_reads),
+            |m| m.add_component(increment),
+            |m| m.add_component(read),
+            |m| m.add_component(consume),
+            |m| m.add_component(install),
+        ],
+    };
+
+    let report = explore(&scenario);
+    assert!(report.orders > 2);
+    assert_eq!(
+        report.order_dependent(),
+        BTreeSet::from(["dispatch_order::read"])
+    );
+    assert!(report.storage_dependent.is_none());
+}
+
+// Components that wait on a protocol are retried until it is installed, so the order they are added in is irrelevant.
+#[test]
+fn waiting_components_are_order_independent() {
+    let scenario = Scenario {
+        setup,
+        components: vec![
+            |m| m.add_component(consume),
+            |m| m.add_component(install),
+            |m| m.add_component(two_reads),
+        ],
+    };
+
+    let report = explore(&scenario);
+    assert!(report.order_dependent.is_empty());
+    assert!(report.storage_dependent.is_none());
+}
+
+// Two writers of the same config leave a different final value depending on which runs last.
+#[test]
+fn competing_writers_change_final_storage() {
+    let scenario = Scenario {
+        setup,
+        components: vec![|m| m.add_component(double), |m| m.add_component(reset)],
+    };
+
+    let report = explore(&scenario);
+    assert!(report.order_dependent.is_empty());
+    assert!(report.storage_dependent.is_some());
+}
@@ -0,0 +1,81 @@
+//! Tests of events whose notify function is a component, and of the virtual clock at the end of its range.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use dxe_core::{ComponentManager, CreateEventError, RegistrationError};
+use r_efi::efi::{self, Guid};
+use sdk::component::params::{Config, ConfigMut};
+
+const GROUP: Guid = Guid::from_fields(0x5d0e_8f3c, 0x1a27, 0x4b69, 0x8e, 0x52, &[1, 2, 3, 4, 5, 6]);
+
+#[test]
+fn conflicting_notify_components_are_rejected_without_creating_an_event() {
+    fn conflicting(_: Config<u32>, _: ConfigMut<u32>) {}
+
+    let mut manager = ComponentManager::new();
+    let result = manager.create_event_with_component(
+        efi::EVT_NOTIFY_SIGNAL,
+        efi::TPL_CALLBACK,
+        Some(&GROUP),
+        conflicting,
+    );
+    assert!(matches!(
+        result,
+        Err(CreateEventError::Registration(
+            RegistrationError::Conflict { .. }
+        ))
+    ));
+    assert_eq!(manager.signal_event_group(&GROUP), 0);
+}
+
+#[test]
+fn invalid_events_are_rejected_without_registering_the_component() {
+    static NOTIFIED: AtomicUsize = AtomicUsize::new(0);
+    fn notify() {
+        NOTIFIED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    let result = manager.create_event_with_component(
+        efi::EVT_NOTIFY_SIGNAL | efi::EVT_NOTIFY_WAIT,
+        efi::TPL_CALLBACK,
+        None,
+        notify,
+    );
+    assert_eq!(
+        result,
+        Err(CreateEventError::Status(efi::Status::INVALID_PARAMETER))
+    );
+
+    // The next notify component takes the slot the rejected one would have used.
+    manager
+        .create_event_with_component(
+            efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(&GROUP),
+            notify,
+        )
+        .unwrap();
+    assert_eq!(manager.signal_event_group(&GROUP), 1);
+    assert_eq!(NOTIFIED.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn timers_saturate_at_the_end_of_time() {
+    let mut manager = ComponentManager::new();
+    let relative = manager.create_event(efi::EVT_TIMER, None).unwrap();
+    let periodic = manager.create_event(efi::EVT_TIMER, None).unwrap();
+
+    manager.advance_time(u64::MAX - 10);
+    manager
+        .set_timer(relative, efi::TIMER_RELATIVE, 100)
+        .unwrap();
+    manager
+        .set_timer(periodic, efi::TIMER_PERIODIC, u64::MAX)
+        .unwrap();
+    assert_eq!(manager.check_event(relative), Err(efi::Status::NOT_READY));
+
+    assert_eq!(manager.advance_time(u64::MAX), 2);
+    assert_eq!(manager.now(), u64::MAX);
+    assert_eq!(manager.check_event(relative), Ok(()));
+    assert_eq!(manager.check_event(periodic), Ok(()));
+}
@@ -0,0 +1,67 @@
+//! Tests of freezing configs, and of how components that access them are registered and run afterwards.
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use dxe_core::{ComponentError, ComponentManager, RegistrationError};
+use sdk::component::{
+    params::{Config, ConfigMut},
+    StorageError,
+};
+
+#[test]
+fn config_writers_cannot_be_registered_once_configs_are_frozen() {
+    fn write(mut value: ConfigMut<u32>) {
+        *value += 1;
+    }
+    fn read(_: Config<u32>) {}
+    fn read_missing(_: Config<u64>) {}
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(1u32);
+    manager.freeze_configs();
+
+    match manager.try_add_component(write) {
+        Err(RegistrationError::ConfigsFrozen { param, .. }) => {
+            assert_eq!(param, "ConfigMut<u32>")
+        }
+        result => panic!("Writer registered with {:?}", result),
+    }
+    // A reader of a config without a value is rejected too, as its default can no longer be added.
+    match manager.try_add_component(read_missing) {
+        Err(RegistrationError::ConfigsFrozen { param, .. }) => {
+            assert_eq!(param, "Config<u64>")
+        }
+        result => panic!("Reader registered with {:?}", result),
+    }
+    assert_eq!(manager.try_add_component(read), Ok(()));
+    assert_eq!(manager.component_count(), 1);
+}
+
+#[test]
+fn pending_config_writers_fail_once_configs_are_frozen() {
+    static READ: AtomicU32 = AtomicU32::new(0);
+    fn write(mut value: ConfigMut<u32>) {
+        *value += 1;
+    }
+    fn read(value: Config<u32>) {
+        READ.store(*value, Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(1u32);
+    manager.add_component(write);
+    manager.add_component(read);
+    manager.freeze_configs();
+
+    // The writer fails right away rather than staying pending, as it could never run.
+    assert_eq!(manager.component_count(), 1);
+    assert_eq!(manager.failures().len(), 1);
+    assert_eq!(
+        manager.failures()[0].error,
+        ComponentError::Storage(StorageError::ConfigsFrozen)
+    );
+
+    manager.run();
+    assert_eq!(READ.load(Ordering::SeqCst), 1);
+    assert_eq!(manager.component_count(), 0);
+    assert_eq!(manager.failures().len(), 1);
+}
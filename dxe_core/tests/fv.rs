@@ -0,0 +1,321 @@
+//! Tests of the firmware volume parser, on volumes built in memory, and of how the drivers they contain are dispatched.
+use std::{cell::RefCell, rc::Rc};
+
+use dxe_core::ComponentManager;
+use r_efi::efi::Guid;
+use sdk::{
+    depex::{Depex, DepexError, Opcode},
+    fv::{FileType, FirmwareVolume, FvError, SectionType, FFS2_GUID},
+    guid::PrettyGuid,
+};
+
+const DRIVER: Guid =
+    Guid::from_fields(0x3a9c_51d0, 0x7e2b, 0x4f18, 0xb6, 0x41, &[1, 1, 1, 1, 1, 1]);
+const OTHER_DRIVER: Guid =
+    Guid::from_fields(0x3a9c_51d0, 0x7e2b, 0x4f18, 0xb6, 0x41, &[2, 2, 2, 2, 2, 2]);
+const NESTED_FV: Guid =
+    Guid::from_fields(0x3a9c_51d0, 0x7e2b, 0x4f18, 0xb6, 0x41, &[3, 3, 3, 3, 3, 3]);
+const PROTOCOL: Guid =
+    Guid::from_fields(0x3a9c_51d0, 0x7e2b, 0x4f18, 0xb6, 0x41, &[4, 4, 4, 4, 4, 4]);
+const SECTION_GUID: Guid =
+    Guid::from_fields(0x3a9c_51d0, 0x7e2b, 0x4f18, 0xb6, 0x41, &[5, 5, 5, 5, 5, 5]);
+
+const ERASE_POLARITY: u32 = 0x0000_0800;
+const HEADER_LENGTH: u16 = 72;
+
+/// Builds a section with a standard header.
+fn section(section_type: SectionType, data: &[u8]) -> Vec<u8> {
+    let size = (4 + data.len()) as u32;
+    let mut bytes = size.to_le_bytes()[..3].to_vec();
+    bytes.push(section_type.0);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Builds a GUID-defined section that does not require processing, with the given data offset.
+fn guid_defined(data_offset: u16, sections: &[u8]) -> Vec<u8> {
+    let mut data = SECTION_GUID.as_bytes().to_vec();
+    data.extend_from_slice(&data_offset.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(sections);
+    section(SectionType::GUID_DEFINED, &data)
+}
+
+/// Builds a section stream, aligning each section to 4 bytes.
+fn sections(sections: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for section in sections {
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        bytes.extend_from_slice(section);
+    }
+    bytes
+}
+
+/// Builds a UCS-2 string, including the null terminator.
+fn ucs2(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Builds a valid file in a volume with the erase polarity set.
+fn file(name: Guid, file_type: FileType, data: &[u8]) -> Vec<u8> {
+    let size = (24 + data.len()) as u32;
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.extend_from_slice(&[0, 0, file_type.0, 0]);
+    bytes.extend_from_slice(&size.to_le_bytes()[..3]);
+    // Header construction, header valid and data valid, inverted for the erase polarity.
+    bytes.push(!0x07);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Builds a volume with the erase polarity set, with a valid checksum.
+fn volume(files: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = vec![0; HEADER_LENGTH as usize];
+    bytes[16..32].copy_from_slice(FFS2_GUID.as_bytes());
+    bytes[40..44].copy_from_slice(b"_FVH");
+    bytes[44..48].copy_from_slice(&ERASE_POLARITY.to_le_bytes());
+    bytes[48..50].copy_from_slice(&HEADER_LENGTH.to_le_bytes());
+    bytes[55] = 2;
+    for file in files {
+        bytes.resize(bytes.len().next_multiple_of(8), 0xff);
+        bytes.extend_from_slice(file);
+    }
+    bytes.resize(bytes.len().next_multiple_of(8) + 24, 0xff);
+    let length = bytes.len() as u64;
+    bytes[32..40].copy_from_slice(&length.to_le_bytes());
+    bytes[56..60].copy_from_slice(&1u32.to_le_bytes());
+    bytes[60..64].copy_from_slice(&(length as u32).to_le_bytes());
+    fix_checksum(&mut bytes);
+    bytes
+}
+
+/// Updates the header checksum of `volume`, so that the header words sum to zero.
+fn fix_checksum(volume: &mut [u8]) {
+    volume[50..52].fill(0);
+    let length = u16::from_le_bytes([volume[48], volume[49]]) as usize;
+    let sum = volume[..length].chunks_exact(2).fold(0u16, |sum, word| {
+        sum.wrapping_add(u16::from_le_bytes([word[0], word[1]]))
+    });
+    volume[50..52].copy_from_slice(&sum.wrapping_neg().to_le_bytes());
+}
+
+/// Builds the file of a driver with the given depex.
+fn driver(name: Guid, depex: &Depex) -> Vec<u8> {
+    let data = sections(&[
+        section(SectionType::DXE_DEPEX, &depex.to_bytes()),
+        section(SectionType::PE32, b"MZ image"),
+    ]);
+    file(name, FileType::DRIVER, &data)
+}
+
+#[test]
+fn drivers_are_listed_with_their_sections() {
+    let depex = Depex::from_protocols(&[PROTOCOL]);
+    let mut version = 7u16.to_le_bytes().to_vec();
+    version.extend_from_slice(&ucs2("1.0"));
+    let driver_data = sections(&[
+        section(SectionType::DXE_DEPEX, &depex.to_bytes()),
+        section(SectionType::PE32, b"MZ image"),
+        section(SectionType::USER_INTERFACE, &ucs2("Driver")),
+        section(SectionType::VERSION, &version),
+    ]);
+    let application_data = sections(&[section(SectionType::PE32, b"MZ app")]);
+    let volume = volume(&[
+        file(DRIVER, FileType::DRIVER, &driver_data),
+        file(OTHER_DRIVER, FileType::APPLICATION, &application_data),
+        file(PROTOCOL, FileType::RAW, b"raw data"),
+    ]);
+
+    let fv = FirmwareVolume::parse(&volume).unwrap();
+    assert_eq!(fv.file_system, FFS2_GUID);
+    assert_eq!(fv.name, None);
+    let files: Vec<_> = fv
+        .files
+        .iter()
+        .map(|file| (file.name, file.file_type))
+        .collect();
+    assert_eq!(
+        files,
+        [
+            (DRIVER, FileType::DRIVER),
+            (OTHER_DRIVER, FileType::APPLICATION),
+            (PROTOCOL, FileType::RAW)
+        ]
+    );
+    assert!(fv.files[2].sections.is_empty());
+    assert_eq!(fv.files[2].data, b"raw data");
+
+    let drivers = fv.drivers().unwrap();
+    assert_eq!(drivers.len(), 1);
+    assert_eq!(drivers[0].name, DRIVER);
+    assert_eq!(drivers[0].ui_name.as_deref(), Some("Driver"));
+    assert_eq!(drivers[0].version, Some((7, "1.0".into())));
+    assert_eq!(drivers[0].depex, Some(depex));
+    assert_eq!(drivers[0].image.as_deref(), Some(&b"MZ image"[..]));
+    assert_eq!(
+        drivers[0].to_string(),
+        format!("Driver ({})", PrettyGuid(&DRIVER))
+    );
+}
+
+#[test]
+fn encapsulation_sections_and_nested_volumes_are_expanded() {
+    let mut uncompressed = 0u32.to_le_bytes().to_vec();
+    uncompressed.push(0);
+    uncompressed.extend_from_slice(&sections(&[section(SectionType::PE32, b"MZ image")]));
+    let driver_data = sections(&[guid_defined(
+        24,
+        &sections(&[section(SectionType::COMPRESSION, &uncompressed)]),
+    )]);
+    let nested = volume(&[file(DRIVER, FileType::DRIVER, &driver_data)]);
+    let volume = volume(&[
+        file(
+            NESTED_FV,
+            FileType::FIRMWARE_VOLUME_IMAGE,
+            &section(SectionType::FIRMWARE_VOLUME_IMAGE, &nested),
+        ),
+        driver(OTHER_DRIVER, &Depex::from_protocols(&[])),
+    ]);
+
+    let drivers = FirmwareVolume::parse(&volume).unwrap().drivers().unwrap();
+    let names: Vec<_> = drivers.iter().map(|driver| driver.name).collect();
+    assert_eq!(names, [DRIVER, OTHER_DRIVER]);
+    assert_eq!(drivers[0].image.as_deref(), Some(&b"MZ image"[..]));
+    assert_eq!(drivers[0].depex, None);
+}
+
+#[test]
+fn invalid_volume_headers_are_rejected() {
+    let valid = volume(&[driver(DRIVER, &Depex::from_protocols(&[]))]);
+    assert!(FirmwareVolume::parse(&valid).is_ok());
+
+    assert_eq!(FirmwareVolume::parse(&valid[..40]), Err(FvError::Truncated));
+    assert_eq!(
+        FirmwareVolume::parse(&valid[..valid.len() - 8]),
+        Err(FvError::Truncated)
+    );
+
+    let mut signature = valid.clone();
+    signature[40] = b'X';
+    assert_eq!(
+        FirmwareVolume::parse(&signature),
+        Err(FvError::InvalidSignature)
+    );
+
+    let mut file_system = valid.clone();
+    file_system[16..32].copy_from_slice(PROTOCOL.as_bytes());
+    fix_checksum(&mut file_system);
+    assert_eq!(
+        FirmwareVolume::parse(&file_system),
+        Err(FvError::UnsupportedFileSystem(PROTOCOL))
+    );
+
+    let mut checksum = valid.clone();
+    checksum[50] ^= 1;
+    assert_eq!(
+        FirmwareVolume::parse(&checksum),
+        Err(FvError::InvalidChecksum)
+    );
+
+    let mut header_length = valid.clone();
+    header_length[48..50].copy_from_slice(&0u16.to_le_bytes());
+    assert_eq!(
+        FirmwareVolume::parse(&header_length),
+        Err(FvError::InvalidHeaderLength)
+    );
+}
+
+#[test]
+fn invalid_files_and_sections_are_rejected() {
+    let mut truncated = file(DRIVER, FileType::DRIVER, &section(SectionType::PE32, b"MZ"));
+    truncated[20] = 0xf0;
+    assert_eq!(
+        FirmwareVolume::parse(&volume(&[truncated])),
+        Err(FvError::InvalidFile { offset: 72 })
+    );
+
+    let overlong = section(SectionType::PE32, b"MZ image");
+    let overlong = file(DRIVER, FileType::DRIVER, &overlong[..8]);
+    assert_eq!(
+        FirmwareVolume::parse(&volume(&[overlong])),
+        Err(FvError::InvalidSection { file: DRIVER })
+    );
+
+    let invalid_depex = file(
+        DRIVER,
+        FileType::DRIVER,
+        &section(SectionType::DXE_DEPEX, &[0x03, 0x08]),
+    );
+    assert_eq!(
+        FirmwareVolume::parse(&volume(&[invalid_depex]))
+            .unwrap()
+            .drivers(),
+        Err(FvError::InvalidDepex {
+            file: DRIVER,
+            error: DepexError::InvalidStack
+        })
+    );
+}
+
+#[test]
+fn guid_defined_sections_must_point_past_their_header() {
+    let inner = sections(&[section(SectionType::PE32, b"MZ image")]);
+    // A data offset of 0 used to parse the same section again, recursing until the stack overflowed.
+    for data_offset in [0, 4, 23, 24 + inner.len() as u16 + 1] {
+        let volume = volume(&[file(
+            DRIVER,
+            FileType::DRIVER,
+            &guid_defined(data_offset, &inner),
+        )]);
+        assert_eq!(
+            FirmwareVolume::parse(&volume),
+            Err(FvError::InvalidSection { file: DRIVER }),
+            "data offset {}",
+            data_offset
+        );
+    }
+}
+
+#[test]
+fn deeply_nested_sections_are_rejected() {
+    let mut nested = section(SectionType::PE32, b"MZ image");
+    for _ in 0..64 {
+        nested = guid_defined(24, &nested);
+    }
+    let volume = volume(&[file(DRIVER, FileType::DRIVER, &nested)]);
+    assert_eq!(
+        FirmwareVolume::parse(&volume),
+        Err(FvError::InvalidSection { file: DRIVER })
+    );
+}
+
+#[test]
+fn sor_drivers_are_held_until_scheduled() {
+    let sor = Depex::new(vec![Opcode::Sor, Opcode::True]).unwrap();
+    let volume = volume(&[
+        driver(DRIVER, &sor),
+        driver(OTHER_DRIVER, &Depex::from_protocols(&[])),
+    ]);
+
+    let dispatched = Rc::new(RefCell::new(Vec::new()));
+    let mut manager = ComponentManager::new();
+    let log = dispatched.clone();
+    let count = manager
+        .add_drivers_from_fv(&volume, move |driver| log.borrow_mut().push(driver.name))
+        .unwrap();
+    assert_eq!(count, 2);
+
+    manager.run();
+    assert_eq!(*dispatched.borrow(), [OTHER_DRIVER]);
+    assert_eq!(manager.component_count(), 1);
+
+    assert!(!manager.schedule(&OTHER_DRIVER));
+    assert!(manager.schedule(&DRIVER));
+    assert!(!manager.schedule(&DRIVER));
+    manager.run();
+    assert_eq!(*dispatched.borrow(), [OTHER_DRIVER, DRIVER]);
+    assert_eq!(manager.component_count(), 0);
+}
@@ -0,0 +1,60 @@
+//! Tests of the HOB list parser and of how HOBs are displayed.
+use r_efi::efi::Guid;
+use sdk::hob::{FirmwareVolume, Hob, HobList, MemoryAllocation, ResourceDescriptor};
+
+/// Builds a HOB with the given type and data after its header.
+fn hob(hob_type: u16, data: &[u8]) -> Vec<u8> {
+    let mut bytes = hob_type.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&((8 + data.len()) as u16).to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[test]
+fn resource_descriptors_are_parsed() {
+    let mut resource = vec![0; 16];
+    resource.extend_from_slice(&0u32.to_le_bytes());
+    resource.extend_from_slice(&0x7u32.to_le_bytes());
+    resource.extend_from_slice(&0x1000u64.to_le_bytes());
+    resource.extend_from_slice(&0x2000u64.to_le_bytes());
+    let bytes = [
+        hob(0x0001, &[0; 48]),
+        hob(0x0003, &resource),
+        hob(0xffff, &[]),
+    ]
+    .concat();
+
+    let hobs = HobList::parse(&bytes).unwrap();
+    assert_eq!(hobs.len(), 2);
+    let descriptor = hobs.of_type::<ResourceDescriptor>().next().unwrap();
+    assert_eq!((descriptor.start, descriptor.length), (0x1000, 0x2000));
+    assert_eq!(
+        Hob::ResourceDescriptor(*descriptor).to_string(),
+        "ResourceDescriptor(0x1000..0x3000, type: 0)"
+    );
+}
+
+#[test]
+fn ranges_past_the_address_space_are_displayed_with_their_length() {
+    let allocation = Hob::MemoryAllocation(MemoryAllocation {
+        name: Guid::from_bytes(&[0; 16]),
+        base: u64::MAX - 0xfff,
+        length: 0x2000,
+        memory_type: 4,
+    });
+    assert_eq!(
+        allocation.to_string(),
+        "MemoryAllocation(0xfffffffffffff000+0x2000, type: 4)"
+    );
+
+    let volume = Hob::FirmwareVolume(FirmwareVolume {
+        base: u64::MAX,
+        length: u64::MAX,
+        names: None,
+    });
+    assert_eq!(
+        volume.to_string(),
+        "FirmwareVolume(0xffffffffffffffff+0xffffffffffffffff)"
+    );
+}
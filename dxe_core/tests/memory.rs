@@ -0,0 +1,223 @@
+//! Tests of the memory map that backs the memory services, and of finding the allocations components leak.
+use r_efi::efi::{self, PhysicalAddress, Status};
+use sdk::{
+    component::{params::MemoryServices, Storage, StorageError},
+    memory::{MemoryMap, PAGE_SIZE},
+};
+
+use dxe_core::ComponentManager;
+
+const PAGE: u64 = PAGE_SIZE as u64;
+
+/// Returns the base address of the arena of `memory`, the start of its first range.
+fn base(memory: &MemoryMap) -> PhysicalAddress {
+    memory.descriptors()[0].physical_start
+}
+
+/// Returns the memory type and page count of each range of `memory`, in order.
+fn ranges(memory: &MemoryMap) -> Vec<(efi::MemoryType, u64)> {
+    memory
+        .descriptors()
+        .iter()
+        .map(|descriptor| (descriptor.r#type, descriptor.number_of_pages))
+        .collect()
+}
+
+#[test]
+fn pages_are_allocated_from_the_top_and_merged_when_freed() {
+    let mut memory = MemoryMap::with_arena(8);
+    let base = base(&memory);
+    let key = memory.map_key();
+
+    let top = memory
+        .allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::BOOT_SERVICES_DATA, 2, 0)
+        .unwrap();
+    assert_eq!(top, base + 6 * PAGE);
+    let below = memory
+        .allocate_pages(
+            efi::ALLOCATE_MAX_ADDRESS,
+            efi::LOADER_DATA,
+            1,
+            base + 3 * PAGE,
+        )
+        .unwrap();
+    assert_eq!(below, base + 2 * PAGE);
+    let fixed = memory
+        .allocate_pages(
+            efi::ALLOCATE_ADDRESS,
+            efi::RUNTIME_SERVICES_DATA,
+            1,
+            base + 4 * PAGE,
+        )
+        .unwrap();
+    assert_eq!(fixed, base + 4 * PAGE);
+    assert_ne!(memory.map_key(), key);
+    assert_eq!(
+        ranges(&memory),
+        [
+            (efi::CONVENTIONAL_MEMORY, 2),
+            (efi::LOADER_DATA, 1),
+            (efi::CONVENTIONAL_MEMORY, 1),
+            (efi::RUNTIME_SERVICES_DATA, 1),
+            (efi::CONVENTIONAL_MEMORY, 1),
+            (efi::BOOT_SERVICES_DATA, 2)
+        ]
+    );
+    assert_eq!(memory.allocated_pages(), 4);
+    assert_eq!(memory.available_pages(), 4);
+    let runtime = memory.descriptors()[3];
+    assert_eq!(runtime.attribute, efi::MEMORY_WB | efi::MEMORY_RUNTIME);
+
+    memory.free_pages(below, 1).unwrap();
+    memory.free_pages(fixed, 1).unwrap();
+    memory.free_pages(top, 2).unwrap();
+    assert_eq!(ranges(&memory), [(efi::CONVENTIONAL_MEMORY, 8)]);
+    assert_eq!(memory.allocations().count(), 0);
+}
+
+#[test]
+fn invalid_page_requests_are_rejected() {
+    let mut memory = MemoryMap::with_arena(4);
+    let base = base(&memory);
+    let any = efi::ALLOCATE_ANY_PAGES;
+
+    assert_eq!(
+        memory.allocate_pages(any, efi::BOOT_SERVICES_DATA, 0, 0),
+        Err(Status::INVALID_PARAMETER)
+    );
+    assert_eq!(
+        memory.allocate_pages(any, efi::CONVENTIONAL_MEMORY, 1, 0),
+        Err(Status::INVALID_PARAMETER)
+    );
+    assert_eq!(
+        memory.allocate_pages(any, efi::BOOT_SERVICES_DATA, 5, 0),
+        Err(Status::OUT_OF_RESOURCES)
+    );
+    assert_eq!(
+        memory.allocate_pages(efi::ALLOCATE_ADDRESS, efi::BOOT_SERVICES_DATA, 1, base + 1),
+        Err(Status::INVALID_PARAMETER)
+    );
+    assert_eq!(
+        memory.allocate_pages(
+            efi::ALLOCATE_ADDRESS,
+            efi::BOOT_SERVICES_DATA,
+            2,
+            base + 3 * PAGE
+        ),
+        Err(Status::NOT_FOUND)
+    );
+    assert_eq!(
+        memory.allocate_pages(efi::ALLOCATE_MAX_ADDRESS, efi::BOOT_SERVICES_DATA, 1, base),
+        Err(Status::OUT_OF_RESOURCES)
+    );
+
+    let address = memory
+        .allocate_pages(any, efi::BOOT_SERVICES_DATA, 1, 0)
+        .unwrap();
+    assert_eq!(
+        memory.allocate_pages(efi::ALLOCATE_ADDRESS, efi::BOOT_SERVICES_DATA, 1, address),
+        Err(Status::NOT_FOUND)
+    );
+    assert_eq!(memory.free_pages(base, 1), Err(Status::NOT_FOUND));
+    assert_eq!(memory.free_pages(address, 2), Err(Status::NOT_FOUND));
+    assert_eq!(
+        memory.free_pages(address + 1, 1),
+        Err(Status::INVALID_PARAMETER)
+    );
+    assert_eq!(memory.free_pages(!(PAGE - 1), 1), Err(Status::NOT_FOUND));
+    assert_eq!(
+        MemoryMap::new().allocate_pages(any, efi::BOOT_SERVICES_DATA, 1, 0),
+        Err(Status::OUT_OF_RESOURCES)
+    );
+}
+
+#[test]
+fn pools_are_zeroed_and_only_freed_as_pools() {
+    let mut memory = MemoryMap::with_arena(4);
+    let buffer = memory
+        .allocate_pool(efi::BOOT_SERVICES_DATA, PAGE_SIZE + 1)
+        .unwrap();
+    // SAFETY: The buffer lies within the arena, and is PAGE_SIZE + 1 bytes long.
+    let bytes = unsafe { std::slice::from_raw_parts(buffer.cast::<u8>(), PAGE_SIZE + 1) };
+    assert!(bytes.iter().all(|byte| *byte == 0));
+    assert_eq!(memory.allocated_pages(), 2);
+    assert_eq!(
+        memory.pools().map(|pool| pool.size).collect::<Vec<_>>(),
+        [PAGE_SIZE + 1]
+    );
+
+    assert_eq!(
+        memory.free_pages(buffer as PhysicalAddress, 2),
+        Err(Status::NOT_FOUND)
+    );
+    assert_eq!(
+        memory.free_pool(buffer.cast::<u8>().wrapping_add(8).cast()),
+        Err(Status::INVALID_PARAMETER)
+    );
+    memory.free_pool(buffer).unwrap();
+    assert_eq!(memory.free_pool(buffer), Err(Status::INVALID_PARAMETER));
+    assert_eq!(memory.allocated_pages(), 0);
+}
+
+#[test]
+fn memory_cannot_be_replaced_once_added() {
+    let mut storage = Storage::new();
+    storage.set_memory(MemoryMap::new()).unwrap();
+    storage.set_memory(MemoryMap::with_arena(2)).unwrap();
+    let address = storage
+        .memory_mut()
+        .allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::BOOT_SERVICES_DATA, 1, 0)
+        .unwrap();
+
+    assert_eq!(
+        storage.set_memory(MemoryMap::with_arena(2)),
+        Err(StorageError::MemoryAdded)
+    );
+    assert!(storage.memory().contains(address));
+    assert_eq!(storage.memory().allocated_pages(), 1);
+}
+
+#[test]
+#[should_panic(expected = "Memory has already been added.")]
+fn memory_is_only_added_once() {
+    let mut manager = ComponentManager::new();
+    manager.add_memory(1);
+    manager.add_memory(1);
+}
+
+#[test]
+fn leaked_allocations_remain_in_the_memory_map() {
+    fn allocate(memory: MemoryServices) {
+        let freed = memory.allocate_pool(efi::BOOT_SERVICES_DATA, 16).unwrap();
+        memory.allocate_pool(efi::LOADER_DATA, 32).unwrap();
+        memory
+            .allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::RUNTIME_SERVICES_DATA, 2, 0)
+            .unwrap();
+        memory.free_pool(freed).unwrap();
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_component(allocate);
+    manager.run();
+    assert_eq!(manager.component_count(), 1, "Waits until memory is added");
+
+    manager.add_memory(8);
+    manager.run();
+    assert_eq!(manager.component_count(), 0);
+
+    let memory = manager.storage().memory();
+    let pools: Vec<_> = memory
+        .pools()
+        .map(|pool| (pool.memory_type, pool.size))
+        .collect();
+    assert_eq!(pools, [(efi::LOADER_DATA, 32)]);
+    let leaked: Vec<_> = memory
+        .allocations()
+        .map(|range| (range.memory_type, range.pages))
+        .collect();
+    // Pages are allocated from the top down, so the later allocation comes first.
+    assert_eq!(
+        leaked,
+        [(efi::RUNTIME_SERVICES_DATA, 2), (efi::LOADER_DATA, 1)]
+    );
+}
@@ -0,0 +1,132 @@
+//! Tests of the [mock protocols](dxe_core::mock::protocols), consumed like the real protocols they mock.
+#![cfg(feature = "std")]
+use std::{cell::RefCell, panic, ptr};
+
+use dxe_core::{
+    mock::{
+        protocols::{MockHandle, MockRng, MOCK_SLOTS},
+        Call,
+    },
+    testing::ComponentTest,
+};
+use r_efi::{efi::Status, protocols::rng};
+use sdk::component::{params::Protocol, Storage};
+
+thread_local! {
+    static RNG: RefCell<Option<MockHandle>> = const { RefCell::new(None) };
+    static DRAWS: RefCell<Vec<(Status, [u8; 4])>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Calls `get_rng` through `rng` for four bytes, returning the status and the bytes.
+fn get_rng(rng: *mut rng::Protocol) -> (Status, [u8; 4]) {
+    let mut value = [0u8; 4];
+    let status = unsafe { ((*rng).get_rng)(rng, ptr::null_mut(), value.len(), value.as_mut_ptr()) };
+    (status, value)
+}
+
+/// Installs a mock RNG that fills buffers with a counting pattern, starting at `first`.
+fn install_rng(storage: &mut Storage, first: u8) -> MockHandle {
+    let mut rng = MockRng::new();
+    rng.on_get_rng(move |_, length, value| {
+        for i in 0..length {
+            unsafe { *value.add(i) = first + i as u8 };
+        }
+        Status::SUCCESS
+    });
+    let handle = rng.handle();
+    rng.install(storage);
+    handle
+}
+
+#[test]
+fn mocks_are_consumed_like_the_protocol() {
+    fn install(storage: &mut Storage) {
+        RNG.set(Some(install_rng(storage, 1)));
+    }
+
+    fn draw(rng: Protocol<rng::Protocol>) {
+        let this = rng.as_ptr();
+        for _ in 0..2 {
+            DRAWS.with_borrow_mut(|draws| draws.push(get_rng(this)));
+        }
+        let mut size = 0;
+        let status = (rng.get_info)(this, &mut size, ptr::null_mut());
+        assert_eq!(status, Status::UNSUPPORTED);
+    }
+
+    let mut test = ComponentTest::new();
+    test.run(install).assert_ran();
+    let rng = RNG.take().unwrap();
+    // The handle scripts the mock once it is installed, and the scripted call skips the handler.
+    rng.script("get_rng", Status::DEVICE_ERROR);
+    test.run(draw).assert_ran();
+
+    assert_eq!(
+        DRAWS.take(),
+        [
+            (Status::DEVICE_ERROR, [0; 4]),
+            (Status::SUCCESS, [1, 2, 3, 4])
+        ]
+    );
+    assert_eq!(
+        rng.calls(),
+        [
+            Call {
+                service: "get_rng",
+                status: Status::DEVICE_ERROR
+            },
+            Call {
+                service: "get_rng",
+                status: Status::SUCCESS
+            },
+            Call {
+                service: "get_info",
+                status: Status::UNSUPPORTED
+            }
+        ]
+    );
+    assert_eq!(rng.call_count("get_rng"), 2);
+    rng.clear_calls();
+    assert!(rng.calls().is_empty());
+}
+
+#[test]
+fn slots_are_reused_once_a_mock_is_dropped() {
+    let mut storage = Storage::new();
+    let mut handles = Vec::new();
+    for index in 0..MOCK_SLOTS {
+        let first = index as u8 * 10;
+        install_rng(&mut storage, first);
+        handles.push(storage.protocols().last().unwrap().handle);
+    }
+    let result = panic::catch_unwind(MockRng::new);
+    let message = *result.err().unwrap().downcast::<String>().unwrap();
+    assert_eq!(
+        message,
+        format!(
+            "At most {} MockRng mocks can exist at the same time on a thread.",
+            MOCK_SLOTS
+        )
+    );
+
+    // Uninstalling a mock drops it, freeing its slot for a new mock, whose calls reach its own handler.
+    let freed = handles.remove(2);
+    let interface = storage.handle_protocol(freed, &rng::PROTOCOL_GUID).unwrap();
+    storage
+        .uninstall_protocol_interface(freed, &rng::PROTOCOL_GUID, interface)
+        .unwrap();
+    let reused = install_rng(&mut storage, 100);
+    handles.push(storage.protocols().last().unwrap().handle);
+
+    let firsts: Vec<_> = handles
+        .iter()
+        .map(|handle| {
+            let interface = storage
+                .handle_protocol(*handle, &rng::PROTOCOL_GUID)
+                .unwrap();
+            get_rng(interface.cast()).1[0]
+        })
+        .collect();
+    assert_eq!(firsts, [0, 10, 30, 40, 50, 60, 70, 100]);
+    assert_eq!(reused.call_count("get_rng"), 1);
+}
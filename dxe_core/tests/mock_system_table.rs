@@ -0,0 +1,241 @@
+//! Tests of the [MockSystemTable], driven through the raw tables it hands out, like the code under test would.
+#![cfg(feature = "std")]
+use std::ptr;
+
+use dxe_core::{
+    mock::{Call, MockSystemTable},
+    ComponentManager,
+};
+use r_efi::efi::{self, Guid, Handle, Status};
+use sdk::component::Storage;
+
+const TEST_GUID: Guid =
+    Guid::from_fields(0x2f6b_1c47, 0x93ad, 0x4e1b, 0x8c, 0x05, &[1, 2, 3, 4, 5, 6]);
+
+/// Installs a protocol with a null interface under [TEST_GUID] on a new handle, through `boot_services`.
+fn install(boot_services: *mut efi::BootServices) -> Status {
+    let mut handle: Handle = ptr::null_mut();
+    let mut guid = TEST_GUID;
+    unsafe {
+        ((*boot_services).install_protocol_interface)(
+            &mut handle,
+            &mut guid,
+            efi::NATIVE_INTERFACE,
+            ptr::null_mut(),
+        )
+    }
+}
+
+/// Writes `text` to `con_out`, returning the status of OutputString.
+fn output(con_out: *mut efi::protocols::simple_text_output::Protocol, text: &str) -> Status {
+    let mut text: Vec<u16> = text.encode_utf16().chain([0]).collect();
+    unsafe { ((*con_out).output_string)(con_out, text.as_mut_ptr()) }
+}
+
+#[test]
+fn calls_are_recorded_in_order() {
+    let mut mock = MockSystemTable::new(Storage::new());
+    let system_table = mock.system_table();
+    let boot_services = unsafe { (*system_table).boot_services };
+
+    assert_eq!(install(boot_services), Status::SUCCESS);
+    let mut guid = TEST_GUID;
+    let mut interface = ptr::null_mut();
+    let status =
+        unsafe { ((*boot_services).locate_protocol)(&mut guid, ptr::null_mut(), &mut interface) };
+    assert_eq!(status, Status::SUCCESS);
+    let status = unsafe {
+        ((*boot_services).load_image)(
+            efi::Boolean::FALSE,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+        )
+    };
+    assert_eq!(status, Status::UNSUPPORTED);
+
+    assert_eq!(
+        mock.calls(),
+        [
+            Call {
+                service: "install_protocol_interface",
+                status: Status::SUCCESS
+            },
+            Call {
+                service: "locate_protocol",
+                status: Status::SUCCESS
+            },
+            Call {
+                service: "load_image",
+                status: Status::UNSUPPORTED
+            },
+        ]
+    );
+    assert_eq!(mock.call_count("locate_protocol"), 1);
+    mock.clear_calls();
+    assert!(mock.calls().is_empty());
+}
+
+#[test]
+fn scripted_statuses_replace_the_service() {
+    let mut mock = MockSystemTable::new(Storage::new());
+    mock.script("install_protocol_interface", Status::OUT_OF_RESOURCES);
+    mock.script("install_protocol_interface", Status::DEVICE_ERROR);
+    mock.script("get_time", Status::SUCCESS);
+    let system_table = mock.system_table();
+    let boot_services = unsafe { (*system_table).boot_services };
+    let runtime_services = unsafe { (*system_table).runtime_services };
+
+    // Scripted statuses are returned in order, without executing the service.
+    assert_eq!(install(boot_services), Status::OUT_OF_RESOURCES);
+    assert_eq!(install(boot_services), Status::DEVICE_ERROR);
+    assert!(!mock.storage().contains_protocol(&TEST_GUID));
+    assert_eq!(install(boot_services), Status::SUCCESS);
+    assert!(mock.storage().contains_protocol(&TEST_GUID));
+
+    // Unimplemented runtime services return UNSUPPORTED unless scripted.
+    let mut time = efi::Time::default();
+    let status = unsafe { ((*runtime_services).get_time)(&mut time, ptr::null_mut()) };
+    assert_eq!(status, Status::SUCCESS);
+    let status = unsafe { ((*runtime_services).get_time)(&mut time, ptr::null_mut()) };
+    assert_eq!(status, Status::UNSUPPORTED);
+    assert_eq!(mock.call_count("get_time"), 2);
+}
+
+#[test]
+fn con_out_collects_output() {
+    let mut mock = MockSystemTable::new(Storage::new());
+    let system_table = mock.system_table();
+    let (con_out, std_err) = unsafe { ((*system_table).con_out, (*system_table).std_err) };
+
+    assert_eq!(output(con_out, "Hello, "), Status::SUCCESS);
+    assert_eq!(output(std_err, "world!\r\n"), Status::SUCCESS);
+    mock.script("output_string", Status::DEVICE_ERROR);
+    assert_eq!(output(con_out, "Dropped"), Status::DEVICE_ERROR);
+
+    assert_eq!(mock.con_out(), "Hello, world!\r\n");
+    assert_eq!(mock.call_count("output_string"), 3);
+}
+
+#[test]
+fn storage_is_unbound_while_borrowed() {
+    let mut mock = MockSystemTable::new(Storage::new());
+    let system_table = mock.system_table();
+    let boot_services = unsafe { (*system_table).boot_services };
+
+    {
+        let storage = mock.storage();
+        let nested = mock.storage();
+        assert_eq!(install(boot_services), Status::NOT_READY);
+        drop(nested);
+        // The storage stays unbound until the last reference is dropped.
+        assert_eq!(install(boot_services), Status::NOT_READY);
+        assert!(!storage.contains_protocol(&TEST_GUID));
+    }
+    assert_eq!(install(boot_services), Status::SUCCESS);
+
+    mock.storage_mut().add_config(1u32).unwrap();
+    assert_eq!(install(boot_services), Status::SUCCESS);
+    assert_eq!(mock.storage().protocols().count(), 2);
+}
+
+#[test]
+fn manager_rebinds_the_mock_storage() {
+    let mut mock = MockSystemTable::new(Storage::new());
+    let mut manager = ComponentManager::new();
+    let _ = manager.boot_services();
+    manager.add_component(|| {});
+    manager.run();
+
+    // The manager bound its own storage while running, and the mock's storage again afterwards.
+    let system_table = mock.system_table();
+    assert_eq!(
+        install(unsafe { (*system_table).boot_services }),
+        Status::SUCCESS
+    );
+    assert!(mock.storage().contains_protocol(&TEST_GUID));
+    assert!(!manager.storage().contains_protocol(&TEST_GUID));
+}
+
+/// Returns a GUID distinct for each `index`.
+fn table_guid(index: u32) -> Guid {
+    Guid::from_fields(index, 0x5e21, 0x4c9a, 0xb3, 0x7d, &[1, 2, 3, 4, 5, 6])
+}
+
+/// Returns the configuration tables the system table points at, read like the code under test would.
+fn configuration_tables(system_table: *mut efi::SystemTable) -> Vec<(Guid, usize)> {
+    let (count, tables) = unsafe {
+        (
+            (*system_table).number_of_table_entries,
+            (*system_table).configuration_table,
+        )
+    };
+    if count == 0 {
+        return Vec::new();
+    }
+    unsafe { std::slice::from_raw_parts(tables, count) }
+        .iter()
+        .map(|table| (table.vendor_guid, table.vendor_table as usize))
+        .collect()
+}
+
+#[test]
+fn configuration_tables_stay_valid_on_every_install_path() {
+    fn install_directly(storage: &mut Storage) {
+        storage
+            .install_configuration_table(&table_guid(100), 100 as *mut _)
+            .unwrap();
+    }
+
+    let mut mock = MockSystemTable::new(Storage::new());
+    let system_table = mock.system_table();
+    let boot_services = unsafe { (*system_table).boot_services };
+    let mut expected = Vec::new();
+
+    // Installing through the boot services grows the tables, which must not leave the system table dangling.
+    let mut first = ptr::null_mut();
+    for index in 0..32 {
+        let mut guid = table_guid(index);
+        let status = unsafe {
+            ((*boot_services).install_configuration_table)(
+                &mut guid,
+                (index as usize + 1) as *mut _,
+            )
+        };
+        assert_eq!(status, Status::SUCCESS);
+        expected.push((guid, index as usize + 1));
+        assert_eq!(configuration_tables(system_table), expected);
+        if index == 0 {
+            first = unsafe { (*system_table).configuration_table };
+        }
+    }
+    // A pointer read before remains readable, if stale.
+    assert_eq!(unsafe { (*first).vendor_guid }, table_guid(0));
+
+    // Tables installed directly in the storage are published once the reference is dropped.
+    mock.storage_mut()
+        .install_configuration_table(&table_guid(32), 33 as *mut _)
+        .unwrap();
+    expected.push((table_guid(32), 33));
+    assert_eq!(configuration_tables(system_table), expected);
+
+    // A manager binds its own storage while running, and the system table shows the mock's tables again afterwards.
+    let mut manager = ComponentManager::new();
+    let _ = manager.boot_services();
+    manager.add_component(install_directly);
+    manager.run();
+    assert_eq!(
+        manager.storage().get_configuration_table(&table_guid(100)),
+        Some(100 as *mut _)
+    );
+    assert_eq!(configuration_tables(system_table), expected);
+
+    assert_eq!(
+        mock.install_configuration_table(table_guid(0), ptr::null_mut()),
+        Status::SUCCESS
+    );
+    expected.remove(0);
+    assert_eq!(configuration_tables(system_table), expected);
+}
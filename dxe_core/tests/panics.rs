@@ -0,0 +1,89 @@
+//! Tests of how the [ComponentManager] isolates components that panic, which it only does with the `std` feature.
+#![cfg(feature = "std")]
+use std::{
+    mem,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use dxe_core::{ComponentError, ComponentManager};
+use r_efi::efi;
+use sdk::component::params::ConfigMut;
+
+/// The Boot Services table of a [ComponentManager], for components to call through.
+#[derive(Copy, Clone)]
+struct BootServices(*mut efi::BootServices);
+
+// SAFETY: The table is only called on the thread running the manager.
+unsafe impl Send for BootServices {}
+unsafe impl Sync for BootServices {}
+
+impl BootServices {
+    fn raise_tpl(self, new_tpl: efi::Tpl) -> efi::Tpl {
+        unsafe { ((*self.0).raise_tpl)(new_tpl) }
+    }
+
+    fn restore_tpl(self, old_tpl: efi::Tpl) {
+        unsafe { ((*self.0).restore_tpl)(old_tpl) }
+    }
+}
+
+#[test]
+fn panicking_components_fail_without_affecting_the_rest() {
+    static SEEN: AtomicU32 = AtomicU32::new(0);
+    static TPL: AtomicUsize = AtomicUsize::new(0);
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(0u32);
+    let boot_services = BootServices(manager.boot_services());
+
+    // Panics while holding its config and a raised task priority level.
+    manager.add_component(move |mut data: ConfigMut<u32>| {
+        *data += 1;
+        boot_services.raise_tpl(efi::TPL_NOTIFY);
+        panic!("Holding {}", *data);
+    });
+    // Leaks its borrow of the config before panicking, so unwinding does not release it.
+    manager.add_component(|mut data: ConfigMut<u32>| {
+        *data += 1;
+        mem::forget(data);
+        panic!("{}", String::from("Leaked"));
+    });
+    manager.add_component(move |mut data: ConfigMut<u32>| {
+        *data += 1;
+        SEEN.store(*data, Ordering::SeqCst);
+        let tpl = boot_services.raise_tpl(efi::TPL_CALLBACK);
+        boot_services.restore_tpl(tpl);
+        TPL.store(tpl, Ordering::SeqCst);
+    });
+    manager.run();
+
+    let failures: Vec<_> = manager
+        .failures()
+        .iter()
+        .map(|failure| failure.error.clone())
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            ComponentError::Panic("Holding 1".into()),
+            ComponentError::Panic("Leaked".into())
+        ]
+    );
+    assert_eq!(
+        manager.failures()[0].to_string(),
+        format!(
+            "Component {} failed: panicked with 'Holding 1'",
+            manager.failures()[0].component
+        )
+    );
+    // The later component borrowed the config mutably, and saw the writes made before both panics.
+    assert_eq!(SEEN.load(Ordering::SeqCst), 3);
+    // It also ran at the task priority level from before the first panic.
+    assert_eq!(TPL.load(Ordering::SeqCst), efi::TPL_APPLICATION);
+    assert_eq!(manager.component_count(), 0);
+    assert_eq!(manager.current_tpl(), efi::TPL_APPLICATION);
+
+    let id = manager.storage().config_id::<u32>().unwrap();
+    let config = manager.storage().try_get_config_untyped(id).unwrap();
+    assert_eq!(config.downcast_ref::<u32>(), Some(&3));
+}
@@ -0,0 +1,168 @@
+//! Tests of PCDs consumed by components, and of how access to them is registered.
+use std::sync::Mutex;
+
+use dxe_core::{ComponentManager, RegistrationError, Resource};
+use r_efi::efi::Guid;
+use sdk::{
+    component::{
+        params::{ConfigMut, Pcd},
+        Storage,
+    },
+    pcd::{PcdError, PcdKind, PcdToken, PcdValue},
+};
+
+const TOKEN_SPACE: Guid =
+    Guid::from_fields(0x4f2a_7c19, 0x0b6e, 0x4d3a, 0xb5, 0x82, &[1, 2, 3, 4, 5, 6]);
+
+macro_rules! pcd {
+    ($name:ident, $value:ty, $token:literal, $kind:ident, $default:expr) => {
+        struct $name;
+
+        impl PcdToken for $name {
+            type Value = $value;
+
+            fn token_space_guid() -> &'static Guid {
+                &TOKEN_SPACE
+            }
+
+            fn token_number() -> u32 {
+                $token
+            }
+
+            fn kind() -> PcdKind {
+                PcdKind::$kind
+            }
+
+            fn default_value() -> $value {
+                $default
+            }
+        }
+    };
+}
+
+pcd!(FixedPcd, u32, 1, Fixed, 1);
+pcd!(PatchablePcd, u16, 2, Patchable, 2);
+pcd!(DynamicPcd, u32, 3, Dynamic, 3);
+pcd!(OtherDynamicPcd, u32, 4, Dynamic, 4);
+pcd!(BufferPcd, Vec<u8>, 5, Dynamic, vec![0; 4]);
+// Declares the token of `DynamicPcd` with another datum type.
+pcd!(MismatchedPcd, u64, 3, Dynamic, 3);
+
+#[test]
+fn fixed_and_patchable_pcds_cannot_be_set() {
+    static RESULTS: Mutex<Vec<Result<(), PcdError>>> = Mutex::new(Vec::new());
+    fn set(mut fixed: Pcd<FixedPcd>, mut patchable: Pcd<PatchablePcd>) {
+        let mut results = RESULTS.lock().unwrap();
+        results.push(fixed.set(10));
+        results.push(patchable.set(20));
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_component(set);
+    manager.run();
+    assert_eq!(
+        *RESULTS.lock().unwrap(),
+        [Err(PcdError::ReadOnly), Err(PcdError::ReadOnly)]
+    );
+
+    // Neither can be set through the database, but the owner of the storage can patch the patchable one.
+    let mut storage = Storage::new();
+    storage.register_pcd::<FixedPcd>();
+    storage.register_pcd::<PatchablePcd>();
+    assert_eq!(
+        storage.set_pcd(&TOKEN_SPACE, 1, PcdValue::U32(10)),
+        Err(PcdError::ReadOnly)
+    );
+    assert_eq!(
+        storage.set_pcd(&TOKEN_SPACE, 2, PcdValue::U16(20)),
+        Err(PcdError::ReadOnly)
+    );
+    storage
+        .patch_pcd(&TOKEN_SPACE, 2, PcdValue::U16(20))
+        .unwrap();
+    assert_eq!(storage.get_pcd(&TOKEN_SPACE, 2), Ok(PcdValue::U16(20)));
+    assert_eq!(storage.get_pcd(&TOKEN_SPACE, 1), Ok(PcdValue::U32(1)));
+}
+
+#[test]
+fn dynamic_pcds_are_set_by_components() {
+    static RESULTS: Mutex<Vec<Result<(), PcdError>>> = Mutex::new(Vec::new());
+    static SEEN: Mutex<Vec<(u32, Vec<u8>)>> = Mutex::new(Vec::new());
+    fn set(mut value: Pcd<DynamicPcd>, mut buffer: Pcd<BufferPcd>) {
+        let mut results = RESULTS.lock().unwrap();
+        results.push(value.set(value.get() + 10));
+        results.push(buffer.set(vec![1, 2]));
+        // A pointer PCD cannot grow past its maximum size.
+        results.push(buffer.set(vec![0; 8]));
+    }
+    fn read(value: Pcd<DynamicPcd>, buffer: Pcd<BufferPcd>) {
+        SEEN.lock().unwrap().push((value.get(), buffer.get()));
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_pcd::<DynamicPcd>(5);
+    manager.add_component(set);
+    manager.add_component(read);
+    manager.run();
+    assert_eq!(
+        *RESULTS.lock().unwrap(),
+        [Ok(()), Ok(()), Err(PcdError::BufferTooSmall)]
+    );
+    assert_eq!(*SEEN.lock().unwrap(), [(15, vec![1, 2])]);
+    assert!(manager.failures().is_empty());
+}
+
+#[test]
+fn mismatched_datum_types_are_rejected_on_registration() {
+    fn mismatched(_: Pcd<MismatchedPcd>) {}
+
+    let mut manager = ComponentManager::new();
+    manager.add_pcd::<DynamicPcd>(5);
+    assert_eq!(
+        manager.try_add_component(mismatched),
+        Err(RegistrationError::PcdTypeMismatch {
+            component: core::any::type_name_of_val(&mismatched).into(),
+            param: format!("Pcd<{}>", core::any::type_name::<MismatchedPcd>()),
+        })
+    );
+    assert_eq!(manager.component_count(), 0);
+}
+
+#[test]
+fn dynamic_pcd_writers_conflict_like_config_writers() {
+    fn two_pcd_writers(_: Pcd<DynamicPcd>, _: Pcd<DynamicPcd>) {}
+    fn two_config_writers(_: ConfigMut<u32>, _: ConfigMut<u32>) {}
+    fn two_tokens(_: Pcd<DynamicPcd>, _: Pcd<OtherDynamicPcd>) {}
+    fn two_readers(_: Pcd<FixedPcd>, _: Pcd<FixedPcd>) {}
+
+    let mut manager = ComponentManager::new();
+    let pcd = format!("Pcd<{}>", core::any::type_name::<DynamicPcd>());
+    for (component, expected) in [
+        (
+            manager.try_add_component(two_pcd_writers),
+            (pcd.clone(), format!("dynamic {}", pcd), Resource::Pcd),
+        ),
+        (
+            manager.try_add_component(two_config_writers),
+            (
+                String::from("ConfigMut<u32>"),
+                String::from("ConfigMut<u32>"),
+                Resource::Config,
+            ),
+        ),
+    ] {
+        match component {
+            Err(RegistrationError::Conflict {
+                param,
+                previous,
+                resource,
+                ..
+            }) => assert_eq!((param, previous, resource), expected),
+            result => panic!("Conflicting writers registered with {:?}", result),
+        }
+    }
+
+    // Writers of different tokens, and readers of the same token, do not conflict.
+    assert_eq!(manager.try_add_component(two_tokens), Ok(()));
+    assert_eq!(manager.try_add_component(two_readers), Ok(()));
+}
@@ -0,0 +1,293 @@
+//! Tests of the PE/COFF parser and loader, on sample images built in memory, and of how images are dispatched.
+use std::{cell::RefCell, ptr, rc::Rc};
+
+use dxe_core::ComponentManager;
+use r_efi::efi::Guid;
+use sdk::{
+    component::params::Storage,
+    pecoff::{Machine, PeError, PeImage, Relocation, RelocationKind, Subsystem},
+};
+
+const PROTOCOL: Guid =
+    Guid::from_fields(0x7b3e_09d4, 0x61c2, 0x4a8f, 0x93, 0x1d, &[1, 2, 3, 4, 5, 6]);
+
+const PE_OFFSET: usize = 0x40;
+const IMAGE_BASE: u64 = 0x4000_0000;
+const ENTRY_POINT: u32 = 0x1010;
+const IMAGE_SIZE: usize = 0x3000;
+const HEADERS_SIZE: usize = 0x200;
+const TEXT_RVA: usize = 0x1000;
+const RELOC_RVA: usize = 0x2000;
+// The offset of the pointer to the entry point in .text, which is relocated.
+const POINTER: usize = 0x8;
+
+/// The format of a sample image.
+#[derive(Copy, Clone)]
+enum Format {
+    Pe32,
+    Pe32Plus,
+}
+
+/// Builds a sample image with a .text section holding a pointer to its entry point, and a .reloc section relocating
+/// that pointer, unless `relocation_type` is None.
+fn sample(format: Format, relocation_type: Option<u16>) -> Vec<u8> {
+    let (machine, magic, optional_size, directories) = match format {
+        Format::Pe32 => (Machine::I386, 0x10bu16, 224usize, 92usize),
+        Format::Pe32Plus => (Machine::X64, 0x20b, 240, 108),
+    };
+    let mut bytes = vec![0u8; 0x600];
+    bytes[..2].copy_from_slice(b"MZ");
+    put(&mut bytes, 0x3c, &(PE_OFFSET as u32).to_le_bytes());
+    bytes[PE_OFFSET..PE_OFFSET + 4].copy_from_slice(b"PE\0\0");
+
+    let coff = PE_OFFSET + 4;
+    put(&mut bytes, coff, &machine.0.to_le_bytes());
+    put(&mut bytes, coff + 2, &2u16.to_le_bytes());
+    put(&mut bytes, coff + 16, &(optional_size as u16).to_le_bytes());
+    // Relocations are stripped if the image has none.
+    let characteristics: u16 = if relocation_type.is_some() {
+        0x0002
+    } else {
+        0x0003
+    };
+    put(&mut bytes, coff + 18, &characteristics.to_le_bytes());
+
+    let optional = coff + 20;
+    put(&mut bytes, optional, &magic.to_le_bytes());
+    put(&mut bytes, optional + 16, &ENTRY_POINT.to_le_bytes());
+    match format {
+        Format::Pe32 => put(
+            &mut bytes,
+            optional + 28,
+            &(IMAGE_BASE as u32).to_le_bytes(),
+        ),
+        Format::Pe32Plus => put(&mut bytes, optional + 24, &IMAGE_BASE.to_le_bytes()),
+    }
+    put(&mut bytes, optional + 32, &0x1000u32.to_le_bytes());
+    put(&mut bytes, optional + 36, &0x200u32.to_le_bytes());
+    put(
+        &mut bytes,
+        optional + 56,
+        &(IMAGE_SIZE as u32).to_le_bytes(),
+    );
+    put(
+        &mut bytes,
+        optional + 60,
+        &(HEADERS_SIZE as u32).to_le_bytes(),
+    );
+    put(
+        &mut bytes,
+        optional + 68,
+        &Subsystem::EFI_BOOT_SERVICE_DRIVER.0.to_le_bytes(),
+    );
+    put(&mut bytes, optional + directories, &16u32.to_le_bytes());
+    if relocation_type.is_some() {
+        let directory = optional + directories + 4 + 5 * 8;
+        put(&mut bytes, directory, &(RELOC_RVA as u32).to_le_bytes());
+        put(&mut bytes, directory + 4, &12u32.to_le_bytes());
+    }
+
+    let sections = optional + optional_size;
+    let section_table = [
+        (".text", TEXT_RVA, 0x200usize),
+        (".reloc", RELOC_RVA, 0x400),
+    ];
+    for (index, (name, rva, raw_offset)) in section_table.into_iter().enumerate() {
+        let header = sections + index * 40;
+        put(&mut bytes, header, name.as_bytes());
+        put(&mut bytes, header + 8, &0x200u32.to_le_bytes());
+        put(&mut bytes, header + 12, &(rva as u32).to_le_bytes());
+        put(&mut bytes, header + 16, &0x200u32.to_le_bytes());
+        put(&mut bytes, header + 20, &(raw_offset as u32).to_le_bytes());
+    }
+
+    let target = IMAGE_BASE + ENTRY_POINT as u64;
+    match format {
+        Format::Pe32 => put(&mut bytes, 0x200 + POINTER, &(target as u32).to_le_bytes()),
+        Format::Pe32Plus => put(&mut bytes, 0x200 + POINTER, &target.to_le_bytes()),
+    }
+    if let Some(relocation_type) = relocation_type {
+        put(&mut bytes, 0x400, &(TEXT_RVA as u32).to_le_bytes());
+        put(&mut bytes, 0x404, &12u32.to_le_bytes());
+        put(
+            &mut bytes,
+            0x408,
+            &(relocation_type << 12 | POINTER as u16).to_le_bytes(),
+        );
+        // An absolute entry pads the block, and is skipped.
+        put(&mut bytes, 0x40a, &0u16.to_le_bytes());
+    }
+    bytes
+}
+
+fn put(bytes: &mut [u8], offset: usize, value: &[u8]) {
+    bytes[offset..offset + value.len()].copy_from_slice(value);
+}
+
+/// Loads `image` into a new buffer at `address`, returning the buffer and the loaded image.
+fn load(image: &PeImage, address: u64) -> Result<(Vec<u8>, u64), PeError> {
+    let mut buffer = vec![0; image.image_size()];
+    let loaded = image.load(&mut buffer, address)?;
+    assert_eq!(loaded.base, address);
+    assert_eq!(loaded.size, IMAGE_SIZE);
+    Ok((buffer, loaded.entry_point))
+}
+
+#[test]
+fn images_are_parsed_and_mapped() {
+    let image = PeImage::parse(&sample(Format::Pe32Plus, Some(10))).unwrap();
+    assert_eq!(image.machine, Machine::X64);
+    assert_eq!(image.subsystem, Subsystem::EFI_BOOT_SERVICE_DRIVER);
+    assert_eq!(image.image_base, IMAGE_BASE);
+    assert_eq!(image.entry_point, ENTRY_POINT);
+    assert_eq!(image.section_alignment, 0x1000);
+    assert_eq!(image.image_size(), IMAGE_SIZE);
+    let names: Vec<_> = image
+        .sections
+        .iter()
+        .map(|section| section.name.as_str())
+        .collect();
+    assert_eq!(names, [".text", ".reloc"]);
+    assert_eq!(image.section_at(ENTRY_POINT).unwrap().name, ".text");
+    assert_eq!(image.section_at(0x1200), None);
+    assert_eq!(
+        image.relocations(),
+        Some(
+            &[Relocation {
+                rva: (TEXT_RVA + POINTER) as u32,
+                kind: RelocationKind::Dir64
+            }][..]
+        )
+    );
+    assert_eq!(&image.image()[..2], b"MZ");
+    assert_eq!(
+        image.image()[TEXT_RVA + POINTER..TEXT_RVA + POINTER + 8],
+        (IMAGE_BASE + ENTRY_POINT as u64).to_le_bytes()
+    );
+}
+
+#[test]
+fn images_are_relocated_to_their_load_address() {
+    let image = PeImage::parse(&sample(Format::Pe32Plus, Some(10))).unwrap();
+    let (buffer, entry_point) = load(&image, IMAGE_BASE).unwrap();
+    assert_eq!(buffer, image.image());
+    assert_eq!(entry_point, IMAGE_BASE + ENTRY_POINT as u64);
+
+    let address = 0x7_0000_0000;
+    let (buffer, entry_point) = load(&image, address).unwrap();
+    assert_eq!(entry_point, address + ENTRY_POINT as u64);
+    let pointer = u64::from_le_bytes(buffer[TEXT_RVA + POINTER..][..8].try_into().unwrap());
+    assert_eq!(pointer, entry_point);
+
+    let image = PeImage::parse(&sample(Format::Pe32, Some(3))).unwrap();
+    assert_eq!(image.machine, Machine::I386);
+    let address = 0x1000_0000;
+    let (buffer, entry_point) = load(&image, address).unwrap();
+    let pointer = u32::from_le_bytes(buffer[TEXT_RVA + POINTER..][..4].try_into().unwrap());
+    assert_eq!(pointer as u64, entry_point);
+}
+
+#[test]
+fn images_without_relocations_only_load_at_their_base() {
+    let image = PeImage::parse(&sample(Format::Pe32Plus, None)).unwrap();
+    assert_eq!(image.relocations(), None);
+    assert!(load(&image, IMAGE_BASE).is_ok());
+    assert_eq!(load(&image, 0x1000), Err(PeError::RelocationsStripped));
+}
+
+#[test]
+fn images_are_only_loaded_where_they_fit() {
+    let image = PeImage::parse(&sample(Format::Pe32Plus, Some(10))).unwrap();
+    let mut buffer = vec![0; IMAGE_SIZE - 1];
+    assert_eq!(
+        image.load(&mut buffer, IMAGE_BASE),
+        Err(PeError::BufferTooSmall {
+            required: IMAGE_SIZE
+        })
+    );
+    let address = u64::MAX - 0xfff;
+    assert_eq!(load(&image, address), Err(PeError::InvalidAddress(address)));
+}
+
+#[test]
+fn malformed_images_are_rejected() {
+    let valid = sample(Format::Pe32Plus, Some(10));
+
+    assert_eq!(PeImage::parse(b"ZM"), Err(PeError::InvalidDosSignature));
+    assert_eq!(PeImage::parse(b"MZ"), Err(PeError::Truncated));
+
+    let mut signature = valid.clone();
+    signature[PE_OFFSET] = b'X';
+    assert_eq!(PeImage::parse(&signature), Err(PeError::InvalidPeSignature));
+
+    let mut magic = valid.clone();
+    put(&mut magic, PE_OFFSET + 24, &0x107u16.to_le_bytes());
+    assert_eq!(
+        PeImage::parse(&magic),
+        Err(PeError::UnsupportedFormat(0x107))
+    );
+
+    assert_eq!(PeImage::parse(&valid[..0x100]), Err(PeError::Truncated));
+
+    let mut entry_point = valid.clone();
+    put(
+        &mut entry_point,
+        PE_OFFSET + 24 + 16,
+        &(IMAGE_SIZE as u32).to_le_bytes(),
+    );
+    assert_eq!(PeImage::parse(&entry_point), Err(PeError::Truncated));
+
+    // The .reloc section is mapped past the end of the image.
+    let mut section = valid.clone();
+    put(
+        &mut section,
+        PE_OFFSET + 24 + 240 + 40 + 12,
+        &0x2f00u32.to_le_bytes(),
+    );
+    assert_eq!(
+        PeImage::parse(&section),
+        Err(PeError::InvalidSection { index: 1 })
+    );
+
+    assert_eq!(
+        PeImage::parse(&sample(Format::Pe32Plus, Some(4))),
+        Err(PeError::UnsupportedRelocation {
+            rva: (TEXT_RVA + POINTER) as u32,
+            kind: 4
+        })
+    );
+}
+
+#[test]
+fn images_wait_for_the_protocols_they_declare() {
+    fn install(storage: &mut Storage) {
+        storage
+            .install_protocol_interface(None, &PROTOCOL, ptr::null_mut())
+            .unwrap();
+    }
+
+    let image = PeImage::parse(&sample(Format::Pe32Plus, Some(10))).unwrap();
+    let loaded = Rc::new(RefCell::new(Vec::new()));
+    let mut manager = ComponentManager::new();
+    manager.add_memory(8);
+    let log = loaded.clone();
+    manager.add_image_with_protocols("Sample", image, &[PROTOCOL], move |image| {
+        log.borrow_mut().push(*image)
+    });
+    manager.run();
+    assert!(loaded.borrow().is_empty());
+    assert_eq!(manager.component_count(), 1);
+
+    manager.add_component(install);
+    manager.run();
+    assert_eq!(manager.component_count(), 0);
+    let loaded = loaded.borrow();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].entry_point, loaded[0].base + ENTRY_POINT as u64);
+    assert!(manager.storage().memory().contains(loaded[0].base));
+    // SAFETY: The image was loaded into the arena, which the manager still owns.
+    let pointer = unsafe {
+        ptr::read_unaligned((loaded[0].base as usize + TEXT_RVA + POINTER) as *const u64)
+    };
+    assert_eq!(pointer, loaded[0].entry_point);
+}
@@ -0,0 +1,365 @@
+//! Property-based tests of how the [ComponentManager] schedules components.
+//!
+//! Each case registers a random set of components against a model of the scheduler:
+//!
+//! - consumers, taking up to two `Config`, `ConfigMut` or `Protocol` parameters, and
+//! - producers, installing a protocol once an optional depex on another protocol is satisfied.
+//!
+//! The manager is run after some of the registrations and at the end, and checked against the model: every component
+//! whose dependencies can be satisfied runs exactly once, components with conflicting parameters are rejected, no
+//! config a component accesses is borrowed elsewhere in a conflicting way while it runs, and `component_count` matches
+//! the pending components. Failing cases are shrunk by proptest; set `PROPTEST_CASES` to explore more of them.
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    collections::BTreeSet,
+};
+
+use dxe_core::{ComponentManager, RegistrationError};
+use proptest::prelude::*;
+use r_efi::efi::Guid;
+use sdk::{
+    component::params::{Config, ConfigMut, Protocol, Storage},
+    depex::Depex,
+    protocol,
+};
+
+/// The number of distinct configs, and of distinct protocols, components pick from.
+const RESOURCES: usize = 3;
+
+const GUIDS: [Guid; RESOURCES] = [
+    Guid::from_fields(0x6a0e_51c2, 0x7d13, 0x4f29, 0x8b, 0x44, &[1, 0, 0, 0, 0, 0]),
+    Guid::from_fields(0x6a0e_51c2, 0x7d13, 0x4f29, 0x8b, 0x44, &[2, 0, 0, 0, 0, 0]),
+    Guid::from_fields(0x6a0e_51c2, 0x7d13, 0x4f29, 0x8b, 0x44, &[3, 0, 0, 0, 0, 0]),
+];
+
+#[derive(Default)]
+struct Value<const N: usize> {
+    count: u32,
+    // Borrowed by every component accessing the config for as long as it runs, shared for a `Config` and exclusively
+    // for a `ConfigMut`, so a conflicting access to the same config at the same time fails to borrow it.
+    borrows: RefCell<()>,
+}
+
+struct Service<const N: usize>;
+
+impl<const N: usize> protocol::Protocol for Service<N> {
+    fn guid() -> &'static Guid {
+        &GUIDS[N]
+    }
+}
+
+/// A parameter of a consumer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Param {
+    Config(usize),
+    ConfigMut(usize),
+    Protocol(usize),
+}
+
+/// A component to register.
+#[derive(Debug, Clone)]
+enum Spec {
+    /// Takes the given parameters. Every `ConfigMut` increments its config.
+    Consumer(Vec<Param>),
+    /// Installs protocol `produces`, once protocol `after` is installed, if any.
+    Producer {
+        produces: usize,
+        after: Option<usize>,
+    },
+}
+
+/// A component to register, and whether to run the manager right after registering it.
+#[derive(Debug, Clone)]
+struct Step {
+    spec: Spec,
+    run: bool,
+}
+
+#[derive(Default)]
+struct Trace {
+    /// The ids of the components that ran, in order.
+    runs: Vec<usize>,
+    /// The components that found a config they access borrowed in a conflicting way, with the parameter.
+    conflicting_borrows: Vec<(usize, Param)>,
+}
+
+thread_local! {
+    static TRACE: RefCell<Trace> = RefCell::default();
+}
+
+/// Records that component `id` ran.
+fn trace(id: usize) {
+    TRACE.with_borrow_mut(|trace| trace.runs.push(id));
+}
+
+/// A borrow of the config a parameter accesses, if any, held until the component returns.
+#[derive(Default)]
+struct Borrow<'a> {
+    _shared: Option<Ref<'a, ()>>,
+    _exclusive: Option<RefMut<'a, ()>>,
+}
+
+/// Uses a parameter the way the model expects, borrowing the config it accesses.
+trait Touch {
+    /// Returns the borrow, or None if the config is already borrowed in a conflicting way.
+    fn touch(&mut self) -> Option<Borrow<'_>>;
+}
+
+impl<const N: usize> Touch for Config<'_, Value<N>> {
+    fn touch(&mut self) -> Option<Borrow<'_>> {
+        let shared = self.borrows.try_borrow().ok()?;
+        Some(Borrow {
+            _shared: Some(shared),
+            ..Borrow::default()
+        })
+    }
+}
+
+impl<const N: usize> Touch for ConfigMut<'_, Value<N>> {
+    fn touch(&mut self) -> Option<Borrow<'_>> {
+        self.count += 1;
+        let exclusive = self.borrows.try_borrow_mut().ok()?;
+        Some(Borrow {
+            _exclusive: Some(exclusive),
+            ..Borrow::default()
+        })
+    }
+}
+
+impl<const N: usize> Touch for Protocol<'_, Service<N>> {
+    fn touch(&mut self) -> Option<Borrow<'_>> {
+        let _: &Service<N> = self;
+        Some(Borrow::default())
+    }
+}
+
+/// Records that component `id` found the config `param` accesses borrowed, if `borrow` is None.
+fn check_borrow(id: usize, param: Param, borrow: &Option<Borrow>) {
+    if borrow.is_none() {
+        TRACE.with_borrow_mut(|trace| trace.conflicting_borrows.push((id, param)));
+    }
+}
+
+/// Expands `$body` with `$t` aliased to the parameter type of `$param`.
+macro_rules! with_param {
+    ($param:expr, $t:ident => $body:expr) => {
+        match $param {
+            Param::Config(0) => {
+                type $t<'a> = Config<'a, Value<0>>;
+                $body
+            }
+            Param::Config(1) => {
+                type $t<'a> = Config<'a, Value<1>>;
+                $body
+            }
+            Param::Config(_) => {
+                type $t<'a> = Config<'a, Value<2>>;
+                $body
+            }
+            Param::ConfigMut(0) => {
+                type $t<'a> = ConfigMut<'a, Value<0>>;
+                $body
+            }
+            Param::ConfigMut(1) => {
+                type $t<'a> = ConfigMut<'a, Value<1>>;
+                $body
+            }
+            Param::ConfigMut(_) => {
+                type $t<'a> = ConfigMut<'a, Value<2>>;
+                $body
+            }
+            Param::Protocol(0) => {
+                type $t<'a> = Protocol<'a, Service<0>>;
+                $body
+            }
+            Param::Protocol(1) => {
+                type $t<'a> = Protocol<'a, Service<1>>;
+                $body
+            }
+            Param::Protocol(_) => {
+                type $t<'a> = Protocol<'a, Service<2>>;
+                $body
+            }
+        }
+    };
+}
+
+/// Registers the component `id` described by `spec`.
+fn register(
+    manager: &mut ComponentManager,
+    id: usize,
+    spec: &Spec,
+) -> Result<(), RegistrationError> {
+    match spec {
+        Spec::Consumer(params) => match params.as_slice() {
+            [] => manager.try_add_component(move || trace(id)),
+            &[pa] => with_param!(pa, A => manager.try_add_component(move |mut a: A<'_>| {
+                trace(id);
+                check_borrow(id, pa, &a.touch());
+            })),
+            &[pa, pb] => with_param!(pa, A => with_param!(pb, B => manager.try_add_component(
+                move |mut a: A<'_>, mut b: B<'_>| {
+                    trace(id);
+                    // Both borrows are held at once, as the component holds both parameters.
+                    let a = a.touch();
+                    let b = b.touch();
+                    check_borrow(id, pa, &a);
+                    check_borrow(id, pb, &b);
+                }
+            ))),
+            _ => unreachable!("Consumers take at most two parameters"),
+        },
+        Spec::Producer { produces, after } => {
+            let produces = *produces;
+            let producer = move |storage: &mut Storage| {
+                trace(id);
+                match produces {
+                    0 => storage.add_protocol(Service::<0>),
+                    1 => storage.add_protocol(Service::<1>),
+                    _ => storage.add_protocol(Service::<2>),
+                };
+            };
+            match after {
+                Some(after) => manager
+                    .add_component_with_depex(producer, Depex::from_protocols(&[GUIDS[*after]])),
+                None => manager.add_component(producer),
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Returns true if two parameters of the same consumer access the same config, and one of them mutably.
+fn conflicts(params: &[Param]) -> bool {
+    params.iter().enumerate().any(|(i, a)| {
+        params[i + 1..].iter().any(|b| match (a, b) {
+            (Param::ConfigMut(x), Param::Config(y) | Param::ConfigMut(y))
+            | (Param::Config(x), Param::ConfigMut(y)) => x == y,
+            _ => false,
+        })
+    })
+}
+
+/// Returns the protocols the producers eventually install.
+fn installable(steps: &[Step]) -> BTreeSet<usize> {
+    let mut installed = BTreeSet::new();
+    loop {
+        let before = installed.len();
+        for step in steps {
+            if let Spec::Producer { produces, after } = step.spec {
+                if after.is_none_or(|after| installed.contains(&after)) {
+                    installed.insert(produces);
+                }
+            }
+        }
+        if installed.len() == before {
+            return installed;
+        }
+    }
+}
+
+/// Returns true if the component can run once the `installed` protocols are.
+fn satisfiable(spec: &Spec, installed: &BTreeSet<usize>) -> bool {
+    match spec {
+        Spec::Consumer(params) => params.iter().all(|param| match param {
+            Param::Protocol(n) => installed.contains(n),
+            _ => true,
+        }),
+        Spec::Producer { after, .. } => after.is_none_or(|after| installed.contains(&after)),
+    }
+}
+
+fn config<const N: usize>(manager: &ComponentManager) -> u32 {
+    let storage = manager.storage();
+    let id = storage
+        .config_id::<Value<N>>()
+        .expect("Configs are added before any component");
+    Config::<Value<N>>::try_new(storage, id)
+        .expect("Configs are not borrowed")
+        .count
+}
+
+fn param() -> impl Strategy<Value = Param> {
+    prop_oneof![
+        (0..RESOURCES).prop_map(Param::Config),
+        (0..RESOURCES).prop_map(Param::ConfigMut),
+        (0..RESOURCES).prop_map(Param::Protocol),
+    ]
+}
+
+fn spec() -> impl Strategy<Value = Spec> {
+    prop_oneof![
+        3 => prop::collection::vec(param(), 0..=2).prop_map(Spec::Consumer),
+        1 => (0..RESOURCES, prop::option::of(0..RESOURCES)).prop_map(|(produces, after)| Spec::Producer { produces, after }),
+    ]
+}
+
+fn steps() -> impl Strategy<Value = Vec<Step>> {
+    prop::collection::vec(
+        (spec(), prop::bool::weighted(0.25)).prop_map(|(spec, run)| Step { spec, run }),
+        0..16,
+    )
+}
+
+proptest! {
+    #[test]
+    fn scheduler_matches_model(steps in steps()) {
+        TRACE.with_borrow_mut(|trace| *trace = Trace::default());
+        let mut manager = ComponentManager::new();
+        manager.add_config(Value::<0>::default());
+        manager.add_config(Value::<1>::default());
+        manager.add_config(Value::<2>::default());
+
+        let mut registered = Vec::new();
+        for (id, step) in steps.iter().enumerate() {
+            let result = register(&mut manager, id, &step.spec);
+            let conflicting = matches!(&step.spec, Spec::Consumer(params) if conflicts(params));
+            prop_assert_eq!(
+                conflicting,
+                matches!(result, Err(RegistrationError::Conflict { .. })),
+                "Component {} was {:?}", id, result
+            );
+            if result.is_ok() {
+                registered.push(id);
+            }
+            if step.run {
+                manager.run();
+            }
+            let runs = TRACE.with_borrow(|trace| trace.runs.len());
+            prop_assert_eq!(manager.component_count(), manager.pending_components().count());
+            prop_assert_eq!(manager.component_count(), registered.len() - runs);
+        }
+        manager.run();
+
+        let registered_steps: Vec<Step> = registered.iter().map(|&id| steps[id].clone()).collect();
+        let installed = installable(&registered_steps);
+        let expected: BTreeSet<usize> = registered
+            .iter()
+            .copied()
+            .filter(|&id| satisfiable(&steps[id].spec, &installed))
+            .collect();
+        let trace = TRACE.with(|trace| trace.take());
+        let ran: BTreeSet<usize> = trace.runs.iter().copied().collect();
+
+        prop_assert_eq!(trace.runs.len(), ran.len(), "A component ran twice: {:?}", trace.runs);
+        prop_assert_eq!(&ran, &expected);
+        prop_assert!(
+            trace.conflicting_borrows.is_empty(),
+            "Components found their configs borrowed: {:?}", trace.conflicting_borrows
+        );
+        prop_assert!(manager.failures().is_empty(), "{:?}", manager.failures());
+        prop_assert_eq!(manager.component_count(), registered.len() - ran.len());
+        prop_assert_eq!(manager.component_count(), manager.pending_components().count());
+
+        // Every consumer that ran incremented the configs it took mutably, and no increment was lost.
+        let increments = |n: usize| {
+            ran.iter()
+                .filter(|&&id| matches!(&steps[id].spec, Spec::Consumer(params) if params.contains(&Param::ConfigMut(n))))
+                .count() as u32
+        };
+        prop_assert_eq!(
+            [config::<0>(&manager), config::<1>(&manager), config::<2>(&manager)],
+            [increments(0), increments(1), increments(2)]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Tests of capturing the state of a manager with a snapshot, and of rerunning components after restoring it.
+use dxe_core::{ComponentError, ComponentManager};
+use r_efi::efi;
+use sdk::component::{
+    params::{Config, ConfigMut},
+    SnapshotError, StorageError,
+};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Boots(u32);
+
+/// A config that cannot be cloned.
+#[derive(Debug, Default, PartialEq)]
+struct Handoff(u32);
+
+fn boot(mut boots: ConfigMut<Boots>) {
+    boots.0 += 1;
+}
+
+/// Reads the value of config `C`, or returns None if it is not registered.
+fn config<C: 'static, T>(manager: &ComponentManager, read: impl FnOnce(&C) -> T) -> Option<T> {
+    let id = manager.storage().config_id::<C>()?;
+    let config = manager.storage().get_config_untyped(id);
+    Some(read(config.downcast_ref::<C>().unwrap()))
+}
+
+#[test]
+fn restoring_a_snapshot_reruns_the_same_boot() {
+    let mut manager = ComponentManager::new();
+    manager.add_config_cloner::<Boots>();
+    manager.add_config(Boots(0));
+    manager.add_memory(4);
+    let page = manager
+        .storage()
+        .memory_mut()
+        .allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::BOOT_SERVICES_DATA, 1, 0)
+        .unwrap();
+    // SAFETY: The page was just allocated from the arena.
+    unsafe { (page as *mut u32).write(1) };
+    let snapshot = manager.snapshot();
+
+    manager.add_component(boot);
+    manager.run();
+    assert_eq!(config(&manager, |boots: &Boots| boots.0), Some(1));
+    manager.add_config(0u64);
+    let event = manager.create_event(efi::EVT_TIMER, None).unwrap();
+    manager.raise_tpl(efi::TPL_NOTIFY);
+    manager.advance_time(100);
+    let other = {
+        let mut memory = manager.storage().memory_mut();
+        memory.free_pages(page, 1).unwrap();
+        memory
+            .allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::LOADER_DATA, 2, 0)
+            .unwrap()
+    };
+    // SAFETY: The pages were just allocated from the arena, and cover the freed page.
+    unsafe { (page as *mut u32).write(2) };
+    assert!(other <= page);
+
+    for _ in 0..2 {
+        manager.restore(&snapshot).unwrap();
+        assert_eq!(config(&manager, |boots: &Boots| boots.0), Some(0));
+        assert_eq!(manager.storage().config_id::<u64>(), None);
+        assert_eq!(
+            manager.check_event(event),
+            Err(efi::Status::INVALID_PARAMETER)
+        );
+        assert_eq!(manager.current_tpl(), efi::TPL_APPLICATION);
+        assert_eq!(manager.now(), 0);
+        assert_eq!(manager.storage().memory().allocated_pages(), 1);
+        // SAFETY: The page is allocated again.
+        assert_eq!(unsafe { (page as *const u32).read() }, 1);
+
+        // Pending components are removed, so the boot is rerun by adding them again.
+        assert_eq!(manager.component_count(), 0);
+        manager.add_component(boot);
+        manager.run();
+        assert_eq!(config(&manager, |boots: &Boots| boots.0), Some(1));
+    }
+}
+
+#[test]
+fn configs_without_a_cloner_are_shared_read_only_with_the_snapshot() {
+    fn hand_off(mut handoff: ConfigMut<Handoff>) {
+        handoff.0 += 1;
+    }
+    fn read(handoff: Config<Handoff>, mut boots: ConfigMut<Boots>) {
+        boots.0 = handoff.0;
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_config_cloner::<Boots>();
+    manager.add_config(Boots(0));
+    manager.add_config(Handoff(1));
+    let snapshot = manager.snapshot();
+
+    manager.add_component(hand_off);
+    manager.add_component(read);
+    manager.run();
+    let errors: Vec<_> = manager
+        .failures()
+        .iter()
+        .map(|failure| failure.error.clone())
+        .collect();
+    let id = manager.storage().config_id::<Handoff>().unwrap();
+    assert_eq!(
+        errors,
+        [ComponentError::Storage(StorageError::ConfigShared(id))]
+    );
+    assert_eq!(config(&manager, |boots: &Boots| boots.0), Some(1));
+
+    // Restoring shares the config again, so it stays read-only until the snapshot is dropped.
+    manager.restore(&snapshot).unwrap();
+    assert_eq!(config(&manager, |handoff: &Handoff| handoff.0), Some(1));
+    assert_eq!(
+        manager.storage().try_get_config_mut_untyped(id).err(),
+        Some(StorageError::ConfigShared(id))
+    );
+    drop(snapshot);
+    manager.add_component(hand_off);
+    manager.run();
+    assert_eq!(config(&manager, |handoff: &Handoff| handoff.0), Some(2));
+}
+
+#[test]
+fn snapshots_of_replaced_memory_cannot_be_restored() {
+    let mut manager = ComponentManager::new();
+    manager.add_config_cloner::<Boots>();
+    let before_memory = manager.snapshot();
+    manager.add_memory(2);
+    let with_memory = manager.snapshot();
+
+    // Restoring a snapshot taken before memory was added frees the arena, so memory can be added again.
+    manager.restore(&before_memory).unwrap();
+    assert!(!manager.storage().memory().has_arena());
+    manager.add_memory(4);
+    manager.add_config(Boots(1));
+
+    let result = manager.restore(&with_memory);
+    assert_eq!(result, Err(SnapshotError::MemoryReplaced));
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "The memory map was replaced since the snapshot was taken and cannot be restored."
+    );
+    // The manager is left unchanged.
+    assert_eq!(manager.storage().memory().available_pages(), 4);
+    assert_eq!(config(&manager, |boots: &Boots| boots.0), Some(1));
+}
+
+#[test]
+fn configs_frozen_is_restored() {
+    let mut manager = ComponentManager::new();
+    let unfrozen = manager.snapshot();
+    manager.freeze_configs();
+    let frozen = manager.snapshot();
+
+    manager.restore(&unfrozen).unwrap();
+    assert!(!manager.configs_frozen());
+    manager.add_config(Boots(1));
+    assert_eq!(config(&manager, |boots: &Boots| boots.0), Some(1));
+
+    manager.restore(&frozen).unwrap();
+    assert!(manager.configs_frozen());
+    assert_eq!(manager.storage().config_id::<Boots>(), None);
+}
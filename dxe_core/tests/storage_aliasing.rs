@@ -0,0 +1,586 @@
+//! Exercises every way a component can borrow the storage, so that aliasing violations are caught by Miri:
+//!
+//! ```text
+//! cargo +nightly miri test -p dxe_core --test storage_aliasing
+//! ```
+//!
+//! The tests also run, and check the expected results, under a regular `cargo test`.
+use std::{
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+#[cfg(feature = "std")]
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+
+use dxe_core::{ComponentError, ComponentManager, RegistrationError};
+use r_efi::efi::{self, Guid, Handle, Status};
+use sdk::{
+    component::params::{
+        Config, ConfigMut, Hob, MemoryServices, Pcd, Protocol, ReadConfig, Storage, StorageView,
+        UseProtocol, Variable, VariableMut, WriteConfig,
+    },
+    component::StorageError,
+    hob::{Cpu, HobList},
+    pcd::{PcdKind, PcdToken},
+    protocol,
+    variable::VariableToken,
+};
+
+const TEST_GUID: Guid =
+    Guid::from_fields(0x8d4e_3a0b, 0x11f2, 0x4c6e, 0x9a, 0x31, &[1, 2, 3, 4, 5, 6]);
+
+const SERVICE_GUID: Guid =
+    Guid::from_fields(0x8d4e_3a0b, 0x11f2, 0x4c6e, 0x9a, 0x31, &[6, 5, 4, 3, 2, 1]);
+
+struct Counter(u32);
+
+impl protocol::Protocol for Counter {
+    fn guid() -> &'static Guid {
+        &TEST_GUID
+    }
+}
+
+/// Another protocol under the GUID of [Counter], with a different layout.
+struct Impostor(u64);
+
+impl protocol::Protocol for Impostor {
+    fn guid() -> &'static Guid {
+        &TEST_GUID
+    }
+}
+
+/// The Boot Services table of a [ComponentManager], for components to call through.
+#[derive(Copy, Clone)]
+struct BootServices(*mut efi::BootServices);
+
+// SAFETY: The table is only called on the thread running the manager.
+unsafe impl Send for BootServices {}
+unsafe impl Sync for BootServices {}
+
+impl BootServices {
+    /// Installs a protocol with a null interface under [SERVICE_GUID] on a new handle.
+    fn install(self) -> Status {
+        let mut handle: Handle = ptr::null_mut();
+        let mut guid = SERVICE_GUID;
+        unsafe {
+            ((*self.0).install_protocol_interface)(
+                &mut handle,
+                &mut guid,
+                efi::NATIVE_INTERFACE,
+                ptr::null_mut(),
+            )
+        }
+    }
+
+    /// Returns the interface of the first protocol installed under `guid`, as LocateProtocol does.
+    fn locate(self, guid: &Guid) -> *mut c_void {
+        let mut guid = *guid;
+        let mut interface = ptr::null_mut();
+        let status =
+            unsafe { ((*self.0).locate_protocol)(&mut guid, ptr::null_mut(), &mut interface) };
+        assert_eq!(status, Status::SUCCESS);
+        interface
+    }
+}
+
+/// The Runtime Services table of a [ComponentManager], for components to call through.
+#[derive(Copy, Clone)]
+struct RuntimeServices(*mut efi::RuntimeServices);
+
+// SAFETY: The table is only called on the thread running the manager.
+unsafe impl Send for RuntimeServices {}
+unsafe impl Sync for RuntimeServices {}
+
+impl RuntimeServices {
+    /// Sets the variable `name` under [TEST_GUID] to `value`.
+    fn set(self, name: &str, value: u32) -> Status {
+        let mut name: Vec<u16> = name.encode_utf16().chain([0]).collect();
+        let mut guid = TEST_GUID;
+        let mut data = value.to_le_bytes();
+        unsafe {
+            ((*self.0).set_variable)(
+                name.as_mut_ptr(),
+                &mut guid,
+                efi::VARIABLE_BOOTSERVICE_ACCESS,
+                data.len(),
+                data.as_mut_ptr().cast(),
+            )
+        }
+    }
+}
+
+/// A HOB list holding the handoff table and a CPU HOB with a 36 bit memory space.
+fn hobs() -> HobList {
+    let mut bytes = vec![0u8; 56 + 16 + 8];
+    bytes[0..4].copy_from_slice(&[0x01, 0x00, 56, 0x00]);
+    bytes[56..60].copy_from_slice(&[0x06, 0x00, 16, 0x00]);
+    bytes[64] = 36;
+    bytes[72..76].copy_from_slice(&[0xff, 0xff, 8, 0x00]);
+    HobList::parse(&bytes).unwrap()
+}
+
+struct DynamicPcd;
+
+impl PcdToken for DynamicPcd {
+    type Value = u32;
+
+    fn token_space_guid() -> &'static Guid {
+        &TEST_GUID
+    }
+
+    fn token_number() -> u32 {
+        1
+    }
+
+    fn kind() -> PcdKind {
+        PcdKind::Dynamic
+    }
+
+    fn default_value() -> u32 {
+        1
+    }
+}
+
+struct FixedPcd;
+
+impl PcdToken for FixedPcd {
+    type Value = u32;
+
+    fn token_space_guid() -> &'static Guid {
+        &TEST_GUID
+    }
+
+    fn token_number() -> u32 {
+        2
+    }
+
+    fn kind() -> PcdKind {
+        PcdKind::Fixed
+    }
+
+    fn default_value() -> u32 {
+        2
+    }
+}
+
+struct First;
+
+impl VariableToken for First {
+    type Value = u32;
+
+    fn vendor_guid() -> &'static Guid {
+        &TEST_GUID
+    }
+
+    fn name() -> &'static str {
+        "First"
+    }
+
+    fn attributes() -> u32 {
+        efi::VARIABLE_BOOTSERVICE_ACCESS
+    }
+}
+
+struct Second;
+
+impl VariableToken for Second {
+    type Value = u32;
+
+    fn vendor_guid() -> &'static Guid {
+        &TEST_GUID
+    }
+
+    fn name() -> &'static str {
+        "Second"
+    }
+
+    fn attributes() -> u32 {
+        efi::VARIABLE_BOOTSERVICE_ACCESS
+    }
+}
+
+#[test]
+fn shared_and_mutable_params_coexist() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+
+    fn configs(
+        count: Config<u32>,
+        mut total: ConfigMut<u64>,
+        mut pcd: Pcd<DynamicPcd>,
+        fixed: Pcd<FixedPcd>,
+    ) {
+        *total += u64::from(*count);
+        pcd.set(pcd.get() + fixed.get()).unwrap();
+    }
+
+    fn variables(
+        first: Variable<First>,
+        mut second: VariableMut<Second>,
+        pcd: Pcd<DynamicPcd>,
+        total: Config<u64>,
+    ) {
+        let value = first.get().unwrap_or(0) + pcd.get() + *total as u32;
+        second.set(value).unwrap();
+    }
+
+    fn check(second: Variable<Second>) {
+        RESULT.store(second.get().unwrap(), Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(5u32);
+    manager.add_config(10u64);
+    manager.add_component(configs);
+    manager.run();
+    manager.add_component(variables);
+    manager.run();
+    manager.add_component(check);
+    manager.run();
+
+    // The dynamic PCD is 1 + 2, and the total 10 + 5.
+    assert_eq!(RESULT.load(Ordering::SeqCst), 18);
+    assert!(manager.failures().is_empty());
+}
+
+#[test]
+fn untracked_params_coexist() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+
+    fn install(storage: &mut Storage) {
+        storage.add_protocol(Counter(3));
+    }
+
+    fn consume(counter: Protocol<Counter>, memory: MemoryServices, mut value: ConfigMut<u32>) {
+        let buffer = memory.allocate_pool(efi::BOOT_SERVICES_DATA, 16).unwrap();
+        *value += counter.0;
+        memory.free_pool(buffer).unwrap();
+        RESULT.store(*value, Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_memory(4);
+    manager.add_config(1u32);
+    manager.add_component(consume);
+    manager.add_component(install);
+    manager.run();
+
+    assert_eq!(RESULT.load(Ordering::SeqCst), 4);
+    assert_eq!(manager.component_count(), 0);
+}
+
+#[test]
+fn storage_view_borrows_declared_resources() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+
+    fn install(storage: &mut Storage) {
+        storage.add_protocol(Counter(5));
+    }
+
+    type Declared = (ReadConfig<u32>, WriteConfig<u64>, UseProtocol<Counter>);
+
+    fn view(view: StorageView<Declared>, mut other: ConfigMut<u16>, memory: MemoryServices) {
+        let count = view.config::<u32>().unwrap();
+        let mut total = view.config_mut::<u64>().unwrap();
+        let buffer = memory.allocate_pool(efi::BOOT_SERVICES_DATA, 16).unwrap();
+        *total += u64::from(*count) + u64::from(view.protocol::<Counter>().map_or(0, |c| c.0));
+        *other += 1;
+        // A second borrow of a config the view holds mutably is rejected rather than aliased.
+        assert!(view.config::<u64>().is_err());
+        memory.free_pool(buffer).unwrap();
+        RESULT.store(*total as u32 + u32::from(*other), Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_memory(4);
+    manager.add_config(1u32);
+    manager.add_config(10u64);
+    manager.add_config(100u16);
+    manager.add_component(install);
+    manager.add_component(view);
+    manager.run();
+
+    assert_eq!(RESULT.load(Ordering::SeqCst), 117);
+    assert!(manager.failures().is_empty());
+}
+
+#[test]
+fn mutable_storage_is_exclusive() {
+    fn mutate(storage: &mut Storage) {
+        storage.add_config(7u16).unwrap();
+    }
+
+    fn after_config(_config: Config<u16>, _storage: &mut Storage) {}
+    fn after_memory(_memory: MemoryServices, _storage: &mut Storage) {}
+    fn before_protocol(_storage: &mut Storage, _counter: Protocol<Counter>) {}
+    fn before_config(_storage: &mut Storage, _config: ConfigMut<u16>) {}
+    fn shared_and_mutable(_shared: &Storage, _storage: &mut Storage) {}
+
+    let mut manager = ComponentManager::new();
+    assert!(manager.try_add_component(mutate).is_ok());
+    assert!(matches!(
+        manager.try_add_component(after_config),
+        Err(RegistrationError::StorageConflict { .. })
+    ));
+    assert!(matches!(
+        manager.try_add_component(after_memory),
+        Err(RegistrationError::StorageConflict { .. })
+    ));
+    assert!(matches!(
+        manager.try_add_component(before_protocol),
+        Err(RegistrationError::StorageConflict { .. })
+    ));
+    assert!(matches!(
+        manager.try_add_component(before_config),
+        Err(RegistrationError::Conflict { .. })
+    ));
+    assert!(matches!(
+        manager.try_add_component(shared_and_mutable),
+        Err(RegistrationError::StorageConflict { .. })
+    ));
+
+    manager.run();
+    assert_eq!(manager.component_count(), 0);
+}
+
+#[test]
+fn notify_components_borrow_storage() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+
+    fn tick(mut ticks: ConfigMut<u32>, pcd: Pcd<FixedPcd>) {
+        *ticks += pcd.get();
+    }
+
+    fn report(ticks: Config<u32>) {
+        RESULT.store(*ticks, Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(0u32);
+    let timer = manager
+        .create_event_with_component(
+            efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            None,
+            tick,
+        )
+        .unwrap();
+    manager.set_timer(timer, efi::TIMER_PERIODIC, 10).unwrap();
+    for _ in 0..3 {
+        manager.advance_time(10);
+    }
+    manager.add_component(report);
+    manager.run();
+
+    assert_eq!(RESULT.load(Ordering::SeqCst), 6);
+}
+
+#[test]
+fn protocols_are_retrieved_as_their_installed_type() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+
+    fn install(storage: &mut Storage) {
+        storage.add_protocol(Counter(3));
+    }
+
+    fn impostor(impostor: Protocol<Impostor>) {
+        RESULT.store(impostor.0 as u32, Ordering::SeqCst);
+    }
+
+    fn counter(counter: Protocol<Counter>) {
+        RESULT.store(counter.0, Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_component(install);
+    manager.add_component(impostor);
+    manager.run();
+    assert_eq!(RESULT.load(Ordering::SeqCst), 0);
+    assert!(matches!(
+        manager.failures()[0].error,
+        ComponentError::Storage(StorageError::ProtocolTypeMismatch { .. })
+    ));
+
+    // An interface installed through a raw pointer carries no type, so it is retrieved as the requested one.
+    static FOREIGN: Counter = Counter(7);
+    let mut manager = ComponentManager::new();
+    manager.add_component(|storage: &mut Storage| {
+        let interface = std::ptr::from_ref(&FOREIGN).cast_mut().cast();
+        storage
+            .install_protocol_interface(None, &TEST_GUID, interface)
+            .unwrap();
+    });
+    manager.add_component(counter);
+    manager.run();
+    assert_eq!(RESULT.load(Ordering::SeqCst), 7);
+    assert!(manager.failures().is_empty());
+}
+
+// EFI code handed the interface of a protocol installed from Rust may write through it, as may its own functions.
+#[test]
+fn protocols_installed_from_rust_are_written_through_their_interface() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+
+    fn bump(counter: Protocol<Counter>) {
+        // SAFETY: The interface is valid while the param is held, and nothing else accesses it.
+        unsafe { (*counter.as_ptr()).0 += 1 };
+    }
+
+    fn read(counter: Protocol<Counter>) {
+        RESULT.store(counter.0, Ordering::SeqCst);
+    }
+
+    let mut manager = ComponentManager::new();
+    let boot_services = BootServices(manager.boot_services());
+    manager.add_component(|storage: &mut Storage| {
+        storage.add_protocol(Counter(3));
+    });
+    manager.add_component(move || {
+        let counter = boot_services.locate(&TEST_GUID).cast::<Counter>();
+        // SAFETY: The protocol was installed as a `Counter`, and nothing else accesses it.
+        unsafe { (*counter).0 += 10 };
+    });
+    manager.add_component(bump);
+    manager.add_component(read);
+    manager.run();
+
+    assert_eq!(RESULT.load(Ordering::SeqCst), 14);
+    assert!(manager.failures().is_empty());
+}
+
+#[test]
+fn components_call_boot_services_through_the_table() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(5u32);
+    let boot_services = BootServices(manager.boot_services());
+    manager.add_component(move || assert_eq!(boot_services.install(), Status::SUCCESS));
+    manager.add_component(move |count: Config<u32>| {
+        assert_eq!(boot_services.install(), Status::SUCCESS);
+        RESULT.store(*count, Ordering::SeqCst);
+    });
+    manager.run();
+
+    assert_eq!(RESULT.load(Ordering::SeqCst), 5);
+    assert!(manager.failures().is_empty());
+    let installed = manager
+        .storage()
+        .protocols()
+        .filter(|info| *info.guid == SERVICE_GUID)
+        .count();
+    assert_eq!(installed, 2);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn managers_on_different_threads_bind_their_own_storage() {
+    let barrier = Arc::new(Barrier::new(2));
+    let threads: Vec<_> = (0..2)
+        .map(|_| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let status = Arc::new(AtomicUsize::new(0));
+                let mut manager = ComponentManager::new();
+                let boot_services = BootServices(manager.boot_services());
+                let result = status.clone();
+                manager.add_component(move || {
+                    // Both managers have bound their storage once both threads get here, and install while both
+                    // remain bound.
+                    barrier.wait();
+                    result.store(boot_services.install().as_usize(), Ordering::SeqCst);
+                    barrier.wait();
+                });
+                manager.run();
+                let installed = manager
+                    .storage()
+                    .protocols()
+                    .filter(|info| *info.guid == SERVICE_GUID)
+                    .count();
+                (status.load(Ordering::SeqCst), installed)
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), (Status::SUCCESS.as_usize(), 1));
+    }
+}
+
+#[test]
+fn components_call_services_while_holding_params() {
+    static RESULT: AtomicU32 = AtomicU32::new(0);
+    static STATUS: AtomicUsize = AtomicUsize::new(0);
+
+    fn set_first(mut first: VariableMut<First>) {
+        first.set(3).unwrap();
+    }
+
+    let mut manager = ComponentManager::new();
+    manager.add_config(5u32);
+    manager.add_memory(4);
+    manager.add_hobs(hobs());
+    let boot_services = BootServices(manager.boot_services());
+    let runtime_services = RuntimeServices(manager.runtime_services());
+    manager.add_component(set_first);
+    manager.add_component(
+        move |cpu: Hob<Cpu>,
+              mut total: ConfigMut<u32>,
+              first: Variable<First>,
+              memory: MemoryServices| {
+            let pool = memory.allocate_pool(efi::BOOT_SERVICES_DATA, 16).unwrap();
+            // Each service only borrows the part of the storage it operates on, none of which the params borrow.
+            assert_eq!(boot_services.install(), Status::SUCCESS);
+            let mut pages = 0;
+            let bs = boot_services.0;
+            unsafe {
+                let tpl = ((*bs).raise_tpl)(efi::TPL_NOTIFY);
+                ((*bs).restore_tpl)(tpl);
+                assert_eq!(
+                    ((*bs).allocate_pages)(
+                        efi::ALLOCATE_ANY_PAGES,
+                        efi::BOOT_SERVICES_DATA,
+                        1,
+                        &mut pages
+                    ),
+                    Status::SUCCESS
+                );
+                assert_eq!(((*bs).free_pages)(pages, 1), Status::SUCCESS);
+                let mut guid = SERVICE_GUID;
+                assert_eq!(
+                    ((*bs).install_configuration_table)(&mut guid, pool),
+                    Status::SUCCESS
+                );
+            }
+            // Registering a new variable keeps the entry held by the param in place, and the held one is not
+            // written while borrowed.
+            assert_eq!(runtime_services.set("Second", 4), Status::SUCCESS);
+            assert_eq!(runtime_services.set("First", 4), Status::ACCESS_DENIED);
+            memory.free_pool(pool).unwrap();
+            *total += u32::from(cpu.memory_space_size) + first.get().unwrap();
+            RESULT.store(*total, Ordering::SeqCst);
+        },
+    );
+    manager.run();
+    assert_eq!(RESULT.load(Ordering::SeqCst), 44);
+
+    // The tables are unbound while a component borrows the whole storage, and bound again afterwards.
+    manager.add_component(move |_storage: &Storage| {
+        STATUS.store(boot_services.install().as_usize(), Ordering::SeqCst);
+    });
+    manager.run();
+    assert_eq!(STATUS.load(Ordering::SeqCst), Status::NOT_READY.as_usize());
+    manager.add_component(move |_storage: &mut Storage| {
+        STATUS.store(boot_services.install().as_usize(), Ordering::SeqCst);
+    });
+    manager.run();
+    assert_eq!(STATUS.load(Ordering::SeqCst), Status::NOT_READY.as_usize());
+    manager.add_component(move || {
+        STATUS.store(boot_services.install().as_usize(), Ordering::SeqCst);
+    });
+    manager.run();
+    assert_eq!(STATUS.load(Ordering::SeqCst), Status::SUCCESS.as_usize());
+    assert!(manager.failures().is_empty());
+}
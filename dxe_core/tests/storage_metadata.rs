@@ -0,0 +1,99 @@
+//! Tests of the metadata a [Storage] lists about its contents, and of how it is displayed.
+use std::any::TypeId;
+
+use r_efi::efi::Guid;
+use sdk::{
+    component::Storage,
+    pcd::{PcdEntry, PcdKind, PcdValue},
+    protocol,
+};
+
+const COUNTER_GUID: Guid =
+    Guid::from_fields(0x6a0f_2c41, 0x3d8e, 0x4b17, 0x8c, 0x25, &[1, 2, 3, 4, 5, 6]);
+
+const FOREIGN_GUID: Guid =
+    Guid::from_fields(0x6a0f_2c41, 0x3d8e, 0x4b17, 0x8c, 0x25, &[6, 5, 4, 3, 2, 1]);
+
+struct Counter;
+
+impl protocol::Protocol for Counter {
+    fn guid() -> &'static Guid {
+        &COUNTER_GUID
+    }
+}
+
+/// Builds a storage with configs, protocols and PCDs registered out of order of their keys.
+fn storage() -> Storage {
+    let mut storage = Storage::new();
+    storage.add_config(7u32).unwrap();
+    storage.register_config::<u64>();
+    let handle = storage.add_protocol(Counter);
+    storage
+        .install_protocol_interface(Some(handle), &FOREIGN_GUID, core::ptr::null_mut())
+        .unwrap();
+    for (guid, token) in [(&FOREIGN_GUID, 5), (&COUNTER_GUID, 9), (&FOREIGN_GUID, 1)] {
+        storage.add_pcd_untyped(
+            guid,
+            token,
+            PcdEntry::new(PcdKind::Dynamic, PcdValue::U8(token as u8)),
+        );
+    }
+    storage
+}
+
+#[test]
+fn configs_and_protocols_are_listed_with_their_names_and_guids() {
+    let storage = storage();
+
+    let configs: Vec<_> = storage
+        .configs()
+        .map(|info| (info.id, info.name, info.type_id, info.initialized))
+        .collect();
+    assert_eq!(
+        configs,
+        [
+            (0, "u32", TypeId::of::<u32>(), true),
+            (1, "u64", TypeId::of::<u64>(), false)
+        ]
+    );
+
+    let protocols: Vec<_> = storage
+        .protocols()
+        .map(|info| (info.handle, *info.guid, info.name))
+        .collect();
+    let handle = protocols[0].0;
+    assert_eq!(
+        protocols,
+        [
+            (handle, COUNTER_GUID, core::any::type_name::<Counter>()),
+            (handle, FOREIGN_GUID, "<foreign>")
+        ]
+    );
+}
+
+#[test]
+fn storage_is_displayed_in_registration_order() {
+    let debug = format!("{:?}", storage());
+    // Storages built the same way are displayed the same way, regardless of how their PCDs are hashed.
+    for _ in 0..8 {
+        assert_eq!(format!("{:?}", storage()), debug);
+    }
+
+    let positions: Vec<_> = [
+        "name: \"u32\"",
+        "name: \"u64\"",
+        "6a0f2c41-3d8e-4b17-8c25-010203040506): \"storage_metadata::Counter\"",
+        "6a0f2c41-3d8e-4b17-8c25-060504030201): \"<foreign>\"",
+        "(6a0f2c41-3d8e-4b17-8c25-060504030201, 5)",
+        "(6a0f2c41-3d8e-4b17-8c25-010203040506, 9)",
+        "(6a0f2c41-3d8e-4b17-8c25-060504030201, 1)",
+    ]
+    .iter()
+    .map(|part| {
+        debug
+            .find(part)
+            .unwrap_or_else(|| panic!("{} is displayed in {}", part, debug))
+    })
+    .collect();
+    assert!(positions.is_sorted(), "{}", debug);
+}
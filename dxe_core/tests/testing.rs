@@ -0,0 +1,156 @@
+//! Tests of the [ComponentTest] harness, which components are tested with in isolation.
+#![cfg(feature = "std")]
+use std::ptr;
+
+use dxe_core::{
+    testing::{ComponentTest, InstalledProtocol, RunOutcome},
+    ComponentError, RegistrationError,
+};
+use r_efi::efi::Guid;
+use sdk::{
+    component::{
+        params::{Config, ConfigMut, Protocol, Storage},
+        StorageError,
+    },
+    protocol,
+};
+
+const FOREIGN_GUID: Guid =
+    Guid::from_fields(0x0d4e_8b71, 0x2a9c, 0x4f63, 0x85, 0x1b, &[1, 2, 3, 4, 5, 6]);
+
+#[derive(Debug, PartialEq)]
+struct Counter(u32);
+
+impl protocol::Protocol for Counter {
+    fn guid() -> &'static Guid {
+        static GUID: Guid =
+            Guid::from_fields(0x0d4e_8b71, 0x2a9c, 0x4f63, 0x85, 0x1b, &[6, 5, 4, 3, 2, 1]);
+        &GUID
+    }
+}
+
+fn increment(mut data: ConfigMut<u32>) {
+    *data += 1;
+}
+
+fn consume(counter: Protocol<Counter>, mut data: ConfigMut<u32>) {
+    *data += counter.0;
+}
+
+#[test]
+fn components_run_or_wait_on_their_parameters() {
+    let mut test = ComponentTest::new();
+    test.add_config(1u32);
+    let run = test.run(increment);
+    run.assert_ran();
+    assert_eq!(run.outcome, RunOutcome::Ran);
+    assert!(run.installed_protocols.is_empty());
+    assert_eq!(*test.config::<u32>(), 2);
+
+    let run = test.run(consume);
+    run.assert_waiting();
+    assert!(!run.ran());
+    assert_eq!(run.outcome, RunOutcome::Waiting);
+    // The waiting component was removed, so it does not run once its protocol is installed.
+    test.add_protocol(Counter(3));
+    assert_eq!(*test.config::<u32>(), 2);
+    test.run(consume).assert_ran();
+    assert_eq!(*test.config::<u32>(), 5);
+}
+
+#[test]
+fn components_that_panic_or_cannot_register_are_reported() {
+    let mut test = ComponentTest::new();
+    test.add_config(0u32);
+    let run = test.run(|_: Config<u32>| panic!("Out of counters"));
+    assert_eq!(
+        run.outcome,
+        RunOutcome::Failed(ComponentError::Panic("Out of counters".into()))
+    );
+    assert_eq!(
+        run.outcome.to_string(),
+        "failed: panicked with 'Out of counters'"
+    );
+
+    let run = test.run(|_: Config<u32>, _: ConfigMut<u32>| {});
+    assert!(matches!(
+        run.outcome,
+        RunOutcome::NotRegistered(RegistrationError::Conflict { .. })
+    ));
+    assert!(!run.ran());
+    // Neither component left anything behind, so the next one runs as usual.
+    test.run(increment).assert_ran();
+    assert_eq!(*test.config::<u32>(), 1);
+}
+
+#[test]
+fn installed_protocols_are_captured() {
+    fn install(storage: &mut Storage) {
+        storage.add_protocol(Counter(7));
+        storage
+            .install_protocol_interface(None, &FOREIGN_GUID, ptr::null_mut())
+            .unwrap();
+    }
+
+    let mut test = ComponentTest::new();
+    let seeded = test.add_protocol(Counter(1));
+    let run = test.run(install);
+    run.assert_ran().assert_installed::<Counter>();
+    let installed: Vec<_> = run
+        .installed_protocols
+        .iter()
+        .map(|InstalledProtocol { guid, name, .. }| (*guid, *name))
+        .collect();
+    assert_eq!(
+        installed,
+        [
+            (
+                *<Counter as protocol::Protocol>::guid(),
+                std::any::type_name::<Counter>()
+            ),
+            (FOREIGN_GUID, "<foreign>")
+        ]
+    );
+    assert!(run
+        .installed_protocols
+        .iter()
+        .all(|installed| installed.handle != seeded));
+
+    // Running the same component again captures only what that run installed.
+    let run = test.run(|| {});
+    assert!(!run.installed::<Counter>());
+    assert!(run.installed_protocols.is_empty());
+    assert_eq!(test.protocol::<Counter>().as_deref(), Some(&Counter(1)));
+}
+
+#[test]
+fn null_foreign_protocols_are_not_read_as_their_type() {
+    fn install_foreign(storage: &mut Storage) {
+        storage
+            .install_protocol_interface(
+                None,
+                <Counter as protocol::Protocol>::guid(),
+                ptr::null_mut(),
+            )
+            .unwrap();
+    }
+
+    let mut test = ComponentTest::new();
+    let run = test.run(install_foreign);
+    // The protocol is installed under the GUID of `Counter`, but not as a `Counter`.
+    assert!(run.installed::<Counter>());
+    assert_eq!(run.installed_protocols[0].name, "<foreign>");
+    assert!(test.protocol::<Counter>().is_none());
+    // Components consuming it fail rather than read a `Counter` from a null pointer.
+    let run = test.run(|_: Protocol<Counter>| {});
+    assert_eq!(
+        run.outcome,
+        RunOutcome::Failed(ComponentError::Storage(
+            StorageError::ProtocolTypeMismatch {
+                guid: *<Counter as protocol::Protocol>::guid(),
+                expected: std::any::type_name::<Counter>(),
+                found: "<foreign>"
+            }
+        ))
+    );
+}
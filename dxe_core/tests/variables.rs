@@ -0,0 +1,159 @@
+//! Tests of the variable store, following the rules of GetVariable, SetVariable and GetNextVariableName.
+use r_efi::efi::{self, Guid, Status};
+use sdk::variable::VariableStore;
+
+const VENDOR: Guid =
+    Guid::from_fields(0x3c1e_9a57, 0x6b2d, 0x4f80, 0x9d, 0x14, &[1, 2, 3, 4, 5, 6]);
+
+const BS: u32 = efi::VARIABLE_BOOTSERVICE_ACCESS;
+const NV_BS: u32 = efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS;
+
+#[test]
+fn variables_are_set_appended_and_deleted() {
+    let mut store = VariableStore::new();
+    store.set(&VENDOR, "Boot", NV_BS, &[1]).unwrap();
+    store
+        .set(&VENDOR, "Boot", NV_BS | efi::VARIABLE_APPEND_WRITE, &[2])
+        .unwrap();
+    assert_eq!(store.get(&VENDOR, "Boot"), Ok((NV_BS, vec![1, 2])));
+
+    // An existing variable cannot change its attributes.
+    assert_eq!(
+        store.set(&VENDOR, "Boot", BS, &[3]),
+        Err(Status::INVALID_PARAMETER)
+    );
+    // Runtime access requires boot service access.
+    assert_eq!(
+        store.set(&VENDOR, "Runtime", efi::VARIABLE_RUNTIME_ACCESS, &[1]),
+        Err(Status::INVALID_PARAMETER)
+    );
+    assert_eq!(
+        store.set(&VENDOR, "", BS, &[1]),
+        Err(Status::INVALID_PARAMETER)
+    );
+
+    // Empty data or no attributes deletes the variable, which can then be set with other attributes.
+    store.set(&VENDOR, "Boot", NV_BS, &[]).unwrap();
+    assert_eq!(store.get(&VENDOR, "Boot"), Err(Status::NOT_FOUND));
+    assert_eq!(store.contains(&VENDOR, "Boot"), Ok(false));
+    assert_eq!(store.set(&VENDOR, "Boot", 0, &[1]), Err(Status::NOT_FOUND));
+    store.set(&VENDOR, "Boot", BS, &[3]).unwrap();
+    store.set(&VENDOR, "Boot", 0, &[3]).unwrap();
+    assert_eq!(store.contains(&VENDOR, "Boot"), Ok(false));
+}
+
+#[test]
+fn names_are_enumerated_in_the_order_variables_were_added() {
+    let mut store = VariableStore::new();
+    assert_eq!(store.next_name(None), Err(Status::NOT_FOUND));
+    for name in ["First", "Second", "Third"] {
+        store.set(&VENDOR, name, BS, &[1]).unwrap();
+    }
+    store.set(&VENDOR, "Second", BS, &[]).unwrap();
+
+    let first = store.next_name(None).unwrap();
+    assert_eq!(first, (VENDOR, "First".to_string()));
+    let third = store.next_name(Some((&VENDOR, &first.1))).unwrap();
+    assert_eq!(third, (VENDOR, "Third".to_string()));
+    assert_eq!(
+        store.next_name(Some((&VENDOR, &third.1))),
+        Err(Status::NOT_FOUND)
+    );
+
+    // Enumeration cannot continue from a variable that does not exist.
+    assert_eq!(
+        store.next_name(Some((&VENDOR, "Second"))),
+        Err(Status::INVALID_PARAMETER)
+    );
+    assert_eq!(
+        store.next_name(Some((&VENDOR, "Missing"))),
+        Err(Status::INVALID_PARAMETER)
+    );
+}
+
+#[test]
+fn authenticated_variables_can_only_be_provisioned() {
+    let authenticated = NV_BS | efi::VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS;
+
+    let mut store = VariableStore::new();
+    assert_eq!(
+        store.set(&VENDOR, "db", authenticated, &[1]),
+        Err(Status::SECURITY_VIOLATION)
+    );
+    store.provision(&VENDOR, "db", authenticated, &[1]).unwrap();
+    assert_eq!(store.get(&VENDOR, "db"), Ok((authenticated, vec![1])));
+
+    // Neither writing nor deleting it without authentication is allowed.
+    assert_eq!(
+        store.set(&VENDOR, "db", NV_BS, &[2]),
+        Err(Status::SECURITY_VIOLATION)
+    );
+    assert_eq!(
+        store.set(&VENDOR, "db", 0, &[]),
+        Err(Status::SECURITY_VIOLATION)
+    );
+    assert_eq!(store.get(&VENDOR, "db"), Ok((authenticated, vec![1])));
+}
+
+#[test]
+fn non_volatile_variables_survive_a_round_trip() {
+    let mut store = VariableStore::new();
+    store.set(&VENDOR, "Boot", NV_BS, &[1, 2]).unwrap();
+    store.set(&VENDOR, "Volatile", BS, &[3]).unwrap();
+    store.set(&VENDOR, "Timeout", NV_BS, &[4]).unwrap();
+
+    let mut loaded = VariableStore::new();
+    loaded.set(&VENDOR, "Boot", BS, &[5]).unwrap();
+    assert_eq!(loaded.load(&store.to_bytes()), Ok(2));
+    assert_eq!(loaded.get(&VENDOR, "Boot"), Ok((NV_BS, vec![1, 2])));
+    assert_eq!(loaded.get(&VENDOR, "Timeout"), Ok((NV_BS, vec![4])));
+    assert_eq!(loaded.get(&VENDOR, "Volatile"), Err(Status::NOT_FOUND));
+    assert_eq!(loaded.to_bytes(), store.to_bytes());
+}
+
+#[test]
+fn corrupted_variables_are_not_loaded() {
+    let mut store = VariableStore::new();
+    store.set(&VENDOR, "Boot", NV_BS, &[1, 2]).unwrap();
+    let bytes = store.to_bytes();
+
+    let mut invalid_name = bytes.clone();
+    // The first byte of the name follows the signature, guid, attributes and name length.
+    invalid_name[4 + 16 + 4 + 4] = 0xff;
+    let mut invalid_attributes = bytes.clone();
+    invalid_attributes[4 + 16..4 + 16 + 4]
+        .copy_from_slice(&efi::VARIABLE_RUNTIME_ACCESS.to_le_bytes());
+    let corrupted = [
+        &b"SRAV"[..],
+        &bytes[..bytes.len() - 1],
+        &invalid_name,
+        &invalid_attributes,
+    ];
+
+    for bytes in corrupted {
+        let mut loaded = VariableStore::new();
+        assert_eq!(loaded.load(bytes), Err(Status::VOLUME_CORRUPTED));
+        assert_eq!(loaded.next_name(None), Err(Status::NOT_FOUND));
+    }
+}
+
+#[test]
+fn variables_borrowed_mutably_are_not_read() {
+    let mut store = VariableStore::new();
+    store.set(&VENDOR, "First", BS, &[1]).unwrap();
+    store.set(&VENDOR, "Second", BS, &[2]).unwrap();
+    let id = store.id(&VENDOR, "First").unwrap();
+
+    let entry = store.entry_mut(id);
+    assert_eq!(store.contains(&VENDOR, "First"), Err(Status::ACCESS_DENIED));
+    assert_eq!(store.contains(&VENDOR, "Second"), Ok(true));
+    assert_eq!(store.get(&VENDOR, "First"), Err(Status::ACCESS_DENIED));
+    assert_eq!(store.next_name(None), Err(Status::ACCESS_DENIED));
+    let names: Vec<_> = store.iter().map(|(_, name, _)| name).collect();
+    assert_eq!(names, ["Second"]);
+    assert!(format!("{:?}", store).contains("Second"));
+    drop(entry);
+
+    assert_eq!(store.contains(&VENDOR, "First"), Ok(true));
+    assert_eq!(store.iter().count(), 2);
+}
@@ -1,11 +1,24 @@
-use dxe_core::ComponentManager;
-use r_efi::efi::{protocols::*, Guid};
-use sdk::component::params::{Config, ConfigMut, Protocol, Storage};
+use dxe_core::{mock::protocols::MockRng, ComponentManager};
+use r_efi::efi::{protocols::*, Guid, Status};
+use sdk::{
+    component::params::{
+        Config, ConfigMut, ConfigTable, MemoryServices, Pcd, Protocol, ReadConfig, Storage,
+        StorageView, UseProtocol, VariableMut,
+    },
+    config_table::ConfigTableType,
+    depex::Depex,
+    fv::FirmwareVolume,
+    pcd::{PcdKind, PcdToken},
+    pecoff::PeImage,
+    variable::{VariableToken, GLOBAL_VARIABLE_GUID},
+};
 
+#[allow(unused)]
 trait TestService {
     fn increment(&self, v: i32) -> i32;
 }
 
+#[allow(unused)]
 trait TestService2 {
     fn decrement(&self, v: i32) -> i32;
 }
@@ -16,19 +29,20 @@ impl TestService for i32 {
     }
 }
 
-// Access conflict to the same configuration. This will panic when registered.
+// Access conflict to the same configuration. This fails to register. Marking it `#[sdk::component]` would reject it
+// at compile time instead.
 #[allow(unused)]
 fn component0(data: Config<i32>, data2: ConfigMut<i32>) {
-    log::info!("This component should have caused a panic when registered.")
+    panic!("This component should never run.")
 }
 
-// Access conflict to the same configuration. This will panic when registered.
+// Access conflict to the same configuration. This fails to register.
 #[allow(unused)]
 fn component1(data: ConfigMut<i32>, data2: ConfigMut<i32>) {
-    log::info!("This component should have caused a panic when registered.")
+    panic!("This component should never run.")
 }
 
-// Access conflict to the same configuration. This will panic when registered.
+// Access conflict to the same configuration. This fails to register.
 #[allow(unused)]
 fn component2(data: ConfigMut<i32>, data2: Config<i32>) {
     panic!("This component should never run.")
@@ -41,12 +55,22 @@ fn component3(_upd4: Protocol<udp4::Protocol>) {
 }
 
 // This component should run, as we eventually publish the protocol.
-fn component4(_rng: Protocol<rng::Protocol>) {
+fn component4(rng: Protocol<rng::Protocol>) {
     log::info!("Component 4: Access to the RNG Protocol.");
     log::info!("  This finally ran because something else registered the protocol");
+
+    let mut value = [0u8; 4];
+    let status = (rng.get_rng)(
+        rng.as_ptr(),
+        std::ptr::null_mut(),
+        value.len(),
+        value.as_mut_ptr(),
+    );
+    log::info!("  get_rng returned {:?}: {:?}", status, value);
 }
 
 // No access conflicts, even if having the same config twice is dumb
+#[sdk::component]
 fn component5(data: Config<i32>, data2: Config<i32>) {
     log::info!("Component 5: Two immutable access to the same configuration.");
     log::info!("  data: {}", *data);
@@ -62,6 +86,7 @@ fn component6(data: Config<usize>) {
 // Mutable access to a configuration allows us to update the config value. Probably
 // not a good idea, and will likely be removed. This is just to show we can have mutable
 // access to values.
+#[sdk::component]
 fn component7(mut data: ConfigMut<i32>) {
     log::info!("Component 7: Mutable access to a configuration value.");
     log::info!("  data: {}", *data);
@@ -69,6 +94,7 @@ fn component7(mut data: ConfigMut<i32>) {
     log::info!("  data after change: {}", *data);
 }
 
+#[sdk::component]
 fn component8(data: Config<i32>) {
     log::info!("Component 8: Showing that the value changed in Component 8 Stuck.");
     log::info!("  data: {}", *data);
@@ -84,36 +110,170 @@ fn component9(_storage: &Storage) {
 // We can have mutable access the entire storage object. This is not recommended as it requires
 // exclusive access to the storage object, meaning no other components can run.
 //
-// Registering a protocol, so we can trigger component 4 on the next run.
+// Registering a protocol, so we can trigger component 4 on the next run. The RNG is a mock that fills the buffer
+// with a counting pattern, as the host has no RNG hardware.
 fn component10(storage: &mut Storage) {
     log::info!("Component 10: We can mutably access the entire underlying storage object.");
     log::info!("  This grants the component exclusive access. e.g. no other components can run.");
 
-    extern "efiapi" fn get_info(
-        _: *mut rng::Protocol,
-        _: *mut usize,
-        _: *mut Guid,
-    ) -> r_efi::efi::Status {
-        todo!()
+    let mut rng = MockRng::new();
+    rng.on_get_rng(|_, length, value| {
+        for i in 0..length {
+            unsafe { *value.add(i) = i as u8 };
+        }
+        Status::SUCCESS
+    });
+    rng.install(storage);
+}
+
+const MDE_MODULE_PKG_TOKEN_SPACE_GUID: Guid = Guid::from_fields(
+    0xA1AFF049,
+    0xFDEB,
+    0x442a,
+    0xB3,
+    0x20,
+    &[0x13, 0xAB, 0x4C, 0xB7, 0x2B, 0xBC],
+);
+
+// A dynamic PCD. Components that consume it are registered with write access to it.
+struct PcdBootCounter;
+
+impl PcdToken for PcdBootCounter {
+    type Value = u32;
+
+    fn token_space_guid() -> &'static Guid {
+        &MDE_MODULE_PKG_TOKEN_SPACE_GUID
     }
 
-    extern "efiapi" fn get_rng(
-        _: *mut rng::Protocol,
-        _: *mut Guid,
-        _: usize,
-        _: *mut u8,
-    ) -> r_efi::efi::Status {
-        todo!()
+    fn token_number() -> u32 {
+        0x0001_0000
     }
 
-    let rng_prot = rng::Protocol {
-        get_info: get_info,
-        get_rng: get_rng,
-    };
-    storage.add_protocol(rng_prot);
+    fn kind() -> PcdKind {
+        PcdKind::Dynamic
+    }
+
+    fn default_value() -> u32 {
+        0
+    }
+}
+
+// Dynamic PCDs can be read and set by a component.
+fn component11(mut counter: Pcd<PcdBootCounter>) {
+    log::info!("Component 11: Access to a dynamic PCD.");
+    log::info!("  counter: {}", counter.get());
+    counter
+        .set(counter.get() + 1)
+        .expect("Dynamic PCDs can be set.");
+    log::info!("  counter after change: {}", counter.get());
+}
+
+// Legacy drivers gate on a dependency expression rather than their parameters. This one waits for the RNG protocol
+// installed by component 10, even though it never uses the protocol itself.
+fn component12() {
+    log::info!("Component 12: Ran because its depex was satisfied.");
+}
+
+// The architecturally defined boot manager timeout, in seconds.
+struct Timeout;
+
+impl VariableToken for Timeout {
+    type Value = u16;
+
+    fn vendor_guid() -> &'static Guid {
+        &GLOBAL_VARIABLE_GUID
+    }
+    fn name() -> &'static str {
+        "Timeout"
+    }
+    fn attributes() -> u32 {
+        r_efi::efi::VARIABLE_NON_VOLATILE
+            | r_efi::efi::VARIABLE_BOOTSERVICE_ACCESS
+            | r_efi::efi::VARIABLE_RUNTIME_ACCESS
+    }
+}
+
+// Variables can be read and written by a component. Setup data and boot options live in variables.
+fn component13(mut timeout: VariableMut<Timeout>) {
+    log::info!("Component 13: Access to a UEFI variable.");
+    log::info!("  timeout: {:?}", timeout.get());
+    if timeout.get().is_none() {
+        timeout.set(5).expect("Timeout can be set.");
+        log::info!("  timeout after change: {:?}", timeout.get());
+    }
+}
+
+// Components can also be event notify functions. This one runs each time a periodic timer fires.
+fn component14(data: Config<i32>) {
+    log::info!("Component 14: Notified by a timer event.");
+    log::info!("  data: {}", *data);
+}
+
+// Drivers allocate typed memory through the memory services. This one runs once memory has been added.
+fn component15(memory: MemoryServices) {
+    log::info!("Component 15: Access to the memory services.");
+    let buffer = memory
+        .allocate_pool(r_efi::efi::BOOT_SERVICES_DATA, 64)
+        .expect("Pool can be allocated.");
+    log::info!("  buffer: {:?}", buffer);
+    log::info!("  memory map: {} descriptors", memory.memory_map().0.len());
+    memory.free_pool(buffer).expect("Pool can be freed.");
+}
+
+struct BootLogo {
+    width: u32,
+    height: u32,
+}
+
+impl ConfigTableType for BootLogo {
+    fn guid() -> &'static Guid {
+        static GUID: Guid = Guid::from_fields(
+            0x6d1b4ec3,
+            0x2a55,
+            0x4b2e,
+            0x9e,
+            0x1a,
+            &[0x50, 0x17, 0x8c, 0x3d, 0x0f, 0x62],
+        );
+        &GUID
+    }
+}
+
+// Configuration tables are consumed by components once installed. This one waits for the boot logo table.
+fn component16(logo: ConfigTable<BootLogo>) {
+    log::info!("Component 16: Access to a configuration table.");
+    log::info!("  logo: {}x{}", logo.width, logo.height);
+}
+
+// Installing a configuration table signals the event group of its GUID.
+fn component17() {
+    log::info!("Component 17: Notified of a configuration table change.");
+}
+
+// A misbehaving component. Its panic is caught, and only this component is marked as failed; the config it held is
+// released for the components that run after it.
+fn component18(mut data: ConfigMut<i32>) {
+    *data += 1;
+    panic!("Component 18: Something went wrong.");
+}
+
+// A storage view looks up the resources it declares while the component runs, without exclusive access to the
+// storage. The RNG protocol may not be installed yet, so the component does not wait for it.
+fn component19(view: StorageView<(ReadConfig<i32>, UseProtocol<rng::Protocol>)>) {
+    log::info!("Component 19: Access to declared resources through a storage view.");
+    log::info!(
+        "  data: {}",
+        *view.config::<i32>().expect("Config is declared.")
+    );
+    log::info!(
+        "  rng installed: {}",
+        view.protocol::<rng::Protocol>().is_ok()
+    );
+    if let Err(err) = view.config::<u32>() {
+        log::info!("  {}", err);
+    }
 }
 
-// fn component10()
 fn main() {
     std::env::set_var("RUST_LOG", "TRACE");
     colog::init();
@@ -124,10 +284,19 @@ fn main() {
     // Will fail. This is mainly to ensure the reverse, however, that creating a component that requires a Config
     // that does not implement Default will fail.
     scheduler.add_config(10i32);
+    scheduler.add_pcd::<PcdBootCounter>(5);
+    scheduler.add_memory(256);
 
-    // scheduler.add_component(component0);
-    // scheduler.add_component(component1);
-    // scheduler.add_component(component2);
+    // Components with conflicting parameters are skipped, rather than stopping the boot.
+    for result in [
+        scheduler.try_add_component(component0),
+        scheduler.try_add_component(component1),
+        scheduler.try_add_component(component2),
+    ] {
+        if let Err(err) = result {
+            log::error!("{}", err);
+        }
+    }
     scheduler.add_component(component3);
     scheduler.add_component(component4);
     scheduler.add_component(component5);
@@ -136,6 +305,50 @@ fn main() {
     scheduler.add_component(component8);
     scheduler.add_component(component9);
     scheduler.add_component(component10);
+    scheduler.add_component(component11);
+    scheduler.add_component(component13);
+    scheduler.add_component(component15);
+    scheduler.add_component(component18);
+    scheduler.add_component(component19);
+    scheduler.add_component_with_depex(
+        component12,
+        Depex::parse_text_with("rng AND NOT FALSE", |name| {
+            (name == "rng").then_some(rng::PROTOCOL_GUID)
+        })
+        .expect("Depex is valid."),
+    );
+
+    // Drivers from a firmware volume image on disk are loaded and relocated alongside the components. Executing them is
+    // not supported on the host, so dispatching a driver only logs its entry point.
+    if let Some(path) = std::env::args().nth(1) {
+        let fv = std::fs::read(&path).expect("Firmware volume can be read.");
+        match FirmwareVolume::parse(&fv).and_then(|fv| fv.drivers()) {
+            Ok(drivers) => {
+                log::info!("Found {} drivers in {}.", drivers.len(), path);
+                for driver in drivers {
+                    let Some(image) = driver.image.as_deref().map(PeImage::parse) else {
+                        log::warn!("Driver {}: No PE32 image.", driver);
+                        continue;
+                    };
+                    match image {
+                        Ok(image) => scheduler.add_image(
+                            driver.to_string(),
+                            image,
+                            driver.depex.clone(),
+                            |loaded| {
+                                log::info!(
+                                    "Image loaded, entry point at {:#x}.",
+                                    loaded.entry_point
+                                )
+                            },
+                        ),
+                        Err(err) => log::error!("Driver {}: {}", driver, err),
+                    }
+                }
+            }
+            Err(err) => log::error!("Failed to parse {}: {}", path, err),
+        }
+    }
 
     log::info!("Components Registered: {}", scheduler.component_count());
     log::info!("");
@@ -143,6 +356,45 @@ fn main() {
     log::info!("Running Components:");
     scheduler.run();
 
+    // Time is virtual, so the one second timer only fires as the clock is advanced, in 100ns units.
+    let timer = scheduler
+        .create_event_with_component(
+            r_efi::efi::EVT_TIMER | r_efi::efi::EVT_NOTIFY_SIGNAL,
+            r_efi::efi::TPL_CALLBACK,
+            None,
+            component14,
+        )
+        .expect("Timer event can be created.");
+    scheduler
+        .set_timer(timer, r_efi::efi::TIMER_PERIODIC, 10_000_000)
+        .expect("Timer can be set.");
+    for _ in 0..3 {
+        scheduler.advance_time(10_000_000);
+    }
+    scheduler
+        .close_event(timer)
+        .expect("Timer event can be closed.");
+
+    scheduler.add_component(component16);
+    scheduler
+        .create_event_with_component(
+            r_efi::efi::EVT_NOTIFY_SIGNAL,
+            r_efi::efi::TPL_CALLBACK,
+            Some(BootLogo::guid()),
+            component17,
+        )
+        .expect("Table event can be created.");
+    let logo = Box::leak(Box::new(BootLogo {
+        width: 800,
+        height: 600,
+    }));
+    scheduler
+        .install_configuration_table(BootLogo::guid(), (logo as *mut BootLogo).cast())
+        .expect("Table can be installed.");
+    scheduler.run();
+
     log::info!("");
     log::info!("Components Not Run: {}", scheduler.component_count());
+    log::info!("Components Failed: {}", scheduler.failures().len());
+    log::debug!("{:#?}", scheduler.storage());
 }
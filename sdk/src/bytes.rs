@@ -0,0 +1,24 @@
+//! Little endian readers for the binary formats parsed by the sdk, e.g. firmware volumes, HOBs and PE images.
+//!
+//! Each reader panics if `bytes` is shorter than the value, so callers check the length first, e.g. with `get`.
+use r_efi::efi::Guid;
+
+pub(crate) fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes(bytes[..2].try_into().expect("Slice is 2 bytes"))
+}
+
+pub(crate) fn read_u24(bytes: &[u8]) -> usize {
+    bytes[0] as usize | (bytes[1] as usize) << 8 | (bytes[2] as usize) << 16
+}
+
+pub(crate) fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().expect("Slice is 4 bytes"))
+}
+
+pub(crate) fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().expect("Slice is 8 bytes"))
+}
+
+pub(crate) fn read_guid(bytes: &[u8]) -> Guid {
+    Guid::from_bytes(bytes[..16].try_into().expect("Slice is 16 bytes"))
+}
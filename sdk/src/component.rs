@@ -1,5 +1,9 @@
 pub mod params;
 /// A sparse vector that can store values at arbitrary indices.
 mod storage;
+mod validation;
 
-pub use storage::Storage;
+pub use storage::{
+    ConfigInfo, ProtocolInfo, SnapshotError, Storage, StorageError, StorageSnapshot,
+};
+pub use validation::{InvalidConfig, ValidateConfig};
@@ -1,88 +1,645 @@
 extern crate alloc;
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 use core::{
-    any::Any,
-    cell::{Ref, RefMut},
+    any::{Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
+    ffi::c_void,
     marker::PhantomData,
     ops::{Deref, DerefMut},
 };
+use r_efi::efi::{self, Status};
 
-use crate::protocol;
+use crate::{
+    config_table::ConfigTableType,
+    hob::{GuidHobData, HobList, HobType},
+    memory::MemoryMap,
+    pcd::{PcdDatum, PcdEntry, PcdError, PcdToken},
+    protocol,
+    variable::{VariableDatum, VariableEntry, VariableToken},
+};
 
 // re-export so that all possible parameters are under the sdk::component::params module.
 pub use super::Storage;
+use super::StorageError;
 
 pub struct Config<'res, T: Default + 'static> {
-    value: Ref<'res, Box<dyn Any>>,
-    _marker: PhantomData<T>,
+    value: Ref<'res, T>,
+}
+
+impl<'res, T: Default + 'static> Config<'res, T> {
+    /// Retrieves the config denoted by `id` from storage, returning an error if it cannot be borrowed or does not hold
+    /// a `T`.
+    pub fn try_new(storage: &'res Storage, id: usize) -> Result<Self, StorageError> {
+        Self::try_from_untyped(storage.try_get_config_untyped(id)?)
+    }
+
+    /// Creates the param from a borrowed config, returning an error if it does not hold a `T`.
+    pub fn try_from_untyped(value: Ref<'res, Box<dyn Any>>) -> Result<Self, StorageError> {
+        Ref::filter_map(value, |value| value.downcast_ref())
+            .map(|value| Config { value })
+            .map_err(|_| StorageError::ConfigTypeMismatch {
+                expected: core::any::type_name::<T>(),
+            })
+    }
 }
 
-impl<'res, T: Default + 'static> Deref for Config<'_, T> {
+impl<T: Default + 'static> Deref for Config<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        self.value.downcast_ref().unwrap()
+        &self.value
     }
 }
 
 impl<'res, T: Default + 'static> From<Ref<'res, Box<dyn Any>>> for Config<'res, T> {
+    /// ## Panics
+    ///
+    /// Panics if the value is not a `T`. See [Config::try_new].
     fn from(value: Ref<'res, Box<dyn Any>>) -> Self {
-        Config {
-            value,
-            _marker: PhantomData,
-        }
+        Self::try_from_untyped(value).unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 // An example of mutating Component parameters, but probably won't keep this exact implementation
 // as config should probably remain immutable.
 pub struct ConfigMut<'res, T: Default + 'static> {
-    value: RefMut<'res, Box<dyn Any>>,
-    _marker: PhantomData<T>,
+    value: RefMut<'res, T>,
+    // The storage the value was retrieved from, so that the value can be validated when dropped. It is held as a
+    // pointer, so the param does not borrow the parts of the storage other params or the service tables access.
+    origin: Option<*const Storage>,
+}
+
+impl<'res, T: Default + 'static> ConfigMut<'res, T> {
+    /// Retrieves the config denoted by `id` from storage. The value is validated when the [ConfigMut] is dropped.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the config cannot be retrieved. See [try_new](Self::try_new).
+    pub fn new(storage: &'res Storage, id: usize) -> Self {
+        Self::try_new(storage, id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves the config denoted by `id` from storage, returning an error if configs are frozen, or if it cannot be
+    /// borrowed or does not hold a `T`. The value is validated when the [ConfigMut] is dropped.
+    pub fn try_new(storage: &'res Storage, id: usize) -> Result<Self, StorageError> {
+        // SAFETY: The storage is borrowed for as long as the param.
+        unsafe { Self::try_new_raw(storage, id) }
+    }
+
+    /// Retrieves the config denoted by `id` through a pointer to the storage. See [try_new](Self::try_new).
+    ///
+    /// ## Safety
+    ///
+    /// - `storage` must point to a valid storage for `'res`, and no config may be added or removed for `'res`.
+    pub unsafe fn try_new_raw(storage: *const Storage, id: usize) -> Result<Self, StorageError> {
+        let mut config = Self::try_from_untyped(Storage::try_get_config_mut_raw(storage, id)?)?;
+        config.origin = Some(storage);
+        Ok(config)
+    }
+
+    fn try_from_untyped(value: RefMut<'res, Box<dyn Any>>) -> Result<Self, StorageError> {
+        RefMut::filter_map(value, |value| value.downcast_mut())
+            .map(|value| ConfigMut {
+                value,
+                origin: None,
+            })
+            .map_err(|_| StorageError::ConfigTypeMismatch {
+                expected: core::any::type_name::<T>(),
+            })
+    }
+}
+
+impl<T: Default + 'static> Drop for ConfigMut<'_, T> {
+    fn drop(&mut self) {
+        if let Some(storage) = self.origin {
+            // SAFETY: The storage outlives the param, as required when it was created.
+            unsafe { Storage::check_config_raw(storage, &*self.value) };
+        }
+    }
 }
 
-impl<'res, T: Default + 'static> Deref for ConfigMut<'_, T> {
+impl<T: Default + 'static> Deref for ConfigMut<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        self.value.downcast_ref().unwrap()
+        &self.value
     }
 }
 
-impl<'res, T: Default + 'static> DerefMut for ConfigMut<'_, T> {
+impl<T: Default + 'static> DerefMut for ConfigMut<'_, T> {
     fn deref_mut(&mut self) -> &mut T {
-        self.value.downcast_mut().unwrap()
+        &mut self.value
     }
 }
 
 impl<'res, T: Default + 'static> From<RefMut<'res, Box<dyn Any>>> for ConfigMut<'res, T> {
+    /// ## Panics
+    ///
+    /// Panics if the value is not a `T`. See [ConfigMut::try_new].
     fn from(value: RefMut<'res, Box<dyn Any>>) -> Self {
-        ConfigMut {
-            value,
+        Self::try_from_untyped(value).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+/// Access to the first installed instance of a protocol.
+///
+/// The interface may be written through by the functions of the protocol, so the param only holds a pointer to it.
+/// Pass [as_ptr](Self::as_ptr) as the `This` argument of those functions, rather than a pointer derived from a
+/// reference to the interface.
+pub struct Protocol<'p, T: protocol::Protocol> {
+    interface: *mut T,
+    _marker: PhantomData<&'p T>,
+}
+
+impl<'p, T: protocol::Protocol> Protocol<'p, T> {
+    /// Creates the param from a pointer to the interface.
+    ///
+    /// ## Safety
+    ///
+    /// - `interface` must point to a valid `T` for `'p`, which may be written through.
+    pub unsafe fn from_ptr(interface: *mut T) -> Self {
+        Protocol {
+            interface,
             _marker: PhantomData,
         }
     }
+
+    /// Returns the pointer to the interface, as installed.
+    pub fn as_ptr(&self) -> *mut T {
+        self.interface
+    }
 }
 
-pub struct Protocol<'p, T: protocol::Protocol> {
-    value: &'p Box<dyn Any>,
+impl<T: protocol::Protocol + 'static> Deref for Protocol<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The interface is valid for the lifetime of the param.
+        unsafe { &*self.interface }
+    }
+}
+
+/// Access to an installed system configuration table.
+pub struct ConfigTable<'t, G: ConfigTableType> {
+    value: &'t G,
+}
+
+impl<G: ConfigTableType> ConfigTable<'_, G> {
+    /// Returns the pointer to the table, as installed in the system table.
+    pub fn as_ptr(&self) -> *const G {
+        self.value
+    }
+}
+
+impl<G: ConfigTableType> Deref for ConfigTable<'_, G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        self.value
+    }
+}
+
+impl<'t, G: ConfigTableType> From<&'t G> for ConfigTable<'t, G> {
+    fn from(value: &'t G) -> Self {
+        ConfigTable { value }
+    }
+}
+
+enum PcdRef<'res> {
+    Shared(Ref<'res, PcdEntry>),
+    Exclusive(RefMut<'res, PcdEntry>),
+}
+
+/// Access to a single PCD. Dynamic PCDs are retrieved with write access, so they can be set.
+pub struct Pcd<'res, T: PcdToken> {
+    entry: PcdRef<'res>,
     _marker: PhantomData<T>,
 }
 
-impl<'p, T: protocol::Protocol + 'static> Deref for Protocol<'_, T> {
+impl<T: PcdToken> Pcd<'_, T> {
+    /// Returns the current value of the PCD.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the platform replaced the PCD with a value of a different datum type. See [try_get](Self::try_get).
+    pub fn get(&self) -> T::Value {
+        self.try_get()
+            .expect("PCD value has the datum type of its token.")
+    }
+
+    /// Returns the current value of the PCD, or an error if it does not have the datum type of `T`.
+    pub fn try_get(&self) -> Result<T::Value, PcdError> {
+        let entry = match &self.entry {
+            PcdRef::Shared(entry) => entry.value(),
+            PcdRef::Exclusive(entry) => entry.value(),
+        };
+        T::Value::from_value(entry).ok_or(PcdError::TypeMismatch)
+    }
+
+    /// Sets the value of the PCD. Only PCDs retrieved with write access can be set.
+    pub fn set(&mut self, value: T::Value) -> Result<(), PcdError> {
+        match &mut self.entry {
+            PcdRef::Shared(_) => Err(PcdError::ReadOnly),
+            PcdRef::Exclusive(entry) => entry.set(value.into_value()),
+        }
+    }
+}
+
+impl<'res, T: PcdToken> From<Ref<'res, PcdEntry>> for Pcd<'res, T> {
+    fn from(value: Ref<'res, PcdEntry>) -> Self {
+        Pcd {
+            entry: PcdRef::Shared(value),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'res, T: PcdToken> From<RefMut<'res, PcdEntry>> for Pcd<'res, T> {
+    fn from(value: RefMut<'res, PcdEntry>) -> Self {
+        Pcd {
+            entry: PcdRef::Exclusive(value),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Access to the HOBs of type `T` handed off from PEI. Dereferences to the first such HOB.
+pub struct Hob<'h, T: HobType> {
+    hobs: &'h HobList,
+    first: &'h T,
+}
+
+impl<'h, T: HobType> Hob<'h, T> {
+    /// Creates the param from a HOB list, returning None if it has no HOB of type `T`.
+    pub fn new(hobs: &'h HobList) -> Option<Self> {
+        let first = hobs.of_type().next()?;
+        Some(Hob { hobs, first })
+    }
+
+    /// Returns all HOBs of type `T`, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &'h T> {
+        self.hobs.of_type()
+    }
+}
+
+impl<T: HobType> Deref for Hob<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        self.value.downcast_ref().unwrap()
+        self.first
     }
 }
 
-impl<'p, P: protocol::Protocol + 'static> From<&'p Box<dyn Any>> for Protocol<'p, P> {
-    fn from(value: &'p Box<dyn Any>) -> Self {
-        Protocol {
-            value,
+/// The typed data of the first GUID extension HOB named by `G`.
+pub struct GuidHob<'h, G: GuidHobData> {
+    value: G,
+    data: &'h [u8],
+}
+
+impl<'h, G: GuidHobData> GuidHob<'h, G> {
+    /// Creates the param from a HOB list, returning None if the HOB does not exist or cannot be parsed.
+    pub fn new(hobs: &'h HobList) -> Option<Self> {
+        let data = hobs.guid_hobs(G::guid()).next()?;
+        Some(GuidHob {
+            value: G::parse(data)?,
+            data,
+        })
+    }
+
+    /// Returns the raw data of the HOB.
+    pub fn data(&self) -> &'h [u8] {
+        self.data
+    }
+}
+
+impl<G: GuidHobData> Deref for GuidHob<'_, G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.value
+    }
+}
+
+/// Read access to a single variable.
+pub struct Variable<'res, T: VariableToken> {
+    entry: Ref<'res, VariableEntry>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: VariableToken> Variable<'_, T> {
+    /// Returns the value of the variable, or None if it does not exist or its data is malformed.
+    pub fn get(&self) -> Option<T::Value> {
+        T::Value::from_bytes(self.entry.data()?)
+    }
+
+    /// Returns the attributes of the variable, or zero if it does not exist.
+    pub fn attributes(&self) -> u32 {
+        self.entry.attributes()
+    }
+}
+
+impl<'res, T: VariableToken> From<Ref<'res, VariableEntry>> for Variable<'res, T> {
+    fn from(entry: Ref<'res, VariableEntry>) -> Self {
+        Variable {
+            entry,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Read and write access to a single variable.
+pub struct VariableMut<'res, T: VariableToken> {
+    entry: RefMut<'res, VariableEntry>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: VariableToken> VariableMut<'_, T> {
+    /// Returns the value of the variable, or None if it does not exist or its data is malformed.
+    pub fn get(&self) -> Option<T::Value> {
+        T::Value::from_bytes(self.entry.data()?)
+    }
+
+    /// Returns the attributes of the variable, or zero if it does not exist.
+    pub fn attributes(&self) -> u32 {
+        self.entry.attributes()
+    }
+
+    /// Sets the variable with the attributes declared by `T`, following the rules of SetVariable.
+    pub fn set(&mut self, value: T::Value) -> Result<(), Status> {
+        self.entry.set(T::attributes(), &value.to_bytes())
+    }
+
+    /// Deletes the variable.
+    pub fn delete(&mut self) -> Result<(), Status> {
+        self.entry.set(0, &[])
+    }
+}
+
+impl<'res, T: VariableToken> From<RefMut<'res, VariableEntry>> for VariableMut<'res, T> {
+    fn from(entry: RefMut<'res, VariableEntry>) -> Self {
+        VariableMut {
+            entry,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Access to the memory allocation services.
+///
+/// The memory map is only borrowed for the duration of each call, so any number of components can allocate memory.
+pub struct MemoryServices<'res> {
+    memory: &'res RefCell<MemoryMap>,
+}
+
+impl<'res> MemoryServices<'res> {
+    pub fn new(memory: &'res RefCell<MemoryMap>) -> Self {
+        MemoryServices { memory }
+    }
+
+    /// Allocates pages of `memory_type`, following the rules of AllocatePages. Returns the allocated address.
+    pub fn allocate_pages(
+        &self,
+        allocate_type: efi::AllocateType,
+        memory_type: efi::MemoryType,
+        pages: usize,
+        address: efi::PhysicalAddress,
+    ) -> Result<efi::PhysicalAddress, Status> {
+        self.memory
+            .borrow_mut()
+            .allocate_pages(allocate_type, memory_type, pages, address)
+    }
+
+    /// Frees pages, following the rules of FreePages.
+    pub fn free_pages(&self, address: efi::PhysicalAddress, pages: usize) -> Result<(), Status> {
+        self.memory.borrow_mut().free_pages(address, pages)
+    }
+
+    /// Allocates a pool buffer of `memory_type`, following the rules of AllocatePool.
+    pub fn allocate_pool(
+        &self,
+        memory_type: efi::MemoryType,
+        size: usize,
+    ) -> Result<*mut c_void, Status> {
+        self.memory.borrow_mut().allocate_pool(memory_type, size)
+    }
+
+    /// Frees a pool buffer, following the rules of FreePool.
+    pub fn free_pool(&self, buffer: *mut c_void) -> Result<(), Status> {
+        self.memory.borrow_mut().free_pool(buffer)
+    }
+
+    /// Returns the memory map and its key, as GetMemoryMap does.
+    pub fn memory_map(&self) -> (Vec<efi::MemoryDescriptor>, usize) {
+        let memory = self.memory.borrow();
+        (memory.descriptors(), memory.map_key())
+    }
+}
+
+/// A resource declared by a [StorageView], reported when the component is initialized so its access can be
+/// registered.
+#[derive(Debug, Copy, Clone)]
+pub enum ViewDeclaration {
+    /// A config, accessed mutably if `writable`.
+    Config {
+        /// The type name of the config.
+        name: &'static str,
+        /// True if the config can be accessed mutably.
+        writable: bool,
+        /// Registers the config in storage, returning its id, or an error if it cannot be registered.
+        register: fn(&mut Storage) -> Result<usize, StorageError>,
+    },
+    /// A protocol.
+    Protocol {
+        /// The type name of the protocol.
+        name: &'static str,
+        /// The GUID of the protocol.
+        guid: &'static efi::Guid,
+    },
+}
+
+/// The resources a [StorageView] may access, declared as a tuple of [ReadConfig], [WriteConfig] and [UseProtocol].
+pub trait ViewAccess: 'static {
+    /// Appends the declared resources to `declarations`.
+    fn declare(declarations: &mut Vec<ViewDeclaration>);
+    /// Returns true if config `type_id` is declared, with write access if `writable`.
+    fn allows_config(type_id: TypeId, writable: bool) -> bool;
+    /// Returns true if the protocol with the given GUID is declared.
+    fn allows_protocol(guid: &efi::Guid) -> bool;
+}
+
+/// Declares read access to config `T` in a [StorageView].
+pub struct ReadConfig<T: Default + 'static>(PhantomData<T>);
+
+/// Declares read and write access to config `T` in a [StorageView].
+pub struct WriteConfig<T: Default + 'static>(PhantomData<T>);
+
+/// Declares access to protocol `P` in a [StorageView].
+pub struct UseProtocol<P: protocol::Protocol + 'static>(PhantomData<P>);
+
+// A config read through a view is created with its default value if it does not exist yet, like Config.
+impl<T: Default + 'static> ViewAccess for ReadConfig<T> {
+    fn declare(declarations: &mut Vec<ViewDeclaration>) {
+        declarations.push(ViewDeclaration::Config {
+            name: core::any::type_name::<T>(),
+            writable: false,
+            register: |storage| {
+                let id = storage.register_config::<T>();
+                storage.try_add_config(id, T::default())?;
+                Ok(id)
+            },
+        });
+    }
+
+    fn allows_config(type_id: TypeId, writable: bool) -> bool {
+        type_id == TypeId::of::<T>() && !writable
+    }
+
+    fn allows_protocol(_guid: &efi::Guid) -> bool {
+        false
+    }
+}
+
+impl<T: Default + 'static> ViewAccess for WriteConfig<T> {
+    fn declare(declarations: &mut Vec<ViewDeclaration>) {
+        declarations.push(ViewDeclaration::Config {
+            name: core::any::type_name::<T>(),
+            writable: true,
+            register: |storage| Ok(storage.register_config::<T>()),
+        });
+    }
+
+    fn allows_config(type_id: TypeId, _writable: bool) -> bool {
+        type_id == TypeId::of::<T>()
+    }
+
+    fn allows_protocol(_guid: &efi::Guid) -> bool {
+        false
+    }
+}
+
+impl<P: protocol::Protocol + 'static> ViewAccess for UseProtocol<P> {
+    fn declare(declarations: &mut Vec<ViewDeclaration>) {
+        declarations.push(ViewDeclaration::Protocol {
+            name: core::any::type_name::<P>(),
+            guid: P::guid(),
+        });
+    }
+
+    fn allows_config(_type_id: TypeId, _writable: bool) -> bool {
+        false
+    }
+
+    fn allows_protocol(guid: &efi::Guid) -> bool {
+        guid == P::guid()
+    }
+}
+
+macro_rules! impl_view_access_tuple {
+    ($($access: ident), *) => {
+        #[allow(unused_variables)]
+        impl<$($access: ViewAccess),*> ViewAccess for ($($access,)*) {
+            fn declare(declarations: &mut Vec<ViewDeclaration>) {
+                $($access::declare(declarations);)*
+            }
+
+            fn allows_config(type_id: TypeId, writable: bool) -> bool {
+                $($access::allows_config(type_id, writable)||)* false
+            }
+
+            fn allows_protocol(guid: &efi::Guid) -> bool {
+                $($access::allows_protocol(guid)||)* false
+            }
+        }
+    }
+}
+
+impl_view_access_tuple!();
+impl_view_access_tuple!(A1);
+impl_view_access_tuple!(A1, A2);
+impl_view_access_tuple!(A1, A2, A3);
+impl_view_access_tuple!(A1, A2, A3, A4);
+impl_view_access_tuple!(A1, A2, A3, A4, A5);
+impl_view_access_tuple!(A1, A2, A3, A4, A5, A6);
+impl_view_access_tuple!(A1, A2, A3, A4, A5, A6, A7);
+impl_view_access_tuple!(A1, A2, A3, A4, A5, A6, A7, A8);
+
+/// Access to the configs and protocols declared by `D`, looked up while the component runs.
+///
+/// Unlike `&Storage`, a view does not give the component exclusive access to the storage, so it can be combined with
+/// other params. Configs are borrowed for as long as the returned value lives, and protocols can be looked up without
+/// the component waiting for them to be installed. Accessing a resource that `D` does not declare returns an error.
+pub struct StorageView<'s, D: ViewAccess> {
+    // Held as a pointer, so the view only borrows the resources it accesses rather than the whole storage.
+    storage: *const Storage,
+    _marker: PhantomData<(&'s Storage, D)>,
+}
+
+impl<'s, D: ViewAccess> StorageView<'s, D> {
+    pub fn new(storage: &'s Storage) -> Self {
+        // SAFETY: The storage is borrowed for as long as the view.
+        unsafe { Self::from_raw(storage) }
+    }
+
+    /// Creates a view through a pointer to the storage.
+    ///
+    /// ## Safety
+    ///
+    /// - `storage` must point to a valid storage for `'s`, and no config may be added or removed for `'s`.
+    pub unsafe fn from_raw(storage: *const Storage) -> Self {
+        StorageView {
+            storage,
             _marker: PhantomData,
         }
     }
+
+    /// Returns the id of config `T`, if it is declared with the requested access and registered.
+    fn config_id<T: Default + 'static>(&self, writable: bool) -> Result<usize, StorageError> {
+        let not_declared = StorageError::ConfigNotDeclared(core::any::type_name::<T>());
+        if !D::allows_config(TypeId::of::<T>(), writable) {
+            return Err(not_declared);
+        }
+        // SAFETY: The storage outlives the view.
+        unsafe { Storage::config_id_raw::<T>(self.storage) }.ok_or(not_declared)
+    }
+
+    /// Borrows config `T`, which must be declared with [ReadConfig] or [WriteConfig].
+    pub fn config<T: Default + 'static>(&self) -> Result<Config<'s, T>, StorageError> {
+        let id = self.config_id::<T>(false)?;
+        // SAFETY: The storage outlives the view.
+        Config::try_from_untyped(unsafe { Storage::try_get_config_raw(self.storage, id)? })
+    }
+
+    /// Borrows config `T` mutably, which must be declared with [WriteConfig]. The value is validated when the
+    /// [ConfigMut] is dropped.
+    pub fn config_mut<T: Default + 'static>(&self) -> Result<ConfigMut<'s, T>, StorageError> {
+        let id = self.config_id::<T>(true)?;
+        // SAFETY: The storage outlives the view.
+        unsafe { ConfigMut::try_new_raw(self.storage, id) }
+    }
+
+    /// Returns the first installed instance of protocol `P`, which must be declared with [UseProtocol].
+    pub fn protocol<P: protocol::Protocol + 'static>(
+        &self,
+    ) -> Result<Protocol<'s, P>, StorageError> {
+        if !D::allows_protocol(P::guid()) {
+            return Err(StorageError::ProtocolNotDeclared(*P::guid()));
+        }
+        // SAFETY: The storage outlives the view. The interface is a `P`, and remains valid while the storage is
+        // borrowed.
+        unsafe {
+            let interface = Storage::try_get_protocol_raw::<P>(self.storage)?;
+            Ok(Protocol::from_ptr(interface))
+        }
+    }
+
+    /// Returns the interface of the first installed instance of the protocol with the given GUID, which must be
+    /// declared with [UseProtocol].
+    pub fn protocol_untyped(&self, guid: &efi::Guid) -> Result<*mut c_void, StorageError> {
+        if !D::allows_protocol(guid) {
+            return Err(StorageError::ProtocolNotDeclared(*guid));
+        }
+        // SAFETY: The storage outlives the view.
+        unsafe { Storage::try_get_protocol_untyped_raw(self.storage, guid) }
+    }
 }
@@ -3,13 +3,25 @@ extern crate alloc;
 use core::{
     any::{Any, TypeId},
     cell::{Ref, RefCell, RefMut},
+    ffi::c_void,
+    fmt, mem, ptr,
 };
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{borrow::Cow, boxed::Box, rc::Rc, string::String, vec::Vec};
 use hashbrown::HashMap;
-use r_efi::efi::Guid;
+use r_efi::efi::{self, Guid, Handle, Status};
 
-use crate::protocol::Protocol;
+use super::validation::{ConfigValidator, InvalidConfig};
+use crate::{
+    config_table::{ConfigTableChange, ConfigTableDatabase},
+    event::EventDatabase,
+    guid::PrettyGuid,
+    hob::HobList,
+    memory::{MemoryMap, MemorySnapshot},
+    pcd::{PcdDatabase, PcdDatum, PcdEntry, PcdError, PcdKind, PcdToken, PcdValue},
+    protocol::{Protocol, ProtocolDatabase, ProtocolInterface},
+    variable::{VariableEntry, VariableStore, VariableToken},
+};
 
 pub struct SparseVec<V> {
     values: Vec<Option<V>>,
@@ -41,14 +53,253 @@ impl<V> SparseVec<V> {
         }
         self.values[index] = Some(value);
     }
+
+    /// Returns an iterator over all values and their indices.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &V)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| value.as_ref().map(|v| (index, v)))
+    }
+
+    /// Returns an iterator over all values mutably.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.values.iter_mut().flatten()
+    }
+}
+
+/// Resets the borrow state of `cell` if a borrow of it was leaked, returning true if it was.
+///
+/// Exclusive access to the cell guarantees that any outstanding borrow can no longer be used.
+fn release_leaked<T>(cell: &mut RefCell<T>, placeholder: impl FnOnce() -> T) -> bool {
+    if cell.try_borrow_mut().is_ok() {
+        return false;
+    }
+    let value = mem::replace(cell.get_mut(), placeholder());
+    *cell = RefCell::new(value);
+    true
+}
+
+/// Metadata about a config registered in [Storage].
+#[derive(Debug, Copy, Clone)]
+pub struct ConfigInfo {
+    /// The global id of the config.
+    pub id: usize,
+    /// The type name of the config.
+    pub name: &'static str,
+    /// The [TypeId] of the config.
+    pub type_id: TypeId,
+    /// True if a value exists for the config. A config can be registered without a value.
+    pub initialized: bool,
+}
+
+/// Metadata about a protocol installed in [Storage].
+#[derive(Debug, Copy, Clone)]
+pub struct ProtocolInfo<'a> {
+    /// The handle the protocol is installed on.
+    pub handle: Handle,
+    /// The guid the protocol is installed under.
+    pub guid: &'a Guid,
+    /// The type name of the protocol, or `<foreign>` if it was installed through a raw pointer.
+    pub name: &'static str,
+}
+
+type CloneFn = fn(&dyn Any) -> Box<dyn Any>;
+
+fn clone_config<C: Clone + 'static>(value: &dyn Any) -> Box<dyn Any> {
+    Box::new(
+        value
+            .downcast_ref::<C>()
+            .expect("Cloner matches config type")
+            .clone(),
+    )
+}
+
+/// A [StorageSnapshot] that cannot be restored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The memory map was replaced since the snapshot was taken, so its allocations no longer exist.
+    MemoryReplaced,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemoryReplaced => write!(
+                f,
+                "The memory map was replaced since the snapshot was taken and cannot be restored."
+            ),
+        }
+    }
+}
+
+/// A resource that cannot be retrieved from a [Storage].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// No config is registered with the id.
+    ConfigNotFound(usize),
+    /// The config does not hold a value of the requested type.
+    ConfigTypeMismatch {
+        /// The type name of the requested config.
+        expected: &'static str,
+    },
+    /// Configs are frozen, so the config cannot be added or retrieved mutably.
+    ConfigsFrozen,
+    /// The config is already borrowed mutably, or borrowed at all when retrieving it mutably.
+    ConfigBorrowed(usize),
+    /// The config cannot be cloned and is shared with a [StorageSnapshot], so it cannot be retrieved mutably.
+    ConfigShared(usize),
+    /// No instance of the protocol is installed.
+    ProtocolNotFound(Guid),
+    /// The protocol was installed from Rust as a different type than the requested one.
+    ProtocolTypeMismatch {
+        /// The GUID of the protocol.
+        guid: Guid,
+        /// The type name of the requested protocol.
+        expected: &'static str,
+        /// The type name the protocol was installed as.
+        found: &'static str,
+    },
+    /// No configuration table with the GUID is installed.
+    ConfigTableNotFound(Guid),
+    /// No PCD is registered with the id.
+    PcdNotFound(usize),
+    /// The PCD is already borrowed mutably, or borrowed at all when retrieving it mutably.
+    PcdBorrowed(usize),
+    /// The PCD does not hold a value of the requested datum type.
+    PcdTypeMismatch(usize),
+    /// No variable is registered with the id.
+    VariableNotFound(usize),
+    /// The variable is already borrowed mutably, or borrowed at all when retrieving it mutably.
+    VariableBorrowed(usize),
+    /// The memory map is already borrowed mutably, or borrowed at all when retrieving it mutably.
+    MemoryBorrowed,
+    /// Memory was already added, so the memory map cannot be replaced.
+    MemoryAdded,
+    /// A storage view accessed a config it did not declare, or accessed it mutably when only declaring a read.
+    ConfigNotDeclared(&'static str),
+    /// A storage view accessed a protocol it did not declare.
+    ProtocolNotDeclared(Guid),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConfigNotFound(id) => write!(f, "Config {} does not exist.", id),
+            Self::ConfigTypeMismatch { expected } => {
+                write!(f, "Config does not hold a value of type {}.", expected)
+            }
+            Self::ConfigsFrozen => {
+                write!(
+                    f,
+                    "Configs are frozen and can no longer be added or mutated."
+                )
+            }
+            Self::ConfigBorrowed(id) => write!(f, "Config {} is already borrowed.", id),
+            Self::ConfigShared(id) => {
+                write!(
+                    f,
+                    "Config {} is shared with a snapshot and is read-only.",
+                    id
+                )
+            }
+            Self::ProtocolNotFound(guid) => {
+                write!(f, "Protocol {} is not installed.", PrettyGuid(guid))
+            }
+            Self::ProtocolTypeMismatch {
+                guid,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Protocol {} is installed as {}, not {}.",
+                PrettyGuid(guid),
+                found,
+                expected
+            ),
+            Self::ConfigTableNotFound(guid) => {
+                write!(
+                    f,
+                    "Configuration table {} is not installed.",
+                    PrettyGuid(guid)
+                )
+            }
+            Self::PcdNotFound(id) => write!(f, "PCD {} does not exist.", id),
+            Self::PcdBorrowed(id) => write!(f, "PCD {} is already borrowed.", id),
+            Self::PcdTypeMismatch(id) => {
+                write!(
+                    f,
+                    "PCD {} does not hold a value of the requested datum type.",
+                    id
+                )
+            }
+            Self::VariableNotFound(id) => write!(f, "Variable {} does not exist.", id),
+            Self::VariableBorrowed(id) => write!(f, "Variable {} is already borrowed.", id),
+            Self::MemoryBorrowed => write!(f, "The memory map is already borrowed."),
+            Self::MemoryAdded => write!(f, "Memory has already been added."),
+            Self::ConfigNotDeclared(name) => {
+                write!(f, "Config {} is not declared by the storage view.", name)
+            }
+            Self::ProtocolNotDeclared(guid) => write!(
+                f,
+                "Protocol {} is not declared by the storage view.",
+                PrettyGuid(guid)
+            ),
+        }
+    }
+}
+
+/// A config captured by a [StorageSnapshot].
+enum ConfigSnapshot {
+    /// A copy of a config with a registered cloner.
+    Copied(Box<dyn Any>, CloneFn),
+    /// A config without a cloner, shared with the storage. It is read-only for as long as it is shared.
+    Shared(Rc<RefCell<Box<dyn Any>>>),
+}
+
+/// A copy of the state of a [Storage], created with [Storage::snapshot] and applied with [Storage::restore].
+///
+/// A snapshot can be restored any number of times.
+pub struct StorageSnapshot {
+    configs: Vec<(usize, ConfigSnapshot)>,
+    config_indices: HashMap<TypeId, usize>,
+    config_types: Vec<(&'static str, TypeId)>,
+    protocol_db: ProtocolDatabase,
+    pcd_db: PcdDatabase,
+    hobs: HobList,
+    variables: VariableStore,
+    config_tables: ConfigTableDatabase,
+    events: EventDatabase,
+    memory: MemorySnapshot,
+    configs_frozen: bool,
 }
 
 // TODO: Flesh out this struct. Probably need something custom, not just a hashmap. Probably
 // just an array storage where the stored item maintains a reference to its original type.
 pub struct Storage {
-    configs: SparseVec<RefCell<Box<dyn Any>>>,
+    // Configs are reference counted so that those without a cloner can be shared with snapshots.
+    configs: SparseVec<Rc<RefCell<Box<dyn Any>>>>,
     config_indices: HashMap<TypeId, usize>,
-    protocol_db: HashMap<Guid, Box<dyn Any>>,
+    // The type name and type id of each registered config, indexed by id.
+    config_types: Vec<(&'static str, TypeId)>,
+    protocol_db: ProtocolDatabase,
+    pcd_db: PcdDatabase,
+    hobs: HobList,
+    variables: VariableStore,
+    config_tables: ConfigTableDatabase,
+    events: EventDatabase,
+    memory: RefCell<MemoryMap>,
+    configs_frozen: bool,
+    // Validators and cloners are keyed by type rather than id, as ids are reassigned when a snapshot is restored.
+    config_validators: HashMap<TypeId, ConfigValidator>,
+    config_cloners: HashMap<TypeId, CloneFn>,
+    config_errors: RefCell<Vec<InvalidConfig>>,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Storage {
@@ -56,54 +307,899 @@ impl Storage {
         Self {
             configs: SparseVec::new(),
             config_indices: HashMap::new(),
-            protocol_db: HashMap::new(),
+            config_types: Vec::new(),
+            protocol_db: ProtocolDatabase::new(),
+            pcd_db: PcdDatabase::new(),
+            hobs: HobList::new(),
+            variables: VariableStore::new(),
+            config_tables: ConfigTableDatabase::new(),
+            events: EventDatabase::new(),
+            memory: RefCell::new(MemoryMap::new()),
+            configs_frozen: false,
+            config_validators: HashMap::new(),
+            config_cloners: HashMap::new(),
+            config_errors: RefCell::new(Vec::new()),
         }
     }
 
     #[inline]
     pub fn register_config<C: Default + 'static>(&mut self) -> usize {
-        self.get_or_register_resource(TypeId::of::<C>())
+        self.get_or_register_resource(TypeId::of::<C>(), core::any::type_name::<C>())
     }
 
-    pub fn get_or_register_resource(&mut self, id: TypeId) -> usize {
+    pub fn get_or_register_resource(&mut self, id: TypeId, name: &'static str) -> usize {
         let idx = self.config_indices.len();
-        *self.config_indices.entry(id).or_insert(idx)
+        *self.config_indices.entry(id).or_insert_with(|| {
+            self.config_types.push((name, id));
+            idx
+        })
+    }
+
+    /// Returns the id of config `C`, or None if it is not registered.
+    pub fn config_id<C: 'static>(&self) -> Option<usize> {
+        // SAFETY: The storage is borrowed for the duration of the call.
+        unsafe { Self::config_id_raw::<C>(self) }
     }
 
     /// Adds a config to the storage if one does not already exist.
+    ///
+    /// The config is added even if it fails validation, in which case the failure is recorded. Returns an error if
+    /// the config does not exist and configs are frozen.
     #[inline]
-    pub fn try_add_config<C: Default + 'static>(&mut self, id: usize, config: C) {
+    pub fn try_add_config<C: Default + 'static>(
+        &mut self,
+        id: usize,
+        config: C,
+    ) -> Result<(), StorageError> {
         if !self.configs.contains(id) {
-            self.configs.insert(id, RefCell::new(Box::new(config)));
+            if self.configs_frozen {
+                return Err(StorageError::ConfigsFrozen);
+            }
+            self.check_config(&config);
+            self.configs
+                .insert(id, Rc::new(RefCell::new(Box::new(config))));
         }
+        Ok(())
     }
 
     #[inline]
     /// Adds a config to the storage, overwriting any existing config.
-    pub fn add_config<C: Default + 'static>(&mut self, config: C) {
+    ///
+    /// The config is not added if it fails validation, in which case the failure is recorded. Returns an error if
+    /// configs are frozen.
+    pub fn add_config<C: Default + 'static>(&mut self, config: C) -> Result<(), StorageError> {
+        if self.configs_frozen {
+            return Err(StorageError::ConfigsFrozen);
+        }
+        let id = self.register_config::<C>();
+        if self.check_config(&config) {
+            self.configs
+                .insert(id, Rc::new(RefCell::new(Box::new(config))));
+        }
+        Ok(())
+    }
+
+    /// Registers a validator that is run whenever a value of config `C` enters the storage.
+    ///
+    /// If a value already exists, it is validated immediately.
+    pub fn add_config_validator<C: Default + 'static>(
+        &mut self,
+        validator: impl Fn(&C) -> Result<(), Cow<'static, str>> + 'static,
+    ) {
         let id = self.register_config::<C>();
-        self.try_add_config(id, config);
+        self.config_validators
+            .insert(TypeId::of::<C>(), ConfigValidator::new(validator));
+        if let Some(config) = self.configs.get(id) {
+            self.check_config(config.borrow().as_ref());
+        }
+    }
+
+    /// Validates a config value against the validator registered for its type, if any.
+    ///
+    /// Returns false and records the failure if the value is invalid.
+    pub fn check_config(&self, value: &dyn Any) -> bool {
+        // SAFETY: The storage is borrowed for the duration of the call.
+        unsafe { Self::check_config_raw(self, value) }
+    }
+
+    /// Registers config `C` as cloneable, so that it can be captured by [snapshot](Self::snapshot).
+    pub fn add_config_cloner<C: Default + Clone + 'static>(&mut self) {
+        self.register_config::<C>();
+        self.config_cloners
+            .insert(TypeId::of::<C>(), clone_config::<C>);
+    }
+
+    /// Captures the configs, protocols, PCDs, events and memory in the storage.
+    ///
+    /// Configs with a registered cloner are copied. Those without one are shared with the snapshot instead, and are
+    /// read-only for as long as the snapshot exists. Validators, cloners and recorded validation failures are not part
+    /// of the snapshot.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if a config with a registered cloner, or the memory map, is currently borrowed mutably.
+    pub fn snapshot(&self) -> StorageSnapshot {
+        let configs = self
+            .configs
+            .iter()
+            .map(|(id, config)| {
+                let (_, type_id) = self.config_types[id];
+                let snapshot = match self.config_cloners.get(&type_id) {
+                    Some(&clone) => ConfigSnapshot::Copied(clone(config.borrow().as_ref()), clone),
+                    None => ConfigSnapshot::Shared(Rc::clone(config)),
+                };
+                (id, snapshot)
+            })
+            .collect();
+
+        StorageSnapshot {
+            configs,
+            config_indices: self.config_indices.clone(),
+            config_types: self.config_types.clone(),
+            protocol_db: self.protocol_db.clone(),
+            pcd_db: self.pcd_db.clone(),
+            hobs: self.hobs.clone(),
+            variables: self.variables.clone(),
+            config_tables: self.config_tables.clone(),
+            events: self.events.clone(),
+            memory: self.memory.borrow().snapshot(),
+            configs_frozen: self.configs_frozen,
+        }
+    }
+
+    /// Restores the storage to the state captured by `snapshot`.
+    ///
+    /// Config ids registered after the snapshot was taken are no longer valid. If the snapshot was taken before memory
+    /// was added, the memory map is reset and memory can be added again. Fails, leaving the storage unchanged, if the
+    /// memory map was replaced since the snapshot was taken.
+    pub fn restore(&mut self, snapshot: &StorageSnapshot) -> Result<(), SnapshotError> {
+        if !self.memory.get_mut().restore(&snapshot.memory) {
+            return Err(SnapshotError::MemoryReplaced);
+        }
+        self.configs = SparseVec::new();
+        for (id, config) in &snapshot.configs {
+            let config = match config {
+                ConfigSnapshot::Copied(config, clone) => {
+                    Rc::new(RefCell::new(clone(config.as_ref())))
+                }
+                ConfigSnapshot::Shared(config) => Rc::clone(config),
+            };
+            self.configs.insert(*id, config);
+        }
+        self.config_indices = snapshot.config_indices.clone();
+        self.config_types = snapshot.config_types.clone();
+        self.protocol_db = snapshot.protocol_db.clone();
+        self.pcd_db = snapshot.pcd_db.clone();
+        self.hobs = snapshot.hobs.clone();
+        self.variables = snapshot.variables.clone();
+        self.config_tables = snapshot.config_tables.clone();
+        self.events = snapshot.events.clone();
+        self.configs_frozen = snapshot.configs_frozen;
+        self.config_errors.get_mut().clear();
+        Ok(())
+    }
+
+    /// Takes all config validation failures recorded since the last call.
+    pub fn take_config_errors(&mut self) -> Vec<InvalidConfig> {
+        core::mem::take(self.config_errors.get_mut())
     }
 
     /// Retrieves a config from the storage.
-    pub fn get_config_untyped(&self, id: usize) -> Ref<Box<dyn Any>> {
-        self.configs.get(id).expect("Config Exists").borrow()
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the config cannot be retrieved. See [try_get_config_untyped](Self::try_get_config_untyped).
+    pub fn get_config_untyped(&self, id: usize) -> Ref<'_, Box<dyn Any>> {
+        self.try_get_config_untyped(id)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves a config from the storage, returning an error if it does not exist or is borrowed mutably.
+    pub fn try_get_config_untyped(&self, id: usize) -> Result<Ref<'_, Box<dyn Any>>, StorageError> {
+        // SAFETY: The config is borrowed for as long as the storage.
+        unsafe { Self::try_get_config_raw(self, id) }
     }
 
     /// Retrieves a mutable config from the storage.
-    pub fn get_config_mut_untyped(&self, id: usize) -> RefMut<Box<dyn Any>> {
-        self.configs.get(id).expect("Config Exists").borrow_mut()
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the config cannot be retrieved, e.g. because configs are frozen. See
+    /// [try_get_config_mut_untyped](Self::try_get_config_mut_untyped).
+    pub fn get_config_mut_untyped(&self, id: usize) -> RefMut<'_, Box<dyn Any>> {
+        self.try_get_config_mut_untyped(id)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves a mutable config from the storage, returning an error if configs are frozen, or if it does not exist
+    /// or is borrowed.
+    pub fn try_get_config_mut_untyped(
+        &self,
+        id: usize,
+    ) -> Result<RefMut<'_, Box<dyn Any>>, StorageError> {
+        // SAFETY: The config is borrowed for as long as the storage.
+        unsafe { Self::try_get_config_mut_raw(self, id) }
+    }
+
+    /// Freezes all configs, making them read-only for the remaining lifetime of the storage.
+    pub fn freeze_configs(&mut self) {
+        self.configs_frozen = true;
+    }
+
+    /// Returns true if configs are frozen.
+    pub fn configs_frozen(&self) -> bool {
+        self.configs_frozen
     }
 
     pub fn contains_protocol(&self, guid: &Guid) -> bool {
-        self.protocol_db.contains_key(guid)
+        self.protocol_db.contains(guid)
+    }
+
+    /// Installs a protocol on a new handle, returning the handle.
+    pub fn add_protocol<P: Protocol + 'static>(&mut self, protocol: P) -> Handle {
+        self.protocol_db
+            .install(None, P::guid(), ProtocolInterface::new(protocol))
+            .expect("Protocol can always be installed on a new handle")
+    }
+
+    /// Installs a protocol on a new handle, to be retrieved as an `I` rather than a `P`. Returns the handle.
+    ///
+    /// ## Safety
+    ///
+    /// - `P` must be `repr(C)` and start with a field of type `I`.
+    pub unsafe fn add_protocol_as<I: 'static, P: Protocol + 'static>(
+        &mut self,
+        protocol: P,
+    ) -> Handle {
+        self.protocol_db
+            .install(None, P::guid(), ProtocolInterface::new_as::<I, P>(protocol))
+            .expect("Protocol can always be installed on a new handle")
+    }
+
+    /// Returns the first installed instance of protocol `P`, or an error if it is not installed, or was installed from
+    /// Rust as a different type.
+    pub fn try_get_protocol<P: Protocol + 'static>(&self) -> Result<*mut P, StorageError> {
+        // SAFETY: The storage is borrowed for the duration of the call.
+        unsafe { Self::try_get_protocol_raw::<P>(self) }
+    }
+
+    /// Returns the interface of the first installed instance of the protocol.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the protocol is not installed.
+    pub fn get_protocol_untyped(&self, guid: &Guid) -> *mut c_void {
+        self.try_get_protocol_untyped(guid)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns the interface of the first installed instance of the protocol, or an error if it is not installed.
+    pub fn try_get_protocol_untyped(&self, guid: &Guid) -> Result<*mut c_void, StorageError> {
+        // SAFETY: The storage is borrowed for the duration of the call.
+        unsafe { Self::try_get_protocol_untyped_raw(self, guid) }
+    }
+
+    /// Installs a protocol interface on `handle`, or on a new handle if `handle` is None. Returns the handle.
+    pub fn install_protocol_interface(
+        &mut self,
+        handle: Option<Handle>,
+        guid: &Guid,
+        interface: *mut c_void,
+    ) -> Result<Handle, Status> {
+        self.protocol_db
+            .install(handle, guid, ProtocolInterface::from_raw(interface))
+    }
+
+    /// Removes a protocol interface from `handle`.
+    pub fn uninstall_protocol_interface(
+        &mut self,
+        handle: Handle,
+        guid: &Guid,
+        interface: *mut c_void,
+    ) -> Result<(), Status> {
+        self.protocol_db
+            .uninstall(handle, guid, interface)
+            .map(|_| ())
+    }
+
+    /// Returns the interface of the protocol installed on `handle`.
+    pub fn handle_protocol(&self, handle: Handle, guid: &Guid) -> Result<*mut c_void, Status> {
+        self.protocol_db
+            .get(handle, guid)
+            .map(ProtocolInterface::as_ptr)
+            .ok_or(Status::UNSUPPORTED)
+    }
+
+    /// Returns the interface of the first installed instance of the protocol.
+    pub fn locate_protocol(&self, guid: &Guid) -> Result<*mut c_void, Status> {
+        self.protocol_db
+            .locate(guid)
+            .map(|(_, interface)| interface.as_ptr())
+            .ok_or(Status::NOT_FOUND)
+    }
+
+    /// Returns all handles the protocol is installed on, or all handles if `guid` is None.
+    pub fn locate_handles(&self, guid: Option<&Guid>) -> Vec<Handle> {
+        self.protocol_db.locate_handles(guid)
+    }
+
+    /// Returns true if the handle exists.
+    pub fn contains_handle(&self, handle: Handle) -> bool {
+        self.protocol_db.contains_handle(handle)
+    }
+
+    /// Returns an iterator over all registered configs.
+    pub fn configs(&self) -> impl Iterator<Item = ConfigInfo> + '_ {
+        self.config_types
+            .iter()
+            .enumerate()
+            .map(|(id, &(name, type_id))| ConfigInfo {
+                id,
+                name,
+                type_id,
+                initialized: self.configs.contains(id),
+            })
+    }
+
+    /// Returns an iterator over all installed protocols.
+    pub fn protocols(&self) -> impl Iterator<Item = ProtocolInfo<'_>> {
+        self.protocol_db
+            .iter()
+            .map(|(handle, guid, interface)| ProtocolInfo {
+                handle,
+                guid,
+                name: interface.name(),
+            })
+    }
+
+    /// Registers a PCD, adding it with its default value if it does not already exist.
+    pub fn register_pcd<T: PcdToken>(&mut self) -> usize {
+        self.pcd_db
+            .get_or_insert(T::token_space_guid(), T::token_number(), || {
+                PcdEntry::new(T::kind(), T::default_value().into_value())
+            })
+    }
+
+    /// Adds a PCD to the storage, overwriting any existing value.
+    pub fn add_pcd<T: PcdToken>(&mut self, value: T::Value) -> usize {
+        self.pcd_db.insert(
+            T::token_space_guid(),
+            T::token_number(),
+            PcdEntry::new(T::kind(), value.into_value()),
+        )
+    }
+
+    /// Adds a PCD that is not declared by a [PcdToken], overwriting any existing value.
+    pub fn add_pcd_untyped(&mut self, guid: &Guid, token: u32, entry: PcdEntry) -> usize {
+        self.pcd_db.insert(guid, token, entry)
+    }
+
+    /// Returns true if the PCD exists in storage.
+    pub fn contains_pcd(&self, guid: &Guid, token: u32) -> bool {
+        self.pcd_db.contains(guid, token)
+    }
+
+    /// Returns the current value of a PCD.
+    pub fn get_pcd(&self, guid: &Guid, token: u32) -> Result<PcdValue, PcdError> {
+        self.pcd_db.get(guid, token)
+    }
+
+    /// Sets the value of a dynamic PCD.
+    pub fn set_pcd(&mut self, guid: &Guid, token: u32, value: PcdValue) -> Result<(), PcdError> {
+        self.pcd_db.set(guid, token, value)
     }
 
-    pub fn add_protocol<P: Protocol + 'static>(&mut self, protocol: P) {
-        self.protocol_db.insert(*P::guid(), Box::new(protocol));
+    /// Sets the value of any PCD, including fixed and patchable PCDs. Intended for populating the database.
+    pub fn patch_pcd(&mut self, guid: &Guid, token: u32, value: PcdValue) -> Result<(), PcdError> {
+        self.pcd_db.patch(guid, token, value)
     }
 
-    pub fn get_protocol_untyped(&self, guid: &Guid) -> &Box<dyn Any> {
-        self.protocol_db.get(guid).expect("Protocol Exists")
+    /// Retrieves a PCD from the storage.
+    pub fn get_pcd_untyped(&self, id: usize) -> Ref<'_, PcdEntry> {
+        self.pcd_db.entry(id)
+    }
+
+    /// Retrieves a PCD from the storage, returning an error if it does not exist or is borrowed mutably.
+    pub fn try_get_pcd_untyped(&self, id: usize) -> Result<Ref<'_, PcdEntry>, StorageError> {
+        // SAFETY: The PCD is borrowed for as long as the storage.
+        unsafe { Self::try_get_pcd_raw(self, id) }
+    }
+
+    /// Retrieves a mutable PCD from the storage.
+    pub fn get_pcd_mut_untyped(&self, id: usize) -> RefMut<'_, PcdEntry> {
+        self.pcd_db.entry_mut(id)
+    }
+
+    /// Retrieves a mutable PCD from the storage, returning an error if it does not exist or is borrowed.
+    pub fn try_get_pcd_mut_untyped(&self, id: usize) -> Result<RefMut<'_, PcdEntry>, StorageError> {
+        // SAFETY: The PCD is borrowed for as long as the storage.
+        unsafe { Self::try_get_pcd_mut_raw(self, id) }
+    }
+
+    /// Adds the HOBs of a HOB list to the storage, after any already added.
+    pub fn add_hobs(&mut self, hobs: HobList) {
+        self.hobs.extend(hobs);
+    }
+
+    /// Returns the HOBs handed off from PEI.
+    pub fn hobs(&self) -> &HobList {
+        &self.hobs
+    }
+
+    /// Registers a variable, returning its id. The variable does not exist until it is set.
+    pub fn register_variable<T: VariableToken>(&mut self) -> usize {
+        self.variables.get_or_insert(T::vendor_guid(), T::name())
+    }
+
+    /// Returns the attributes and data of a variable, following the rules of GetVariable.
+    pub fn get_variable(&self, guid: &Guid, name: &str) -> Result<(u32, Vec<u8>), Status> {
+        self.variables.get(guid, name)
+    }
+
+    /// Sets, appends to or deletes a variable, following the rules of SetVariable.
+    pub fn set_variable(
+        &mut self,
+        guid: &Guid,
+        name: &str,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), Status> {
+        self.variables.set(guid, name, attributes, data)
+    }
+
+    /// Returns the variable following `previous`, following the rules of GetNextVariableName.
+    pub fn get_next_variable_name(
+        &self,
+        previous: Option<(&Guid, &str)>,
+    ) -> Result<(Guid, String), Status> {
+        self.variables.next_name(previous)
+    }
+
+    /// Returns the variable store.
+    pub fn variables(&self) -> &VariableStore {
+        &self.variables
+    }
+
+    /// Returns the variable store mutably, e.g. to provision or load variables.
+    pub fn variables_mut(&mut self) -> &mut VariableStore {
+        &mut self.variables
+    }
+
+    /// Retrieves a variable from the storage.
+    pub fn get_variable_untyped(&self, id: usize) -> Ref<'_, VariableEntry> {
+        self.variables.entry(id)
+    }
+
+    /// Retrieves a variable from the storage, returning an error if it does not exist or is borrowed mutably.
+    pub fn try_get_variable_untyped(
+        &self,
+        id: usize,
+    ) -> Result<Ref<'_, VariableEntry>, StorageError> {
+        // SAFETY: The variable is borrowed for as long as the storage.
+        unsafe { Self::try_get_variable_raw(self, id) }
+    }
+
+    /// Retrieves a mutable variable from the storage.
+    pub fn get_variable_mut_untyped(&self, id: usize) -> RefMut<'_, VariableEntry> {
+        self.variables.entry_mut(id)
+    }
+
+    /// Retrieves a mutable variable from the storage, returning an error if it does not exist or is borrowed.
+    pub fn try_get_variable_mut_untyped(
+        &self,
+        id: usize,
+    ) -> Result<RefMut<'_, VariableEntry>, StorageError> {
+        // SAFETY: The variable is borrowed for as long as the storage.
+        unsafe { Self::try_get_variable_mut_raw(self, id) }
+    }
+
+    /// Installs, replaces or, if `table` is null, removes a configuration table, following the rules of
+    /// InstallConfigurationTable. The event group of the table's GUID is signaled on success.
+    pub fn install_configuration_table(
+        &mut self,
+        guid: &Guid,
+        table: *mut c_void,
+    ) -> Result<ConfigTableChange, Status> {
+        let change = self.config_tables.install(guid, table)?;
+        self.events.signal_group(guid);
+        Ok(change)
+    }
+
+    /// Returns the configuration table with the given GUID, if installed.
+    pub fn get_configuration_table(&self, guid: &Guid) -> Option<*mut c_void> {
+        self.config_tables.get(guid)
+    }
+
+    /// Returns true if a configuration table with the given GUID is installed.
+    pub fn contains_configuration_table(&self, guid: &Guid) -> bool {
+        self.config_tables.contains(guid)
+    }
+
+    /// Returns all installed configuration tables.
+    pub fn configuration_tables(&self) -> &[efi::ConfigurationTable] {
+        self.config_tables.tables()
+    }
+
+    /// Returns the event database.
+    pub fn events(&self) -> &EventDatabase {
+        &self.events
+    }
+
+    /// Returns the event database mutably, e.g. to create, signal or close events.
+    pub fn events_mut(&mut self) -> &mut EventDatabase {
+        &mut self.events
+    }
+
+    /// Sets the memory map, e.g. to one over a new arena.
+    ///
+    /// Fails if the memory map already has an arena, as replacing it would free memory still in use, e.g. by loaded
+    /// images or by allocations handed out through the boot services.
+    pub fn set_memory(&mut self, memory: MemoryMap) -> Result<(), StorageError> {
+        if self.memory.get_mut().has_arena() {
+            return Err(StorageError::MemoryAdded);
+        }
+        self.memory = RefCell::new(memory);
+        Ok(())
+    }
+
+    /// Returns the memory map.
+    pub fn memory(&self) -> Ref<'_, MemoryMap> {
+        self.memory.borrow()
+    }
+
+    /// Returns the memory map, or an error if it is borrowed mutably.
+    pub fn try_memory(&self) -> Result<Ref<'_, MemoryMap>, StorageError> {
+        self.memory
+            .try_borrow()
+            .map_err(|_| StorageError::MemoryBorrowed)
+    }
+
+    /// Returns the memory map mutably, to allocate or free memory.
+    pub fn memory_mut(&self) -> RefMut<'_, MemoryMap> {
+        self.memory.borrow_mut()
+    }
+
+    /// Returns the memory map mutably, or an error if it is borrowed.
+    pub fn try_memory_mut(&self) -> Result<RefMut<'_, MemoryMap>, StorageError> {
+        self.memory
+            .try_borrow_mut()
+            .map_err(|_| StorageError::MemoryBorrowed)
+    }
+
+    /// Releases borrows of configs, PCDs, variables and the memory map that were leaked rather than dropped.
+    ///
+    /// Borrows are normally released when the value holding them is dropped, including while unwinding from a panic.
+    /// One that was forgotten instead would leave its resource borrowed for good; as this requires exclusive access to
+    /// the storage, no such borrow can still be in use. Returns the number of borrows released.
+    pub fn release_leaked_borrows(&mut self) -> usize {
+        let placeholder = || Box::new(()) as Box<dyn Any>;
+        let configs = self
+            .configs
+            .values_mut()
+            // Configs shared with a snapshot are skipped, as they cannot be accessed exclusively.
+            .filter_map(Rc::get_mut)
+            .map(|cell| release_leaked(cell, placeholder))
+            .filter(|&released| released)
+            .count();
+        let pcds = self
+            .pcd_db
+            .cells_mut()
+            .map(|cell| {
+                release_leaked(cell, || {
+                    PcdEntry::new(PcdKind::Fixed, PcdValue::Bool(false))
+                })
+            })
+            .filter(|&released| released)
+            .count();
+        let variables = self
+            .variables
+            .cells_mut()
+            .map(|cell| release_leaked(cell, VariableEntry::new))
+            .filter(|&released| released)
+            .count();
+        let memory = release_leaked(&mut self.memory, MemoryMap::new) as usize;
+        let errors = release_leaked(&mut self.config_errors, Vec::new) as usize;
+        configs + pcds + variables + memory + errors
+    }
+}
+
+/// Access to the individual parts of a storage through a pointer to it.
+///
+/// Each function only creates a reference to the part of the storage it accesses, never to the whole storage, so it
+/// can be used while other parts are borrowed. The DXE core uses these while a component runs, as the component's
+/// params and the EFI service tables it calls into each access the storage through the same pointer.
+///
+/// For all functions, `this` must point to a valid storage for as long as a returned reference or borrow is used.
+impl Storage {
+    /// Returns the id of config `C`, or None if it is not registered. See [config_id](Self::config_id).
+    ///
+    /// ## Safety
+    ///
+    /// - The config registry must not be modified during the call.
+    pub unsafe fn config_id_raw<C: 'static>(this: *const Self) -> Option<usize> {
+        (*ptr::addr_of!((*this).config_indices))
+            .get(&TypeId::of::<C>())
+            .copied()
+    }
+
+    /// Returns true if configs are frozen. See [configs_frozen](Self::configs_frozen).
+    ///
+    /// ## Safety
+    ///
+    /// - Configs must not be frozen during the call.
+    pub unsafe fn configs_frozen_raw(this: *const Self) -> bool {
+        *ptr::addr_of!((*this).configs_frozen)
+    }
+
+    /// Validates a config value, recording the failure if it is invalid. See [check_config](Self::check_config).
+    ///
+    /// ## Safety
+    ///
+    /// - The validators must not be modified during the call.
+    pub unsafe fn check_config_raw(this: *const Self, value: &dyn Any) -> bool {
+        let validators = &*ptr::addr_of!((*this).config_validators);
+        let Some(validator) = validators.get(&value.type_id()) else {
+            return true;
+        };
+        match validator.validate(value) {
+            Ok(()) => true,
+            Err(err) => {
+                (*ptr::addr_of!((*this).config_errors))
+                    .borrow_mut()
+                    .push(err);
+                false
+            }
+        }
+    }
+
+    /// Borrows the config denoted by `id` for `'a`. See [try_get_config_untyped](Self::try_get_config_untyped).
+    ///
+    /// ## Safety
+    ///
+    /// - No config may be added or removed for `'a`.
+    pub unsafe fn try_get_config_raw<'a>(
+        this: *const Self,
+        id: usize,
+    ) -> Result<Ref<'a, Box<dyn Any>>, StorageError> {
+        (*ptr::addr_of!((*this).configs))
+            .get(id)
+            .ok_or(StorageError::ConfigNotFound(id))?
+            .try_borrow()
+            .map_err(|_| StorageError::ConfigBorrowed(id))
+    }
+
+    /// Borrows the config denoted by `id` mutably for `'a`. See
+    /// [try_get_config_mut_untyped](Self::try_get_config_mut_untyped).
+    ///
+    /// ## Safety
+    ///
+    /// - No config may be added or removed for `'a`.
+    pub unsafe fn try_get_config_mut_raw<'a>(
+        this: *const Self,
+        id: usize,
+    ) -> Result<RefMut<'a, Box<dyn Any>>, StorageError> {
+        if Self::configs_frozen_raw(this) {
+            return Err(StorageError::ConfigsFrozen);
+        }
+        let config = (*ptr::addr_of!((*this).configs))
+            .get(id)
+            .ok_or(StorageError::ConfigNotFound(id))?;
+        if Rc::strong_count(config) > 1 {
+            return Err(StorageError::ConfigShared(id));
+        }
+        config
+            .try_borrow_mut()
+            .map_err(|_| StorageError::ConfigBorrowed(id))
+    }
+
+    /// Borrows the PCD denoted by `id` for `'a`. See [try_get_pcd_untyped](Self::try_get_pcd_untyped).
+    ///
+    /// ## Safety
+    ///
+    /// - No PCD may be added for `'a`.
+    pub unsafe fn try_get_pcd_raw<'a>(
+        this: *const Self,
+        id: usize,
+    ) -> Result<Ref<'a, PcdEntry>, StorageError> {
+        (*ptr::addr_of!((*this).pcd_db))
+            .cell(id)
+            .ok_or(StorageError::PcdNotFound(id))?
+            .try_borrow()
+            .map_err(|_| StorageError::PcdBorrowed(id))
+    }
+
+    /// Borrows the PCD denoted by `id` mutably for `'a`. See [try_get_pcd_mut_untyped](Self::try_get_pcd_mut_untyped).
+    ///
+    /// ## Safety
+    ///
+    /// - No PCD may be added for `'a`.
+    pub unsafe fn try_get_pcd_mut_raw<'a>(
+        this: *const Self,
+        id: usize,
+    ) -> Result<RefMut<'a, PcdEntry>, StorageError> {
+        (*ptr::addr_of!((*this).pcd_db))
+            .cell(id)
+            .ok_or(StorageError::PcdNotFound(id))?
+            .try_borrow_mut()
+            .map_err(|_| StorageError::PcdBorrowed(id))
+    }
+
+    /// Borrows the variable denoted by `id` for `'a`. See [try_get_variable_untyped](Self::try_get_variable_untyped).
+    ///
+    /// ## Safety
+    ///
+    /// - No variable may be registered for `'a`.
+    pub unsafe fn try_get_variable_raw<'a>(
+        this: *const Self,
+        id: usize,
+    ) -> Result<Ref<'a, VariableEntry>, StorageError> {
+        (*ptr::addr_of!((*this).variables))
+            .cell(id)
+            .ok_or(StorageError::VariableNotFound(id))?
+            .try_borrow()
+            .map_err(|_| StorageError::VariableBorrowed(id))
+    }
+
+    /// Borrows the variable denoted by `id` mutably for `'a`. See
+    /// [try_get_variable_mut_untyped](Self::try_get_variable_mut_untyped).
+    ///
+    /// ## Safety
+    ///
+    /// - No variable may be registered for `'a`.
+    pub unsafe fn try_get_variable_mut_raw<'a>(
+        this: *const Self,
+        id: usize,
+    ) -> Result<RefMut<'a, VariableEntry>, StorageError> {
+        (*ptr::addr_of!((*this).variables))
+            .cell(id)
+            .ok_or(StorageError::VariableNotFound(id))?
+            .try_borrow_mut()
+            .map_err(|_| StorageError::VariableBorrowed(id))
+    }
+
+    /// Returns the first installed instance of protocol `P`. See [try_get_protocol](Self::try_get_protocol).
+    ///
+    /// ## Safety
+    ///
+    /// - The protocol database must not be modified during the call.
+    pub unsafe fn try_get_protocol_raw<P: Protocol + 'static>(
+        this: *const Self,
+    ) -> Result<*mut P, StorageError> {
+        let (_, interface) = (*ptr::addr_of!((*this).protocol_db))
+            .locate(P::guid())
+            .ok_or(StorageError::ProtocolNotFound(*P::guid()))?;
+        interface
+            .downcast::<P>()
+            .ok_or(StorageError::ProtocolTypeMismatch {
+                guid: *P::guid(),
+                expected: core::any::type_name::<P>(),
+                found: interface.name(),
+            })
+    }
+
+    /// Returns the interface of the first installed instance of the protocol. See
+    /// [try_get_protocol_untyped](Self::try_get_protocol_untyped).
+    ///
+    /// ## Safety
+    ///
+    /// - The protocol database must not be modified during the call.
+    pub unsafe fn try_get_protocol_untyped_raw(
+        this: *const Self,
+        guid: &Guid,
+    ) -> Result<*mut c_void, StorageError> {
+        (*ptr::addr_of!((*this).protocol_db))
+            .locate(guid)
+            .map(|(_, interface)| interface.as_ptr())
+            .ok_or(StorageError::ProtocolNotFound(*guid))
+    }
+
+    /// Returns the configuration table with the given GUID, if installed. See
+    /// [get_configuration_table](Self::get_configuration_table).
+    ///
+    /// ## Safety
+    ///
+    /// - The configuration tables must not be modified during the call.
+    pub unsafe fn get_configuration_table_raw(
+        this: *const Self,
+        guid: &Guid,
+    ) -> Option<*mut c_void> {
+        (*ptr::addr_of!((*this).config_tables)).get(guid)
+    }
+
+    /// Returns the HOB list for `'a`. See [hobs](Self::hobs).
+    ///
+    /// ## Safety
+    ///
+    /// - No HOB may be added for `'a`.
+    pub unsafe fn hobs_raw<'a>(this: *const Self) -> &'a HobList {
+        &*ptr::addr_of!((*this).hobs)
+    }
+
+    /// Returns the cell holding the memory map for `'a`.
+    ///
+    /// ## Safety
+    ///
+    /// - The memory map must not be replaced for `'a`.
+    pub unsafe fn memory_raw<'a>(this: *const Self) -> &'a RefCell<MemoryMap> {
+        &*ptr::addr_of!((*this).memory)
+    }
+
+    /// Returns a pointer to the protocol database.
+    ///
+    /// ## Safety
+    ///
+    /// - `this` must point to a valid storage.
+    pub unsafe fn protocol_db_ptr(this: *mut Self) -> *mut ProtocolDatabase {
+        ptr::addr_of_mut!((*this).protocol_db)
+    }
+
+    /// Returns a pointer to the event database.
+    ///
+    /// ## Safety
+    ///
+    /// - `this` must point to a valid storage.
+    pub unsafe fn events_ptr(this: *mut Self) -> *mut EventDatabase {
+        ptr::addr_of_mut!((*this).events)
+    }
+
+    /// Returns a pointer to the configuration table database.
+    ///
+    /// ## Safety
+    ///
+    /// - `this` must point to a valid storage.
+    pub unsafe fn config_tables_ptr(this: *mut Self) -> *mut ConfigTableDatabase {
+        ptr::addr_of_mut!((*this).config_tables)
+    }
+
+    /// Returns a pointer to the variable store.
+    ///
+    /// ## Safety
+    ///
+    /// - `this` must point to a valid storage.
+    pub unsafe fn variables_ptr(this: *mut Self) -> *mut VariableStore {
+        ptr::addr_of_mut!((*this).variables)
+    }
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let configs =
+            DebugWith(|f: &mut fmt::Formatter<'_>| f.debug_list().entries(self.configs()).finish());
+        let protocols = DebugWith(|f: &mut fmt::Formatter<'_>| {
+            f.debug_map()
+                .entries(
+                    self.protocols()
+                        .map(|info| ((info.handle, PrettyGuid(info.guid)), info.name)),
+                )
+                .finish()
+        });
+        let hobs =
+            DebugWith(|f: &mut fmt::Formatter<'_>| {
+                f.debug_list()
+                    .entries(self.hobs.iter().map(|hob| {
+                        DebugWith(move |f: &mut fmt::Formatter<'_>| write!(f, "{}", hob))
+                    }))
+                    .finish()
+            });
+        f.debug_struct("Storage")
+            .field("configs", &configs)
+            .field("configs_frozen", &self.configs_frozen)
+            .field("protocols", &protocols)
+            .field("pcds", &self.pcd_db)
+            .field("hobs", &hobs)
+            .field("variables", &self.variables)
+            .field("config_tables", &self.config_tables)
+            .field("events", &self.events)
+            .field("memory", &self.memory.borrow())
+            .finish()
+    }
+}
+
+/// Formats a value with the given closure.
+struct DebugWith<F>(F);
+
+impl<F: Fn(&mut fmt::Formatter<'_>) -> fmt::Result> fmt::Debug for DebugWith<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.0)(f)
     }
 }
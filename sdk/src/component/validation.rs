@@ -0,0 +1,57 @@
+extern crate alloc;
+
+use alloc::{borrow::Cow, boxed::Box};
+use core::{any::Any, fmt};
+
+/// Allows a config type to validate its own value whenever it enters [Storage](super::Storage).
+///
+/// Implementing this trait does not register the validator. `ValidateConfig::validate` can be passed directly to
+/// [Storage::add_config_validator](super::Storage::add_config_validator).
+pub trait ValidateConfig {
+    /// Returns a description of why the value is invalid, if it is.
+    fn validate(&self) -> Result<(), Cow<'static, str>>;
+}
+
+/// A config value that failed validation.
+#[derive(Debug, Clone)]
+pub struct InvalidConfig {
+    /// The type name of the config.
+    pub config: &'static str,
+    /// Why the value is invalid.
+    pub reason: Cow<'static, str>,
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Config {} is invalid: {}", self.config, self.reason)
+    }
+}
+
+type ValidateFn = dyn Fn(&dyn Any) -> Result<(), Cow<'static, str>>;
+
+/// A type erased validator for a single config type.
+pub(crate) struct ConfigValidator {
+    name: &'static str,
+    check: Box<ValidateFn>,
+}
+
+impl ConfigValidator {
+    pub fn new<C: 'static>(
+        validator: impl Fn(&C) -> Result<(), Cow<'static, str>> + 'static,
+    ) -> Self {
+        Self {
+            name: core::any::type_name::<C>(),
+            check: Box::new(move |value| {
+                validator(value.downcast_ref().expect("Validator matches config type"))
+            }),
+        }
+    }
+
+    /// Validates a type erased config value.
+    pub fn validate(&self, value: &dyn Any) -> Result<(), InvalidConfig> {
+        (self.check)(value).map_err(|reason| InvalidConfig {
+            config: self.name,
+            reason,
+        })
+    }
+}
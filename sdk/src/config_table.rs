@@ -0,0 +1,95 @@
+//! The system configuration tables, such as the ACPI, SMBIOS and device tree tables.
+//!
+//! Tables are keyed by GUID and follow the rules of InstallConfigurationTable. Installing, replacing or removing a table
+//! signals the event group of the same GUID, so components that depend on a table can rebuild their state by
+//! registering a notify function in that group.
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{ffi::c_void, fmt};
+
+use r_efi::efi::{self, Guid, Status};
+
+use crate::guid::PrettyGuid;
+
+/// A system configuration table, identified by its GUID.
+pub trait ConfigTableType {
+    fn guid() -> &'static Guid;
+}
+
+/// The change made by [ConfigTableDatabase::install].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigTableChange {
+    Installed,
+    Replaced,
+    Removed,
+}
+
+/// The installed system configuration tables, in the order they were installed.
+#[derive(Clone, Default)]
+pub struct ConfigTableDatabase {
+    tables: Vec<efi::ConfigurationTable>,
+}
+
+impl ConfigTableDatabase {
+    pub fn new() -> Self {
+        Self { tables: Vec::new() }
+    }
+
+    /// Installs, replaces or, if `table` is null, removes a table, following the rules of InstallConfigurationTable.
+    pub fn install(
+        &mut self,
+        guid: &Guid,
+        table: *mut c_void,
+    ) -> Result<ConfigTableChange, Status> {
+        let existing = self.tables.iter().position(|t| t.vendor_guid == *guid);
+        match (existing, table.is_null()) {
+            (Some(index), true) => {
+                self.tables.remove(index);
+                Ok(ConfigTableChange::Removed)
+            }
+            (Some(index), false) => {
+                self.tables[index].vendor_table = table;
+                Ok(ConfigTableChange::Replaced)
+            }
+            (None, true) => Err(Status::NOT_FOUND),
+            (None, false) => {
+                self.tables.push(efi::ConfigurationTable {
+                    vendor_guid: *guid,
+                    vendor_table: table,
+                });
+                Ok(ConfigTableChange::Installed)
+            }
+        }
+    }
+
+    /// Returns the table with the given GUID, if installed.
+    pub fn get(&self, guid: &Guid) -> Option<*mut c_void> {
+        self.tables
+            .iter()
+            .find(|t| t.vendor_guid == *guid)
+            .map(|t| t.vendor_table)
+    }
+
+    /// Returns true if a table with the given GUID is installed.
+    pub fn contains(&self, guid: &Guid) -> bool {
+        self.get(guid).is_some()
+    }
+
+    /// Returns all installed tables, in the layout of the system table's configuration table array.
+    pub fn tables(&self) -> &[efi::ConfigurationTable] {
+        &self.tables
+    }
+}
+
+impl fmt::Debug for ConfigTableDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.tables
+                    .iter()
+                    .map(|t| (PrettyGuid(&t.vendor_guid), t.vendor_table)),
+            )
+            .finish()
+    }
+}
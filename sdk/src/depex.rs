@@ -0,0 +1,456 @@
+//! PI dependency expressions (DEPEX), as used to gate the dispatch of DXE drivers.
+//!
+//! A [Depex] can be parsed from the binary form found in a DEPEX section of a firmware file, or from a textual form
+//! similar to the `[Depex]` section of an EDK2 INF file. It is evaluated against the protocols installed in a
+//! [Storage].
+//!
+//! The textual form is an infix expression of `TRUE`, `FALSE`, protocol guids, `NOT`, `AND`, `OR` and parentheses.
+//! Like EDK2, `AND` and `OR` have the same precedence and are evaluated left to right, while `NOT` binds tighter. The
+//! expression may be preceded by `SOR`, and may instead be a single `BEFORE <guid>` or `AFTER <guid>`. A trailing
+//! `END` is optional. Guids are written in registry format, or as names resolved by the caller.
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use r_efi::efi::Guid;
+
+use crate::{
+    component::Storage,
+    guid::{parse_guid, PrettyGuid},
+};
+
+/// A single DXE dependency expression opcode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Opcode {
+    Before(Guid),
+    After(Guid),
+    Push(Guid),
+    And,
+    Or,
+    Not,
+    True,
+    False,
+    End,
+    Sor,
+}
+
+impl Opcode {
+    const BEFORE: u8 = 0x00;
+    const AFTER: u8 = 0x01;
+    const PUSH: u8 = 0x02;
+    const AND: u8 = 0x03;
+    const OR: u8 = 0x04;
+    const NOT: u8 = 0x05;
+    const TRUE: u8 = 0x06;
+    const FALSE: u8 = 0x07;
+    const END: u8 = 0x08;
+    const SOR: u8 = 0x09;
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Opcode::Before(guid) => write!(f, "BEFORE {}", PrettyGuid(guid)),
+            Opcode::After(guid) => write!(f, "AFTER {}", PrettyGuid(guid)),
+            Opcode::Push(guid) => write!(f, "PUSH {}", PrettyGuid(guid)),
+            Opcode::And => f.write_str("AND"),
+            Opcode::Or => f.write_str("OR"),
+            Opcode::Not => f.write_str("NOT"),
+            Opcode::True => f.write_str("TRUE"),
+            Opcode::False => f.write_str("FALSE"),
+            Opcode::End => f.write_str("END"),
+            Opcode::Sor => f.write_str("SOR"),
+        }
+    }
+}
+
+/// Errors that can occur when parsing a dependency expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepexError {
+    /// An unknown opcode was found at the given offset.
+    InvalidOpcode { opcode: u8, offset: usize },
+    /// The expression ended in the middle of an opcode, or without an END opcode.
+    UnexpectedEnd,
+    /// Data was found after the END opcode.
+    TrailingData,
+    /// An opcode required more operands than were on the stack, or the expression did not produce exactly one value.
+    InvalidStack,
+    /// A SOR opcode was found somewhere other than the start of the expression.
+    MisplacedSor,
+    /// A BEFORE or AFTER opcode was combined with other opcodes.
+    MisplacedBeforeAfter,
+    /// An unknown token was found in a textual expression.
+    UnknownToken(String),
+    /// A textual expression had unbalanced parentheses.
+    UnbalancedParentheses,
+}
+
+impl fmt::Display for DepexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DepexError::InvalidOpcode { opcode, offset } => {
+                write!(f, "Invalid opcode {:#04x} at offset {}", opcode, offset)
+            }
+            DepexError::UnexpectedEnd => f.write_str("Unexpected end of expression"),
+            DepexError::TrailingData => f.write_str("Data found after the END opcode"),
+            DepexError::InvalidStack => {
+                f.write_str("Expression does not evaluate to a single value")
+            }
+            DepexError::MisplacedSor => f.write_str("SOR must be the first opcode"),
+            DepexError::MisplacedBeforeAfter => {
+                f.write_str("BEFORE and AFTER must be the only opcode besides END")
+            }
+            DepexError::UnknownToken(token) => write!(f, "Unknown token '{}'", token),
+            DepexError::UnbalancedParentheses => f.write_str("Unbalanced parentheses"),
+        }
+    }
+}
+
+/// A validated DXE dependency expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Depex {
+    // Always terminated by END, and guaranteed to evaluate to a single value.
+    opcodes: Vec<Opcode>,
+}
+
+impl Depex {
+    /// Creates a dependency expression from opcodes, validating its structure.
+    pub fn new(mut opcodes: Vec<Opcode>) -> Result<Self, DepexError> {
+        match opcodes.iter().position(|op| *op == Opcode::End) {
+            Some(end) if end + 1 != opcodes.len() => return Err(DepexError::TrailingData),
+            Some(_) => {}
+            None => opcodes.push(Opcode::End),
+        }
+
+        let body = &opcodes[..opcodes.len() - 1];
+        if let [Opcode::Before(_) | Opcode::After(_)] = body {
+            return Ok(Self { opcodes });
+        }
+
+        let mut depth = 0usize;
+        for (index, opcode) in body.iter().enumerate() {
+            depth = match opcode {
+                Opcode::Before(_) | Opcode::After(_) => {
+                    return Err(DepexError::MisplacedBeforeAfter)
+                }
+                Opcode::Sor if index != 0 => return Err(DepexError::MisplacedSor),
+                Opcode::Sor => depth,
+                Opcode::Push(_) | Opcode::True | Opcode::False => depth + 1,
+                Opcode::Not if depth >= 1 => depth,
+                Opcode::And | Opcode::Or if depth >= 2 => depth - 1,
+                Opcode::Not | Opcode::And | Opcode::Or => return Err(DepexError::InvalidStack),
+                Opcode::End => unreachable!("END is always the last opcode"),
+            };
+        }
+        if depth != 1 {
+            return Err(DepexError::InvalidStack);
+        }
+        Ok(Self { opcodes })
+    }
+
+    /// Creates a dependency expression that is satisfied once all of `protocols` are installed.
+    pub fn from_protocols(protocols: &[Guid]) -> Self {
+        let mut opcodes: Vec<Opcode> = protocols.iter().copied().map(Opcode::Push).collect();
+        match opcodes.len() {
+            0 => opcodes.push(Opcode::True),
+            count => opcodes.extend(core::iter::repeat_n(Opcode::And, count - 1)),
+        }
+        opcodes.push(Opcode::End);
+        Self { opcodes }
+    }
+
+    /// Parses the binary form of a dependency expression, as found in a DEPEX section.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DepexError> {
+        let mut opcodes = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let opcode = bytes[offset];
+            offset += 1;
+            let mut guid = || {
+                let raw: &[u8; 16] = bytes
+                    .get(offset..offset + 16)
+                    .ok_or(DepexError::UnexpectedEnd)?
+                    .try_into()
+                    .expect("Slice is 16 bytes");
+                offset += 16;
+                Ok(Guid::from_bytes(raw))
+            };
+            let opcode = match opcode {
+                Opcode::BEFORE => Opcode::Before(guid()?),
+                Opcode::AFTER => Opcode::After(guid()?),
+                Opcode::PUSH => Opcode::Push(guid()?),
+                Opcode::AND => Opcode::And,
+                Opcode::OR => Opcode::Or,
+                Opcode::NOT => Opcode::Not,
+                Opcode::TRUE => Opcode::True,
+                Opcode::FALSE => Opcode::False,
+                Opcode::END => Opcode::End,
+                Opcode::SOR => Opcode::Sor,
+                opcode => {
+                    return Err(DepexError::InvalidOpcode {
+                        opcode,
+                        offset: offset - 1,
+                    })
+                }
+            };
+            opcodes.push(opcode);
+            if opcode == Opcode::End {
+                break;
+            }
+        }
+        if opcodes.last() != Some(&Opcode::End) {
+            return Err(DepexError::UnexpectedEnd);
+        }
+        if offset != bytes.len() {
+            return Err(DepexError::TrailingData);
+        }
+        Self::new(opcodes)
+    }
+
+    /// Parses the textual form of a dependency expression, where all guids are in registry format.
+    pub fn parse_text(text: &str) -> Result<Self, DepexError> {
+        Self::parse_text_with(text, |_| None)
+    }
+
+    /// Parses the textual form of a dependency expression, resolving guid names with `resolve`.
+    pub fn parse_text_with(
+        text: &str,
+        resolve: impl Fn(&str) -> Option<Guid>,
+    ) -> Result<Self, DepexError> {
+        let tokens = tokenize(text);
+        let mut parser = TextParser {
+            tokens: &tokens,
+            position: 0,
+            resolve: &resolve,
+            opcodes: Vec::new(),
+        };
+        parser.parse()?;
+        Self::new(parser.opcodes)
+    }
+
+    /// Returns the opcodes of the expression, including the terminating END.
+    pub fn opcodes(&self) -> &[Opcode] {
+        &self.opcodes
+    }
+
+    /// Serializes the expression into its binary form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for opcode in &self.opcodes {
+            let (op, guid) = match opcode {
+                Opcode::Before(guid) => (Opcode::BEFORE, Some(guid)),
+                Opcode::After(guid) => (Opcode::AFTER, Some(guid)),
+                Opcode::Push(guid) => (Opcode::PUSH, Some(guid)),
+                Opcode::And => (Opcode::AND, None),
+                Opcode::Or => (Opcode::OR, None),
+                Opcode::Not => (Opcode::NOT, None),
+                Opcode::True => (Opcode::TRUE, None),
+                Opcode::False => (Opcode::FALSE, None),
+                Opcode::End => (Opcode::END, None),
+                Opcode::Sor => (Opcode::SOR, None),
+            };
+            bytes.push(op);
+            if let Some(guid) = guid {
+                bytes.extend_from_slice(guid.as_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Returns true if the driver should only be scheduled on request (SOR).
+    pub fn is_schedule_on_request(&self) -> bool {
+        self.opcodes.first() == Some(&Opcode::Sor)
+    }
+
+    /// Returns the driver this driver must be dispatched immediately before, if any.
+    pub fn before(&self) -> Option<&Guid> {
+        match self.opcodes.first() {
+            Some(Opcode::Before(guid)) => Some(guid),
+            _ => None,
+        }
+    }
+
+    /// Returns the driver this driver must be dispatched immediately after, if any.
+    pub fn after(&self) -> Option<&Guid> {
+        match self.opcodes.first() {
+            Some(Opcode::After(guid)) => Some(guid),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the expression, using `installed` to check whether a protocol is installed.
+    ///
+    /// BEFORE and AFTER expressions are ordering constraints rather than conditions, so they evaluate to true. SOR is
+    /// ignored; it is up to the dispatcher to hold back schedule on request drivers.
+    pub fn evaluate_with(&self, installed: impl Fn(&Guid) -> bool) -> bool {
+        let mut stack = Vec::new();
+        for opcode in &self.opcodes {
+            match opcode {
+                Opcode::Before(_) | Opcode::After(_) => return true,
+                Opcode::Push(guid) => stack.push(installed(guid)),
+                Opcode::True => stack.push(true),
+                Opcode::False => stack.push(false),
+                Opcode::Not => {
+                    let value = stack.pop().expect("Validated expression");
+                    stack.push(!value);
+                }
+                Opcode::And | Opcode::Or => {
+                    let rhs = stack.pop().expect("Validated expression");
+                    let lhs = stack.pop().expect("Validated expression");
+                    stack.push(if *opcode == Opcode::And {
+                        lhs && rhs
+                    } else {
+                        lhs || rhs
+                    });
+                }
+                Opcode::Sor => {}
+                Opcode::End => break,
+            }
+        }
+        stack.pop().expect("Validated expression")
+    }
+
+    /// Evaluates the expression against the protocols installed in `storage`.
+    pub fn evaluate(&self, storage: &Storage) -> bool {
+        self.evaluate_with(|guid| storage.contains_protocol(guid))
+    }
+}
+
+impl fmt::Display for Depex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, opcode) in self.opcodes.iter().enumerate() {
+            if index != 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", opcode)?;
+        }
+        Ok(())
+    }
+}
+
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    for word in text.split_whitespace() {
+        let mut rest = word;
+        while !rest.is_empty() {
+            match rest.find(['(', ')']) {
+                Some(0) => {
+                    tokens.push(&rest[..1]);
+                    rest = &rest[1..];
+                }
+                Some(index) => {
+                    tokens.push(&rest[..index]);
+                    rest = &rest[index..];
+                }
+                None => {
+                    tokens.push(rest);
+                    rest = "";
+                }
+            }
+        }
+    }
+    tokens
+}
+
+/// A recursive descent parser that compiles the infix textual form into postfix opcodes.
+struct TextParser<'a> {
+    tokens: &'a [&'a str],
+    position: usize,
+    resolve: &'a dyn Fn(&str) -> Option<Guid>,
+    opcodes: Vec<Opcode>,
+}
+
+impl<'a> TextParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, DepexError> {
+        let token = self.peek().ok_or(DepexError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn guid(&mut self) -> Result<Guid, DepexError> {
+        let token = self.next()?;
+        parse_guid(token)
+            .or_else(|| (self.resolve)(token))
+            .ok_or_else(|| DepexError::UnknownToken(token.into()))
+    }
+
+    fn parse(&mut self) -> Result<(), DepexError> {
+        match self.peek() {
+            Some("BEFORE") | Some("AFTER") => {
+                let before = self.next()? == "BEFORE";
+                let guid = self.guid()?;
+                self.opcodes.push(if before {
+                    Opcode::Before(guid)
+                } else {
+                    Opcode::After(guid)
+                });
+            }
+            Some("SOR") => {
+                self.position += 1;
+                self.opcodes.push(Opcode::Sor);
+                self.expression()?;
+            }
+            _ => self.expression()?,
+        }
+
+        if self.peek() == Some("END") {
+            self.position += 1;
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some(")") => Err(DepexError::UnbalancedParentheses),
+            Some("BEFORE") | Some("AFTER") => Err(DepexError::MisplacedBeforeAfter),
+            Some("SOR") => Err(DepexError::MisplacedSor),
+            Some(token) => Err(DepexError::UnknownToken(token.into())),
+        }
+    }
+
+    fn expression(&mut self) -> Result<(), DepexError> {
+        self.unary()?;
+        while let Some(op @ ("AND" | "OR")) = self.peek() {
+            let opcode = if op == "AND" { Opcode::And } else { Opcode::Or };
+            self.position += 1;
+            self.unary()?;
+            self.opcodes.push(opcode);
+        }
+        Ok(())
+    }
+
+    fn unary(&mut self) -> Result<(), DepexError> {
+        match self.peek() {
+            Some("NOT") => {
+                self.position += 1;
+                self.unary()?;
+                self.opcodes.push(Opcode::Not);
+            }
+            Some("TRUE") => {
+                self.position += 1;
+                self.opcodes.push(Opcode::True);
+            }
+            Some("FALSE") => {
+                self.position += 1;
+                self.opcodes.push(Opcode::False);
+            }
+            Some("(") => {
+                self.position += 1;
+                self.expression()?;
+                if self.next().map_err(|_| DepexError::UnbalancedParentheses)? != ")" {
+                    return Err(DepexError::UnbalancedParentheses);
+                }
+            }
+            Some(")") => return Err(DepexError::UnbalancedParentheses),
+            Some("BEFORE") | Some("AFTER") => return Err(DepexError::MisplacedBeforeAfter),
+            Some("SOR") => return Err(DepexError::MisplacedSor),
+            _ => {
+                let guid = self.guid()?;
+                self.opcodes.push(Opcode::Push(guid));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,450 @@
+//! An emulation of UEFI events, timers and task priority levels (TPL).
+//!
+//! The [EventDatabase] implements the CreateEvent, SignalEvent, CheckEvent, SetTimer and CloseEvent rules, including
+//! event groups. Time is virtual: timers only fire when the clock is moved forward with [EventDatabase::advance_time],
+//! which makes timer driven code deterministic on the host.
+//!
+//! The database does not call notify functions itself, as it cannot know how to run them. Signaling an event queues its
+//! notification, and the owner of the database dispatches ready notifications with [EventDatabase::begin_notify] and
+//! [EventDatabase::end_notify]. A notification is ready once the current TPL is below its notify TPL; notifications
+//! are dispatched from the highest notify TPL down, and in the order they were queued within a TPL.
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{ffi::c_void, fmt};
+
+use r_efi::efi::{self, Guid, Status, Tpl};
+
+use crate::guid::PrettyGuid;
+
+/// The event group signaled when ExitBootServices is called.
+pub const EVENT_GROUP_EXIT_BOOT_SERVICES: Guid = Guid::from_fields(
+    0x27abf055,
+    0xb1b8,
+    0x4c26,
+    0x80,
+    0x48,
+    &[0x74, 0x8f, 0x37, 0xba, 0xa2, 0xdf],
+);
+
+/// The event group signaled when SetVirtualAddressMap is called.
+pub const EVENT_GROUP_VIRTUAL_ADDRESS_CHANGE: Guid = Guid::from_fields(
+    0x13fa7698,
+    0xc831,
+    0x49c7,
+    0x87,
+    0xea,
+    &[0x8f, 0x43, 0xfc, 0xc2, 0x51, 0x96],
+);
+
+const VALID_EVENT_TYPES: u32 = efi::EVT_TIMER
+    | efi::EVT_RUNTIME
+    | efi::EVT_NOTIFY_WAIT
+    | efi::EVT_NOTIFY_SIGNAL
+    | efi::EVT_SIGNAL_EXIT_BOOT_SERVICES
+    | efi::EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE;
+
+/// The notify function of an event.
+#[derive(Debug, Copy, Clone)]
+pub enum EventNotify {
+    /// The event has no notify function.
+    None,
+    /// An EFI notify function and its context, e.g. registered by a C based driver.
+    Efi {
+        function: efi::EventNotify,
+        context: *mut c_void,
+    },
+    /// A notify function identified by the owner of the database, e.g. a component.
+    Owner(usize),
+}
+
+/// A notification that is ready to be dispatched.
+#[derive(Debug, Copy, Clone)]
+pub struct Notification {
+    /// The event being notified.
+    pub event: efi::Event,
+    /// The notify function to call.
+    pub notify: EventNotify,
+    /// The TPL to restore once the notify function returns, with [EventDatabase::end_notify].
+    pub previous_tpl: Tpl,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Timer {
+    trigger_time: u64,
+    period: u64,
+}
+
+#[derive(Debug, Clone)]
+struct EventEntry {
+    event_type: u32,
+    notify_tpl: Tpl,
+    notify: EventNotify,
+    group: Option<Guid>,
+    signaled: bool,
+    queued: bool,
+    timer: Option<Timer>,
+}
+
+/// A database of events keyed by their handle.
+#[derive(Clone)]
+pub struct EventDatabase {
+    events: BTreeMap<usize, EventEntry>,
+    next_event: usize,
+    // Queued notifications, in the order they were signaled.
+    queue: Vec<usize>,
+    current_tpl: Tpl,
+    // The virtual time, in 100ns units.
+    now: u64,
+}
+
+impl Default for EventDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventDatabase {
+    pub fn new() -> Self {
+        Self {
+            events: BTreeMap::new(),
+            next_event: 1,
+            queue: Vec::new(),
+            current_tpl: efi::TPL_APPLICATION,
+            now: 0,
+        }
+    }
+
+    fn key(event: efi::Event) -> usize {
+        event as usize
+    }
+
+    fn entry(&self, event: efi::Event) -> Result<&EventEntry, Status> {
+        self.events
+            .get(&Self::key(event))
+            .ok_or(Status::INVALID_PARAMETER)
+    }
+
+    fn entry_mut(&mut self, event: efi::Event) -> Result<&mut EventEntry, Status> {
+        self.events
+            .get_mut(&Self::key(event))
+            .ok_or(Status::INVALID_PARAMETER)
+    }
+
+    /// Creates an event, following the rules of CreateEventEx. Returns the new event.
+    pub fn create(
+        &mut self,
+        event_type: u32,
+        notify_tpl: Tpl,
+        notify: EventNotify,
+        group: Option<&Guid>,
+    ) -> Result<efi::Event, Status> {
+        if event_type & !VALID_EVENT_TYPES != 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let notify_types = event_type & (efi::EVT_NOTIFY_WAIT | efi::EVT_NOTIFY_SIGNAL);
+        if notify_types == efi::EVT_NOTIFY_WAIT | efi::EVT_NOTIFY_SIGNAL {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let notify = if notify_types != 0 {
+            if matches!(notify, EventNotify::None)
+                || !matches!(
+                    notify_tpl,
+                    efi::TPL_APPLICATION
+                        | efi::TPL_CALLBACK
+                        | efi::TPL_NOTIFY
+                        | efi::TPL_HIGH_LEVEL
+                )
+            {
+                return Err(Status::INVALID_PARAMETER);
+            }
+            notify
+        } else {
+            EventNotify::None
+        };
+
+        // The legacy exit boot services and virtual address change event types are event groups.
+        let group = match event_type {
+            efi::EVT_SIGNAL_EXIT_BOOT_SERVICES => Some(EVENT_GROUP_EXIT_BOOT_SERVICES),
+            efi::EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE => Some(EVENT_GROUP_VIRTUAL_ADDRESS_CHANGE),
+            _ => group.copied(),
+        };
+
+        let key = self.next_event;
+        self.next_event += 1;
+        self.events.insert(
+            key,
+            EventEntry {
+                event_type,
+                notify_tpl,
+                notify,
+                group,
+                signaled: false,
+                queued: false,
+                timer: None,
+            },
+        );
+        Ok(key as efi::Event)
+    }
+
+    /// Closes an event, cancelling its timer and any queued notification.
+    pub fn close(&mut self, event: efi::Event) -> Result<(), Status> {
+        self.events
+            .remove(&Self::key(event))
+            .ok_or(Status::INVALID_PARAMETER)?;
+        self.queue.retain(|key| *key != Self::key(event));
+        Ok(())
+    }
+
+    /// Closes every event whose notify function matches `filter`, returning the number of events closed.
+    pub fn close_all(&mut self, filter: impl Fn(&EventNotify) -> bool) -> usize {
+        let len = self.events.len();
+        self.events.retain(|_, entry| !filter(&entry.notify));
+        let events = &self.events;
+        self.queue.retain(|key| events.contains_key(key));
+        len - self.events.len()
+    }
+
+    /// Returns true if the event exists.
+    pub fn contains(&self, event: efi::Event) -> bool {
+        self.events.contains_key(&Self::key(event))
+    }
+
+    /// Signals an event, or all events of its group, following the rules of SignalEvent.
+    pub fn signal(&mut self, event: efi::Event) -> Result<(), Status> {
+        match self.entry(event)?.group {
+            Some(group) => {
+                self.signal_group(&group);
+            }
+            None => self.signal_one(Self::key(event)),
+        }
+        Ok(())
+    }
+
+    /// Signals all events of a group. Returns the number of events signaled.
+    pub fn signal_group(&mut self, group: &Guid) -> usize {
+        let keys: Vec<usize> = self
+            .events
+            .iter()
+            .filter(|(_, entry)| entry.group.as_ref() == Some(group))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &keys {
+            self.signal_one(*key);
+        }
+        keys.len()
+    }
+
+    fn signal_one(&mut self, key: usize) {
+        let entry = self.events.get_mut(&key).expect("Event exists");
+        if entry.signaled {
+            return;
+        }
+        entry.signaled = true;
+        if entry.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+            self.queue_notify(key);
+        }
+    }
+
+    fn queue_notify(&mut self, key: usize) {
+        let entry = self.events.get_mut(&key).expect("Event exists");
+        if !entry.queued {
+            entry.queued = true;
+            self.queue.push(key);
+        }
+    }
+
+    /// Checks whether an event is signaled, following the rules of CheckEvent.
+    ///
+    /// A signaled event is reset and `SUCCESS` returned. Otherwise `NOT_READY` is returned, and for wait events the
+    /// notify function is queued, so it may signal the event; the caller should dispatch it and check again.
+    pub fn check(&mut self, event: efi::Event) -> Result<(), Status> {
+        let key = Self::key(event);
+        let entry = self.entry_mut(event)?;
+        if entry.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        if entry.signaled {
+            entry.signaled = false;
+            return Ok(());
+        }
+        if entry.event_type & efi::EVT_NOTIFY_WAIT != 0 {
+            self.queue_notify(key);
+        }
+        Err(Status::NOT_READY)
+    }
+
+    /// Returns true if the event is signaled, without resetting it.
+    pub fn is_signaled(&self, event: efi::Event) -> Result<bool, Status> {
+        Ok(self.entry(event)?.signaled)
+    }
+
+    /// Sets, or cancels, the timer of an event, following the rules of SetTimer. `trigger_time` is in 100ns units.
+    pub fn set_timer(
+        &mut self,
+        event: efi::Event,
+        delay: efi::TimerDelay,
+        trigger_time: u64,
+    ) -> Result<(), Status> {
+        let now = self.now;
+        let entry = self.entry_mut(event)?;
+        if entry.event_type & efi::EVT_TIMER == 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        entry.timer = match delay {
+            efi::TIMER_CANCEL => None,
+            efi::TIMER_RELATIVE => Some(Timer {
+                trigger_time: now.saturating_add(trigger_time),
+                period: 0,
+            }),
+            efi::TIMER_PERIODIC => Some(Timer {
+                trigger_time: now.saturating_add(trigger_time),
+                // A period of zero fires on every tick of the clock.
+                period: trigger_time.max(1),
+            }),
+            _ => return Err(Status::INVALID_PARAMETER),
+        };
+        Ok(())
+    }
+
+    /// Returns the current virtual time, in 100ns units.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Returns the trigger time of the earliest pending timer of the given events, or of all events if None.
+    pub fn next_trigger_time(&self, events: Option<&[efi::Event]>) -> Option<u64> {
+        self.events
+            .iter()
+            .filter(|(key, _)| {
+                events.is_none_or(|events| events.iter().any(|event| Self::key(*event) == **key))
+            })
+            .filter_map(|(_, entry)| entry.timer.map(|timer| timer.trigger_time))
+            .min()
+    }
+
+    /// Moves the virtual clock forward by `delta` 100ns units, signaling all timers that expire. Periodic timers that
+    /// expire multiple times are only signaled once. Returns the number of timers signaled.
+    pub fn advance_time(&mut self, delta: u64) -> usize {
+        self.now = self.now.saturating_add(delta);
+        let now = self.now;
+        let mut expired = Vec::new();
+        for (key, entry) in self.events.iter_mut() {
+            let Some(timer) = &mut entry.timer else {
+                continue;
+            };
+            if timer.trigger_time > now {
+                continue;
+            }
+            expired.push(*key);
+            match (now - timer.trigger_time).checked_div(timer.period) {
+                Some(elapsed_periods) => {
+                    timer.trigger_time = elapsed_periods
+                        .saturating_add(1)
+                        .saturating_mul(timer.period)
+                        .saturating_add(timer.trigger_time)
+                }
+                None => entry.timer = None,
+            }
+        }
+        for key in &expired {
+            self.signal_one(*key);
+        }
+        expired.len()
+    }
+
+    /// Returns the current TPL.
+    pub fn current_tpl(&self) -> Tpl {
+        self.current_tpl
+    }
+
+    /// Raises the TPL, following the rules of RaiseTPL. Returns the previous TPL.
+    ///
+    /// Raising to a lower TPL is invalid, and leaves the TPL unchanged.
+    pub fn raise_tpl(&mut self, new_tpl: Tpl) -> Tpl {
+        let previous = self.current_tpl;
+        self.current_tpl = new_tpl.max(previous);
+        previous
+    }
+
+    /// Restores the TPL, following the rules of RestoreTPL. The caller should dispatch any notifications that became
+    /// ready.
+    ///
+    /// Restoring to a higher TPL is invalid, and leaves the TPL unchanged.
+    pub fn restore_tpl(&mut self, old_tpl: Tpl) {
+        self.current_tpl = old_tpl.min(self.current_tpl);
+    }
+
+    /// Returns true if a queued notification can be dispatched at the current TPL.
+    pub fn has_ready_notify(&self) -> bool {
+        self.queue
+            .iter()
+            .any(|key| self.events[key].notify_tpl > self.current_tpl)
+    }
+
+    /// Removes the next ready notification from the queue, and raises the TPL to its notify TPL. The caller must call
+    /// the notify function, and then [end_notify](Self::end_notify).
+    ///
+    /// `filter` selects which notify functions the caller is able to dispatch.
+    pub fn begin_notify(&mut self, filter: impl Fn(&EventNotify) -> bool) -> Option<Notification> {
+        let current_tpl = self.current_tpl;
+        let (index, key) = self
+            .queue
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| self.events[key].notify_tpl > current_tpl)
+            // The first of the highest notify TPL, as max_by_key returns the last maximum.
+            .rev()
+            .max_by_key(|(_, key)| self.events[key].notify_tpl)
+            .filter(|(_, key)| filter(&self.events[key].notify))
+            .map(|(index, key)| (index, *key))?;
+        self.queue.remove(index);
+
+        let entry = self.events.get_mut(&key).expect("Event exists");
+        entry.queued = false;
+        // Signal events are reset before they are notified, so the notify function can signal them again.
+        if entry.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+            entry.signaled = false;
+        }
+        self.current_tpl = entry.notify_tpl;
+        Some(Notification {
+            event: key as efi::Event,
+            notify: entry.notify,
+            previous_tpl: current_tpl,
+        })
+    }
+
+    /// Completes a notification started with [begin_notify](Self::begin_notify), restoring the TPL.
+    pub fn end_notify(&mut self, notification: &Notification) {
+        self.current_tpl = notification.previous_tpl;
+    }
+}
+
+impl fmt::Debug for EventDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let events = self.events.iter().map(|(key, entry)| {
+            (
+                key,
+                (
+                    entry.event_type,
+                    entry.notify_tpl,
+                    entry.group.as_ref().map(PrettyGuid),
+                    entry.signaled,
+                ),
+            )
+        });
+        f.debug_struct("EventDatabase")
+            .field("events", &DebugMap(events))
+            .field("current_tpl", &self.current_tpl)
+            .field("now", &self.now)
+            .finish()
+    }
+}
+
+struct DebugMap<I>(I);
+
+impl<K: fmt::Debug, V: fmt::Debug, I: Iterator<Item = (K, V)> + Clone> fmt::Debug for DebugMap<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.0.clone()).finish()
+    }
+}
@@ -0,0 +1,462 @@
+//! PI Firmware Volume (FV) and Firmware File System (FFS) parsing.
+//!
+//! [FirmwareVolume::parse] reads an FV image, such as one read from disk, and [FirmwareVolume::drivers] returns the
+//! DXE drivers it contains, including those in nested FV images, along with their GUIDs and dependency expressions.
+//!
+//! Encapsulation sections are expanded when possible. Sections that are not compressed and GUID-defined sections that
+//! do not require processing are replaced by the sections they contain. Compressed sections and GUID-defined sections
+//! that require processing (e.g. LZMA) are kept as-is, as no decompressors are available here.
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use r_efi::efi::Guid;
+
+use crate::{
+    bytes::{read_guid, read_u16, read_u24, read_u32, read_u64},
+    depex::{Depex, DepexError},
+    guid::PrettyGuid,
+};
+
+/// The `EFI_FIRMWARE_FILE_SYSTEM2_GUID` file system.
+pub const FFS2_GUID: Guid = Guid::from_fields(
+    0x8c8ce578,
+    0x8a3d,
+    0x4f1c,
+    0x99,
+    0x35,
+    &[0x89, 0x61, 0x85, 0xc3, 0x2d, 0xd3],
+);
+
+/// The `EFI_FIRMWARE_FILE_SYSTEM3_GUID` file system, which supports large files.
+pub const FFS3_GUID: Guid = Guid::from_fields(
+    0x5473c07a,
+    0x3dcb,
+    0x4dca,
+    0xbd,
+    0x6f,
+    &[0x1e, 0x96, 0x89, 0xe7, 0x34, 0x9a],
+);
+
+const FV_SIGNATURE: &[u8; 4] = b"_FVH";
+const FVB2_ERASE_POLARITY: u32 = 0x0000_0800;
+const FV_HEADER_SIZE: usize = 56;
+
+const FFS_ATTRIB_LARGE_FILE: u8 = 0x01;
+const FILE_HEADER_SIZE: usize = 24;
+const FILE_HEADER2_SIZE: usize = 32;
+const FILE_DATA_VALID: u8 = 0x04;
+const FILE_MARKED_FOR_UPDATE: u8 = 0x08;
+
+const SECTION_HEADER_SIZE: usize = 4;
+const SECTION_HEADER2_SIZE: usize = 8;
+const GUID_DEFINED_HEADER_SIZE: usize = 20;
+// Encapsulation sections nested deeper than this are rejected rather than risking a stack overflow.
+const MAX_SECTION_DEPTH: usize = 8;
+const NOT_COMPRESSED: u8 = 0x00;
+const GUIDED_SECTION_PROCESSING_REQUIRED: u16 = 0x01;
+
+/// The type of an FFS file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FileType(pub u8);
+
+impl FileType {
+    pub const RAW: Self = Self(0x01);
+    pub const FREEFORM: Self = Self(0x02);
+    pub const SECURITY_CORE: Self = Self(0x03);
+    pub const PEI_CORE: Self = Self(0x04);
+    pub const DXE_CORE: Self = Self(0x05);
+    pub const PEIM: Self = Self(0x06);
+    pub const DRIVER: Self = Self(0x07);
+    pub const COMBINED_PEIM_DRIVER: Self = Self(0x08);
+    pub const APPLICATION: Self = Self(0x09);
+    pub const MM: Self = Self(0x0a);
+    pub const FIRMWARE_VOLUME_IMAGE: Self = Self(0x0b);
+    pub const COMBINED_MM_DXE: Self = Self(0x0c);
+    pub const MM_CORE: Self = Self(0x0d);
+    pub const MM_STANDALONE: Self = Self(0x0e);
+    pub const MM_CORE_STANDALONE: Self = Self(0x0f);
+    pub const FFS_PAD: Self = Self(0xf0);
+
+    /// Returns true if files of this type are dispatched by the DXE dispatcher.
+    pub fn is_dxe_driver(&self) -> bool {
+        matches!(
+            *self,
+            Self::DRIVER | Self::COMBINED_PEIM_DRIVER | Self::COMBINED_MM_DXE
+        )
+    }
+}
+
+/// The type of an FFS section.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SectionType(pub u8);
+
+impl SectionType {
+    pub const COMPRESSION: Self = Self(0x01);
+    pub const GUID_DEFINED: Self = Self(0x02);
+    pub const DISPOSABLE: Self = Self(0x03);
+    pub const PE32: Self = Self(0x10);
+    pub const PIC: Self = Self(0x11);
+    pub const TE: Self = Self(0x12);
+    pub const DXE_DEPEX: Self = Self(0x13);
+    pub const VERSION: Self = Self(0x14);
+    pub const USER_INTERFACE: Self = Self(0x15);
+    pub const COMPATIBILITY16: Self = Self(0x16);
+    pub const FIRMWARE_VOLUME_IMAGE: Self = Self(0x17);
+    pub const FREEFORM_SUBTYPE_GUID: Self = Self(0x18);
+    pub const RAW: Self = Self(0x19);
+    pub const PEI_DEPEX: Self = Self(0x1b);
+    pub const MM_DEPEX: Self = Self(0x1c);
+}
+
+/// Errors that can occur when parsing a firmware volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FvError {
+    /// The image is smaller than its headers claim.
+    Truncated,
+    /// The FV header does not have the `_FVH` signature.
+    InvalidSignature,
+    /// The FV header length is smaller than the FV header.
+    InvalidHeaderLength,
+    /// The FV header checksum is invalid.
+    InvalidChecksum,
+    /// The FV does not use a supported firmware file system.
+    UnsupportedFileSystem(Guid),
+    /// The file at the given offset of the FV has an invalid header.
+    InvalidFile { offset: usize },
+    /// A section of the given file has an invalid header.
+    InvalidSection { file: Guid },
+    /// The DEPEX section of the given file could not be parsed.
+    InvalidDepex { file: Guid, error: DepexError },
+}
+
+impl fmt::Display for FvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FvError::Truncated => f.write_str("Firmware volume is truncated"),
+            FvError::InvalidSignature => f.write_str("Firmware volume has an invalid signature"),
+            FvError::InvalidHeaderLength => {
+                f.write_str("Firmware volume has an invalid header length")
+            }
+            FvError::InvalidChecksum => f.write_str("Firmware volume has an invalid checksum"),
+            FvError::UnsupportedFileSystem(guid) => {
+                write!(f, "Unsupported file system {}", PrettyGuid(guid))
+            }
+            FvError::InvalidFile { offset } => write!(f, "Invalid file at offset {:#x}", offset),
+            FvError::InvalidSection { file } => {
+                write!(f, "Invalid section in file {}", PrettyGuid(file))
+            }
+            FvError::InvalidDepex { file, error } => {
+                write!(f, "Invalid depex in file {}: {}", PrettyGuid(file), error)
+            }
+        }
+    }
+}
+
+/// A single section of an FFS file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The type of the section.
+    pub section_type: SectionType,
+    /// The section data, without the section header.
+    pub data: Vec<u8>,
+}
+
+/// A single file in a firmware volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    /// The name of the file.
+    pub name: Guid,
+    /// The type of the file.
+    pub file_type: FileType,
+    /// The sections of the file, with supported encapsulation sections expanded. Empty for raw and pad files.
+    pub sections: Vec<Section>,
+    /// The file data, without the file header.
+    pub data: Vec<u8>,
+}
+
+impl File {
+    /// Returns the first section of the given type.
+    pub fn section(&self, section_type: SectionType) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| section.section_type == section_type)
+    }
+
+    /// Returns the name of the file from its user interface section.
+    pub fn ui_name(&self) -> Option<String> {
+        self.section(SectionType::USER_INTERFACE)
+            .map(|section| decode_ucs2(&section.data))
+    }
+
+    /// Returns the build number and version string of the file from its version section.
+    pub fn version(&self) -> Option<(u16, String)> {
+        let section = self.section(SectionType::VERSION)?;
+        let build = u16::from_le_bytes(section.data.get(..2)?.try_into().ok()?);
+        Some((build, decode_ucs2(&section.data[2..])))
+    }
+}
+
+/// A DXE driver found in a firmware volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Driver {
+    /// The file name of the driver.
+    pub name: Guid,
+    /// The type of the driver file.
+    pub file_type: FileType,
+    /// The name of the driver from its user interface section.
+    pub ui_name: Option<String>,
+    /// The build number and version string of the driver.
+    pub version: Option<(u16, String)>,
+    /// The dependency expression of the driver. Drivers without one have no dependencies.
+    pub depex: Option<Depex>,
+    /// The PE32 or TE image of the driver, if it is not in an unsupported encapsulation section.
+    pub image: Option<Vec<u8>>,
+}
+
+impl fmt::Display for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.ui_name {
+            Some(name) => write!(f, "{} ({})", name, PrettyGuid(&self.name)),
+            None => write!(f, "{}", PrettyGuid(&self.name)),
+        }
+    }
+}
+
+/// A parsed PI firmware volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareVolume {
+    /// The name of the volume, from its extended header.
+    pub name: Option<Guid>,
+    /// The firmware file system of the volume.
+    pub file_system: Guid,
+    /// The attributes of the volume.
+    pub attributes: u32,
+    /// The files of the volume, excluding free space.
+    pub files: Vec<File>,
+}
+
+impl FirmwareVolume {
+    /// Parses a firmware volume image.
+    pub fn parse(bytes: &[u8]) -> Result<Self, FvError> {
+        let header = bytes.get(..FV_HEADER_SIZE).ok_or(FvError::Truncated)?;
+        if &header[40..44] != FV_SIGNATURE {
+            return Err(FvError::InvalidSignature);
+        }
+        let file_system = read_guid(&header[16..32]);
+        if file_system != FFS2_GUID && file_system != FFS3_GUID {
+            return Err(FvError::UnsupportedFileSystem(file_system));
+        }
+        let length = usize::try_from(read_u64(&header[32..40])).map_err(|_| FvError::Truncated)?;
+        let attributes = read_u32(&header[44..48]);
+        let header_length = read_u16(&header[48..50]) as usize;
+        let ext_header_offset = read_u16(&header[52..54]) as usize;
+        if header_length < FV_HEADER_SIZE {
+            return Err(FvError::InvalidHeaderLength);
+        }
+
+        let bytes = bytes.get(..length).ok_or(FvError::Truncated)?;
+        let header = bytes.get(..header_length).ok_or(FvError::Truncated)?;
+        let checksum = header
+            .chunks_exact(2)
+            .fold(0u16, |sum, word| sum.wrapping_add(read_u16(word)));
+        if checksum != 0 {
+            return Err(FvError::InvalidChecksum);
+        }
+
+        let (name, mut offset) = if ext_header_offset != 0 {
+            let ext_header = bytes
+                .get(ext_header_offset..ext_header_offset + 20)
+                .ok_or(FvError::Truncated)?;
+            let ext_header_size = read_u32(&ext_header[16..20]) as usize;
+            (
+                Some(read_guid(&ext_header[..16])),
+                ext_header_offset + ext_header_size,
+            )
+        } else {
+            (None, header_length)
+        };
+
+        let erase_byte = if attributes & FVB2_ERASE_POLARITY != 0 {
+            0xff
+        } else {
+            0x00
+        };
+        let mut files = Vec::new();
+        loop {
+            offset = align_up(offset, 8);
+            let Some(header) = bytes.get(offset..offset + FILE_HEADER_SIZE) else {
+                break;
+            };
+            if header.iter().all(|byte| *byte == erase_byte) {
+                break;
+            }
+            let (file, size) =
+                parse_file(&bytes[offset..], erase_byte).ok_or(FvError::InvalidFile { offset })?;
+            if let Some(file) = file {
+                files.push(file?);
+            }
+            offset += size;
+        }
+
+        Ok(Self {
+            name,
+            file_system,
+            attributes,
+            files,
+        })
+    }
+
+    /// Returns the DXE drivers in the volume and any volumes nested in it, in the order they appear.
+    pub fn drivers(&self) -> Result<Vec<Driver>, FvError> {
+        let mut drivers = Vec::new();
+        for file in &self.files {
+            if file.file_type == FileType::FIRMWARE_VOLUME_IMAGE {
+                for section in &file.sections {
+                    if section.section_type == SectionType::FIRMWARE_VOLUME_IMAGE {
+                        drivers.extend(Self::parse(&section.data)?.drivers()?);
+                    }
+                }
+                continue;
+            }
+            if !file.file_type.is_dxe_driver() {
+                continue;
+            }
+            let depex = file
+                .section(SectionType::DXE_DEPEX)
+                .map(|section| Depex::parse(&section.data))
+                .transpose()
+                .map_err(|error| FvError::InvalidDepex {
+                    file: file.name,
+                    error,
+                })?;
+            let image = file
+                .section(SectionType::PE32)
+                .or_else(|| file.section(SectionType::TE))
+                .map(|section| section.data.clone());
+            drivers.push(Driver {
+                name: file.name,
+                file_type: file.file_type,
+                ui_name: file.ui_name(),
+                version: file.version(),
+                depex,
+                image,
+            });
+        }
+        Ok(drivers)
+    }
+}
+
+/// Parses the file at the start of `bytes`, returning it if it is valid and not a pad file, and its size.
+fn parse_file(bytes: &[u8], erase_byte: u8) -> Option<(Option<Result<File, FvError>>, usize)> {
+    let header = bytes.get(..FILE_HEADER_SIZE)?;
+    let name = read_guid(&header[..16]);
+    let file_type = FileType(header[18]);
+    let attributes = header[19];
+    let (size, header_size) = if attributes & FFS_ATTRIB_LARGE_FILE != 0 {
+        let size = read_u64(bytes.get(24..FILE_HEADER2_SIZE)?);
+        (usize::try_from(size).ok()?, FILE_HEADER2_SIZE)
+    } else {
+        (read_u24(&header[20..23]), FILE_HEADER_SIZE)
+    };
+    if size < header_size {
+        return None;
+    }
+    let data = bytes.get(header_size..size)?;
+
+    // The state bits are inverted when the erase polarity is set. The highest set bit is the current state.
+    let state = header[23] ^ erase_byte;
+    let state = if state == 0 {
+        0
+    } else {
+        1 << (7 - state.leading_zeros())
+    };
+    if (state != FILE_DATA_VALID && state != FILE_MARKED_FOR_UPDATE)
+        || file_type == FileType::FFS_PAD
+    {
+        return Some((None, size));
+    }
+
+    let sections = match file_type {
+        FileType::RAW => Ok(Vec::new()),
+        _ => parse_sections(data, 0).ok_or(FvError::InvalidSection { file: name }),
+    };
+    let file = sections.map(|sections| File {
+        name,
+        file_type,
+        sections,
+        data: data.to_vec(),
+    });
+    Some((Some(file), size))
+}
+
+/// Parses a section stream nested in `depth` encapsulation sections, expanding supported encapsulation sections.
+fn parse_sections(bytes: &[u8], depth: usize) -> Option<Vec<Section>> {
+    if depth > MAX_SECTION_DEPTH {
+        return None;
+    }
+    let mut sections = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let header = bytes.get(offset..offset + SECTION_HEADER_SIZE)?;
+        let section_type = SectionType(header[3]);
+        let (size, header_size) = match read_u24(&header[..3]) {
+            0xff_ffff => {
+                let size = read_u32(bytes.get(offset + 4..offset + SECTION_HEADER2_SIZE)?);
+                (size as usize, SECTION_HEADER2_SIZE)
+            }
+            size => (size, SECTION_HEADER_SIZE),
+        };
+        if size < header_size {
+            return None;
+        }
+        let section = bytes.get(offset..offset + size)?;
+        let data = &section[header_size..];
+
+        match section_type {
+            SectionType::COMPRESSION if *data.get(4)? == NOT_COMPRESSED => {
+                sections.extend(parse_sections(&data[5..], depth + 1)?);
+            }
+            SectionType::GUID_DEFINED => {
+                let data_offset = read_u16(data.get(16..18)?) as usize;
+                let attributes = read_u16(data.get(18..20)?);
+                // The data offset is relative to the start of the section, and must be past the GUID-defined header.
+                if data_offset < header_size + GUID_DEFINED_HEADER_SIZE
+                    || data_offset > section.len()
+                {
+                    return None;
+                }
+                if attributes & GUIDED_SECTION_PROCESSING_REQUIRED == 0 {
+                    sections.extend(parse_sections(&section[data_offset..], depth + 1)?);
+                } else {
+                    sections.push(Section {
+                        section_type,
+                        data: data.to_vec(),
+                    });
+                }
+            }
+            _ => sections.push(Section {
+                section_type,
+                data: data.to_vec(),
+            }),
+        }
+        offset = align_up(offset + size, 4);
+    }
+    Some(sections)
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+/// Decodes a null terminated UCS-2 string.
+fn decode_ucs2(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(read_u16)
+        .take_while(|unit| *unit != 0);
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
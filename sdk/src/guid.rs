@@ -0,0 +1,51 @@
+use core::fmt;
+
+use r_efi::efi::Guid;
+
+/// Formats a [Guid] in its registry format, e.g. `3152bca5-eade-433d-862e-c01cdc291f44`.
+pub struct PrettyGuid<'a>(pub &'a Guid);
+
+impl fmt::Display for PrettyGuid<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (time_low, time_mid, time_hi, clk_hi, clk_low, node) = self.0.as_fields();
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            time_low, time_mid, time_hi, clk_hi, clk_low
+        )?;
+        node.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl fmt::Debug for PrettyGuid<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Parses a [Guid] in its registry format, e.g. `3152bca5-eade-433d-862e-c01cdc291f44`.
+pub fn parse_guid(text: &str) -> Option<Guid> {
+    let mut parts = text.split('-');
+    let mut next = |len: usize| {
+        let part = parts.next()?;
+        (part.len() == len && part.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| u64::from_str_radix(part, 16).ok())
+            .flatten()
+    };
+    let time_low = next(8)? as u32;
+    let time_mid = next(4)? as u16;
+    let time_hi = next(4)? as u16;
+    let clk = next(4)? as u16;
+    let node = next(12)?.to_be_bytes();
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Guid::from_fields(
+        time_low,
+        time_mid,
+        time_hi,
+        (clk >> 8) as u8,
+        clk as u8,
+        &[node[2], node[3], node[4], node[5], node[6], node[7]],
+    ))
+}
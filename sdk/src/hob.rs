@@ -0,0 +1,387 @@
+//! PI Hand-Off Block (HOB) list parsing.
+//!
+//! The HOB list is produced by the PEI phase and describes the platform to the DXE phase. [HobList::parse] reads a HOB
+//! list from memory, after which it can be loaded into [Storage](crate::component::Storage) and consumed by components
+//! through the `Hob<T>` and `GuidHob<G>` params.
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{fmt, slice};
+
+use r_efi::efi::Guid;
+
+use crate::{
+    bytes::{read_guid, read_u16, read_u32, read_u64},
+    guid::PrettyGuid,
+};
+
+const HOB_TYPE_HANDOFF: u16 = 0x0001;
+const HOB_TYPE_MEMORY_ALLOCATION: u16 = 0x0002;
+const HOB_TYPE_RESOURCE_DESCRIPTOR: u16 = 0x0003;
+const HOB_TYPE_GUID_EXTENSION: u16 = 0x0004;
+const HOB_TYPE_FV: u16 = 0x0005;
+const HOB_TYPE_CPU: u16 = 0x0006;
+const HOB_TYPE_FV2: u16 = 0x0009;
+const HOB_TYPE_FV3: u16 = 0x000c;
+const HOB_TYPE_UNUSED: u16 = 0xfffe;
+const HOB_TYPE_END_OF_HOB_LIST: u16 = 0xffff;
+
+const HOB_HEADER_SIZE: usize = 8;
+
+/// The Phase Handoff Information Table, which is always the first HOB.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HandoffInfoTable {
+    pub version: u32,
+    pub boot_mode: u32,
+    pub memory_top: u64,
+    pub memory_bottom: u64,
+    pub free_memory_top: u64,
+    pub free_memory_bottom: u64,
+    pub end_of_hob_list: u64,
+}
+
+/// A memory range allocated before the DXE phase.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryAllocation {
+    /// Identifies the allocation, e.g. the BSP stack or a module. Zero for generic allocations.
+    pub name: Guid,
+    pub base: u64,
+    pub length: u64,
+    /// The `EFI_MEMORY_TYPE` of the allocation.
+    pub memory_type: u32,
+}
+
+/// A system resource, such as a range of system memory or MMIO.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResourceDescriptor {
+    pub owner: Guid,
+    pub resource_type: u32,
+    pub attributes: u32,
+    pub start: u64,
+    pub length: u64,
+}
+
+/// Data passed from PEI under a GUID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuidExtension {
+    pub name: Guid,
+    pub data: Vec<u8>,
+}
+
+/// A firmware volume that should be made available to the DXE dispatcher.
+///
+/// Produced by the FV, FV2 and FV3 HOBs. The names are only present in FV2 and FV3 HOBs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FirmwareVolume {
+    pub base: u64,
+    pub length: u64,
+    /// The name of the volume, and of the file it was extracted from.
+    pub names: Option<(Guid, Guid)>,
+}
+
+/// Describes the address spaces of the processor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cpu {
+    /// The number of address bits of the memory space.
+    pub memory_space_size: u8,
+    /// The number of address bits of the I/O space.
+    pub io_space_size: u8,
+}
+
+/// A single HOB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hob {
+    HandoffInfoTable(HandoffInfoTable),
+    MemoryAllocation(MemoryAllocation),
+    ResourceDescriptor(ResourceDescriptor),
+    GuidExtension(GuidExtension),
+    FirmwareVolume(FirmwareVolume),
+    Cpu(Cpu),
+    /// A HOB of a type that is not parsed, with its data after the HOB header.
+    Other {
+        hob_type: u16,
+        data: Vec<u8>,
+    },
+}
+
+/// Allows a HOB variant to be retrieved by type, e.g. through the `Hob<T>` param.
+pub trait HobType: Sized + 'static {
+    /// Returns the value of the HOB if it is of this type.
+    fn from_hob(hob: &Hob) -> Option<&Self>;
+}
+
+macro_rules! impl_hob_type {
+    ($($variant:ident),*) => {
+        $(
+            impl HobType for $variant {
+                fn from_hob(hob: &Hob) -> Option<&Self> {
+                    match hob {
+                        Hob::$variant(value) => Some(value),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_hob_type!(
+    HandoffInfoTable,
+    MemoryAllocation,
+    ResourceDescriptor,
+    GuidExtension,
+    FirmwareVolume,
+    Cpu
+);
+
+/// Typed data passed from PEI in a GUID extension HOB, retrieved through the `GuidHob<G>` param.
+pub trait GuidHobData: Sized + 'static {
+    /// The name of the GUID extension HOB.
+    fn guid() -> &'static Guid;
+    /// Parses the data of the HOB, returning None if it is malformed.
+    fn parse(data: &[u8]) -> Option<Self>;
+}
+
+/// Errors that can occur when parsing a HOB list.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HobError {
+    /// The HOB at the given offset is shorter than its type requires, or extends past the end of the list.
+    Truncated { offset: usize },
+    /// The list does not end with an END_OF_HOB_LIST HOB.
+    MissingEnd,
+    /// The first HOB is not the Phase Handoff Information Table.
+    MissingHandoff,
+}
+
+impl fmt::Display for HobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HobError::Truncated { offset } => write!(f, "HOB at offset {:#x} is truncated", offset),
+            HobError::MissingEnd => f.write_str("HOB list has no end"),
+            HobError::MissingHandoff => f.write_str("HOB list does not start with a handoff HOB"),
+        }
+    }
+}
+
+/// A parsed HOB list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HobList {
+    hobs: Vec<Hob>,
+}
+
+impl HobList {
+    /// Creates an empty HOB list.
+    pub const fn new() -> Self {
+        Self { hobs: Vec::new() }
+    }
+
+    /// Parses a HOB list, up to and including its END_OF_HOB_LIST HOB.
+    pub fn parse(bytes: &[u8]) -> Result<Self, HobError> {
+        let mut hobs = Vec::new();
+        let mut offset = 0;
+        loop {
+            let header = bytes
+                .get(offset..offset + HOB_HEADER_SIZE)
+                .ok_or(HobError::MissingEnd)?;
+            let hob_type = read_u16(&header[0..2]);
+            let length = read_u16(&header[2..4]) as usize;
+            if hob_type == HOB_TYPE_END_OF_HOB_LIST {
+                break;
+            }
+            let hob = bytes
+                .get(offset..offset + length)
+                .filter(|hob| hob.len() >= HOB_HEADER_SIZE)
+                .ok_or(HobError::Truncated { offset })?;
+            if hob_type != HOB_TYPE_UNUSED {
+                hobs.push(parse_hob(hob_type, hob).ok_or(HobError::Truncated { offset })?);
+            }
+            offset += length;
+        }
+
+        if !matches!(hobs.first(), Some(Hob::HandoffInfoTable(_))) {
+            return Err(HobError::MissingHandoff);
+        }
+        Ok(Self { hobs })
+    }
+
+    /// Parses the HOB list at `ptr`.
+    ///
+    /// ## Safety
+    ///
+    /// `ptr` must point to a HOB list starting with a Phase Handoff Information Table, whose `end_of_hob_list` is the
+    /// address of the END_OF_HOB_LIST HOB.
+    pub unsafe fn from_ptr(ptr: *const u8) -> Result<Self, HobError> {
+        let header = slice::from_raw_parts(ptr, HOB_HEADER_SIZE);
+        if read_u16(&header[0..2]) != HOB_TYPE_HANDOFF {
+            return Err(HobError::MissingHandoff);
+        }
+        let handoff = slice::from_raw_parts(ptr, 56);
+        let end = read_u64(&handoff[48..56]) as usize;
+        let length = end.checked_sub(ptr as usize).ok_or(HobError::MissingEnd)? + HOB_HEADER_SIZE;
+        Self::parse(slice::from_raw_parts(ptr, length))
+    }
+
+    /// Returns all HOBs in the list, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Hob> {
+        self.hobs.iter()
+    }
+
+    /// Returns the number of HOBs in the list.
+    pub fn len(&self) -> usize {
+        self.hobs.len()
+    }
+
+    /// Returns true if the list has no HOBs.
+    pub fn is_empty(&self) -> bool {
+        self.hobs.is_empty()
+    }
+
+    /// Returns all HOBs of type `T`, in order.
+    pub fn of_type<T: HobType>(&self) -> impl Iterator<Item = &T> {
+        self.hobs.iter().filter_map(T::from_hob)
+    }
+
+    /// Returns the data of all GUID extension HOBs named `guid`, in order.
+    pub fn guid_hobs<'a>(&'a self, guid: &'a Guid) -> impl Iterator<Item = &'a [u8]> {
+        self.of_type::<GuidExtension>()
+            .filter(move |hob| hob.name == *guid)
+            .map(|hob| hob.data.as_slice())
+    }
+
+    /// Returns the Phase Handoff Information Table, if the list is not empty.
+    pub fn handoff(&self) -> Option<&HandoffInfoTable> {
+        self.of_type().next()
+    }
+
+    /// Appends the HOBs of another list.
+    pub fn extend(&mut self, other: HobList) {
+        self.hobs.extend(other.hobs);
+    }
+}
+
+/// Formats a base address and length as `base..end`, or as `base+length` if the end does not fit in 64 bits, as the
+/// HOBs are read from memory that is not trusted to be well formed.
+struct AddressRange(u64, u64);
+
+impl fmt::Display for AddressRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.checked_add(self.1) {
+            Some(end) => write!(f, "{:#x}..{:#x}", self.0, end),
+            None => write!(f, "{:#x}+{:#x}", self.0, self.1),
+        }
+    }
+}
+
+impl fmt::Display for Hob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hob::HandoffInfoTable(hob) => write!(f, "Handoff(boot_mode: {:#x})", hob.boot_mode),
+            Hob::MemoryAllocation(hob) => write!(
+                f,
+                "MemoryAllocation({}, type: {})",
+                AddressRange(hob.base, hob.length),
+                hob.memory_type
+            ),
+            Hob::ResourceDescriptor(hob) => write!(
+                f,
+                "ResourceDescriptor({}, type: {})",
+                AddressRange(hob.start, hob.length),
+                hob.resource_type
+            ),
+            Hob::GuidExtension(hob) => write!(
+                f,
+                "GuidExtension({}, {} bytes)",
+                PrettyGuid(&hob.name),
+                hob.data.len()
+            ),
+            Hob::FirmwareVolume(hob) => {
+                write!(f, "FirmwareVolume({})", AddressRange(hob.base, hob.length))
+            }
+            Hob::Cpu(hob) => write!(
+                f,
+                "Cpu(memory: {} bits, io: {} bits)",
+                hob.memory_space_size, hob.io_space_size
+            ),
+            Hob::Other { hob_type, data } => {
+                write!(f, "Other({:#06x}, {} bytes)", hob_type, data.len())
+            }
+        }
+    }
+}
+
+/// Parses a single HOB, including its header. Returns None if it is too short for its type.
+fn parse_hob(hob_type: u16, hob: &[u8]) -> Option<Hob> {
+    let hob = match hob_type {
+        HOB_TYPE_HANDOFF => {
+            let hob = hob.get(..56)?;
+            Hob::HandoffInfoTable(HandoffInfoTable {
+                version: read_u32(&hob[8..12]),
+                boot_mode: read_u32(&hob[12..16]),
+                memory_top: read_u64(&hob[16..24]),
+                memory_bottom: read_u64(&hob[24..32]),
+                free_memory_top: read_u64(&hob[32..40]),
+                free_memory_bottom: read_u64(&hob[40..48]),
+                end_of_hob_list: read_u64(&hob[48..56]),
+            })
+        }
+        HOB_TYPE_MEMORY_ALLOCATION => {
+            let hob = hob.get(..44)?;
+            Hob::MemoryAllocation(MemoryAllocation {
+                name: read_guid(&hob[8..24]),
+                base: read_u64(&hob[24..32]),
+                length: read_u64(&hob[32..40]),
+                memory_type: read_u32(&hob[40..44]),
+            })
+        }
+        HOB_TYPE_RESOURCE_DESCRIPTOR => {
+            let hob = hob.get(..48)?;
+            Hob::ResourceDescriptor(ResourceDescriptor {
+                owner: read_guid(&hob[8..24]),
+                resource_type: read_u32(&hob[24..28]),
+                attributes: read_u32(&hob[28..32]),
+                start: read_u64(&hob[32..40]),
+                length: read_u64(&hob[40..48]),
+            })
+        }
+        HOB_TYPE_GUID_EXTENSION => Hob::GuidExtension(GuidExtension {
+            name: read_guid(hob.get(8..24)?),
+            data: hob[24..].to_vec(),
+        }),
+        HOB_TYPE_FV => {
+            let hob = hob.get(..24)?;
+            Hob::FirmwareVolume(FirmwareVolume {
+                base: read_u64(&hob[8..16]),
+                length: read_u64(&hob[16..24]),
+                names: None,
+            })
+        }
+        HOB_TYPE_FV2 => {
+            let hob = hob.get(..56)?;
+            Hob::FirmwareVolume(FirmwareVolume {
+                base: read_u64(&hob[8..16]),
+                length: read_u64(&hob[16..24]),
+                names: Some((read_guid(&hob[24..40]), read_guid(&hob[40..56]))),
+            })
+        }
+        HOB_TYPE_FV3 => {
+            let hob = hob.get(..64)?;
+            Hob::FirmwareVolume(FirmwareVolume {
+                base: read_u64(&hob[8..16]),
+                length: read_u64(&hob[16..24]),
+                names: Some((read_guid(&hob[32..48]), read_guid(&hob[48..64]))),
+            })
+        }
+        HOB_TYPE_CPU => {
+            let hob = hob.get(..10)?;
+            Hob::Cpu(Cpu {
+                memory_space_size: hob[8],
+                io_space_size: hob[9],
+            })
+        }
+        hob_type => Hob::Other {
+            hob_type,
+            data: hob[HOB_HEADER_SIZE..].to_vec(),
+        },
+    };
+    Some(hob)
+}
@@ -1,3 +1,16 @@
 #![no_std]
+mod bytes;
 pub mod component;
+pub mod config_table;
+pub mod depex;
+pub mod event;
+pub mod fv;
+pub mod guid;
+pub mod hob;
+pub mod memory;
+pub mod pcd;
+pub mod pecoff;
 pub mod protocol;
+pub mod variable;
+
+pub use sdk_macros::component;
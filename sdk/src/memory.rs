@@ -0,0 +1,413 @@
+//! A page and pool allocator that tracks a UEFI memory map.
+//!
+//! The [MemoryMap] manages an arena of host memory, so the addresses it hands out can be dereferenced on the host. All
+//! of the arena starts as conventional memory, and allocations convert ranges of it to the requested memory type. The
+//! map follows the rules of AllocatePages, FreePages, AllocatePool, FreePool and GetMemoryMap.
+//!
+//! Each pool allocation occupies whole pages. This keeps the memory map exact, which makes leaks of either kind show up
+//! in [MemoryMap::allocations].
+extern crate alloc;
+
+use alloc::{
+    alloc::{alloc_zeroed, dealloc, Layout},
+    collections::BTreeMap,
+    vec::Vec,
+};
+use core::{ffi::c_void, fmt};
+
+use r_efi::efi::{self, MemoryType, PhysicalAddress, Status};
+
+/// The size of a page, in bytes.
+pub const PAGE_SIZE: usize = 0x1000;
+
+// Memory types reserved for OEMs and OS loaders start at these values, and are valid to allocate.
+const OEM_RESERVED_MIN: MemoryType = 0x70000000;
+
+/// A range of pages of the same memory type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub memory_type: MemoryType,
+    pub address: PhysicalAddress,
+    pub pages: usize,
+}
+
+impl Allocation {
+    fn end(&self) -> PhysicalAddress {
+        self.address + (self.pages * PAGE_SIZE) as u64
+    }
+}
+
+/// An outstanding pool allocation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PoolAllocation {
+    pub memory_type: MemoryType,
+    pub address: PhysicalAddress,
+    pub size: usize,
+}
+
+/// A memory map over an arena of host memory.
+pub struct MemoryMap {
+    // The arena, or None if the memory map is empty.
+    arena: Option<(PhysicalAddress, Layout)>,
+    // Sorted, non-overlapping ranges that cover the whole arena. Adjacent ranges have different memory types.
+    ranges: Vec<Allocation>,
+    pools: BTreeMap<PhysicalAddress, PoolAllocation>,
+    map_key: usize,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryMap {
+    /// Creates an empty memory map. All allocations fail with `OUT_OF_RESOURCES` or `NOT_FOUND`.
+    pub fn new() -> Self {
+        Self {
+            arena: None,
+            ranges: Vec::new(),
+            pools: BTreeMap::new(),
+            map_key: 0,
+        }
+    }
+
+    /// Creates a memory map over a newly allocated arena of `pages` pages of conventional memory.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the arena cannot be allocated.
+    pub fn with_arena(pages: usize) -> Self {
+        let mut map = Self::new();
+        if pages == 0 {
+            return map;
+        }
+        let layout = pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| Layout::from_size_align(size, PAGE_SIZE).ok())
+            .expect("Arena size is valid");
+        // SAFETY: The layout is not zero sized.
+        let base = unsafe { alloc_zeroed(layout) };
+        assert!(!base.is_null(), "Arena of {} pages can be allocated", pages);
+        map.arena = Some((base as PhysicalAddress, layout));
+        map.ranges.push(Allocation {
+            memory_type: efi::CONVENTIONAL_MEMORY,
+            address: base as PhysicalAddress,
+            pages,
+        });
+        map
+    }
+
+    /// Returns true if the memory map has an arena to allocate from.
+    pub fn has_arena(&self) -> bool {
+        self.arena.is_some()
+    }
+
+    /// Returns true if `address` lies within the arena.
+    pub fn contains(&self, address: PhysicalAddress) -> bool {
+        self.arena
+            .is_some_and(|(base, layout)| address >= base && address - base < layout.size() as u64)
+    }
+
+    /// Returns the key of the current memory map, which changes whenever the map does.
+    pub fn map_key(&self) -> usize {
+        self.map_key
+    }
+
+    fn is_allocatable(memory_type: MemoryType) -> bool {
+        match memory_type {
+            efi::CONVENTIONAL_MEMORY => false,
+            efi::RESERVED_MEMORY_TYPE..efi::PERSISTENT_MEMORY => true,
+            _ => memory_type >= OEM_RESERVED_MIN,
+        }
+    }
+
+    /// Returns the memory types of all ranges overlapping `[start, end)`, or None if it is not within the arena.
+    fn types_in(
+        &self,
+        start: PhysicalAddress,
+        end: PhysicalAddress,
+    ) -> Option<impl Iterator<Item = MemoryType> + '_> {
+        if start >= end || !self.contains(start) || !self.contains(end - 1) {
+            return None;
+        }
+        Some(
+            self.ranges
+                .iter()
+                .filter(move |range| range.address < end && range.end() > start)
+                .map(|range| range.memory_type),
+        )
+    }
+
+    /// Sets the memory type of `[start, end)`, which must lie within the arena.
+    fn set_type(&mut self, start: PhysicalAddress, end: PhysicalAddress, memory_type: MemoryType) {
+        let mut ranges: Vec<Allocation> = Vec::with_capacity(self.ranges.len() + 2);
+        let mut push = |range: Allocation| {
+            if range.pages == 0 {
+                return;
+            }
+            match ranges.last_mut() {
+                Some(last) if last.memory_type == range.memory_type => last.pages += range.pages,
+                _ => ranges.push(range),
+            }
+        };
+        let pages_between = |start: PhysicalAddress, end: PhysicalAddress| {
+            (end.saturating_sub(start) / PAGE_SIZE as u64) as usize
+        };
+        for range in &self.ranges {
+            if range.end() <= start || range.address >= end {
+                push(*range);
+                continue;
+            }
+            push(Allocation {
+                pages: pages_between(range.address, start),
+                ..*range
+            });
+            let overlap = range.address.max(start);
+            push(Allocation {
+                memory_type,
+                address: overlap,
+                pages: pages_between(overlap, range.end().min(end)),
+            });
+            push(Allocation {
+                address: end,
+                pages: pages_between(end, range.end()),
+                ..*range
+            });
+        }
+        self.ranges = ranges;
+        self.map_key += 1;
+    }
+
+    /// Allocates pages of `memory_type`, following the rules of AllocatePages.
+    ///
+    /// `address` is the maximum address for `ALLOCATE_MAX_ADDRESS`, or the address to allocate for `ALLOCATE_ADDRESS`,
+    /// and is ignored otherwise. Pages are allocated from the top of the arena down. Returns the allocated address.
+    pub fn allocate_pages(
+        &mut self,
+        allocate_type: efi::AllocateType,
+        memory_type: MemoryType,
+        pages: usize,
+        address: PhysicalAddress,
+    ) -> Result<PhysicalAddress, Status> {
+        if !Self::is_allocatable(memory_type) || pages == 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let size = pages
+            .checked_mul(PAGE_SIZE)
+            .ok_or(Status::OUT_OF_RESOURCES)? as u64;
+
+        let max_end = match allocate_type {
+            efi::ALLOCATE_ANY_PAGES => u64::MAX,
+            efi::ALLOCATE_MAX_ADDRESS => address.saturating_add(1),
+            efi::ALLOCATE_ADDRESS => {
+                if !address.is_multiple_of(PAGE_SIZE as u64) {
+                    return Err(Status::INVALID_PARAMETER);
+                }
+                let end = address.checked_add(size).ok_or(Status::NOT_FOUND)?;
+                let free = self
+                    .types_in(address, end)
+                    .ok_or(Status::NOT_FOUND)?
+                    .all(|memory_type| memory_type == efi::CONVENTIONAL_MEMORY);
+                if !free {
+                    return Err(Status::NOT_FOUND);
+                }
+                self.set_type(address, end, memory_type);
+                return Ok(address);
+            }
+            _ => return Err(Status::INVALID_PARAMETER),
+        };
+
+        let start = self
+            .ranges
+            .iter()
+            .rev()
+            .filter(|range| range.memory_type == efi::CONVENTIONAL_MEMORY)
+            .find_map(|range| {
+                let end = range.end().min(max_end & !(PAGE_SIZE as u64 - 1));
+                let start = end.checked_sub(size)?;
+                (start >= range.address).then_some(start)
+            });
+        let start = start.ok_or(Status::OUT_OF_RESOURCES)?;
+        self.set_type(start, start + size, memory_type);
+        Ok(start)
+    }
+
+    /// Frees pages, following the rules of FreePages. The pages must have been allocated with
+    /// [allocate_pages](Self::allocate_pages), and must not be part of a pool allocation.
+    pub fn free_pages(&mut self, address: PhysicalAddress, pages: usize) -> Result<(), Status> {
+        if !address.is_multiple_of(PAGE_SIZE as u64) || pages == 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let end = pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|size| address.checked_add(size as u64))
+            .ok_or(Status::NOT_FOUND)?;
+        let allocated = self
+            .types_in(address, end)
+            .ok_or(Status::NOT_FOUND)?
+            .all(|memory_type| memory_type != efi::CONVENTIONAL_MEMORY);
+        let pooled = self.pools.values().any(|pool| {
+            pool.address < end
+                && pool.address + Self::pool_pages(pool.size) as u64 * PAGE_SIZE as u64 > address
+        });
+        if !allocated || pooled {
+            return Err(Status::NOT_FOUND);
+        }
+        self.set_type(address, end, efi::CONVENTIONAL_MEMORY);
+        Ok(())
+    }
+
+    fn pool_pages(size: usize) -> usize {
+        size.div_ceil(PAGE_SIZE).max(1)
+    }
+
+    /// Allocates a pool buffer of `memory_type`, following the rules of AllocatePool. The buffer is zeroed.
+    pub fn allocate_pool(
+        &mut self,
+        memory_type: MemoryType,
+        size: usize,
+    ) -> Result<*mut c_void, Status> {
+        if !Self::is_allocatable(memory_type) {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let pages = Self::pool_pages(size);
+        let address = self.allocate_pages(efi::ALLOCATE_ANY_PAGES, memory_type, pages, 0)?;
+        // SAFETY: The pages lie within the arena, and were not allocated until now.
+        unsafe { core::ptr::write_bytes(address as *mut u8, 0, pages * PAGE_SIZE) };
+        self.pools.insert(
+            address,
+            PoolAllocation {
+                memory_type,
+                address,
+                size,
+            },
+        );
+        Ok(address as *mut c_void)
+    }
+
+    /// Frees a pool buffer, following the rules of FreePool.
+    pub fn free_pool(&mut self, buffer: *mut c_void) -> Result<(), Status> {
+        let pool = self
+            .pools
+            .remove(&(buffer as PhysicalAddress))
+            .ok_or(Status::INVALID_PARAMETER)?;
+        let end = pool.address + (Self::pool_pages(pool.size) * PAGE_SIZE) as u64;
+        self.set_type(pool.address, end, efi::CONVENTIONAL_MEMORY);
+        Ok(())
+    }
+
+    /// Returns the memory map, as GetMemoryMap does.
+    pub fn descriptors(&self) -> Vec<efi::MemoryDescriptor> {
+        self.ranges
+            .iter()
+            .map(|range| efi::MemoryDescriptor {
+                r#type: range.memory_type,
+                physical_start: range.address,
+                virtual_start: 0,
+                number_of_pages: range.pages as u64,
+                attribute: match range.memory_type {
+                    efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA => {
+                        efi::MEMORY_WB | efi::MEMORY_RUNTIME
+                    }
+                    _ => efi::MEMORY_WB,
+                },
+            })
+            .collect()
+    }
+
+    /// Returns all ranges of allocated memory, e.g. to detect leaks.
+    pub fn allocations(&self) -> impl Iterator<Item = &Allocation> {
+        self.ranges
+            .iter()
+            .filter(|range| range.memory_type != efi::CONVENTIONAL_MEMORY)
+    }
+
+    /// Returns all outstanding pool allocations, by address.
+    pub fn pools(&self) -> impl Iterator<Item = &PoolAllocation> {
+        self.pools.values()
+    }
+
+    /// Returns the number of allocated pages, including those of pool allocations.
+    pub fn allocated_pages(&self) -> usize {
+        self.allocations().map(|range| range.pages).sum()
+    }
+
+    /// Returns the number of pages of conventional memory left to allocate.
+    pub fn available_pages(&self) -> usize {
+        self.ranges
+            .iter()
+            .filter(|range| range.memory_type == efi::CONVENTIONAL_MEMORY)
+            .map(|range| range.pages)
+            .sum()
+    }
+
+    /// Captures the allocations of the memory map and the contents of its arena.
+    pub fn snapshot(&self) -> MemorySnapshot {
+        let contents = match self.arena {
+            // SAFETY: The arena is allocated and initialized for the lifetime of the map.
+            Some((base, layout)) => unsafe {
+                core::slice::from_raw_parts(base as *const u8, layout.size()).to_vec()
+            },
+            None => Vec::new(),
+        };
+        MemorySnapshot {
+            arena: self.arena,
+            ranges: self.ranges.clone(),
+            pools: self.pools.clone(),
+            map_key: self.map_key,
+            contents,
+        }
+    }
+
+    /// Restores the allocations and arena contents captured by `snapshot`.
+    ///
+    /// A snapshot of a memory map without an arena resets the map, freeing its arena if it has one. Returns false,
+    /// leaving the map unchanged, if the snapshot was taken of a different arena.
+    pub fn restore(&mut self, snapshot: &MemorySnapshot) -> bool {
+        let Some((base, layout)) = snapshot.arena else {
+            *self = Self::new();
+            return true;
+        };
+        if self.arena != snapshot.arena {
+            return false;
+        }
+        // SAFETY: The arena is allocated with the same layout as when the snapshot was taken.
+        unsafe {
+            core::slice::from_raw_parts_mut(base as *mut u8, layout.size())
+                .copy_from_slice(&snapshot.contents)
+        };
+        self.ranges.clone_from(&snapshot.ranges);
+        self.pools.clone_from(&snapshot.pools);
+        self.map_key = snapshot.map_key;
+        true
+    }
+}
+
+/// A copy of a [MemoryMap] and the contents of its arena, created with [MemoryMap::snapshot].
+pub struct MemorySnapshot {
+    arena: Option<(PhysicalAddress, Layout)>,
+    ranges: Vec<Allocation>,
+    pools: BTreeMap<PhysicalAddress, PoolAllocation>,
+    map_key: usize,
+    contents: Vec<u8>,
+}
+
+impl Drop for MemoryMap {
+    fn drop(&mut self) {
+        if let Some((base, layout)) = self.arena {
+            // SAFETY: The arena was allocated with this layout in `with_arena`.
+            unsafe { dealloc(base as *mut u8, layout) };
+        }
+    }
+}
+
+impl fmt::Debug for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryMap")
+            .field("ranges", &self.ranges)
+            .field("pools", &self.pools.len())
+            .field("map_key", &self.map_key)
+            .finish()
+    }
+}
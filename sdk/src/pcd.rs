@@ -0,0 +1,296 @@
+//! A Platform Configuration Database (PCD), modeled after the EDK2 PCD database.
+//!
+//! Each PCD is identified by a token space [Guid] and a token number. A PCD is one of three kinds:
+//!
+//! - [PcdKind::Fixed]: The value is set when the database is populated and can never change.
+//! - [PcdKind::Patchable]: The value may be patched, but only by code that owns the database.
+//! - [PcdKind::Dynamic]: The value may be changed at any time, including by components.
+//!
+//! Components consume PCDs through the `Pcd<TOKEN>` component parameter, where `TOKEN` implements [PcdToken].
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{
+    cell::{Ref, RefCell, RefMut},
+    fmt,
+};
+
+use hashbrown::HashMap;
+use r_efi::efi::Guid;
+
+use crate::guid::PrettyGuid;
+
+/// The kind of a PCD, which determines when its value may be changed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PcdKind {
+    /// The value is fixed when the database is populated.
+    Fixed,
+    /// The value can be patched by the owner of the database.
+    Patchable,
+    /// The value can be changed at any time.
+    Dynamic,
+}
+
+/// The value of a PCD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PcdValue {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    /// A pointer (VOID*) PCD, stored as a byte buffer.
+    Ptr(Vec<u8>),
+}
+
+impl PcdValue {
+    /// Returns the size, in bytes, of the value.
+    pub fn size(&self) -> usize {
+        match self {
+            PcdValue::Bool(_) | PcdValue::U8(_) => 1,
+            PcdValue::U16(_) => 2,
+            PcdValue::U32(_) => 4,
+            PcdValue::U64(_) => 8,
+            PcdValue::Ptr(buffer) => buffer.len(),
+        }
+    }
+
+    /// Returns true if both values have the same datum type.
+    pub fn same_type(&self, other: &PcdValue) -> bool {
+        core::mem::discriminant(self) == core::mem::discriminant(other)
+    }
+}
+
+/// Errors that can occur when accessing the PCD database.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PcdError {
+    /// No PCD is registered for the token space guid and token number.
+    NotFound,
+    /// The PCD cannot be changed through this interface.
+    ReadOnly,
+    /// The new value does not have the same datum type as the PCD.
+    TypeMismatch,
+    /// The new pointer value is larger than the maximum size of the PCD.
+    BufferTooSmall,
+}
+
+/// A Rust type that can be stored as the value of a PCD.
+pub trait PcdDatum: Sized {
+    /// Converts the type into a [PcdValue].
+    fn into_value(self) -> PcdValue;
+    /// Converts a [PcdValue] into the type, returning None if the datum type does not match.
+    fn from_value(value: &PcdValue) -> Option<Self>;
+}
+
+macro_rules! impl_pcd_datum {
+    ($ty:ty, $variant:ident) => {
+        impl PcdDatum for $ty {
+            fn into_value(self) -> PcdValue {
+                PcdValue::$variant(self)
+            }
+
+            fn from_value(value: &PcdValue) -> Option<Self> {
+                match value {
+                    PcdValue::$variant(v) => Some(v.clone()),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_pcd_datum!(bool, Bool);
+impl_pcd_datum!(u8, U8);
+impl_pcd_datum!(u16, U16);
+impl_pcd_datum!(u32, U32);
+impl_pcd_datum!(u64, U64);
+impl_pcd_datum!(Vec<u8>, Ptr);
+
+/// The declaration of a PCD, similar to a PCD entry in an EDK2 DEC file.
+pub trait PcdToken: 'static {
+    /// The datum type of the PCD.
+    type Value: PcdDatum;
+
+    /// The token space guid the PCD belongs to.
+    fn token_space_guid() -> &'static Guid;
+    /// The token number of the PCD in its token space.
+    fn token_number() -> u32;
+    /// The kind of the PCD.
+    fn kind() -> PcdKind;
+    /// The value of the PCD if the platform does not provide one.
+    fn default_value() -> Self::Value;
+}
+
+/// A single PCD in the database.
+#[derive(Debug, Clone)]
+pub struct PcdEntry {
+    kind: PcdKind,
+    value: PcdValue,
+    max_size: usize,
+}
+
+impl PcdEntry {
+    /// Creates a new PCD entry. The maximum size of a pointer PCD is the size of its initial value.
+    pub fn new(kind: PcdKind, value: PcdValue) -> Self {
+        Self {
+            kind,
+            max_size: value.size(),
+            value,
+        }
+    }
+
+    /// Creates a new pointer PCD entry with a maximum size larger than its initial value.
+    pub fn with_max_size(kind: PcdKind, value: PcdValue, max_size: usize) -> Self {
+        Self {
+            kind,
+            max_size: max_size.max(value.size()),
+            value,
+        }
+    }
+
+    /// Returns the kind of the PCD.
+    pub fn kind(&self) -> PcdKind {
+        self.kind
+    }
+
+    /// Returns the current value of the PCD.
+    pub fn value(&self) -> &PcdValue {
+        &self.value
+    }
+
+    /// Returns the maximum size, in bytes, of the PCD.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Sets the value of the PCD. Only dynamic PCDs can be set; fixed and patchable PCDs are changed by the owner of
+    /// the database, through [PcdDatabase::patch].
+    pub fn set(&mut self, value: PcdValue) -> Result<(), PcdError> {
+        if self.kind != PcdKind::Dynamic {
+            return Err(PcdError::ReadOnly);
+        }
+        self.patch(value)
+    }
+
+    /// Sets the value of the PCD regardless of its kind. Only the owner of the database should patch values.
+    fn patch(&mut self, value: PcdValue) -> Result<(), PcdError> {
+        if !self.value.same_type(&value) {
+            return Err(PcdError::TypeMismatch);
+        }
+        if value.size() > self.max_size {
+            return Err(PcdError::BufferTooSmall);
+        }
+        self.value = value;
+        Ok(())
+    }
+}
+
+/// A database of PCDs keyed by token space guid and token number.
+#[derive(Default, Clone)]
+pub struct PcdDatabase {
+    entries: Vec<RefCell<PcdEntry>>,
+    // The token space guid and token number of each entry, indexed by id, so PCDs are listed in registration order.
+    keys: Vec<(Guid, u32)>,
+    indices: HashMap<(Guid, u32), usize>,
+}
+
+impl PcdDatabase {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            keys: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Returns the id of the PCD, if it exists.
+    pub fn id(&self, guid: &Guid, token: u32) -> Option<usize> {
+        self.indices.get(&(*guid, token)).copied()
+    }
+
+    /// Adds a PCD to the database, returning its id. If the PCD already exists, its entry is replaced.
+    pub fn insert(&mut self, guid: &Guid, token: u32, entry: PcdEntry) -> usize {
+        match self.id(guid, token) {
+            Some(id) => {
+                self.entries[id] = RefCell::new(entry);
+                id
+            }
+            None => {
+                let id = self.entries.len();
+                self.entries.push(RefCell::new(entry));
+                self.keys.push((*guid, token));
+                self.indices.insert((*guid, token), id);
+                id
+            }
+        }
+    }
+
+    /// Adds a PCD to the database if it does not already exist, returning its id.
+    pub fn get_or_insert(
+        &mut self,
+        guid: &Guid,
+        token: u32,
+        entry: impl FnOnce() -> PcdEntry,
+    ) -> usize {
+        match self.id(guid, token) {
+            Some(id) => id,
+            None => self.insert(guid, token, entry()),
+        }
+    }
+
+    /// Returns true if the PCD exists.
+    pub fn contains(&self, guid: &Guid, token: u32) -> bool {
+        self.indices.contains_key(&(*guid, token))
+    }
+
+    /// Returns the current value of the PCD.
+    pub fn get(&self, guid: &Guid, token: u32) -> Result<PcdValue, PcdError> {
+        let id = self.id(guid, token).ok_or(PcdError::NotFound)?;
+        Ok(self.entries[id].borrow().value().clone())
+    }
+
+    /// Sets the value of a dynamic PCD.
+    pub fn set(&self, guid: &Guid, token: u32, value: PcdValue) -> Result<(), PcdError> {
+        let id = self.id(guid, token).ok_or(PcdError::NotFound)?;
+        self.entries[id].borrow_mut().set(value)
+    }
+
+    /// Sets the value of any PCD, including fixed and patchable PCDs. Used when populating the database.
+    pub fn patch(&mut self, guid: &Guid, token: u32, value: PcdValue) -> Result<(), PcdError> {
+        let id = self.id(guid, token).ok_or(PcdError::NotFound)?;
+        self.entries[id].get_mut().patch(value)
+    }
+
+    /// Returns the cell holding a PCD entry, or None if no PCD has the id.
+    pub fn cell(&self, id: usize) -> Option<&RefCell<PcdEntry>> {
+        self.entries.get(id)
+    }
+
+    /// Returns the cells holding all PCD entries mutably.
+    pub(crate) fn cells_mut(&mut self) -> impl Iterator<Item = &mut RefCell<PcdEntry>> {
+        self.entries.iter_mut()
+    }
+
+    /// Retrieves a PCD entry by id.
+    pub fn entry(&self, id: usize) -> Ref<'_, PcdEntry> {
+        self.entries[id].borrow()
+    }
+
+    /// Retrieves a mutable PCD entry by id.
+    pub fn entry_mut(&self, id: usize) -> RefMut<'_, PcdEntry> {
+        self.entries[id].borrow_mut()
+    }
+}
+
+impl fmt::Debug for PcdDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.keys
+                    .iter()
+                    .zip(&self.entries)
+                    .map(|((guid, token), entry)| ((PrettyGuid(guid), token), entry)),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,375 @@
+//! PE/COFF image parsing and loading.
+//!
+//! [PeImage::parse] reads a PE32 or PE32+ image, such as the PE32 section of a DXE driver, maps its sections to their
+//! virtual addresses and reads its base relocations. [PeImage::load] copies the mapped image to its final location and
+//! applies the relocations, returning the address of the entry point. Images are never executed here.
+extern crate alloc;
+
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::bytes::{read_u16, read_u32, read_u64};
+
+const DOS_SIGNATURE: &[u8; 2] = b"MZ";
+const PE_SIGNATURE: &[u8; 4] = b"PE\0\0";
+const DOS_LFANEW_OFFSET: usize = 0x3c;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+const RELOCS_STRIPPED: u16 = 0x0001;
+const BASE_RELOCATION_DIRECTORY: usize = 5;
+const BASE_RELOCATION_BLOCK_SIZE: usize = 8;
+
+const REL_BASED_ABSOLUTE: u16 = 0;
+const REL_BASED_HIGHLOW: u16 = 3;
+const REL_BASED_DIR64: u16 = 10;
+
+/// The target machine of an image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Machine(pub u16);
+
+impl Machine {
+    pub const I386: Self = Self(0x014c);
+    pub const ARM_THUMB2: Self = Self(0x01c4);
+    pub const EBC: Self = Self(0x0ebc);
+    pub const X64: Self = Self(0x8664);
+    pub const AARCH64: Self = Self(0xaa64);
+    pub const RISCV64: Self = Self(0x5064);
+    pub const LOONGARCH64: Self = Self(0x6264);
+}
+
+/// The subsystem of an image, which determines how it is loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Subsystem(pub u16);
+
+impl Subsystem {
+    pub const EFI_APPLICATION: Self = Self(10);
+    pub const EFI_BOOT_SERVICE_DRIVER: Self = Self(11);
+    pub const EFI_RUNTIME_DRIVER: Self = Self(12);
+}
+
+/// An error encountered while parsing or loading an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeError {
+    /// The image ends before a header or section it describes.
+    Truncated,
+    /// The image does not start with a DOS header.
+    InvalidDosSignature,
+    /// The image does not have a PE header.
+    InvalidPeSignature,
+    /// The optional header is neither PE32 nor PE32+.
+    UnsupportedFormat(u16),
+    /// A section lies outside of the file or of the loaded image.
+    InvalidSection { index: usize },
+    /// A base relocation lies outside of the loaded image.
+    InvalidRelocation { rva: u32 },
+    /// A base relocation has a type that is not supported.
+    UnsupportedRelocation { rva: u32, kind: u16 },
+    /// The image must be relocated, but its relocations were stripped.
+    RelocationsStripped,
+    /// The destination is smaller than the loaded image.
+    BufferTooSmall { required: usize },
+    /// The entry point of the image does not fit in the address space when loaded at the address.
+    InvalidAddress(u64),
+}
+
+impl fmt::Display for PeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeError::Truncated => f.write_str("Image is truncated"),
+            PeError::InvalidDosSignature => f.write_str("Image has an invalid DOS signature"),
+            PeError::InvalidPeSignature => f.write_str("Image has an invalid PE signature"),
+            PeError::UnsupportedFormat(magic) => {
+                write!(f, "Unsupported optional header magic {:#x}", magic)
+            }
+            PeError::InvalidSection { index } => write!(f, "Invalid section {}", index),
+            PeError::InvalidRelocation { rva } => write!(f, "Invalid relocation at {:#x}", rva),
+            PeError::UnsupportedRelocation { rva, kind } => {
+                write!(f, "Unsupported relocation type {} at {:#x}", kind, rva)
+            }
+            PeError::RelocationsStripped => f.write_str("Image cannot be relocated"),
+            PeError::BufferTooSmall { required } => {
+                write!(f, "Image requires a buffer of {:#x} bytes", required)
+            }
+            PeError::InvalidAddress(address) => {
+                write!(f, "Image cannot be loaded at {:#x}", address)
+            }
+        }
+    }
+}
+
+/// A section of an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The name of the section, e.g. `.text`.
+    pub name: String,
+    /// The address of the section, relative to the image base.
+    pub virtual_address: u32,
+    /// The size of the section once loaded.
+    pub virtual_size: u32,
+    /// The section flags, e.g. whether it is executable.
+    pub characteristics: u32,
+}
+
+/// The type of a base relocation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// The 32 bits at the address are adjusted by the relocation delta.
+    HighLow,
+    /// The 64 bits at the address are adjusted by the relocation delta.
+    Dir64,
+}
+
+/// A base relocation, applied when the image is loaded at an address other than its preferred base.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// The address to adjust, relative to the image base.
+    pub rva: u32,
+    pub kind: RelocationKind,
+}
+
+/// An image loaded with [PeImage::load].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LoadedImage {
+    /// The address the image was loaded at.
+    pub base: u64,
+    /// The size of the loaded image.
+    pub size: usize,
+    /// The address of the entry point.
+    pub entry_point: u64,
+    pub machine: Machine,
+    pub subsystem: Subsystem,
+}
+
+/// A parsed PE/COFF image, with its sections mapped to their virtual addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeImage {
+    pub machine: Machine,
+    pub subsystem: Subsystem,
+    /// The preferred load address of the image.
+    pub image_base: u64,
+    /// The address of the entry point, relative to the image base.
+    pub entry_point: u32,
+    /// The alignment of the sections once loaded, and so the required alignment of the load address.
+    pub section_alignment: u32,
+    pub sections: Vec<Section>,
+    relocations: Option<Vec<Relocation>>,
+    image: Vec<u8>,
+}
+
+impl PeImage {
+    /// Parses an image, mapping its sections and reading its base relocations.
+    pub fn parse(bytes: &[u8]) -> Result<Self, PeError> {
+        if bytes.get(..2) != Some(DOS_SIGNATURE) {
+            return Err(PeError::InvalidDosSignature);
+        }
+        let pe = read_u32(field(bytes, DOS_LFANEW_OFFSET, 4)?) as usize;
+        if bytes.get(pe..pe + 4) != Some(PE_SIGNATURE) {
+            return Err(PeError::InvalidPeSignature);
+        }
+        let coff = bytes
+            .get(pe + 4..pe + 4 + COFF_HEADER_SIZE)
+            .ok_or(PeError::Truncated)?;
+        let machine = Machine(read_u16(coff));
+        let section_count = read_u16(&coff[2..]) as usize;
+        let optional_header_size = read_u16(&coff[16..]) as usize;
+        let characteristics = read_u16(&coff[18..]);
+
+        let optional_offset = pe + 4 + COFF_HEADER_SIZE;
+        let optional = bytes
+            .get(optional_offset..optional_offset + optional_header_size)
+            .ok_or(PeError::Truncated)?;
+        let magic = read_u16(optional.get(..2).ok_or(PeError::Truncated)?);
+        let (image_base, directories_offset) = match magic {
+            PE32_MAGIC => (read_u32(field(optional, 28, 4)?) as u64, 92),
+            PE32_PLUS_MAGIC => (read_u64(field(optional, 24, 8)?), 108),
+            _ => return Err(PeError::UnsupportedFormat(magic)),
+        };
+        let entry_point = read_u32(field(optional, 16, 4)?);
+        let section_alignment = read_u32(field(optional, 32, 4)?);
+        let image_size = read_u32(field(optional, 56, 4)?) as usize;
+        let headers_size = read_u32(field(optional, 60, 4)?) as usize;
+        let subsystem = Subsystem(read_u16(field(optional, 68, 2)?));
+        let directory_count = read_u32(field(optional, directories_offset, 4)?) as usize;
+        let relocation_directory = (directory_count > BASE_RELOCATION_DIRECTORY)
+            .then(|| {
+                field(
+                    optional,
+                    directories_offset + 4 + BASE_RELOCATION_DIRECTORY * 8,
+                    8,
+                )
+            })
+            .transpose()?
+            .map(|directory| {
+                (
+                    read_u32(directory) as usize,
+                    read_u32(&directory[4..]) as usize,
+                )
+            });
+
+        if headers_size > image_size || entry_point as usize >= image_size {
+            return Err(PeError::Truncated);
+        }
+        let mut image = vec![0u8; image_size];
+        let headers = bytes.get(..headers_size).ok_or(PeError::Truncated)?;
+        image[..headers_size].copy_from_slice(headers);
+
+        let section_headers = optional_offset + optional_header_size;
+        let mut sections = Vec::with_capacity(section_count);
+        for index in 0..section_count {
+            let offset = section_headers + index * SECTION_HEADER_SIZE;
+            let header = bytes
+                .get(offset..offset + SECTION_HEADER_SIZE)
+                .ok_or(PeError::Truncated)?;
+            let section = Section {
+                name: String::from_utf8_lossy(&header[..8])
+                    .trim_end_matches('\0')
+                    .into(),
+                virtual_address: read_u32(&header[12..]),
+                virtual_size: read_u32(&header[8..]),
+                characteristics: read_u32(&header[36..]),
+            };
+            let raw_size = read_u32(&header[16..]) as usize;
+            let raw_offset = read_u32(&header[20..]) as usize;
+            // Uninitialized data, or padding past the virtual size, is not copied.
+            let copy_size = match section.virtual_size {
+                0 => raw_size,
+                virtual_size => raw_size.min(virtual_size as usize),
+            };
+            let start = section.virtual_address as usize;
+            let invalid = || PeError::InvalidSection { index };
+            let data = bytes
+                .get(raw_offset..raw_offset.checked_add(copy_size).ok_or_else(invalid)?)
+                .ok_or_else(invalid)?;
+            image
+                .get_mut(start..start.checked_add(copy_size).ok_or_else(invalid)?)
+                .ok_or_else(invalid)?
+                .copy_from_slice(data);
+            sections.push(section);
+        }
+
+        let relocations = match relocation_directory {
+            _ if characteristics & RELOCS_STRIPPED != 0 => None,
+            Some((rva, size)) if size != 0 => Some(parse_relocations(&image, rva, size)?),
+            _ => Some(Vec::new()),
+        };
+
+        Ok(Self {
+            machine,
+            subsystem,
+            image_base,
+            entry_point,
+            section_alignment,
+            sections,
+            relocations,
+            image,
+        })
+    }
+
+    /// Returns the size of the loaded image.
+    pub fn image_size(&self) -> usize {
+        self.image.len()
+    }
+
+    /// Returns the mapped image, as loaded at its preferred base.
+    pub fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    /// Returns the base relocations of the image, or None if they were stripped.
+    pub fn relocations(&self) -> Option<&[Relocation]> {
+        self.relocations.as_deref()
+    }
+
+    /// Returns the section containing `rva`, if any.
+    pub fn section_at(&self, rva: u32) -> Option<&Section> {
+        self.sections.iter().find(|section| {
+            rva >= section.virtual_address && rva - section.virtual_address < section.virtual_size
+        })
+    }
+
+    /// Loads the image into `destination`, which will be located at `address`, applying base relocations.
+    ///
+    /// `destination` must be at least [image_size](Self::image_size) bytes. Bytes past the image are left untouched.
+    pub fn load(&self, destination: &mut [u8], address: u64) -> Result<LoadedImage, PeError> {
+        let destination =
+            destination
+                .get_mut(..self.image.len())
+                .ok_or(PeError::BufferTooSmall {
+                    required: self.image.len(),
+                })?;
+        let entry_point = address
+            .checked_add(self.entry_point as u64)
+            .ok_or(PeError::InvalidAddress(address))?;
+        destination.copy_from_slice(&self.image);
+
+        let delta = address.wrapping_sub(self.image_base);
+        if delta != 0 {
+            let relocations = self
+                .relocations
+                .as_ref()
+                .ok_or(PeError::RelocationsStripped)?;
+            for relocation in relocations {
+                let target = &mut destination[relocation.rva as usize..];
+                match relocation.kind {
+                    RelocationKind::HighLow => {
+                        let value = read_u32(target).wrapping_add(delta as u32);
+                        target[..4].copy_from_slice(&value.to_le_bytes());
+                    }
+                    RelocationKind::Dir64 => {
+                        let value = read_u64(target).wrapping_add(delta);
+                        target[..8].copy_from_slice(&value.to_le_bytes());
+                    }
+                }
+            }
+        }
+
+        Ok(LoadedImage {
+            base: address,
+            size: self.image.len(),
+            entry_point,
+            machine: self.machine,
+            subsystem: self.subsystem,
+        })
+    }
+}
+
+/// Reads the base relocation blocks at `rva` in the mapped image.
+fn parse_relocations(image: &[u8], rva: usize, size: usize) -> Result<Vec<Relocation>, PeError> {
+    let mut blocks = rva
+        .checked_add(size)
+        .and_then(|end| image.get(rva..end))
+        .ok_or(PeError::InvalidRelocation { rva: rva as u32 })?;
+    let mut relocations = Vec::new();
+    while blocks.len() >= BASE_RELOCATION_BLOCK_SIZE {
+        let page = read_u32(blocks);
+        let block_size = read_u32(&blocks[4..]) as usize;
+        if block_size < BASE_RELOCATION_BLOCK_SIZE || block_size > blocks.len() {
+            return Err(PeError::InvalidRelocation { rva: page });
+        }
+        for entry in blocks[BASE_RELOCATION_BLOCK_SIZE..block_size].chunks_exact(2) {
+            let entry = read_u16(entry);
+            let rva = page.wrapping_add((entry & 0xfff) as u32);
+            let (kind, width) = match entry >> 12 {
+                REL_BASED_ABSOLUTE => continue,
+                REL_BASED_HIGHLOW => (RelocationKind::HighLow, 4),
+                REL_BASED_DIR64 => (RelocationKind::Dir64, 8),
+                kind => return Err(PeError::UnsupportedRelocation { rva, kind }),
+            };
+            if (rva as usize).saturating_add(width) > image.len() {
+                return Err(PeError::InvalidRelocation { rva });
+            }
+            relocations.push(Relocation { rva, kind });
+        }
+        blocks = &blocks[block_size..];
+    }
+    Ok(relocations)
+}
+
+/// Returns `len` bytes of `bytes` at `offset`, or `Truncated`.
+fn field(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], PeError> {
+    bytes.get(offset..offset + len).ok_or(PeError::Truncated)
+}
@@ -1,15 +1,234 @@
-pub trait Protocol {
-    fn guid() -> &'static r_efi::efi::Guid;
-}
-
-impl Protocol for r_efi::protocols::rng::Protocol {
-    fn guid() -> &'static r_efi::efi::Guid {
-        &r_efi::protocols::rng::PROTOCOL_GUID
-    }
-}
-
-impl Protocol for r_efi::protocols::udp4::Protocol {
-    fn guid() -> &'static r_efi::efi::Guid {
-        &r_efi::protocols::udp4::PROTOCOL_GUID
-    }
-}
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, rc::Rc, vec::Vec};
+use core::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    ffi::c_void,
+};
+
+use r_efi::efi::{Guid, Handle, Status};
+
+/// A protocol, identified by its GUID.
+///
+/// A protocol installed from Rust can only be retrieved as the type it was installed as. One installed through the EFI
+/// boot services carries no type, so it is retrieved as whichever type is requested for its GUID; such types must be
+/// laid out as the protocol the GUID names.
+pub trait Protocol {
+    fn guid() -> &'static r_efi::efi::Guid;
+}
+
+impl Protocol for r_efi::protocols::rng::Protocol {
+    fn guid() -> &'static r_efi::efi::Guid {
+        &r_efi::protocols::rng::PROTOCOL_GUID
+    }
+}
+
+impl Protocol for r_efi::protocols::udp4::Protocol {
+    fn guid() -> &'static r_efi::efi::Guid {
+        &r_efi::protocols::udp4::PROTOCOL_GUID
+    }
+}
+
+/// The interface of an installed protocol.
+///
+/// Protocols installed from Rust own their interface, while protocols installed through the EFI boot services only
+/// hold a pointer to an interface owned by the installer.
+#[derive(Clone)]
+pub struct ProtocolInterface {
+    name: &'static str,
+    // The type the interface can be retrieved as, or None if it was installed through a raw pointer.
+    type_id: Option<TypeId>,
+    interface: *mut c_void,
+    // Keeps the interface of a protocol installed from Rust alive. Protocols are never mutated through storage, so
+    // they are reference counted and shared with snapshots. The interface is held in an `UnsafeCell`, as EFI code
+    // handed the pointer, e.g. by LocateProtocol, may write through it; restoring a snapshot does not undo such writes.
+    owner: Option<Rc<dyn Any>>,
+}
+
+impl ProtocolInterface {
+    /// Creates an interface that owns the protocol.
+    pub fn new<P: Protocol + 'static>(protocol: P) -> Self {
+        // SAFETY: Any type starts with itself.
+        unsafe { Self::new_as::<P, P>(protocol) }
+    }
+
+    /// Creates an interface that owns the protocol, and can be retrieved as an `I` rather than a `P`.
+    ///
+    /// ## Safety
+    ///
+    /// - `P` must be `repr(C)` and start with a field of type `I`.
+    pub unsafe fn new_as<I: 'static, P: Protocol + 'static>(protocol: P) -> Self {
+        let owner = Rc::new(UnsafeCell::new(protocol));
+        Self {
+            name: core::any::type_name::<P>(),
+            type_id: Some(TypeId::of::<I>()),
+            interface: owner.get().cast(),
+            owner: Some(owner),
+        }
+    }
+
+    /// Creates an interface from a pointer owned by the installer of the protocol.
+    pub fn from_raw(interface: *mut c_void) -> Self {
+        Self {
+            name: "<foreign>",
+            type_id: None,
+            interface,
+            owner: None,
+        }
+    }
+
+    /// Returns the interface as a `P`, or None if it was installed from Rust as a different type.
+    ///
+    /// An interface installed through a raw pointer carries no type, so it is returned unless it is null, which no `P`
+    /// can be read from.
+    pub fn downcast<P: Protocol + 'static>(&self) -> Option<*mut P> {
+        match self.type_id {
+            Some(type_id) if type_id != TypeId::of::<P>() => None,
+            None if self.interface.is_null() => None,
+            _ => Some(self.interface.cast()),
+        }
+    }
+
+    /// Returns the type name of the protocol, or `<foreign>` if it was installed through a raw pointer.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns true if the interface was installed from Rust, and is owned by the storage.
+    pub fn is_owned(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    /// Returns the pointer to the protocol interface.
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.interface
+    }
+}
+
+/// A database of handles and the protocols installed on them, following the semantics of the EFI boot services.
+///
+/// Handles are opaque, non-null values that exist for as long as at least one protocol is installed on them.
+#[derive(Clone)]
+pub struct ProtocolDatabase {
+    // Handles in the order they were created, with their protocols in the order they were installed.
+    handles: BTreeMap<usize, Vec<(Guid, ProtocolInterface)>>,
+    next_handle: usize,
+}
+
+impl Default for ProtocolDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProtocolDatabase {
+    pub fn new() -> Self {
+        Self {
+            handles: BTreeMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    fn key(handle: Handle) -> usize {
+        handle as usize
+    }
+
+    /// Installs a protocol on `handle`, or on a new handle if `handle` is None. Returns the handle.
+    ///
+    /// Fails with INVALID_PARAMETER if the handle does not exist or the protocol is already installed on it.
+    pub fn install(
+        &mut self,
+        handle: Option<Handle>,
+        guid: &Guid,
+        interface: ProtocolInterface,
+    ) -> Result<Handle, Status> {
+        let key = match handle {
+            Some(handle) => Self::key(handle),
+            None => {
+                let key = self.next_handle;
+                self.next_handle += 1;
+                self.handles.insert(key, Vec::new());
+                key
+            }
+        };
+
+        let protocols = self
+            .handles
+            .get_mut(&key)
+            .ok_or(Status::INVALID_PARAMETER)?;
+        if protocols.iter().any(|(g, _)| g == guid) {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        protocols.push((*guid, interface));
+        Ok(key as Handle)
+    }
+
+    /// Removes the protocol `guid` with the given interface pointer from `handle`. The handle is removed once it has
+    /// no protocols left.
+    ///
+    /// Fails with NOT_FOUND if no such protocol is installed on the handle.
+    pub fn uninstall(
+        &mut self,
+        handle: Handle,
+        guid: &Guid,
+        interface: *mut c_void,
+    ) -> Result<ProtocolInterface, Status> {
+        let key = Self::key(handle);
+        let protocols = self.handles.get_mut(&key).ok_or(Status::NOT_FOUND)?;
+        let index = protocols
+            .iter()
+            .position(|(g, i)| g == guid && i.as_ptr() == interface)
+            .ok_or(Status::NOT_FOUND)?;
+        let (_, removed) = protocols.remove(index);
+        if protocols.is_empty() {
+            self.handles.remove(&key);
+        }
+        Ok(removed)
+    }
+
+    /// Returns the protocol `guid` installed on `handle`.
+    pub fn get(&self, handle: Handle, guid: &Guid) -> Option<&ProtocolInterface> {
+        self.handles
+            .get(&Self::key(handle))?
+            .iter()
+            .find_map(|(g, i)| (g == guid).then_some(i))
+    }
+
+    /// Returns the first installed instance of the protocol `guid` and the handle it is installed on.
+    pub fn locate(&self, guid: &Guid) -> Option<(Handle, &ProtocolInterface)> {
+        self.iter()
+            .find_map(|(handle, g, i)| (g == guid).then_some((handle, i)))
+    }
+
+    /// Returns true if any instance of the protocol `guid` is installed.
+    pub fn contains(&self, guid: &Guid) -> bool {
+        self.locate(guid).is_some()
+    }
+
+    /// Returns true if the handle exists.
+    pub fn contains_handle(&self, handle: Handle) -> bool {
+        self.handles.contains_key(&Self::key(handle))
+    }
+
+    /// Returns all handles the protocol `guid` is installed on, or all handles if `guid` is None.
+    pub fn locate_handles(&self, guid: Option<&Guid>) -> Vec<Handle> {
+        self.handles
+            .iter()
+            .filter(|(_, protocols)| match guid {
+                Some(guid) => protocols.iter().any(|(g, _)| g == guid),
+                None => true,
+            })
+            .map(|(key, _)| *key as Handle)
+            .collect()
+    }
+
+    /// Returns an iterator over every installed protocol and the handle it is installed on.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &Guid, &ProtocolInterface)> {
+        self.handles.iter().flat_map(|(key, protocols)| {
+            protocols
+                .iter()
+                .map(move |(guid, interface)| (*key as Handle, guid, interface))
+        })
+    }
+}
@@ -0,0 +1,417 @@
+//! An in-memory UEFI variable store, following the semantics of the GetVariable, SetVariable and GetNextVariableName
+//! runtime services.
+//!
+//! Each variable is identified by a vendor [Guid] and a name, and has a set of `efi::VARIABLE_*` attributes. Non
+//! volatile variables can be serialized with [VariableStore::to_bytes] and loaded again with [VariableStore::load], e.g.
+//! to persist them to a file on the host.
+//!
+//! Authentication descriptors are not verified. Variables with an authenticated write attribute can only be written by
+//! the owner of the store through [VariableStore::provision]; SetVariable returns `SECURITY_VIOLATION` for them.
+//!
+//! Components consume variables through the `Variable<TOKEN>` and `VariableMut<TOKEN>` component parameters, where
+//! `TOKEN` implements [VariableToken].
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    cell::{Ref, RefCell, RefMut},
+    fmt,
+};
+
+use hashbrown::HashMap;
+use r_efi::efi::{self, Guid, Status};
+
+use crate::{bytes::read_u32, guid::PrettyGuid};
+
+/// The vendor guid of the architecturally defined variables, such as `BootOrder` and `Timeout`.
+pub const GLOBAL_VARIABLE_GUID: Guid = Guid::from_fields(
+    0x8be4df61,
+    0x93ca,
+    0x11d2,
+    0xaa,
+    0x0d,
+    &[0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c],
+);
+
+const AUTHENTICATED_ACCESS: u32 = efi::VARIABLE_AUTHENTICATED_WRITE_ACCESS
+    | efi::VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS
+    | efi::VARIABLE_ENHANCED_AUTHENTICATED_ACCESS;
+
+const STORE_SIGNATURE: &[u8; 4] = b"VARS";
+
+/// A Rust type that can be stored as the data of a variable.
+pub trait VariableDatum: Sized {
+    /// Converts the type into the variable data.
+    fn to_bytes(&self) -> Vec<u8>;
+    /// Converts variable data into the type, returning None if the data is malformed.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_variable_datum {
+    ($($ty:ty),*) => {
+        $(
+            impl VariableDatum for $ty {
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    Some(<$ty>::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_variable_datum!(u8, u16, u32, u64);
+
+impl VariableDatum for bool {
+    fn to_bytes(&self) -> Vec<u8> {
+        alloc::vec![*self as u8]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [value] => Some(*value != 0),
+            _ => None,
+        }
+    }
+}
+
+impl VariableDatum for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+/// The declaration of a variable.
+pub trait VariableToken: 'static {
+    /// The data type of the variable.
+    type Value: VariableDatum;
+
+    /// The vendor guid of the variable.
+    fn vendor_guid() -> &'static Guid;
+    /// The name of the variable.
+    fn name() -> &'static str;
+    /// The attributes the variable is set with.
+    fn attributes() -> u32;
+}
+
+/// A single variable in the store. A variable without data does not exist, as far as the variable services are
+/// concerned; it only reserves an id for a component that uses it.
+#[derive(Debug, Clone)]
+pub struct VariableEntry {
+    attributes: u32,
+    data: Option<Vec<u8>>,
+}
+
+impl VariableEntry {
+    pub(crate) const fn new() -> Self {
+        Self {
+            attributes: 0,
+            data: None,
+        }
+    }
+
+    /// Returns the attributes of the variable.
+    pub fn attributes(&self) -> u32 {
+        self.attributes
+    }
+
+    /// Returns the data of the variable, if it exists.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    /// Sets, appends to or deletes the variable, following the rules of SetVariable.
+    pub fn set(&mut self, attributes: u32, data: &[u8]) -> Result<(), Status> {
+        if attributes & AUTHENTICATED_ACCESS != 0
+            || (self.data.is_some() && self.attributes & AUTHENTICATED_ACCESS != 0)
+        {
+            return Err(Status::SECURITY_VIOLATION);
+        }
+        self.set_unauthenticated(attributes, data)
+    }
+
+    fn set_unauthenticated(&mut self, attributes: u32, data: &[u8]) -> Result<(), Status> {
+        if attributes & efi::VARIABLE_RUNTIME_ACCESS != 0
+            && attributes & efi::VARIABLE_BOOTSERVICE_ACCESS == 0
+        {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let append = attributes & efi::VARIABLE_APPEND_WRITE != 0;
+        let attributes = attributes & !efi::VARIABLE_APPEND_WRITE;
+        let delete = !append && (data.is_empty() || attributes == 0);
+
+        match &mut self.data {
+            None if delete => Err(Status::NOT_FOUND),
+            // Appending nothing to a variable that does not exist is a no-op.
+            None if data.is_empty() => Ok(()),
+            None if attributes & efi::VARIABLE_BOOTSERVICE_ACCESS == 0 => {
+                Err(Status::INVALID_PARAMETER)
+            }
+            None => {
+                self.attributes = attributes;
+                self.data = Some(data.to_vec());
+                Ok(())
+            }
+            Some(_) if delete => {
+                self.attributes = 0;
+                self.data = None;
+                Ok(())
+            }
+            Some(_) if attributes != self.attributes => Err(Status::INVALID_PARAMETER),
+            Some(existing) if append => {
+                existing.extend_from_slice(data);
+                Ok(())
+            }
+            Some(existing) => {
+                *existing = data.to_vec();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A store of variables keyed by vendor guid and name. Variables are enumerated in the order they were first added.
+///
+/// Each entry is boxed, so a borrowed entry stays in place while other variables are registered, e.g. when the runtime
+/// services set a new variable while a component holds a `Variable` param. An entry borrowed mutably cannot be read or
+/// written through the store until it is released; the store returns `ACCESS_DENIED` instead.
+#[derive(Default, Clone)]
+pub struct VariableStore {
+    // Boxed, so that borrowed entries stay in place when the vector grows.
+    #[allow(clippy::vec_box)]
+    entries: Vec<Box<(Guid, String, RefCell<VariableEntry>)>>,
+    indices: HashMap<(Guid, String), usize>,
+}
+
+impl VariableStore {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Returns the id of the variable, if it has been registered.
+    pub fn id(&self, guid: &Guid, name: &str) -> Option<usize> {
+        self.indices.get(&(*guid, name.to_string())).copied()
+    }
+
+    /// Registers the variable if it is not already registered, returning its id. The variable does not exist until it
+    /// is set.
+    pub fn get_or_insert(&mut self, guid: &Guid, name: &str) -> usize {
+        match self.id(guid, name) {
+            Some(id) => id,
+            None => {
+                let id = self.entries.len();
+                self.entries.push(Box::new((
+                    *guid,
+                    name.to_string(),
+                    RefCell::new(VariableEntry::new()),
+                )));
+                self.indices.insert((*guid, name.to_string()), id);
+                id
+            }
+        }
+    }
+
+    /// Returns true if the variable exists, or `ACCESS_DENIED` if it is borrowed mutably.
+    pub fn contains(&self, guid: &Guid, name: &str) -> Result<bool, Status> {
+        match self.id(guid, name) {
+            Some(id) => Self::exists(&self.entries[id].2),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the attributes and data of the variable, or `NOT_FOUND` if it does not exist.
+    pub fn get(&self, guid: &Guid, name: &str) -> Result<(u32, Vec<u8>), Status> {
+        let id = self.id(guid, name).ok_or(Status::NOT_FOUND)?;
+        let entry = self.entries[id]
+            .2
+            .try_borrow()
+            .map_err(|_| Status::ACCESS_DENIED)?;
+        let data = entry.data.clone().ok_or(Status::NOT_FOUND)?;
+        Ok((entry.attributes, data))
+    }
+
+    /// Sets, appends to or deletes the variable, following the rules of SetVariable.
+    pub fn set(
+        &mut self,
+        guid: &Guid,
+        name: &str,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), Status> {
+        if name.is_empty() {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let id = self.get_or_insert(guid, name);
+        self.entries[id]
+            .2
+            .try_borrow_mut()
+            .map_err(|_| Status::ACCESS_DENIED)?
+            .set(attributes, data)
+    }
+
+    /// Sets the variable regardless of its authenticated write attributes. Used when populating the store.
+    pub fn provision(
+        &mut self,
+        guid: &Guid,
+        name: &str,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), Status> {
+        if name.is_empty() {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let id = self.get_or_insert(guid, name);
+        let entry = self.entries[id].2.get_mut();
+        // Replace rather than update, so the attributes of an existing variable can change.
+        *entry = VariableEntry::new();
+        entry.set_unauthenticated(attributes, data)
+    }
+
+    /// Returns the variable following `previous`, or the first variable if `previous` is None.
+    ///
+    /// Returns `INVALID_PARAMETER` if `previous` does not exist, and `NOT_FOUND` after the last variable.
+    pub fn next_name(&self, previous: Option<(&Guid, &str)>) -> Result<(Guid, String), Status> {
+        let start = match previous {
+            None => 0,
+            Some((guid, name)) => match self.id(guid, name) {
+                Some(id) if Self::exists(&self.entries[id].2)? => id + 1,
+                _ => return Err(Status::INVALID_PARAMETER),
+            },
+        };
+        for entry in &self.entries[start..] {
+            let (guid, name, entry) = &**entry;
+            if Self::exists(entry)? {
+                return Ok((*guid, name.clone()));
+            }
+        }
+        Err(Status::NOT_FOUND)
+    }
+
+    /// Returns true if the entry holds a variable, or `ACCESS_DENIED` if it is borrowed mutably.
+    fn exists(entry: &RefCell<VariableEntry>) -> Result<bool, Status> {
+        entry
+            .try_borrow()
+            .map(|entry| entry.data.is_some())
+            .map_err(|_| Status::ACCESS_DENIED)
+    }
+
+    /// Returns the vendor guid, name and attributes of all existing variables.
+    ///
+    /// Variables borrowed mutably are skipped, as whether they exist cannot be known until they are released.
+    pub fn iter(&self) -> impl Iterator<Item = (&Guid, &str, u32)> {
+        self.entries.iter().filter_map(|entry| {
+            let (guid, name, entry) = &**entry;
+            let entry = entry.try_borrow().ok()?;
+            entry
+                .data
+                .is_some()
+                .then_some((guid, name.as_str(), entry.attributes))
+        })
+    }
+
+    /// Returns the cell holding a variable entry, or None if no variable has the id.
+    pub fn cell(&self, id: usize) -> Option<&RefCell<VariableEntry>> {
+        self.entries.get(id).map(|entry| &entry.2)
+    }
+
+    /// Returns the cells holding all variable entries mutably.
+    pub(crate) fn cells_mut(&mut self) -> impl Iterator<Item = &mut RefCell<VariableEntry>> {
+        self.entries.iter_mut().map(|entry| &mut entry.2)
+    }
+
+    /// Retrieves a variable entry by id.
+    pub fn entry(&self, id: usize) -> Ref<'_, VariableEntry> {
+        self.entries[id].2.borrow()
+    }
+
+    /// Retrieves a mutable variable entry by id.
+    pub fn entry_mut(&self, id: usize) -> RefMut<'_, VariableEntry> {
+        self.entries[id].2.borrow_mut()
+    }
+
+    /// Serializes all non volatile variables.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = STORE_SIGNATURE.to_vec();
+        for entry in &self.entries {
+            let (guid, name, entry) = &**entry;
+            let entry = entry.borrow();
+            let Some(data) = &entry.data else {
+                continue;
+            };
+            if entry.attributes & efi::VARIABLE_NON_VOLATILE == 0 {
+                continue;
+            }
+            bytes.extend_from_slice(guid.as_bytes());
+            bytes.extend_from_slice(&entry.attributes.to_le_bytes());
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    /// Loads variables serialized with [to_bytes](Self::to_bytes), replacing any existing variables with the same
+    /// vendor guid and name. Returns the number of variables loaded, or `VOLUME_CORRUPTED` if the data is malformed.
+    pub fn load(&mut self, bytes: &[u8]) -> Result<usize, Status> {
+        let mut reader = bytes
+            .strip_prefix(STORE_SIGNATURE.as_slice())
+            .ok_or(Status::VOLUME_CORRUPTED)?;
+
+        let mut variables = Vec::new();
+        while !reader.is_empty() {
+            let guid = Guid::from_bytes(
+                take(&mut reader, 16)?
+                    .try_into()
+                    .expect("Slice is 16 bytes"),
+            );
+            let attributes = read_u32(take(&mut reader, 4)?);
+            let name_len = read_u32(take(&mut reader, 4)?) as usize;
+            let name = core::str::from_utf8(take(&mut reader, name_len)?)
+                .map_err(|_| Status::VOLUME_CORRUPTED)?;
+            let data_len = read_u32(take(&mut reader, 4)?) as usize;
+            let data = take(&mut reader, data_len)?;
+            variables.push((guid, name, attributes, data));
+        }
+
+        for (guid, name, attributes, data) in &variables {
+            self.provision(guid, name, *attributes, data)
+                .map_err(|_| Status::VOLUME_CORRUPTED)?;
+        }
+        Ok(variables.len())
+    }
+}
+
+/// Splits `len` bytes off the front of `reader`.
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], Status> {
+    let (value, rest) = reader
+        .split_at_checked(len)
+        .ok_or(Status::VOLUME_CORRUPTED)?;
+    *reader = rest;
+    Ok(value)
+}
+
+impl fmt::Debug for VariableStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(guid, name, attributes)| ((PrettyGuid(guid), name), attributes)),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,126 @@
+//! Procedural macros for writing components, re-exported by the `sdk` crate.
+use proc_macro::TokenStream;
+use quote::ToTokens;
+use syn::{parse_macro_input, FnArg, GenericArgument, ItemFn, Pat, PathArguments, Type};
+
+/// A config borrowed by a parameter of a component.
+struct ConfigBorrow {
+    /// The name of the parameter.
+    param: String,
+    /// The wrapper the config is borrowed through, i.e. `Config` or `ConfigMut`.
+    wrapper: String,
+    /// The config type, as written.
+    config: String,
+    mutable: bool,
+}
+
+impl ConfigBorrow {
+    /// Returns the config borrowed by a parameter of type `ty`, if it is a `Config` or `ConfigMut`.
+    fn new(param: String, ty: &Type) -> Option<Self> {
+        let Type::Path(path) = ty else {
+            return None;
+        };
+        if path.qself.is_some() {
+            return None;
+        }
+        let segment = path.path.segments.last()?;
+        let mutable = match segment.ident.to_string().as_str() {
+            "Config" => false,
+            "ConfigMut" => true,
+            _ => return None,
+        };
+        let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+            return None;
+        };
+        // Lifetimes are skipped, leaving the config type.
+        let config = arguments.args.iter().find_map(|argument| match argument {
+            GenericArgument::Type(config) => Some(config),
+            _ => None,
+        })?;
+        Some(Self {
+            param,
+            wrapper: segment.ident.to_string(),
+            config: config.to_token_stream().to_string(),
+            mutable,
+        })
+    }
+}
+
+/// Rejects, at compile time, a component that borrows the same config mutably more than once, or both mutably and
+/// immutably.
+///
+/// Such a component would otherwise only fail when it is added to the component manager. The function itself is left
+/// unchanged.
+///
+/// ```
+/// # struct Config<T>(T);
+/// # struct ConfigMut<T>(T);
+/// #[sdk_macros::component]
+/// fn component(data: Config<i32>, data2: Config<i32>, other: ConfigMut<u32>) {}
+/// ```
+///
+/// ```compile_fail
+/// # struct Config<T>(T);
+/// # struct ConfigMut<T>(T);
+/// #[sdk_macros::component]
+/// fn component(data: Config<i32>, data2: ConfigMut<i32>) {}
+/// ```
+///
+/// Configs are compared by how their types are written, so a config named through two different paths or aliases is
+/// not caught here, and is still rejected when the component is added.
+#[proc_macro_attribute]
+pub fn component(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let function = parse_macro_input!(item as ItemFn);
+    let mut errors: Option<syn::Error> = None;
+    let mut push_error = |error: syn::Error| match &mut errors {
+        Some(errors) => errors.combine(error),
+        None => errors = Some(error),
+    };
+
+    if !attr.is_empty() {
+        push_error(syn::Error::new_spanned(
+            proc_macro2::TokenStream::from(attr),
+            "#[component] takes no arguments",
+        ));
+    }
+
+    let mut borrows: Vec<ConfigBorrow> = Vec::new();
+    for input in &function.sig.inputs {
+        let FnArg::Typed(input) = input else {
+            continue;
+        };
+        let param = match &*input.pat {
+            Pat::Ident(pat) => pat.ident.to_string(),
+            pat => pat.to_token_stream().to_string(),
+        };
+        let Some(borrow) = ConfigBorrow::new(param, &input.ty) else {
+            continue;
+        };
+        let previous = borrows.iter().find(|previous| {
+            previous.config == borrow.config && (previous.mutable || borrow.mutable)
+        });
+        if let Some(previous) = previous {
+            push_error(syn::Error::new_spanned(
+                &input.ty,
+                format!(
+                    "`{}: {}<{}>` conflicts with the previous `{}: {}<{}>` parameter, as a component can borrow a \
+                     config either mutably once, or immutably any number of times",
+                    borrow.param,
+                    borrow.wrapper,
+                    borrow.config,
+                    previous.param,
+                    previous.wrapper,
+                    previous.config
+                ),
+            ));
+        }
+        borrows.push(borrow);
+    }
+
+    // The function is emitted even if it conflicts, so that the conflict is the only error reported.
+    let mut output = function.into_token_stream();
+    if let Some(errors) = errors {
+        output.extend(errors.into_compile_error());
+    }
+    output.into()
+}